@@ -64,6 +64,14 @@ impl CacheError {
     pub(super) const TIMEOUT_MARKER: &[u8] = b"timeout";
     pub(super) const DOWNLOAD_ERROR_MARKER: &[u8] = b"downloaderror";
 
+    /// Whether this error is transient, so that retrying the computation may succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout(_) | Self::DownloadError(_) | Self::InternalError
+        )
+    }
+
     /// Writes error markers and details to a file.
     ///
     /// * If `self` is [`InternalError`](Self::InternalError), it does nothing.
@@ -1,11 +1,19 @@
 use std::fmt::{self, Write};
 use std::sync::Arc;
 
-use sha2::{Digest, Sha256};
 use symbolicator_sources::RemoteFile;
 
 use crate::types::Scope;
 
+/// An offset applied to all cache versions when [`CacheKey`]s are hashed using blake3.
+///
+/// The hash determines the on-disk path of a cache file. Switching the hashing algorithm thus
+/// requires a version bump, so that files written using different algorithms never share the
+/// same versioned directory. Offsetting the version keeps the `v$version/aa/bbccdd/eeff...`
+/// path format intact.
+#[cfg(feature = "blake3-keys")]
+const BLAKE3_VERSION_OFFSET: u32 = 1 << 16;
+
 #[derive(Debug, Clone, Eq)]
 pub struct CacheKey {
     metadata: Arc<str>,
@@ -19,8 +27,19 @@ impl fmt::Display for CacheKey {
 }
 
 impl PartialEq for CacheKey {
+    /// Compares two [`CacheKey`]s by their hash.
+    ///
+    /// In debug builds, this additionally asserts that keys with equal hashes were built from
+    /// identical metadata, to surface cache key construction bugs early.
     fn eq(&self, other: &Self) -> bool {
-        self.hash == other.hash
+        let eq = self.hash == other.hash;
+        debug_assert!(
+            !eq || self.metadata == other.metadata,
+            "`CacheKey` hash collision: {:?} != {:?}",
+            self.metadata,
+            other.metadata,
+        );
+        eq
     }
 }
 
@@ -38,17 +57,70 @@ impl CacheKey {
         builder.build()
     }
 
+    /// Creates a [`CacheKey`] from its [`metadata`](Self::metadata), such as the one written next
+    /// to a cache file.
+    pub fn from_metadata(metadata: impl Into<String>) -> Self {
+        CacheKeyBuilder {
+            metadata: metadata.into(),
+        }
+        .build()
+    }
+
     /// Returns the human-readable metadata that forms the basis of the [`CacheKey`].
     pub fn metadata(&self) -> &str {
         &self.metadata
     }
 
+    /// Returns the hash of the [`metadata`](Self::metadata), which identifies the [`CacheKey`].
+    pub fn digest(&self) -> [u8; 32] {
+        self.hash
+    }
+
     /// Returns the relative path for this cache key.
     ///
     /// The relative path is a sha-256 hash hex-formatted like so:
     /// `v$version/aa/bbccdd/eeff...`
+    ///
+    /// See [`write_cache_path`](Self::write_cache_path) for details on the sharding scheme.
     pub fn cache_path(&self, version: u32) -> String {
-        let mut path = format!("v{version}/{:02x}/", self.hash[0]);
+        // `v$version/` + 3 hex-formatted segments, with 2 separators
+        let mut path = String::with_capacity(12 + self.hash.len() * 2 + 2);
+        self.write_cache_path(version, &mut path);
+        path
+    }
+
+    /// Returns the relative path for this cache key within the given `namespace`.
+    ///
+    /// Paths within a namespace are prefixed with `ns/$namespace/`, which keeps the entries of
+    /// multiple deployments sharing a cache apart. Without a namespace, this is the same as the
+    /// [`cache_path`](Self::cache_path).
+    pub fn namespaced_cache_path(&self, namespace: Option<&str>, version: u32) -> String {
+        let mut path = String::new();
+        if let Some(namespace) = namespace {
+            path.write_fmt(format_args!("ns/{namespace}/")).unwrap();
+        }
+        self.write_cache_path(version, &mut path);
+        path
+    }
+
+    /// Appends the relative path for this cache key to the given `path` buffer.
+    ///
+    /// This allows re-using the same buffer when dealing with a large number of cache keys.
+    /// The buffer is not cleared before writing.
+    ///
+    /// The cache files are sharded into a two-level directory hierarchy based on the hash:
+    /// - The first directory is named after the first hash byte (`aa`), giving 256 top-level
+    ///   directories per version.
+    /// - The second directory is named after the next three hash bytes (`bbccdd`).
+    /// - The filename consists of the remaining 28 hash bytes (`eeff...`).
+    ///
+    /// When the `blake3-keys` feature is enabled, the `version` is offset by a fixed amount, to
+    /// keep blake3 hashed keys apart from sha-256 hashed ones.
+    pub fn write_cache_path(&self, version: u32, path: &mut String) {
+        #[cfg(feature = "blake3-keys")]
+        let version = version + BLAKE3_VERSION_OFFSET;
+        path.write_fmt(format_args!("v{version}/{:02x}/", self.hash[0]))
+            .unwrap();
         for b in &self.hash[1..4] {
             path.write_fmt(format_args!("{b:02x}")).unwrap();
         }
@@ -56,21 +128,34 @@ impl CacheKey {
         for b in &self.hash[4..] {
             path.write_fmt(format_args!("{b:02x}")).unwrap();
         }
-        path
     }
 
     /// Create a [`CacheKeyBuilder`] that can be used to build a cache key consisting of all its
     /// contributing sources.
     pub fn scoped_builder(scope: &Scope) -> CacheKeyBuilder {
-        let metadata = format!("scope: {scope}\n\n");
+        let metadata = Self::scope_metadata(scope);
+        CacheKeyBuilder { metadata }
+    }
+
+    /// Returns the start of the [`metadata`](Self::metadata) of all keys of the given `scope`.
+    ///
+    /// The metadata starts with the scope, followed by an empty line.
+    pub fn scope_metadata(scope: &Scope) -> String {
+        format!("scope: {scope}\n\n")
+    }
+
+    /// Create a [`CacheKeyBuilder`] seeded with the metadata of this [`CacheKey`].
+    ///
+    /// This can be used to derive a new cache key from an existing one, by appending further
+    /// sources to it.
+    pub fn to_builder(&self) -> CacheKeyBuilder {
+        let metadata = self.metadata.to_string();
         CacheKeyBuilder { metadata }
     }
 
     #[cfg(test)]
     pub fn for_testing(key: impl Into<String>) -> Self {
-        let metadata = key.into();
-
-        CacheKeyBuilder { metadata }.build()
+        Self::from_metadata(key)
     }
 }
 
@@ -86,19 +171,42 @@ pub struct CacheKeyBuilder {
 
 impl CacheKeyBuilder {
     /// Writes metadata about the [`RemoteFile`] into the [`CacheKey`].
+    ///
+    /// This includes the type of source the file resides on, so that files of sources with
+    /// the same id but of a different type do not collide.
     pub fn write_file_meta(&mut self, file: &RemoteFile) -> Result<(), fmt::Error> {
         self.metadata.write_fmt(format_args!(
-            "source: {}\nlocation: {}\n",
+            "source: {}\ntype: {}\nlocation: {}\n",
             file.source_id(),
+            file.type_name(),
             file.uri()
         ))
     }
 
+    /// Writes an arbitrary `key: value` attribute into the [`CacheKey`].
+    ///
+    /// Attributes are appended in the order they are written, and that order is significant:
+    /// writing the same attributes in a different order results in a different [`CacheKey`].
+    ///
+    /// Returns an error if `key` is empty or contains a newline or colon, as that would make the
+    /// metadata ambiguous.
+    pub fn write_attribute(
+        &mut self,
+        key: &str,
+        value: impl fmt::Display,
+    ) -> Result<(), fmt::Error> {
+        if key.is_empty() || key.contains(['\n', ':']) {
+            return Err(fmt::Error);
+        }
+        self.metadata.write_fmt(format_args!("{key}: {value}\n"))
+    }
+
     /// Finalize the [`CacheKey`].
+    ///
+    /// The metadata is hashed using sha-256 by default, or blake3 when the `blake3-keys`
+    /// feature is enabled.
     pub fn build(self) -> CacheKey {
-        let hash = Sha256::digest(&self.metadata);
-        // FIXME: `sha2` should really adopt const generics, this is such a pain right now
-        let hash = <[u8; 32]>::try_from(hash).expect("sha256 outputs 32 bytes");
+        let hash = digest(&self.metadata);
 
         CacheKey {
             metadata: self.metadata.into(),
@@ -107,24 +215,100 @@ impl CacheKeyBuilder {
     }
 }
 
+/// Hashes the given metadata using the configured hashing backend.
+#[cfg(not(feature = "blake3-keys"))]
+fn digest(metadata: &str) -> [u8; 32] {
+    sha256_digest(metadata)
+}
+
+/// Hashes the given metadata using the configured hashing backend.
+#[cfg(feature = "blake3-keys")]
+fn digest(metadata: &str) -> [u8; 32] {
+    blake3_digest(metadata)
+}
+
+#[cfg(any(test, not(feature = "blake3-keys")))]
+fn sha256_digest(metadata: &str) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+
+    let hash = Sha256::digest(metadata);
+    // FIXME: `sha2` should really adopt const generics, this is such a pain right now
+    <[u8; 32]>::try_from(hash).expect("sha256 outputs 32 bytes")
+}
+
+#[cfg(feature = "blake3-keys")]
+fn blake3_digest(metadata: &str) -> [u8; 32] {
+    blake3::hash(metadata.as_bytes()).into()
+}
+
 impl fmt::Write for CacheKeyBuilder {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         self.metadata.write_str(s)
     }
 }
 
+/// The serialized representation of a [`CacheKey`].
+///
+/// The `hash` is serialized hex-encoded, and verified against the `metadata` when deserializing.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedCacheKey<'a> {
+    #[serde(borrow)]
+    metadata: std::borrow::Cow<'a, str>,
+    #[serde(borrow)]
+    hash: std::borrow::Cow<'a, str>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CacheKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedCacheKey {
+            metadata: self.metadata().into(),
+            hash: data_encoding::HEXLOWER.encode(&self.hash).into(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CacheKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let serialized = SerializedCacheKey::deserialize(deserializer)?;
+
+        let stored = data_encoding::HEXLOWER
+            .decode(serialized.hash.as_bytes())
+            .map_err(D::Error::custom)?;
+        let hash = digest(&serialized.metadata);
+        if stored != hash {
+            return Err(D::Error::custom(
+                "`CacheKey` hash does not match its metadata",
+            ));
+        }
+
+        Ok(CacheKey {
+            metadata: serialized.metadata.into(),
+            hash,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
     use std::sync::Arc;
 
     use symbolicator_sources::{
-        FilesystemRemoteFile, FilesystemSourceConfig, SourceId, SourceLocation,
+        FilesystemRemoteFile, FilesystemSourceConfig, GcsRemoteFile, GcsSourceConfig, GcsSourceKey,
+        HttpRemoteFile, HttpSourceAuth, HttpSourceConfig, S3Region, S3RemoteFile, S3SourceConfig,
+        S3SourceKey, SentryFileId, SentryRemoteFile, SentrySourceConfig, SourceId, SourceLocation,
     };
 
     use super::*;
 
     #[test]
+    #[cfg(not(feature = "blake3-keys"))]
     fn test_hashed_paths() {
         let scope = Scope::Global;
         let source = Arc::new(FilesystemSourceConfig {
@@ -139,11 +323,11 @@ mod tests {
 
         assert_eq!(
             &key.cache_path(0),
-            "v0/6f/200788/bd4e6760d55bf6bd50c6d6e98b52379e194f9989fb788b4d37796427"
+            "v0/bb/a27272/0e2d0cdcb32c5903329a0c6d86b0f9db443c4fc2f3278c446fe11bc7"
         );
         assert_eq!(
             key.metadata(),
-            "scope: global\n\nsource: foo\nlocation: file:///bar.baz\n"
+            "scope: global\n\nsource: foo\ntype: filesystem\nlocation: file:///bar.baz\n"
         );
 
         let built_key = CacheKey::from_scoped_file(&scope, &file);
@@ -161,11 +345,402 @@ mod tests {
 
         assert_eq!(
             &key.cache_path(0),
-            "v0/07/e89036/d56878a462eb7949a744afa0a4deb5ed1b7a8154be16f7dd3b220518"
+            "v0/42/983a85/b403c74962238cd1b15a8e34356245ed5346ede387fa28ad93795478"
+        );
+        assert_eq!(
+            key.metadata(),
+            "scope: global\n\nsource: foo\ntype: filesystem\nlocation: file:///bar.baz\n\nsecond_source:\nsource: foo\ntype: filesystem\nlocation: file:///bar.quux\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "blake3-keys"))]
+    fn test_sha256_keys() {
+        let metadata = "scope: global\n\nsource: foo\n";
+        let key = CacheKey::for_testing(metadata);
+
+        assert_eq!(key.hash.len(), 32);
+        assert_eq!(key.hash, sha256_digest(metadata));
+        assert!(key.cache_path(1).starts_with("v1/"));
+    }
+
+    #[test]
+    #[cfg(feature = "blake3-keys")]
+    fn test_blake3_keys() {
+        let metadata = "scope: global\n\nsource: foo\n";
+        let key = CacheKey::for_testing(metadata);
+
+        let sha256 = sha256_digest(metadata);
+        let blake3 = blake3_digest(metadata);
+        assert_eq!(sha256.len(), 32);
+        assert_eq!(blake3.len(), 32);
+        assert_ne!(sha256, blake3);
+        assert_eq!(key.hash, blake3);
+
+        let sha256_key = CacheKey {
+            metadata: key.metadata.clone(),
+            hash: sha256,
+        };
+        assert_ne!(key.cache_path(1), sha256_key.cache_path(1));
+        assert!(key
+            .cache_path(1)
+            .starts_with(&format!("v{}/", 1 + BLAKE3_VERSION_OFFSET)));
+    }
+
+    #[test]
+    fn test_eq_compares_hash() {
+        let key = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+        let same = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+        let other = CacheKey::for_testing("scope: global\n\nsource: bar\n");
+
+        assert_eq!(key, same);
+        assert_ne!(key, other);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "`CacheKey` hash collision")]
+    fn test_eq_detects_collision() {
+        let key = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+        let colliding = CacheKey {
+            metadata: "scope: global\n\nsource: bar\n".into(),
+            hash: key.hash,
+        };
+
+        let _ = key == colliding;
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_roundtrip() {
+        let key = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+
+        let json = serde_json::to_string(&key).unwrap();
+        let deserialized: CacheKey = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(deserialized, key);
+        assert_eq!(deserialized.metadata(), key.metadata());
+        assert_eq!(deserialized.cache_path(1), key.cache_path(1));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_tampered() {
+        let key = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+
+        let mut value = serde_json::to_value(&key).unwrap();
+        value["metadata"] = "scope: global\n\nsource: bar\n".into();
+        let err = serde_json::from_value::<CacheKey>(value).unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+
+        let mut value = serde_json::to_value(&key).unwrap();
+        value["hash"] = "not hex".into();
+        assert!(serde_json::from_value::<CacheKey>(value).is_err());
+    }
+
+    #[test]
+    fn test_write_attribute() {
+        let mut builder = CacheKey::scoped_builder(&Scope::Global);
+        builder.write_attribute("arch", "arm64").unwrap();
+        builder.write_attribute("version", 3).unwrap();
+        let key = builder.build();
+
+        assert_eq!(key.metadata(), "scope: global\n\narch: arm64\nversion: 3\n");
+
+        let mut builder = CacheKey::scoped_builder(&Scope::Global);
+        builder.write_attribute("version", 3).unwrap();
+        builder.write_attribute("arch", "arm64").unwrap();
+        let reordered = builder.build();
+
+        assert_ne!(key, reordered);
+        assert_ne!(key.cache_path(1), reordered.cache_path(1));
+    }
+
+    #[test]
+    fn test_write_attribute_invalid_key() {
+        let mut builder = CacheKey::scoped_builder(&Scope::Global);
+        assert!(builder.write_attribute("", "foo").is_err());
+        assert!(builder.write_attribute("foo:bar", "foo").is_err());
+        assert!(builder.write_attribute("foo\nbar", "foo").is_err());
+
+        assert_eq!(builder.build().metadata(), "scope: global\n\n");
+    }
+
+    #[test]
+    fn test_to_builder() {
+        let source = Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("foo"),
+            path: PathBuf::new(),
+            files: Default::default(),
+        });
+        let object = RemoteFile::from(FilesystemRemoteFile::new(
+            source.clone(),
+            SourceLocation::new("foo/bar.baz"),
+        ));
+        let plist = RemoteFile::from(FilesystemRemoteFile::new(
+            source,
+            SourceLocation::new("foo/Info.plist"),
+        ));
+
+        let primary = CacheKey::from_scoped_file(&Scope::Global, &object);
+        let mut builder = primary.to_builder();
+        builder.write_file_meta(&plist).unwrap();
+        let derived = builder.build();
+
+        let mut builder = CacheKey::scoped_builder(&Scope::Global);
+        builder.write_file_meta(&object).unwrap();
+        builder.write_file_meta(&plist).unwrap();
+        let from_scratch = builder.build();
+
+        assert_ne!(derived, primary);
+        assert_eq!(derived, from_scratch);
+        assert_eq!(derived.metadata(), from_scratch.metadata());
+        assert_eq!(derived.cache_path(1), from_scratch.cache_path(1));
+    }
+
+    #[test]
+    fn test_s3_key_stable_across_credentials() {
+        let s3_file = |access_key: &str, secret_key: &str| {
+            let source = Arc::new(S3SourceConfig {
+                id: SourceId::new("s3"),
+                bucket: "bucket".into(),
+                prefix: "prefix".into(),
+                source_key: Arc::new(S3SourceKey {
+                    region: S3Region::from("us-east-1"),
+                    aws_credentials_provider: Default::default(),
+                    access_key: access_key.into(),
+                    secret_key: secret_key.into(),
+                }),
+                files: Default::default(),
+            });
+            RemoteFile::from(S3RemoteFile::new(
+                source,
+                SourceLocation::new("foo/bar.baz"),
+            ))
+        };
+
+        let key = CacheKey::from_scoped_file(&Scope::Global, &s3_file("access", "secret"));
+        let rotated = CacheKey::from_scoped_file(&Scope::Global, &s3_file("rotated", "rotated"));
+
+        assert_eq!(
+            key.metadata(),
+            "scope: global\n\nsource: s3\ntype: s3\nlocation: s3://bucket/prefix/foo/bar.baz\n"
         );
+        assert_eq!(key, rotated);
+        assert_eq!(key.metadata(), rotated.metadata());
+        assert_eq!(key.cache_path(1), rotated.cache_path(1));
+    }
+
+    #[test]
+    fn test_gcs_key_stable_across_credentials() {
+        let gcs_file = |private_key: &str| {
+            let source = Arc::new(GcsSourceConfig {
+                id: SourceId::new("gcs"),
+                bucket: "bucket".into(),
+                prefix: "/prefix/".into(),
+                source_key: Arc::new(GcsSourceKey {
+                    private_key: private_key.into(),
+                    client_email: "client@example.com".into(),
+                }),
+                files: Default::default(),
+            });
+            RemoteFile::from(GcsRemoteFile::new(
+                source,
+                SourceLocation::new("foo/bar.baz"),
+            ))
+        };
+
+        let key = CacheKey::from_scoped_file(&Scope::Global, &gcs_file("private"));
+        let refreshed = CacheKey::from_scoped_file(&Scope::Global, &gcs_file("refreshed"));
+
         assert_eq!(
             key.metadata(),
-            "scope: global\n\nsource: foo\nlocation: file:///bar.baz\n\nsecond_source:\nsource: foo\nlocation: file:///bar.quux\n"
+            "scope: global\n\nsource: gcs\ntype: gcs\nlocation: gs://bucket/prefix/foo/bar.baz\n"
+        );
+        assert_eq!(key, refreshed);
+        assert_eq!(key.cache_path(1), refreshed.cache_path(1));
+    }
+
+    #[test]
+    fn test_write_cache_path() {
+        let keys = [
+            CacheKey::for_testing("foo"),
+            CacheKey::for_testing("bar"),
+            CacheKey::for_testing("scope: global\n\nsource: foo\n"),
+        ];
+
+        let mut buf = String::new();
+        for key in &keys {
+            for version in [0, 1, 1234] {
+                buf.clear();
+                key.write_cache_path(version, &mut buf);
+                assert_eq!(buf, key.cache_path(version));
+
+                #[cfg(feature = "blake3-keys")]
+                let version = version + BLAKE3_VERSION_OFFSET;
+                let segments: Vec<_> = buf.split('/').collect();
+                assert_eq!(segments.len(), 4);
+                assert_eq!(segments[0], format!("v{version}"));
+                assert_eq!(segments[1].len(), 2);
+                assert_eq!(segments[2].len(), 6);
+                assert_eq!(segments[3].len(), 56);
+            }
+        }
+
+        // the buffer is appended to
+        let mut buf = String::from("prefix/");
+        keys[0].write_cache_path(0, &mut buf);
+        assert_eq!(buf, format!("prefix/{}", keys[0].cache_path(0)));
+    }
+
+    #[test]
+    fn test_namespaced_cache_path() {
+        let key = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+
+        assert_eq!(key.namespaced_cache_path(None, 1), key.cache_path(1));
+
+        let one = key.namespaced_cache_path(Some("one"), 1);
+        let two = key.namespaced_cache_path(Some("two"), 1);
+        assert_eq!(one, format!("ns/one/{}", key.cache_path(1)));
+        assert_eq!(two, format!("ns/two/{}", key.cache_path(1)));
+        assert!(!one.starts_with(&two) && !two.starts_with(&one));
+    }
+
+    #[test]
+    fn test_source_types() {
+        let id = SourceId::new("foo");
+        let location = SourceLocation::new("bar.baz");
+        let url = "https://example.com/".parse().unwrap();
+
+        let filesystem = FilesystemRemoteFile::new(
+            Arc::new(FilesystemSourceConfig {
+                id: id.clone(),
+                path: PathBuf::new(),
+                files: Default::default(),
+            }),
+            location.clone(),
+        );
+        let http = HttpRemoteFile::new(
+            Arc::new(HttpSourceConfig {
+                id: id.clone(),
+                url,
+                headers: Default::default(),
+                auth: None,
+                bypass_proxy: false,
+                files: Default::default(),
+            }),
+            location.clone(),
         );
+        let s3 = S3RemoteFile::new(
+            Arc::new(S3SourceConfig {
+                id: id.clone(),
+                bucket: "bucket".into(),
+                prefix: String::new(),
+                source_key: Arc::new(S3SourceKey {
+                    region: S3Region::from("us-east-1"),
+                    aws_credentials_provider: Default::default(),
+                    access_key: "access".into(),
+                    secret_key: "secret".into(),
+                }),
+                files: Default::default(),
+            }),
+            location.clone(),
+        );
+        let gcs = GcsRemoteFile::new(
+            Arc::new(GcsSourceConfig {
+                id: id.clone(),
+                bucket: "bucket".into(),
+                prefix: String::new(),
+                source_key: Arc::new(GcsSourceKey {
+                    private_key: "private".into(),
+                    client_email: "client@example.com".into(),
+                }),
+                files: Default::default(),
+            }),
+            location,
+        );
+        let sentry = SentryRemoteFile::new(
+            Arc::new(SentrySourceConfig {
+                id,
+                url: "https://sentry.example.com/".parse().unwrap(),
+                token: "token".into(),
+            }),
+            SentryFileId("123".into()),
+            None,
+        );
+
+        let files: [(RemoteFile, &str); 5] = [
+            (filesystem.into(), "type: filesystem\n"),
+            (http.into(), "type: http\n"),
+            (s3.into(), "type: s3\n"),
+            (gcs.into(), "type: gcs\n"),
+            (sentry.into(), "type: sentry\n"),
+        ];
+        for (file, type_line) in &files {
+            let key = CacheKey::from_scoped_file(&Scope::Global, file);
+            assert!(
+                key.metadata().contains(type_line),
+                "{:?} does not contain {type_line:?}",
+                key.metadata()
+            );
+        }
+
+        // the hash changes even if only the source type differs
+        let key = CacheKey::from_scoped_file(&Scope::Global, &files[0].0);
+        let retyped = key.metadata().replace("type: filesystem", "type: http");
+        let retyped = CacheKey::for_testing(retyped);
+        assert_ne!(key, retyped);
+        assert_ne!(key.cache_path(0), retyped.cache_path(0));
+    }
+
+    #[test]
+    fn test_http_auth_not_in_key() {
+        let http_file = |auth| -> RemoteFile {
+            let source = Arc::new(HttpSourceConfig {
+                id: SourceId::new("foo"),
+                url: "https://example.com/".parse().unwrap(),
+                headers: Default::default(),
+                auth,
+                bypass_proxy: false,
+                files: Default::default(),
+            });
+            HttpRemoteFile::new(source, SourceLocation::new("bar.baz")).into()
+        };
+
+        let key = CacheKey::from_scoped_file(&Scope::Global, &http_file(None));
+        let auths = [
+            HttpSourceAuth::Basic {
+                username: "user".into(),
+                password: Some("password".into()),
+            },
+            HttpSourceAuth::Bearer {
+                token: "rotated-token".into(),
+            },
+        ];
+        for auth in auths {
+            let authenticated = CacheKey::from_scoped_file(&Scope::Global, &http_file(Some(auth)));
+            assert_eq!(key, authenticated);
+            assert_eq!(key.metadata(), authenticated.metadata());
+        }
+    }
+
+    #[test]
+    fn test_http_proxy_not_in_key() {
+        let http_file = |bypass_proxy| -> RemoteFile {
+            let source = Arc::new(HttpSourceConfig {
+                id: SourceId::new("foo"),
+                url: "https://example.com/".parse().unwrap(),
+                headers: Default::default(),
+                auth: None,
+                bypass_proxy,
+                files: Default::default(),
+            });
+            HttpRemoteFile::new(source, SourceLocation::new("bar.baz")).into()
+        };
+
+        let proxied = CacheKey::from_scoped_file(&Scope::Global, &http_file(false));
+        let direct = CacheKey::from_scoped_file(&Scope::Global, &http_file(true));
+        assert_eq!(proxied, direct);
+        assert_eq!(proxied.metadata(), direct.metadata());
     }
 }
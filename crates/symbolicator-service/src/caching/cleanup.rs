@@ -1,13 +1,17 @@
-use std::fs::{read_dir, remove_dir, remove_file};
+use std::collections::BTreeSet;
+use std::fs::{read_dir, read_to_string, remove_dir, remove_file};
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 use anyhow::{anyhow, Result};
 
 use crate::config::Config;
+use crate::types::Scope;
 
-use super::fs::catch_not_found;
-use super::{Cache, Caches};
+use super::fs::{catch_not_found, is_read_only_error};
+use super::validators::VALIDATORS_EXTENSION;
+use super::{Cache, CacheKey, Caches};
 
 /// Entry function for the cleanup command.
 ///
@@ -21,12 +25,30 @@ impl Caches {
     ///
     /// We need to do this on startup of the main symbolicator process to avoid accidentally
     /// leaving temporary files which survive a hard crash.
+    ///
+    /// This includes the temporary files of caches whose directory was overridden, and the
+    /// directory owned by symbolicator within the configured `tmp_dir` if the caches use it. The
+    /// rest of the `tmp_dir` is left alone, as it might be shared.
     pub fn clear_tmp(&self, config: &Config) -> io::Result<()> {
-        if let Some(ref tmp) = config.cache_dir("tmp") {
-            if tmp.exists() {
-                std::fs::remove_dir_all(tmp)?;
+        let tmp_dirs = [
+            config.cache_dir("tmp"),
+            // all caches within the `cache_dir` share the same temporary directory
+            self.cficaches.tmp_dir.clone(),
+            self.objects.tmp_dir.clone(),
+            self.symcaches.tmp_dir.clone(),
+        ];
+        let tmp_dirs: BTreeSet<_> = tmp_dirs.into_iter().flatten().collect();
+
+        for tmp in tmp_dirs {
+            let cleared = catch_not_found(|| std::fs::remove_dir_all(&tmp))
+                .and_then(|_| std::fs::create_dir_all(&tmp));
+            match cleared {
+                // read-only caches use the system's temporary directory instead
+                Err(err) if is_read_only_error(&err) => {
+                    tracing::warn!("Cannot clear the read-only directory {}", tmp.display());
+                }
+                result => result?,
             }
-            std::fs::create_dir_all(tmp)?;
         }
         Ok(())
     }
@@ -76,9 +98,151 @@ impl Caches {
             None => Ok(()),
         }
     }
+
+    /// Removes all the cache entries belonging to the given `scope` from all caches.
+    ///
+    /// The global scope cannot be purged, as its entries are shared with all other scopes.
+    /// Items of the scope held in memory are dropped as well, see [`Cache::purge_scope`].
+    ///
+    /// Returns the total number of removed cache entries.
+    pub fn purge_scope(&self, scope: &Scope) -> Result<usize> {
+        // NOTE: a `Scoped("global")` scope would match the metadata of the global scope
+        anyhow::ensure!(
+            scope.as_ref() != "global",
+            "the global scope cannot be purged"
+        );
+
+        // Destructure so we do not accidentally forget to purge one of our members.
+        let Self {
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        } = &self;
+
+        let caches = [
+            objects,
+            object_meta,
+            symcaches,
+            cficaches,
+            diagnostics,
+            auxdifs,
+            il2cpp,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+        ];
+
+        // Just like `cleanup`, we want to attempt purging all caches before failing.
+        let mut removed = 0;
+        let mut first_error = None;
+        for cache in caches {
+            match cache.purge_scope(scope) {
+                Ok(num) => removed += num,
+                Err(err) => {
+                    let stderr: &dyn std::error::Error = &*err;
+                    tracing::error!(stderr, "Failed to purge cache");
+                    if first_error.is_none() {
+                        first_error = Some(err);
+                    }
+                }
+            }
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(removed),
+        }
+    }
+
+    /// Removes cache files from all caches on demand, according to the given [`PruneOptions`].
+    ///
+    /// Files which were not used within the `max_age` are removed first. Then, the least
+    /// recently used files are evicted until all caches together fit into `max_size`, just like
+    /// the `max_cache_size` of a single cache is enforced by [`cleanup`](Self::cleanup).
+    ///
+    /// Returns the removed files, or the files which would be removed for a `dry_run`.
+    pub fn prune(&self, options: PruneOptions) -> Result<Vec<PrunedFile>> {
+        // Destructure so we do not accidentally forget to prune one of our members.
+        let Self {
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        } = &self;
+
+        let caches = [
+            objects,
+            object_meta,
+            symcaches,
+            cficaches,
+            diagnostics,
+            auxdifs,
+            il2cpp,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+        ];
+
+        let mut entries = Vec::new();
+        let mut total_size = 0;
+        for cache_dir in caches.iter().filter_map(|cache| cache.cache_dir()) {
+            collect_cache_files(cache_dir, &mut entries, &mut total_size)?;
+        }
+
+        let mut pruned = Vec::new();
+        if let Some(max_age) = options.max_age {
+            let (expired, unexpired): (Vec<_>, Vec<_>) = entries
+                .into_iter()
+                .partition(|entry| entry.mtime.elapsed().unwrap_or_default() > max_age);
+            entries = unexpired;
+
+            let expired_size = expired.iter().map(|entry| entry.size).sum();
+            let expired = evict_lru(expired, expired_size, 0, options.dry_run);
+            total_size -= expired.iter().map(|file| file.size).sum::<u64>();
+            pruned.extend(expired);
+        }
+
+        if let Some(max_size) = options.max_size {
+            pruned.extend(evict_lru(entries, total_size, max_size, options.dry_run));
+        }
+
+        Ok(pruned)
+    }
 }
 
 impl Cache {
+    /// Removes all the cache entries belonging to the given `scope`.
+    ///
+    /// As the scope is hashed into the cache path, entries are matched using the metadata file
+    /// which is written alongside each cache item. Items without a metadata file are kept.
+    /// The items of the scope held in memory by the [`Cacher`](super::Cacher)s of this cache
+    /// are dropped as well.
+    ///
+    /// Returns the number of removed cache entries.
+    pub fn purge_scope(&self, scope: &Scope) -> Result<usize> {
+        self.scope_invalidators.invalidate(scope);
+
+        let Some(cache_dir) = self.cache_dir.as_ref() else {
+            return Ok(0);
+        };
+
+        tracing::info!("Purging scope {} from cache: {}", scope, self.name);
+        let header = CacheKey::scope_metadata(scope);
+        purge_directory_recursive(cache_dir, &header)
+    }
+
     pub fn cleanup(&self) -> Result<()> {
         tracing::info!("Cleaning up cache: {}", self.name);
         let cache_dir = self.cache_dir.as_ref().ok_or_else(|| {
@@ -87,6 +251,53 @@ impl Cache {
 
         self.cleanup_directory_recursive(cache_dir)?;
 
+        let max_cache_size = self.cache_config.max_cache_size();
+        let missing_free_space = self.missing_free_space(cache_dir);
+        if max_cache_size.is_some() || missing_free_space > 0 {
+            self.evict_to_size(cache_dir, max_cache_size, missing_free_space)?;
+        }
+
+        Ok(())
+    }
+
+    /// Evicts the least recently used cache items until the cache is at most `max_cache_size`
+    /// bytes large, and at least `free_bytes` were evicted.
+    ///
+    /// The latter makes room on a volume which is below its `min_free_space`. As the caches are
+    /// cleaned up one after the other, this evicts from the first caches on a volume first.
+    ///
+    /// Cache items are ordered by their `mtime`, which is bumped regularly for items in use.
+    /// Metadata files are evicted together with the cache item they belong to, orphaned ones
+    /// are evicted before any cache item. Files that are
+    /// currently being written live in the separate `tmp` directory and are persisted atomically,
+    /// so they are never considered for eviction.
+    fn evict_to_size(
+        &self,
+        cache_dir: &Path,
+        max_cache_size: Option<u64>,
+        free_bytes: u64,
+    ) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut total_size = 0;
+        collect_cache_files(cache_dir, &mut entries, &mut total_size)?;
+
+        let max_cache_size = max_cache_size
+            .unwrap_or(u64::MAX)
+            .min(total_size.saturating_sub(free_bytes));
+
+        if total_size <= max_cache_size {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "Evicting from cache {} ({} bytes, limit {} bytes)",
+            self.name,
+            total_size,
+            max_cache_size
+        );
+
+        evict_lru(entries, total_size, max_cache_size, false);
+
         Ok(())
     }
 
@@ -135,9 +346,23 @@ impl Cache {
     fn try_cleanup_path(&self, path: &Path) -> Result<bool> {
         tracing::trace!("Checking {}", path.display());
         anyhow::ensure!(path.is_file(), "not a file");
+
+        // validators are kept as long as their cache item, and removed together with it
+        if path
+            .extension()
+            .map_or(false, |ext| ext == VALIDATORS_EXTENSION)
+        {
+            if path.with_extension("").is_file() {
+                return Ok(false);
+            }
+            catch_not_found(|| remove_file(path))?;
+            return Ok(true);
+        }
+
         if catch_not_found(|| self.check_expiry(path))?.is_none() {
             tracing::debug!("Removing {}", path.display());
             catch_not_found(|| remove_file(path))?;
+            catch_not_found(|| remove_file(path.with_extension(VALIDATORS_EXTENSION)))?;
 
             return Ok(true);
         }
@@ -145,3 +370,170 @@ impl Cache {
         Ok(false)
     }
 }
+
+/// Limits for removing cache files with [`Caches::prune`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PruneOptions {
+    /// Removes the cache files which were not used for longer than this.
+    pub max_age: Option<Duration>,
+    /// Evicts the least recently used cache files until all caches together are at most this
+    /// many bytes large.
+    pub max_size: Option<u64>,
+    /// Only reports the cache files which would be removed, without removing them.
+    pub dry_run: bool,
+}
+
+/// A cache file removed by [`Caches::prune`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrunedFile {
+    /// The path of the cache file.
+    pub path: PathBuf,
+    /// The combined size of the cache file, its metadata file and its validators.
+    pub size: u64,
+}
+
+/// Evicts the least recently used `entries` until their `total_size` is at most `max_size`.
+///
+/// Orphaned metadata files and validators are evicted first, as they are of no use without
+/// their cache item. Files which fail to be removed are skipped. Returns the removed files, or
+/// the files which would be removed for a `dry_run`.
+fn evict_lru(
+    mut entries: Vec<CacheFileEntry>,
+    mut total_size: u64,
+    max_size: u64,
+    dry_run: bool,
+) -> Vec<PrunedFile> {
+    entries.sort_by_key(|entry| (!entry.orphan, entry.mtime));
+
+    let mut evicted = Vec::new();
+    for entry in entries {
+        if total_size <= max_size {
+            break;
+        }
+
+        if !dry_run {
+            tracing::debug!("Evicting {}", entry.path.display());
+            if let Err(e) = catch_not_found(|| remove_file(&entry.path)) {
+                sentry::with_scope(
+                    |scope| scope.set_extra("path", entry.path.display().to_string().into()),
+                    || tracing::error!("Failed to evict cache file: {:?}", e),
+                );
+                continue;
+            }
+            let _ = catch_not_found(|| remove_file(entry.path.with_extension("txt")));
+            let _ =
+                catch_not_found(|| remove_file(entry.path.with_extension(VALIDATORS_EXTENSION)));
+        }
+
+        total_size = total_size.saturating_sub(entry.size);
+        evicted.push(PrunedFile {
+            path: entry.path,
+            size: entry.size,
+        });
+    }
+    evicted
+}
+
+/// A cache item considered for size-based eviction.
+pub(super) struct CacheFileEntry {
+    path: PathBuf,
+    /// The combined size of the cache item, its metadata file and its validators.
+    size: u64,
+    pub(super) mtime: SystemTime,
+    /// Whether this is a metadata file or validators without a cache item.
+    pub(super) orphan: bool,
+}
+
+/// Recursively collects all the cache items in `directory`, along with the total size of all files.
+pub(super) fn collect_cache_files(
+    directory: &Path,
+    entries: &mut Vec<CacheFileEntry>,
+    total_size: &mut u64,
+) -> Result<()> {
+    let dir_entries = match catch_not_found(|| read_dir(directory))? {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    for entry in dir_entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_cache_files(&path, entries, total_size)?;
+            continue;
+        }
+
+        let Some(metadata) = catch_not_found(|| path.metadata())? else {
+            continue;
+        };
+        *total_size += metadata.len();
+
+        // metadata files and validators are accounted for, and evicted together with their
+        // cache item. Orphaned ones are evicted on their own.
+        if path
+            .extension()
+            .map_or(false, |ext| ext == "txt" || ext == VALIDATORS_EXTENSION)
+        {
+            if !path.with_extension("").is_file() {
+                entries.push(CacheFileEntry {
+                    path,
+                    size: metadata.len(),
+                    mtime: metadata.modified()?,
+                    orphan: true,
+                });
+            }
+            continue;
+        }
+
+        let mut sidecar_size = 0;
+        for extension in ["txt", VALIDATORS_EXTENSION] {
+            sidecar_size += catch_not_found(|| path.with_extension(extension).metadata())?
+                .map_or(0, |m| m.len());
+        }
+        entries.push(CacheFileEntry {
+            path,
+            size: metadata.len() + sidecar_size,
+            mtime: metadata.modified()?,
+            orphan: false,
+        });
+    }
+
+    Ok(())
+}
+
+/// Recursively removes all cache items in `directory` whose metadata starts with `header`.
+///
+/// Returns the number of removed cache items.
+fn purge_directory_recursive(directory: &Path, header: &str) -> Result<usize> {
+    let entries = match catch_not_found(|| read_dir(directory))? {
+        Some(x) => x,
+        None => return Ok(0),
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            removed += purge_directory_recursive(&path, header)?;
+            continue;
+        }
+
+        if path.extension().map_or(true, |ext| ext != "txt") {
+            continue;
+        }
+        let Some(metadata) = catch_not_found(|| read_to_string(&path))? else {
+            continue;
+        };
+        if !metadata.starts_with(header) {
+            continue;
+        }
+
+        let item_path = path.with_extension("");
+        tracing::debug!("Purging {}", item_path.display());
+        catch_not_found(|| remove_file(&item_path))?;
+        catch_not_found(|| remove_file(item_path.with_extension(VALIDATORS_EXTENSION)))?;
+        catch_not_found(|| remove_file(&path))?;
+        removed += 1;
+    }
+
+    Ok(removed)
+}
@@ -1,7 +1,7 @@
 use std::fmt;
 
 /// All known cache names.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CacheName {
     Objects,
     ObjectMeta,
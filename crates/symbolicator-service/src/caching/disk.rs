@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::FreeSpace;
+
+use super::{Cache, Caches};
+
+/// The space of the volume a path is on, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    /// The space available to unprivileged users.
+    pub available: u64,
+    /// The total size of the volume.
+    pub total: u64,
+}
+
+impl DiskSpace {
+    /// Gathers the space of the volume the given `path` is on.
+    #[cfg(unix)]
+    pub fn of(path: &Path) -> io::Result<Self> {
+        use std::ffi::CString;
+        use std::mem::MaybeUninit;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: `path` is a valid C string, and `stat` is only read once `statvfs` succeeded.
+        let stat = unsafe {
+            if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            stat.assume_init()
+        };
+
+        let block_size = u64::from(stat.f_frsize);
+        Ok(Self {
+            available: u64::from(stat.f_bavail) * block_size,
+            total: u64::from(stat.f_blocks) * block_size,
+        })
+    }
+
+    /// Gathers the space of the volume the given `path` is on.
+    #[cfg(not(unix))]
+    pub fn of(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "gathering the disk space is not supported on this platform",
+        ))
+    }
+
+    /// Returns the number of bytes which need to be freed to have `min_free_space` available.
+    pub fn missing(&self, min_free_space: FreeSpace) -> u64 {
+        min_free_space
+            .bytes(self.total)
+            .saturating_sub(self.available)
+    }
+}
+
+/// A cache volume with less free space than the configured `min_free_space`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LowDiskSpace {
+    /// The directory of a cache on the volume.
+    pub path: PathBuf,
+    /// The space of the volume.
+    pub space: DiskSpace,
+    /// The minimum free space of the volume, in bytes.
+    pub min_free: u64,
+}
+
+impl fmt::Display for LowDiskSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "only {} of {} bytes are free on the volume of `{}`, below the minimum of {} bytes",
+            self.space.available,
+            self.space.total,
+            self.path.display(),
+            self.min_free
+        )
+    }
+}
+
+impl Caches {
+    /// Checks that the volumes of all caches have at least `min_free_space` available.
+    ///
+    /// The free space of each volume is reported as a metric. Returns the volumes which are
+    /// below the threshold.
+    pub fn check_free_space(&self, min_free_space: FreeSpace) -> Vec<LowDiskSpace> {
+        self.check_free_space_with(min_free_space, DiskSpace::of)
+    }
+
+    fn check_free_space_with(
+        &self,
+        min_free_space: FreeSpace,
+        disk_space: impl Fn(&Path) -> io::Result<DiskSpace>,
+    ) -> Vec<LowDiskSpace> {
+        // Destructure so we do not accidentally forget to check one of our members.
+        let Self {
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        } = self;
+
+        let caches = [
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        ];
+
+        // Cache files are moved into place from the temporary directory of their cache, so all
+        // caches sharing a temporary directory are on the same volume.
+        let mut volumes: BTreeMap<&Path, &Cache> = BTreeMap::new();
+        for cache in caches {
+            if let (Some(tmp_dir), Some(_)) = (&cache.tmp_dir, &cache.cache_dir) {
+                volumes.entry(tmp_dir.as_path()).or_insert(cache);
+            }
+        }
+
+        let mut low = vec![];
+        for cache in volumes.into_values() {
+            let Some(path) = cache.cache_dir() else {
+                continue;
+            };
+            let space = match disk_space(path) {
+                Ok(space) => space,
+                Err(err) => {
+                    let error: &dyn std::error::Error = &err;
+                    tracing::warn!(
+                        error,
+                        "Failed to gather the disk space of {}",
+                        path.display()
+                    );
+                    continue;
+                }
+            };
+
+            metric!(gauge("caches.disk.available") = space.available, "cache" => cache.name().as_ref());
+            metric!(gauge("caches.disk.total") = space.total, "cache" => cache.name().as_ref());
+
+            if space.missing(min_free_space) > 0 {
+                low.push(LowDiskSpace {
+                    path: path.to_owned(),
+                    space,
+                    min_free: min_free_space.bytes(space.total),
+                });
+            }
+        }
+        low
+    }
+}
+
+impl Cache {
+    /// Returns the number of bytes which need to be evicted from the volume of this cache to have
+    /// the configured `min_free_space` available.
+    pub(super) fn missing_free_space(&self, cache_dir: &Path) -> u64 {
+        let Some(min_free_space) = self.min_free_space else {
+            return 0;
+        };
+        match DiskSpace::of(cache_dir) {
+            Ok(space) => space.missing(min_free_space),
+            Err(err) => {
+                let error: &dyn std::error::Error = &err;
+                tracing::warn!(
+                    error,
+                    "Failed to gather the disk space of {}",
+                    cache_dir.display()
+                );
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{CacheConfigs, Config};
+
+    use super::*;
+
+    #[test]
+    fn test_missing() {
+        let space = DiskSpace {
+            available: 100,
+            total: 1000,
+        };
+
+        assert_eq!(space.missing(FreeSpace::Bytes(50)), 0);
+        assert_eq!(space.missing(FreeSpace::Bytes(150)), 50);
+        assert_eq!(space.missing(FreeSpace::Percent(10.0)), 0);
+        assert_eq!(space.missing(FreeSpace::Percent(25.0)), 150);
+    }
+
+    #[test]
+    fn test_check_free_space() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let objects_dir = tempdir.path().join("objects_volume");
+        let config = Config {
+            cache_dir: Some(tempdir.path().join("cache")),
+            caches: CacheConfigs {
+                objects_dir: Some(objects_dir.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let caches = Caches::from_config(&config).unwrap();
+
+        // the overridden objects directory is on a full volume, all other caches are fine
+        let checked = std::cell::RefCell::new(vec![]);
+        let disk_space = |path: &Path| {
+            checked.borrow_mut().push(path.to_owned());
+            let available = if path == objects_dir { 10 } else { 500 };
+            Ok(DiskSpace {
+                available,
+                total: 1000,
+            })
+        };
+
+        let low = caches.check_free_space_with(FreeSpace::Percent(5.0), disk_space);
+
+        // one check for the objects volume, and one for the volume of all other caches
+        assert_eq!(checked.borrow().len(), 2);
+        assert_eq!(
+            low,
+            [LowDiskSpace {
+                path: objects_dir,
+                space: DiskSpace {
+                    available: 10,
+                    total: 1000,
+                },
+                min_free: 50,
+            }]
+        );
+
+        let low = caches.check_free_space_with(FreeSpace::Bytes(5), |_| {
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        });
+        assert!(low.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_space() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let space = DiskSpace::of(tempdir.path()).unwrap();
+        assert!(space.total > 0);
+        assert!(space.available <= space.total);
+    }
+}
@@ -1,14 +1,16 @@
-use std::io;
+use std::fmt;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::AtomicIsize;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime};
 
 use filetime::FileTime;
 use symbolic::common::ByteView;
 use tempfile::NamedTempFile;
 
-use crate::config::{CacheConfig, Config};
+use crate::config::{CacheCompression, CacheConfig, Config, FailedComputations, FreeSpace};
+use crate::types::Scope;
 
 use super::cache_error::cache_entry_from_bytes;
 use super::{CacheEntry, CacheError, CacheName};
@@ -20,6 +22,113 @@ use super::{CacheEntry, CacheError, CacheName};
 /// This is being debounced to once every hour to not have to touch them on every single use.
 const TOUCH_EVERY: Duration = Duration::from_secs(3600);
 
+/// The header of cache files which are stored compressed, followed by a zstd stream.
+///
+/// Files without this header are read as they are, so compressed and uncompressed files can be
+/// mixed within the same cache directory.
+pub(super) const ZSTD_HEADER: &[u8] = b"SYMZSTD\0";
+
+/// Drops the items of a scope from the in-memory cache of a [`Cacher`](super::Cacher).
+type ScopeInvalidator = Box<dyn Fn(&Scope) + Send + Sync>;
+
+/// The [`ScopeInvalidator`]s of the [`Cacher`](super::Cacher)s of a [`Cache`].
+///
+/// These are shared between all clones of the [`Cache`].
+#[derive(Clone, Default)]
+pub(super) struct ScopeInvalidators(Arc<Mutex<Vec<ScopeInvalidator>>>);
+
+impl ScopeInvalidators {
+    /// Registers a function which drops the items of a scope from an in-memory cache.
+    pub(super) fn register(&self, invalidator: impl Fn(&Scope) + Send + Sync + 'static) {
+        self.0.lock().unwrap().push(Box::new(invalidator));
+    }
+
+    /// Drops the items of the given `scope` from all the registered in-memory caches.
+    pub(super) fn invalidate(&self, scope: &Scope) {
+        for invalidator in self.0.lock().unwrap().iter() {
+            invalidator(scope);
+        }
+    }
+}
+
+impl fmt::Debug for ScopeInvalidators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self.0.lock().map(|invalidators| invalidators.len());
+        f.debug_tuple("ScopeInvalidators")
+            .field(&len.unwrap_or_default())
+            .finish()
+    }
+}
+
+/// Returns the directory for temporary files of a cache whose directory was overridden.
+///
+/// Temporary files are moved into the cache by renaming them, so they need to be on the same
+/// volume as the overridden directory, which might be different from the one of `cache_dir`.
+/// They are thus placed in a sibling directory with a `.tmp` suffix.
+fn override_tmp_dir(dir: &Path) -> PathBuf {
+    // Normalize away trailing separators, so the suffix is not appended as a child.
+    let mut tmp_dir = dir.components().collect::<PathBuf>().into_os_string();
+    tmp_dir.push(".tmp");
+    tmp_dir.into()
+}
+
+/// The directory within the configured `tmp_dir` which holds the temporary files of the caches.
+///
+/// This directory is cleared on startup, while the rest of the `tmp_dir` might be shared.
+const OWNED_TMP_DIR: &str = "symbolicator-tmp";
+
+/// Returns the directory for temporary files of the cache in `cache_dir`.
+///
+/// Temporary files are moved into the cache by renaming them, which is only atomic within a
+/// single filesystem. The configured `tmp_dir` is thus only used if it is on the same filesystem
+/// as the `cache_dir`, otherwise this falls back to the `default` directory next to the cache.
+/// Within the `tmp_dir`, only the [`OWNED_TMP_DIR`] is used.
+fn select_tmp_dir(tmp_dir: Option<&Path>, cache_dir: &Path, default: PathBuf) -> PathBuf {
+    let Some(tmp_dir) = tmp_dir else {
+        return default;
+    };
+    let owned_tmp_dir = tmp_dir.join(OWNED_TMP_DIR);
+    let same_filesystem = std::fs::create_dir_all(&owned_tmp_dir)
+        .and_then(|_| is_same_filesystem(&owned_tmp_dir, cache_dir));
+    match same_filesystem {
+        Ok(true) => owned_tmp_dir,
+        Ok(false) => {
+            tracing::warn!(
+                tmp_dir = %tmp_dir.display(),
+                cache_dir = %cache_dir.display(),
+                "tmp_dir is on a different filesystem than the cache, using {} instead",
+                default.display()
+            );
+            default
+        }
+        Err(err) => {
+            tracing::warn!(
+                tmp_dir = %tmp_dir.display(),
+                error = %err,
+                "Failed to use tmp_dir, using {} instead",
+                default.display()
+            );
+            default
+        }
+    }
+}
+
+/// Whether the existing paths `a` and `b` are on the same filesystem.
+#[cfg(unix)]
+fn is_same_filesystem(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(std::fs::metadata(a)?.dev() == std::fs::metadata(b)?.dev())
+}
+
+/// Whether the existing paths `a` and `b` are on the same filesystem.
+///
+/// This cannot be checked on this platform, so they are assumed to be on different ones.
+#[cfg(not(unix))]
+fn is_same_filesystem(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
 /// Common cache configuration.
 ///
 /// Many parts of Symbolicator use a cache to save having to re-download data or reprocess
@@ -39,10 +148,11 @@ pub struct Cache {
     ///
     /// When writing a new file into the cache it is best to write it to a temporary file in
     /// a sibling directory, once fully written it can then be atomically moved to the
-    /// actual location withing the [`cache_dir`](Self::cache_dir).
+    /// actual location withing the [`cache_dir`](Self::cache_dir). This is the configured
+    /// `tmp_dir` if it is on the same filesystem as the `cache_dir`.
     ///
     /// Just like for `cache_dir` when this cache is disabled this will be `None`.
-    tmp_dir: Option<PathBuf>,
+    pub(super) tmp_dir: Option<PathBuf>,
 
     /// Time when this process started.
     start_time: SystemTime,
@@ -55,6 +165,37 @@ pub struct Cache {
 
     /// The capacity (in bytes) of the in-memory cache.
     pub(super) in_memory_capacity: u64,
+
+    /// The maximum number of entries in the in-memory cache.
+    ///
+    /// If set, this replaces the [`in_memory_capacity`](Self::in_memory_capacity).
+    pub(super) in_memory_entries: Option<u64>,
+
+    /// Whether computed items are kept in memory at all.
+    ///
+    /// If not, items are only shared between concurrent requests for them.
+    pub(super) keep_in_memory: bool,
+
+    /// The minimum free space on the volume of this cache, which is ensured during cleanup.
+    pub(super) min_free_space: Option<FreeSpace>,
+
+    /// What concurrent requests receive when the computation they waited for fails.
+    pub(super) failed_computations: FailedComputations,
+
+    /// Whether to write the metadata of the cache key next to each cache file.
+    pub(super) write_metadata: bool,
+
+    /// How cache files are compressed on disk.
+    compression: CacheCompression,
+
+    /// Whether the cache directory cannot be written to.
+    ///
+    /// A read-only cache still serves its existing files, but computed items are not written to
+    /// it. This is detected when the cache is created, and when writing to it fails later on.
+    read_only: Arc<AtomicBool>,
+
+    /// Drops the items of a scope held in memory, see [`purge_scope`](Self::purge_scope).
+    pub(super) scope_invalidators: ScopeInvalidators,
 }
 
 impl Cache {
@@ -65,12 +206,37 @@ impl Cache {
         max_lazy_refreshes: Arc<AtomicIsize>,
         in_memory_capacity: u64,
     ) -> io::Result<Self> {
-        let tmp_dir = config.cache_dir("tmp");
-        let cache_dir = config.cache_dir(name.as_ref());
+        let dir_override = match name {
+            CacheName::Objects => config.caches.objects_dir.as_deref(),
+            CacheName::Symcaches => config.caches.symcaches_dir.as_deref(),
+            _ => None,
+        };
+        let (cache_dir, tmp_dir) = match dir_override {
+            Some(dir) => (Some(dir.to_owned()), Some(override_tmp_dir(dir))),
+            None => (config.cache_dir(name.as_ref()), config.cache_dir("tmp")),
+        };
 
         if let Some(ref dir) = cache_dir {
             std::fs::create_dir_all(dir)?;
+
+            // `max_age` falls back to `mtime`, which is bumped whenever an item is used.
+            if cache_config.max_age().is_some() && dir.metadata()?.created().is_err() {
+                tracing::warn!(
+                    "The {name} cache does not record file creation times, so `max_age` only \
+                     expires items which have not been used for that long"
+                );
+            }
         }
+        let tmp_dir = cache_dir
+            .as_deref()
+            .zip(tmp_dir)
+            .map(|(cache_dir, default)| {
+                select_tmp_dir(config.tmp_dir.as_deref(), cache_dir, default)
+            });
+        let read_only = [&cache_dir, &tmp_dir]
+            .into_iter()
+            .flatten()
+            .any(|dir| !is_writable(dir));
 
         Ok(Cache {
             name,
@@ -80,9 +246,25 @@ impl Cache {
             cache_config,
             max_lazy_refreshes,
             in_memory_capacity,
+            in_memory_entries: None,
+            keep_in_memory: config.caches.in_memory.file_caches,
+            write_metadata: config.caches.write_metadata,
+            compression: config.caches.compression,
+            min_free_space: config.caches.min_free_space,
+            failed_computations: config.caches.failed_computations,
+            read_only: Arc::new(AtomicBool::new(read_only)),
+            scope_invalidators: Default::default(),
         })
     }
 
+    /// Limits the in-memory cache to a number of entries instead of their size in bytes, if given.
+    ///
+    /// This is meant for items backed by `mmap`, whose size in memory is not known.
+    pub fn with_in_memory_entries(mut self, entries: Option<u64>) -> Self {
+        self.in_memory_entries = entries;
+        self
+    }
+
     pub fn name(&self) -> CacheName {
         self.name
     }
@@ -95,11 +277,34 @@ impl Cache {
         self.max_lazy_refreshes.clone()
     }
 
+    /// Whether the cache directory cannot be written to, see [`Cache::set_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Switches the cache into read-only mode after writing to it failed with `err`.
+    ///
+    /// Existing cache files are still served, but new items are no longer written. This is
+    /// logged only once, when the cache becomes read-only.
+    pub(super) fn set_read_only(&self, err: &io::Error) {
+        if !self.read_only.swap(true, Ordering::Relaxed) {
+            tracing::warn!(
+                error = err as &dyn std::error::Error,
+                "The {} cache is read-only, new items are not cached on disk",
+                self.name
+            );
+        }
+    }
+
     /// Validate cache expiration of path.
     ///
     /// If cache should not be used, `Err(io::ErrorKind::NotFound)` is returned.
     /// If cache is usable, `Ok(x)` is returned with the opened [`ByteView`], and
     /// an [`ExpirationTime`] that indicates whether the file should be touched before using.
+    ///
+    /// The [`ByteView`] holds the raw contents of the file, which are still compressed for
+    /// compressed files, see [`decompress`]. Only positive entries are compressed, so the entry is
+    /// classified from the start of the file without decompressing it.
     pub(super) fn check_expiry(
         &self,
         path: &Path,
@@ -135,7 +340,24 @@ impl Cache {
                 }
 
                 // we want to touch good caches once every `TOUCH_EVERY`
-                let touch_in = TOUCH_EVERY.saturating_sub(mtime_elapsed);
+                let mut touch_in = TOUCH_EVERY.saturating_sub(mtime_elapsed);
+
+                if let Some(max_age) = self.cache_config.max_age() {
+                    // `mtime` is bumped whenever the item is used, so we rather use the creation
+                    // time to determine the age of the item. Without creation times, items in
+                    // use are only recomputed when they are evicted, see `Cache::from_config`.
+                    let created = metadata.created().unwrap_or(mtime);
+                    let age = created.elapsed().unwrap_or_default();
+                    let expires_in = max_age.saturating_sub(age);
+
+                    if expires_in == Duration::ZERO {
+                        return Err(io::ErrorKind::NotFound.into());
+                    }
+
+                    // make sure the item is checked again once it reaches its `max_age`
+                    touch_in = touch_in.min(expires_in);
+                }
+
                 ExpirationTime::TouchIn(touch_in)
             }
             ExpirationStrategy::Negative => {
@@ -189,22 +411,59 @@ impl Cache {
         // an open ByteView we can be sure to have a cache hit.
         catch_not_found(|| {
             let (cache_entry, mut expiration) = self.check_expiry(path)?;
+            let cache_entry = match cache_entry {
+                Ok(bv) => Ok(decompress(bv)?),
+                Err(err) => Err(err),
+            };
 
             let should_touch = matches!(expiration, ExpirationTime::TouchIn(Duration::ZERO));
-            if should_touch {
-                filetime::set_file_mtime(path, FileTime::now())?;
-                // well, we just touched the file ;-)
-                expiration = ExpirationTime::TouchIn(TOUCH_EVERY);
+            if should_touch && self.is_read_only() {
+                // the file cannot be touched, keep it in memory as if it was
+                expiration = ExpirationTime::RefreshIn(TOUCH_EVERY);
+            } else if should_touch {
+                match filetime::set_file_mtime(path, FileTime::now()) {
+                    // well, we just touched the file ;-)
+                    Ok(()) => expiration = ExpirationTime::TouchIn(TOUCH_EVERY),
+                    Err(err) if is_read_only_error(&err) => {
+                        self.set_read_only(&err);
+                        expiration = ExpirationTime::RefreshIn(TOUCH_EVERY);
+                    }
+                    Err(err) => return Err(err),
+                }
             }
 
             Ok((cache_entry, expiration))
         })
     }
 
+    /// Compresses the `contents` of a cache file, if compression is configured.
+    ///
+    /// Returns a temporary file holding the compressed contents, or the given `temp_file` if the
+    /// contents are stored uncompressed.
+    pub(super) fn compress(
+        &self,
+        temp_file: NamedTempFile,
+        contents: &[u8],
+    ) -> io::Result<NamedTempFile> {
+        match self.compression {
+            CacheCompression::None => Ok(temp_file),
+            CacheCompression::Zstd => {
+                let mut compressed = self.tempfile()?;
+                let file = compressed.as_file_mut();
+                file.write_all(ZSTD_HEADER)?;
+                zstd::stream::copy_encode(contents, file, 0)?;
+                Ok(compressed)
+            }
+        }
+    }
+
     /// Create a new temporary file to use in the cache.
+    ///
+    /// Read-only caches use the system's temporary directory instead, as their items are never
+    /// moved into the cache.
     pub fn tempfile(&self) -> io::Result<NamedTempFile> {
         match self.tmp_dir {
-            Some(ref path) => {
+            Some(ref path) if !self.is_read_only() => {
                 // The `cleanup` process could potentially remove the parent directories we are
                 // operating in, so be defensive here and retry the fs operations.
                 const MAX_RETRIES: usize = 2;
@@ -238,11 +497,34 @@ impl Cache {
                     }
                 }
             }
-            None => Ok(NamedTempFile::new()?),
+            _ => Ok(NamedTempFile::new()?),
         }
     }
 }
 
+/// Whether `err` signals that a cache directory cannot be written to.
+pub(crate) fn is_read_only_error(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    if err.raw_os_error() == Some(libc::EROFS) {
+        return true;
+    }
+    err.kind() == io::ErrorKind::PermissionDenied
+}
+
+/// Checks whether files can be created in `dir`, creating the directory if needed.
+///
+/// Only errors signalling a read-only directory, see [`is_read_only_error`], make a directory
+/// count as not writable.
+fn is_writable(dir: &Path) -> bool {
+    !matches!(probe_writable(dir), Err(err) if is_read_only_error(&err))
+}
+
+/// Creates an anonymous temporary file in `dir`, creating the directory if needed.
+pub(super) fn probe_writable(dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir).and_then(|_| tempfile::tempfile_in(dir))?;
+    Ok(())
+}
+
 /// Expiration strategies for cache items. These aren't named after the strategies themselves right
 /// now but after the type of cache entry they should be used on instead.
 #[derive(Debug, PartialEq, Eq)]
@@ -277,7 +559,10 @@ impl ExpirationTime {
         match strategy {
             ExpirationStrategy::None => {
                 // we want to touch good caches once every hour
-                Self::TouchIn(Duration::from_secs(3600))
+                let touch_in = Duration::from_secs(3600);
+                let max_age = config.max_age().unwrap_or(Duration::MAX);
+
+                Self::TouchIn(touch_in.min(max_age))
             }
             ExpirationStrategy::Negative => {
                 let retry_misses_after = config.retry_misses_after().unwrap_or(Duration::MAX);
@@ -307,6 +592,18 @@ impl ExpirationTime {
     }
 }
 
+/// Decompresses the contents of a cache file which was stored compressed.
+///
+/// Uncompressed files, such as the ones written before compression was turned on, are returned
+/// unchanged.
+pub(super) fn decompress(bv: ByteView<'static>) -> io::Result<ByteView<'static>> {
+    if !bv.starts_with(ZSTD_HEADER) {
+        return Ok(bv);
+    }
+    let decompressed = zstd::decode_all(&bv[ZSTD_HEADER.len()..])?;
+    Ok(ByteView::from_vec(decompressed))
+}
+
 /// Checks the cache contents in `buf` and returns the cleanup strategy that should be used
 /// for the item.
 pub(super) fn expiration_strategy<T>(status: &CacheEntry<T>) -> ExpirationStrategy {
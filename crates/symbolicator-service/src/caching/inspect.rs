@@ -0,0 +1,156 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use symbolic::common::ByteView;
+use symbolic::debuginfo::{Archive, FileFormat};
+use symbolic::symcache::SymCache;
+
+use super::cache_error::cache_entry_from_bytes;
+use super::fs::{catch_not_found, decompress, ZSTD_HEADER};
+use super::{CacheError, CacheKey};
+
+/// The kind of contents of a cache file, see [`CacheFileInfo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheFileContents {
+    /// A cached error, such as a file which was not found.
+    Error(CacheError),
+    /// An object file, in the given format.
+    Object(FileFormat),
+    /// A symcache.
+    SymCache,
+    /// Contents which are not recognized.
+    Unknown,
+}
+
+impl fmt::Display for CacheFileContents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error(err) => write!(f, "cached error ({err})"),
+            Self::Object(format) => write!(f, "object ({format})"),
+            Self::SymCache => write!(f, "symcache"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Information about a single file of a cache, as gathered by [`inspect`].
+#[derive(Debug, Clone)]
+pub struct CacheFileInfo {
+    /// The path of the cache file.
+    pub path: PathBuf,
+    /// The metadata of the [`CacheKey`] of the file, if it was written.
+    pub metadata: Option<String>,
+    /// Whether the path of the file matches the hash of its `metadata`.
+    pub hash_matches: Option<bool>,
+    /// Whether the file is stored compressed.
+    pub compressed: bool,
+    /// The size of the uncompressed contents, in bytes.
+    pub size: usize,
+    /// The kind of contents of the file.
+    pub contents: CacheFileContents,
+    /// The error encountered when parsing the contents of the file, if any.
+    pub parse_error: Option<String>,
+}
+
+impl CacheFileInfo {
+    /// Whether the file is consistent with its metadata and has valid contents.
+    pub fn is_valid(&self) -> bool {
+        self.hash_matches != Some(false) && self.parse_error.is_none()
+    }
+}
+
+impl fmt::Display for CacheFileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "path: {}", self.path.display())?;
+        match &self.metadata {
+            Some(metadata) => {
+                writeln!(f, "metadata:")?;
+                for line in metadata.lines() {
+                    writeln!(f, "  {line}")?;
+                }
+            }
+            None => writeln!(f, "metadata: missing")?,
+        }
+        match self.hash_matches {
+            Some(true) => writeln!(f, "hash: matches the metadata")?,
+            Some(false) => writeln!(f, "hash: does NOT match the metadata")?,
+            None => writeln!(f, "hash: unknown")?,
+        }
+        writeln!(f, "compressed: {}", self.compressed)?;
+        writeln!(f, "size: {} bytes", self.size)?;
+        writeln!(f, "contents: {}", self.contents)?;
+        match &self.parse_error {
+            Some(err) => write!(f, "valid: false ({err})"),
+            None => write!(f, "valid: true"),
+        }
+    }
+}
+
+/// Inspects the cache file at `path`.
+///
+/// This reads the metadata written next to the file, checks that the path of the file is the hash
+/// of that metadata, and detects and parses the contents of the file.
+pub fn inspect(path: &Path) -> io::Result<CacheFileInfo> {
+    let data = ByteView::open(path)?;
+    let compressed = data.starts_with(ZSTD_HEADER);
+    let data = decompress(data)?;
+
+    let metadata = catch_not_found(|| fs::read_to_string(path.with_extension("txt")))?;
+    let hash_matches = metadata
+        .as_ref()
+        .map(|metadata| hash_matches(path, metadata));
+
+    let size = data.len();
+    let (contents, parse_error) = match cache_entry_from_bytes(data) {
+        Err(err) => (CacheFileContents::Error(err), None),
+        Ok(data) => parse_contents(&data),
+    };
+
+    Ok(CacheFileInfo {
+        path: path.to_owned(),
+        metadata,
+        hash_matches,
+        compressed,
+        size,
+        contents,
+        parse_error,
+    })
+}
+
+/// Checks whether `path` ends in the hashed path of the cache key with the given `metadata`.
+///
+/// Only the hash is compared, as the version of the cache is not part of the metadata.
+fn hash_matches(path: &Path, metadata: &str) -> bool {
+    let expected = CacheKey::from_metadata(metadata).cache_path(0);
+    // strip the `v$version/` prefix
+    let expected = expected.split_once('/').map_or("", |(_, hash)| hash);
+
+    let mut actual: Vec<_> = path.iter().rev().take(3).collect();
+    actual.reverse();
+    actual.iter().collect::<PathBuf>() == Path::new(expected)
+}
+
+/// Detects the kind of `data` and checks that it can be parsed.
+fn parse_contents(data: &[u8]) -> (CacheFileContents, Option<String>) {
+    let format = Archive::peek(data);
+    if format != FileFormat::Unknown {
+        let contents = CacheFileContents::Object(format);
+        let parse_error = match Archive::parse(data) {
+            Ok(archive) => archive
+                .objects()
+                .find_map(Result::err)
+                .map(|err| err.to_string()),
+            Err(err) => Some(err.to_string()),
+        };
+        return (contents, parse_error);
+    }
+
+    if data.starts_with(b"SYMC") || data.starts_with(b"CMYS") {
+        let parse_error = SymCache::parse(data).err().map(|err| err.to_string());
+        return (CacheFileContents::SymCache, parse_error);
+    }
+
+    (CacheFileContents::Unknown, None)
+}
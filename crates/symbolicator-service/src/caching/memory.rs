@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use std::future::Future;
 use std::path::Path;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
@@ -7,13 +8,16 @@ use std::time::{Duration, Instant};
 use futures::future::BoxFuture;
 use parking_lot::Mutex;
 use sentry::{Hub, SentryFutureExt};
+use serde::{Deserialize, Serialize};
 use symbolic::common::ByteView;
 use tempfile::NamedTempFile;
 
 use super::shared_cache::{CacheStoreReason, SharedCacheRef};
+use crate::config::FailedComputations;
 use crate::utils::futures::CallOnDrop;
 
-use super::{Cache, CacheEntry, CacheError, CacheKey, ExpirationTime};
+use super::fs::{decompress, is_read_only_error};
+use super::{Cache, CacheEntry, CacheError, CacheKey, ExpirationTime, Revalidation, Validators};
 
 type InMemoryItem<T> = (Instant, CacheEntry<T>);
 type InMemoryCache<T> = moka::future::Cache<CacheKey, InMemoryItem<T>>;
@@ -69,20 +73,44 @@ impl<T: CacheItemRequest> Clone for Cacher<T> {
 
 impl<T: CacheItemRequest> Cacher<T> {
     pub fn new(config: Cache, shared_cache: SharedCacheRef) -> Self {
-        let cache = InMemoryCache::builder()
-            .max_capacity(config.in_memory_capacity)
+        let builder = InMemoryCache::builder()
             .name(config.name().as_ref())
             // NOTE: even though we have a per-item TTL, we still want to have a hard limit here
             .time_to_live(Duration::from_secs(60 * 60))
-            // NOTE: we count all the bookkeeping structures to the weight as well
-            .weigher(|_k, v| {
-                let value_size =
-                    v.1.as_ref()
-                        .map_or(0, T::weight)
-                        .max(std::mem::size_of::<CacheError>() as u32);
-                std::mem::size_of::<(CacheKey, Instant)>() as u32 + value_size
-            })
-            .build();
+            .support_invalidation_closures();
+        let cache = match config.in_memory_entries {
+            // concurrent computations are still deduplicated, but the result is evicted right away
+            _ if !config.keep_in_memory => builder.max_capacity(0).build(),
+            // without a weigher, each item counts as one entry
+            Some(entries) => builder.max_capacity(entries).build(),
+            None => builder
+                .max_capacity(config.in_memory_capacity)
+                // NOTE: we count all the bookkeeping structures to the weight as well
+                .weigher(|_k, v| {
+                    let value_size =
+                        v.1.as_ref()
+                            .map_or(0, T::weight)
+                            .max(std::mem::size_of::<CacheError>() as u32);
+                    std::mem::size_of::<(CacheKey, Instant)>() as u32 + value_size
+                })
+                .build(),
+        };
+
+        let in_memory = cache.clone();
+        config.scope_invalidators.register(move |scope| {
+            let metadata = CacheKey::scope_metadata(scope);
+            // This only fails if the cache does not support invalidation closures.
+            in_memory
+                .invalidate_entries_if(move |key, _| key.metadata().starts_with(&metadata))
+                .ok();
+        });
+
+        if config.is_read_only() {
+            tracing::warn!(
+                "The {} cache is read-only, new items are not cached on disk",
+                config.name()
+            );
+        }
 
         Cacher {
             config,
@@ -95,6 +123,22 @@ impl<T: CacheItemRequest> Cacher<T> {
     pub fn tempfile(&self) -> std::io::Result<NamedTempFile> {
         self.config.tempfile()
     }
+
+    /// Checks whether an item for the given `key` is cached, without loading or computing it.
+    ///
+    /// The item counts as cached if it is held in memory, or if a file for any of the
+    /// [`CacheVersions`] exists in the file system cache, even if it is expired.
+    pub fn is_cached(&self, key: &CacheKey) -> bool {
+        if self.cache.contains_key(key) {
+            return true;
+        }
+        let Some(cache_dir) = self.config.cache_dir() else {
+            return false;
+        };
+        T::VERSIONS
+            .lookup_order()
+            .any(|version| cache_dir.join(key.cache_path(version)).is_file())
+    }
 }
 
 /// Cache Version Configuration used during cache lookup and generation.
@@ -114,6 +158,27 @@ pub struct CacheVersions {
     pub fallbacks: &'static [u32],
 }
 
+impl CacheVersions {
+    /// Returns all the versions in lookup order, starting with `current` followed by all the
+    /// `fallbacks`.
+    pub fn lookup_order(&self) -> impl Iterator<Item = u32> + '_ {
+        std::iter::once(self.current).chain(self.fallbacks.iter().copied())
+    }
+}
+
+/// How a single request makes use of the caches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheMode {
+    /// Cached items are used, and computed items are cached.
+    #[default]
+    Use,
+    /// Cached items are ignored, and the computed items replace them in the caches.
+    Refresh,
+    /// Cached items are ignored, and the computed items are not cached at all.
+    Bypass,
+}
+
 pub trait CacheItemRequest: 'static + Send + Sync + Clone {
     type Item: 'static + Send + Sync + Clone;
 
@@ -124,6 +189,25 @@ pub trait CacheItemRequest: 'static + Send + Sync + Clone {
     /// system. This is used to populate the cache for a previously missing element.
     fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry>;
 
+    /// Invoked instead of [`compute`](Self::compute) to compute an instance of this item, along
+    /// with the [`Validators`] of its source.
+    ///
+    /// When an expired item is recomputed, the `validators` stored with it are passed in. If the
+    /// source reports that it has not been modified since, [`Revalidation::NotModified`] is
+    /// returned without writing to `temp_file`, and the expired item is used again.
+    ///
+    /// By default, this always computes the item anew, without any validators.
+    fn compute_conditional<'a>(
+        &'a self,
+        temp_file: &'a mut NamedTempFile,
+        _validators: Option<&'a Validators>,
+    ) -> BoxFuture<'a, CacheEntry<Revalidation>> {
+        Box::pin(async move {
+            self.compute(temp_file).await?;
+            Ok(Revalidation::Modified(None))
+        })
+    }
+
     /// Loads an existing element from the cache.
     fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item>;
 
@@ -173,7 +257,7 @@ impl<T: CacheItemRequest> Cacher<T> {
                 if let Some(shared_cache) = self.shared_cache.get() {
                     shared_cache.store(
                         name,
-                        &cache_key,
+                        &key.namespaced_cache_path(shared_cache.namespace(), version),
                         byteview.clone(),
                         CacheStoreReason::Refresh,
                     );
@@ -200,22 +284,36 @@ impl<T: CacheItemRequest> Cacher<T> {
 
     /// Compute an item.
     ///
-    /// The item is computed using [`T::compute`](CacheItemRequest::compute), and saved in the cache
-    /// if one is configured. The `is_refresh` flag is used only to tag computation metrics.
+    /// The item is computed using [`T::compute_conditional`](CacheItemRequest::compute_conditional),
+    /// and saved in the cache if one is configured. If an expired item with [`Validators`] exists
+    /// in the cache, it is revalidated and kept if it was not modified.
+    /// The `is_refresh` flag is used only to tag computation metrics.
+    /// Unless the `mode` is [`CacheMode::Use`], the item is not fetched from the shared cache, and
+    /// it is neither saved in the cache nor in the shared cache with [`CacheMode::Bypass`].
     ///
     /// This method does not take care of ensuring the computation only happens once even
     /// for concurrent requests, see the public [`Cacher::compute_memoized`] for this.
-    async fn compute(&self, request: T, key: &CacheKey, is_refresh: bool) -> CacheEntry<T::Item> {
+    async fn compute(
+        &self,
+        request: T,
+        key: &CacheKey,
+        is_refresh: bool,
+        mode: CacheMode,
+    ) -> CacheEntry<T::Item> {
         let name = self.config.name();
         let cache_path = key.cache_path(T::VERSIONS.current);
         let mut temp_file = self.tempfile()?;
 
-        let shared_cache_hit = if let Some(shared_cache) = self.shared_cache.get() {
-            let temp_fd = tokio::fs::File::from_std(temp_file.reopen()?);
-            shared_cache.fetch(name, &cache_path, temp_fd).await
-        } else {
-            false
-        };
+        let shared_cache = self.shared_cache.get();
+        let shared_cache_hit =
+            if let Some(shared_cache) = shared_cache.filter(|_| mode == CacheMode::Use) {
+                let temp_fd = tokio::fs::File::from_std(temp_file.reopen()?);
+                let shared_path =
+                    key.namespaced_cache_path(shared_cache.namespace(), T::VERSIONS.current);
+                shared_cache.fetch(name, &shared_path, temp_fd).await
+            } else {
+                false
+            };
 
         let mut entry = if shared_cache_hit {
             let byte_view = ByteView::map_file_ref(temp_file.as_file())?;
@@ -224,14 +322,47 @@ impl<T: CacheItemRequest> Cacher<T> {
             Err(CacheError::NotFound)
         };
 
+        let mut validators = None;
         if entry.is_err() {
             metric!(counter("caches.computation") += 1, "cache" => name.as_ref());
-            match request.compute(&mut temp_file).await {
-                Ok(()) => {
+
+            // An expired item of the current version can be revalidated, as long as we still have
+            // its contents to fall back to.
+            let mut expired = self
+                .config
+                .cache_dir()
+                .filter(|_| mode == CacheMode::Use)
+                .and_then(|cache_dir| {
+                    let path = cache_dir.join(&cache_path);
+                    let validators = Validators::read(&path)?;
+                    let contents = ByteView::open(&path).ok()?;
+                    Some((validators, decompress(contents).ok()?))
+                });
+
+            let revalidation = request
+                .compute_conditional(&mut temp_file, expired.as_ref().map(|(v, _)| v))
+                .await;
+            let computed = match revalidation {
+                Ok(Revalidation::NotModified) => match expired.take() {
+                    Some((expired_validators, contents)) => {
+                        metric!(counter("caches.revalidated") += 1, "cache" => name.as_ref());
+                        // Writing the contents to a new file also restarts its `max_age`.
+                        std::fs::write(temp_file.path(), &contents)?;
+                        Ok(Some(expired_validators))
+                    }
+                    None => Err(CacheError::InternalError),
+                },
+                Ok(Revalidation::Modified(validators)) => Ok(validators),
+                Err(err) => Err(err),
+            };
+
+            match computed {
+                Ok(computed_validators) => {
                     // Now we have written the data to the tempfile we can mmap it, persisting it later
                     // is fine as it does not move filesystem boundaries there.
                     let byte_view = ByteView::map_file_ref(temp_file.as_file())?;
                     entry = Ok(byte_view);
+                    validators = computed_validators;
                 }
                 Err(err) => {
                     let mut temp_fd = tokio::fs::File::from_std(temp_file.reopen()?);
@@ -242,7 +373,11 @@ impl<T: CacheItemRequest> Cacher<T> {
             }
         }
 
-        if let Some(cache_dir) = self.config.cache_dir() {
+        if let Some(cache_dir) = self
+            .config
+            .cache_dir()
+            .filter(|_| mode != CacheMode::Bypass && !self.config.is_read_only())
+        {
             // Cache is enabled, write it!
             let mut cache_path = cache_dir.join(&cache_path);
 
@@ -275,21 +410,41 @@ impl<T: CacheItemRequest> Cacher<T> {
 
             tracing::trace!("Creating {name} at path {:?}", cache_path.display());
 
-            persist_tempfile(temp_file, &cache_path)?;
+            // Only positive entries are compressed, the markers of negative entries stay readable.
+            let temp_file = match &entry {
+                Ok(byte_view) => self.config.compress(temp_file, byte_view)?,
+                Err(_) => temp_file,
+            };
+            match persist_tempfile(temp_file, &cache_path) {
+                // the computed item is still returned, it is just not cached on disk
+                Err(err) if is_read_only_error(&err) => self.config.set_read_only(&err),
+                Err(err) => return Err(err.into()),
+                Ok(_) => {
+                    // Stale validators are removed, so they are not used for the new contents.
+                    if let Err(err) = Validators::write(validators.as_ref(), &cache_path) {
+                        tracing::error!(error = &err as &dyn std::error::Error);
+                    }
 
-            // NOTE: we only create the metadata file once, but do not regularly touch it for now
-            cache_path.set_extension("txt");
-            if let Err(err) = std::fs::write(cache_path, key.metadata()) {
-                tracing::error!(error = &err as &dyn std::error::Error);
+                    // NOTE: we only create the metadata file once, but do not regularly touch it
+                    // for now
+                    if self.config.write_metadata {
+                        cache_path.set_extension("txt");
+                        if let Err(err) = std::fs::write(cache_path, key.metadata()) {
+                            tracing::error!(error = &err as &dyn std::error::Error);
+                        }
+                    }
+                }
             }
         };
 
         // TODO: Not handling negative caches probably has a huge perf impact.  Need to
         // figure out negative caches.  Maybe put them in redis with a TTL?
-        if !shared_cache_hit {
+        if !shared_cache_hit && mode != CacheMode::Bypass {
             if let Ok(byteview) = &entry {
-                if let Some(shared_cache) = self.shared_cache.get() {
-                    shared_cache.store(name, &cache_path, byteview.clone(), CacheStoreReason::New);
+                if let Some(shared_cache) = shared_cache {
+                    let shared_path =
+                        key.namespaced_cache_path(shared_cache.namespace(), T::VERSIONS.current);
+                    shared_cache.store(name, &shared_path, byteview.clone(), CacheStoreReason::New);
                 }
             }
         }
@@ -309,20 +464,94 @@ impl<T: CacheItemRequest> Cacher<T> {
     ///
     /// Cache computation can fail, in which case [`T::compute`](CacheItemRequest::compute)
     /// will return an `Err`. This err may be persisted in the cache for a time.
-    pub async fn compute_memoized(&self, request: T, cache_key: CacheKey) -> CacheEntry<T::Item> {
+    pub fn compute_memoized(
+        &self,
+        request: T,
+        cache_key: CacheKey,
+    ) -> impl Future<Output = CacheEntry<T::Item>> + '_ {
+        self.compute_memoized_with_mode(request, cache_key, CacheMode::Use)
+    }
+
+    /// Computes an item like [`compute_memoized`](Self::compute_memoized), using the caches
+    /// according to the given [`CacheMode`].
+    ///
+    /// With [`CacheMode::Refresh`], the item is computed even if it is cached, and replaces the
+    /// cached item. Concurrent computations are still deduplicated. With [`CacheMode::Bypass`],
+    /// the item is computed without being deduplicated or cached at all.
+    ///
+    /// The `status` of the span of this call records where the item came from: `memory`, a file
+    /// `hit`, a `miss` which was computed, or `bypass`.
+    #[tracing::instrument(
+        name = "cache",
+        skip_all,
+        fields(cache = %self.config.name(), status = tracing::field::Empty)
+    )]
+    pub async fn compute_memoized_with_mode(
+        &self,
+        request: T,
+        cache_key: CacheKey,
+        mode: CacheMode,
+    ) -> CacheEntry<T::Item> {
         let name = self.config.name();
         metric!(counter("caches.access") += 1, "cache" => name.as_ref());
 
-        let init = Box::pin(async {
-            // cache_path is None when caching is disabled.
-            if let Some(cache_dir) = self.config.cache_dir() {
-                let versions = std::iter::once(T::VERSIONS.current)
-                    .chain(T::VERSIONS.fallbacks.iter().copied());
+        if mode == CacheMode::Bypass {
+            metric!(counter("caches.bypass") += 1, "cache" => name.as_ref());
+            tracing::Span::current().record("status", "bypass");
+            return self
+                .compute(request, &cache_key, false, mode)
+                .bind_hub(Hub::new_from_top(Hub::current()))
+                .await;
+        }
 
-                for version in versions {
+        // Failures of computations are not kept in memory when they are retried, see below.
+        let retry_failures = self.config.failed_computations == FailedComputations::Retry;
+        let retry_request = retry_failures.then(|| request.clone());
+
+        let (is_fresh, (expiration, item)) = self
+            .lookup_or_compute(request, &cache_key, mode, retry_failures)
+            .await;
+        if !is_fresh {
+            tracing::Span::current().record("status", "memory");
+        }
+
+        // With `FailedComputations::Retry`, failed computations expire right away. An expired
+        // failure which this request did not compute itself thus stems from a concurrent
+        // computation it waited for, which is retried once.
+        let is_failure = matches!(item, Err(ref err) if err.is_transient());
+        match retry_request {
+            Some(request) if is_failure && !is_fresh && expiration <= Instant::now() => {
+                metric!(counter("caches.computation.retry") += 1, "cache" => name.as_ref());
+                let (_, (_, item)) = self
+                    .lookup_or_compute(request, &cache_key, CacheMode::Refresh, retry_failures)
+                    .await;
+                item
+            }
+            _ => item,
+        }
+    }
+
+    /// Looks up an item in the in-memory and file system caches, computing it if it is missing.
+    ///
+    /// Concurrent calls for the same `cache_key` share a single lookup or computation. Returns
+    /// whether this call performed it, along with the item and its expiration.
+    async fn lookup_or_compute(
+        &self,
+        request: T,
+        cache_key: &CacheKey,
+        mode: CacheMode,
+        retry_failures: bool,
+    ) -> (bool, InMemoryItem<T::Item>) {
+        let name = self.config.name();
+
+        let init = Box::pin(async {
+            // cache_path is None when caching is disabled, and cached files are ignored when
+            // refreshing.
+            if let Some(cache_dir) = self.config.cache_dir().filter(|_| mode == CacheMode::Use) {
+                for version in T::VERSIONS.lookup_order() {
                     // try the new cache key first, then fall back to the old cache key
                     let item = match self
-                        .lookup_local_cache(&request, cache_dir, &cache_key, version)
+                        .lookup_local_cache(&request, cache_dir, cache_key, version)
                     {
                         Err(CacheError::NotFound) => continue,
                         Err(err) => {
@@ -332,6 +561,8 @@ impl<T: CacheItemRequest> Cacher<T> {
                         }
                         Ok(item) => item,
                     };
+                    metric!(counter("cache.hit") += 1, "cache" => name.as_ref());
+                    tracing::Span::current().record("status", "hit");
 
                     if version != T::VERSIONS.current {
                         // we have found an outdated cache that we will use right away,
@@ -352,9 +583,11 @@ impl<T: CacheItemRequest> Cacher<T> {
             // A file was not found. If this spikes, it's possible that the filesystem cache
             // just got pruned.
             metric!(counter("caches.file.miss") += 1, "cache" => name.as_ref());
+            metric!(counter("cache.miss") += 1, "cache" => name.as_ref());
+            tracing::Span::current().record("status", "miss");
 
             let item = self
-                .compute(request, &cache_key, false)
+                .compute(request, cache_key, false, mode)
                 // NOTE: We have seen this deadlock with an SDK that was deadlocking on
                 // out-of-order Scope pops.
                 // To guarantee that this does not happen is really the responsibility of
@@ -362,23 +595,30 @@ impl<T: CacheItemRequest> Cacher<T> {
                 .bind_hub(Hub::new_from_top(Hub::current()))
                 .await;
 
+            if retry_failures && matches!(item, Err(ref err) if err.is_transient()) {
+                return (Instant::now(), item);
+            }
+
             // we just created a fresh cache, so use the initial expiration times
             let expiration = ExpirationTime::for_fresh_status(&self.config, &item);
 
             (expiration.as_instant(), item)
         });
-        let replace_if = |v: &InMemoryItem<T::Item>| Instant::now() >= v.0;
+        let replace_if =
+            |v: &InMemoryItem<T::Item>| mode == CacheMode::Refresh || Instant::now() >= v.0;
 
         let entry = self
             .cache
-            .entry_by_ref(&cache_key)
+            .entry_by_ref(cache_key)
             .or_insert_with_if(init, replace_if)
             .await;
 
-        if !entry.is_fresh() {
+        let is_fresh = entry.is_fresh();
+        if !is_fresh {
             metric!(counter("caches.memory.hit") += 1, "cache" => name.as_ref());
+            metric!(counter("cache.hit") += 1, "cache" => name.as_ref());
         }
-        entry.into_value().1
+        (is_fresh, entry.into_value())
     }
 
     fn spawn_refresh(&self, cache_key: CacheKey, request: T) {
@@ -410,6 +650,8 @@ impl<T: CacheItemRequest> Cacher<T> {
         refreshes.insert(cache_key.clone());
         drop(refreshes);
 
+        metric!(counter("cache.stale_refresh") += 1, "cache" => name.as_ref());
+
         tracing::trace!(
             "Spawning deduplicated {} computation for path {:?}",
             name,
@@ -429,7 +671,9 @@ impl<T: CacheItemRequest> Cacher<T> {
             let transaction = sentry::start_transaction(ctx);
             sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
 
-            let item = this.compute(request, &cache_key, true).await;
+            let item = this
+                .compute(request, &cache_key, true, CacheMode::Use)
+                .await;
 
             // we just created a fresh cache, so use the initial expiration times
             let expiration = ExpirationTime::for_fresh_status(&this.config, &item);
@@ -38,6 +38,13 @@
 //! - `services.shared_cache.fetch(hit:true)`: Accesses served by the shared-cache layer.
 //! - `caches.computation`: Actual computations being run, and not served by any of the caching layers.
 //!
+//! Additionally, the following metrics summarize all the caching layers:
+//!
+//! - `cache.hit`: Accesses served by either the in-memory or the file-system layer.
+//! - `cache.miss`: Accesses that were not served by either of those layers.
+//! - `cache.stale_refresh`: Accesses served by an outdated cache version, triggering a background
+//!   refresh.
+//!
 //! NOTE: The sum of shared-cache hits and computations can exceed the number of cache misses of
 //! previous layers in case of lazy cache recomputation.
 //!
@@ -65,6 +72,13 @@
 //! re-computed. The `max_unused_for` option is rather a time-to-idle value, after which the item
 //! will be evicted. File-system `mtime` is used to check for these. Cache items that are in use
 //! will have their `mtime` updated once an hour to keep them from expiring.
+//! The optional `max_age` option is a time-to-live for successful items, after which they are
+//! re-computed regardless of use. It is based on the file creation time, which requires a
+//! filesystem and kernel recording it (`statx` on Linux 4.11+). Otherwise `mtime` is used, so
+//! only items which went unused for `max_age` expire, and a warning is logged on startup.
+//!
+//! The optional `max_cache_size` option puts a limit on the total size of a cache directory. When
+//! the limit is exceeded, the cleanup evicts the least recently used items, again based on `mtime`.
 //!
 //! The "downloaded" category defaults to keeping entries alive for up to 24 hours, will retry
 //! "missing" items every hour, and "malformed" items every 24 hours.
@@ -150,7 +164,9 @@
 //! [`Cacher::compute_memoized`] to hide all the details of the [`CacheItemRequest`] struct and how
 //! the cache item itself is being computed / loaded.
 
+use std::collections::BTreeSet;
 use std::io;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicIsize;
 use std::sync::Arc;
 
@@ -160,20 +176,33 @@ mod cache_error;
 mod cache_key;
 mod cleanup;
 mod config;
+mod disk;
 mod fs;
+mod inspect;
 mod memory;
 mod shared_cache;
+mod stats;
 #[cfg(test)]
 mod tests;
+mod validators;
 
 pub use cache_error::{CacheEntry, CacheError};
 pub use cache_key::{CacheKey, CacheKeyBuilder};
-pub use cleanup::cleanup;
+pub use cleanup::{cleanup, PruneOptions, PrunedFile};
 pub use config::CacheName;
+pub use disk::{DiskSpace, LowDiskSpace};
+pub(crate) use fs::is_read_only_error;
 pub use fs::{Cache, ExpirationStrategy, ExpirationTime};
-pub use memory::{CacheItemRequest, CacheVersions, Cacher};
+pub use inspect::{inspect, CacheFileContents, CacheFileInfo};
+pub use memory::{CacheItemRequest, CacheMode, CacheVersions, Cacher};
 pub use shared_cache::{CacheStoreReason, SharedCacheConfig, SharedCacheRef, SharedCacheService};
+pub use stats::CacheStats;
+pub use validators::{Revalidation, Validators};
 
+/// The local caches of all the services.
+///
+/// Clones share their state, such as whether a cache is read-only, with the original caches.
+#[derive(Debug, Clone)]
 pub struct Caches {
     /// Caches for object files, used by [`crate::services::objects::ObjectsActor`].
     pub objects: Cache,
@@ -254,7 +283,8 @@ impl Caches {
                 config.caches.derived.into(),
                 max_lazy_recomputations.clone(),
                 default_cap,
-            )?,
+            )?
+            .with_in_memory_entries(in_memory.symcache_memory_entries),
             cficaches: Cache::from_config(
                 CacheName::Cficaches,
                 config,
@@ -292,4 +322,86 @@ impl Caches {
             )?,
         })
     }
+
+    /// Returns the names of the caches whose directory cannot be written to.
+    ///
+    /// These caches still serve their existing files, but do not store new items on disk.
+    pub fn read_only(&self) -> Vec<CacheName> {
+        // Destructure so we do not accidentally forget to check one of our members.
+        let Self {
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        } = self;
+
+        [
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        ]
+        .into_iter()
+        .filter(|cache| cache.is_read_only())
+        .map(|cache| cache.name())
+        .collect()
+    }
+
+    /// Checks that new files can be created in the directories of all writable caches.
+    ///
+    /// This probes the cache and temporary directories the caches actually use, and skips the
+    /// caches which are already [`read_only`](Self::read_only). Returns the directories which
+    /// cannot be written to, along with the error.
+    pub fn check_writable(&self) -> Vec<(PathBuf, io::Error)> {
+        // Destructure so we do not accidentally forget to check one of our members.
+        let Self {
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        } = self;
+
+        let dirs: BTreeSet<_> = [
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        ]
+        .into_iter()
+        .filter(|cache| !cache.is_read_only())
+        .flat_map(|cache| [cache.cache_dir.as_deref(), cache.tmp_dir.as_deref()])
+        .flatten()
+        .collect();
+
+        dirs.into_iter()
+            .filter_map(|dir| match fs::probe_writable(dir) {
+                Ok(()) => None,
+                Err(err) => Some((dir.to_owned(), err)),
+            })
+            .collect()
+    }
 }
@@ -54,6 +54,14 @@ pub struct SharedCacheConfig {
     #[serde(default = "default_max_upload_queue_size")]
     pub max_upload_queue_size: usize,
 
+    /// An optional namespace which keeps the entries of this deployment apart from others
+    /// sharing the same backend.
+    ///
+    /// The paths of all entries are prefixed with `ns/$namespace/`. The namespace must not
+    /// contain slashes or control characters.
+    #[serde(default)]
+    pub namespace: Option<String>,
+
     /// The backend to use for the shared cache.
     #[serde(flatten)]
     pub backend: SharedCacheBackendConfig,
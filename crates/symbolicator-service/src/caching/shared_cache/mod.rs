@@ -512,6 +512,7 @@ pub struct SharedCacheService {
     backend: Arc<SharedCacheBackend>,
     upload_queue_tx: mpsc::Sender<UploadMessage>,
     runtime: tokio::runtime::Handle,
+    namespace: Option<String>,
 }
 
 impl SharedCacheService {
@@ -542,6 +543,7 @@ impl SharedCacheService {
                 backend,
                 upload_queue_tx: tx,
                 runtime,
+                namespace: config.namespace,
             });
         }
     }
@@ -660,6 +662,12 @@ impl SharedCacheService {
         self.backend.name()
     }
 
+    /// The namespace the entries of this deployment are stored in, see
+    /// [`CacheKey::namespaced_cache_path`](crate::caching::CacheKey::namespaced_cache_path).
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
     /// Retrieve a file from the shared cache.
     ///
     /// Looks up the `key` in the shared cache, if found the cache contents will be written
@@ -828,6 +836,7 @@ mod tests {
         let cfg = SharedCacheConfig {
             max_concurrent_uploads: 10,
             max_upload_queue_size: 10,
+            namespace: None,
             backend: SharedCacheBackendConfig::Filesystem(FilesystemSharedCacheConfig {
                 path: dir.path().to_path_buf(),
             }),
@@ -856,6 +865,7 @@ mod tests {
         let cfg = SharedCacheConfig {
             max_concurrent_uploads: 10,
             max_upload_queue_size: 10,
+            namespace: None,
             backend: SharedCacheBackendConfig::Filesystem(FilesystemSharedCacheConfig {
                 path: dir.path().to_path_buf(),
             }),
@@ -883,6 +893,7 @@ mod tests {
         let cfg = SharedCacheConfig {
             max_concurrent_uploads: 10,
             max_upload_queue_size: 10,
+            namespace: None,
             backend: SharedCacheBackendConfig::Filesystem(FilesystemSharedCacheConfig {
                 path: dir.path().to_path_buf(),
             }),
@@ -917,6 +928,7 @@ mod tests {
         let cfg = SharedCacheConfig {
             max_concurrent_uploads: 10,
             max_upload_queue_size: 10,
+            namespace: None,
             backend: SharedCacheBackendConfig::Gcs(GcsSharedCacheConfig::from(credentials)),
         };
         let svc = SharedCacheService::new(Some(cfg), tokio::runtime::Handle::current());
@@ -963,6 +975,7 @@ mod tests {
         let cfg = SharedCacheConfig {
             max_concurrent_uploads: 10,
             max_upload_queue_size: 10,
+            namespace: None,
             backend: SharedCacheBackendConfig::Gcs(GcsSharedCacheConfig::from(credentials)),
         };
         let svc = SharedCacheService::new(Some(cfg), tokio::runtime::Handle::current());
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::cleanup::collect_cache_files;
+use super::{Cache, Caches};
+
+/// Statistics about the files of a [`Cache`] on disk.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// The number of cache items, not counting their metadata files.
+    pub files: usize,
+    /// The total size of all files, including metadata files.
+    pub bytes: u64,
+    /// The modification time of the least recently written or touched cache item.
+    pub oldest: Option<DateTime<Utc>>,
+    /// The modification time of the most recently written or touched cache item.
+    pub newest: Option<DateTime<Utc>>,
+}
+
+impl Caches {
+    /// Gathers the [`CacheStats`] of all caches, keyed by the cache name.
+    ///
+    /// This walks the complete cache directory, and can thus be expensive for large caches.
+    pub fn stats(&self) -> Result<BTreeMap<String, CacheStats>> {
+        // Destructure so we do not accidentally forget to report one of our members.
+        let Self {
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        } = self;
+
+        let caches = [
+            objects,
+            object_meta,
+            auxdifs,
+            il2cpp,
+            symcaches,
+            cficaches,
+            ppdb_caches,
+            sourcemap_caches,
+            sourcefiles,
+            diagnostics,
+        ];
+
+        let mut stats = BTreeMap::new();
+        for cache in caches {
+            stats.insert(cache.name().to_string(), cache.stats()?);
+        }
+        Ok(stats)
+    }
+}
+
+impl Cache {
+    /// Gathers the [`CacheStats`] of this cache.
+    ///
+    /// A disabled cache reports no files.
+    pub fn stats(&self) -> Result<CacheStats> {
+        let Some(cache_dir) = self.cache_dir() else {
+            return Ok(CacheStats::default());
+        };
+
+        let mut entries = vec![];
+        let mut bytes = 0;
+        collect_cache_files(cache_dir, &mut entries, &mut bytes)?;
+
+        // orphaned metadata files and validators count towards the size, but are not cache items
+        entries.retain(|entry| !entry.orphan);
+        let mtimes = entries.iter().map(|entry| entry.mtime);
+        Ok(CacheStats {
+            files: entries.len(),
+            bytes,
+            oldest: mtimes.clone().min().map(DateTime::from),
+            newest: mtimes.max().map(DateTime::from),
+        })
+    }
+}
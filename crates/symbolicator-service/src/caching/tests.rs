@@ -1,8 +1,9 @@
 use std::convert::TryInto;
 use std::fs::{self, File};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::thread::sleep;
 use std::time::{Duration, SystemTime};
 
@@ -14,14 +15,17 @@ use tempfile::NamedTempFile;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
 use crate::config::{
-    CacheConfig, CacheConfigs, Config, DerivedCacheConfig, DiagnosticsCacheConfig,
-    DownloadedCacheConfig,
+    CacheCompression, CacheConfig, CacheConfigs, Config, DerivedCacheConfig,
+    DiagnosticsCacheConfig, DownloadedCacheConfig, FailedComputations, FreeSpace,
 };
 use crate::test;
+use crate::types::Scope;
 
 use super::cache_error::cache_entry_from_bytes;
-use super::fs::ExpirationStrategy;
-use super::shared_cache::config::{SharedCacheBackendConfig, SharedCacheConfig};
+use super::fs::{ExpirationStrategy, ZSTD_HEADER};
+use super::shared_cache::config::{
+    FilesystemSharedCacheConfig, SharedCacheBackendConfig, SharedCacheConfig,
+};
 use super::*;
 
 fn tempdir() -> io::Result<tempfile::TempDir> {
@@ -47,6 +51,107 @@ fn test_cache_dir_created() {
     assert!(fsinfo.is_dir());
 }
 
+#[test]
+fn test_cache_dir_overrides() {
+    let basedir = tempdir().unwrap();
+    let cachedir = basedir.path().join("cache");
+    let objects_dir = basedir.path().join("large/objects");
+    let symcaches_dir = basedir.path().join("fast/symcaches/");
+
+    let config = Config {
+        cache_dir: Some(cachedir.clone()),
+        caches: CacheConfigs {
+            objects_dir: Some(objects_dir.clone()),
+            symcaches_dir: Some(symcaches_dir.clone()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let caches = Caches::from_config(&config).unwrap();
+    caches.clear_tmp(&config).unwrap();
+
+    assert_eq!(caches.objects.cache_dir(), Some(objects_dir.as_path()));
+    assert_eq!(caches.symcaches.cache_dir(), Some(symcaches_dir.as_path()));
+    assert_eq!(
+        caches.cficaches.cache_dir(),
+        Some(cachedir.join("cficaches").as_path())
+    );
+
+    // temporary files are kept on the same volume as the overridden directories
+    assert_eq!(
+        caches.objects.tmp_dir,
+        Some(basedir.path().join("large/objects.tmp"))
+    );
+    assert_eq!(
+        caches.symcaches.tmp_dir,
+        Some(basedir.path().join("fast/symcaches.tmp"))
+    );
+    assert_eq!(caches.cficaches.tmp_dir, Some(cachedir.join("tmp")));
+    for tmp_dir in ["large/objects.tmp", "fast/symcaches.tmp", "cache/tmp"] {
+        assert!(basedir.path().join(tmp_dir).is_dir());
+    }
+
+    let temp_file = caches.symcaches.tempfile().unwrap();
+    assert!(temp_file
+        .path()
+        .starts_with(basedir.path().join("fast/symcaches.tmp")));
+}
+
+#[test]
+fn test_tmp_dir() {
+    let basedir = tempdir().unwrap();
+    let cachedir = basedir.path().join("cache");
+    let scratch = basedir.path().join("scratch");
+
+    let config = Config {
+        cache_dir: Some(cachedir.clone()),
+        tmp_dir: Some(scratch.clone()),
+        ..Default::default()
+    };
+    let caches = Caches::from_config(&config).unwrap();
+
+    // files of others in the shared `tmp_dir` survive clearing the temporary files
+    let shared_file = scratch.join("shared");
+    File::create(&shared_file).unwrap();
+    let owned_tmp_dir = scratch.join("symbolicator-tmp");
+    File::create(owned_tmp_dir.join("leftover")).unwrap();
+
+    caches.clear_tmp(&config).unwrap();
+
+    assert!(shared_file.exists());
+    assert!(!owned_tmp_dir.join("leftover").exists());
+    assert_eq!(caches.cficaches.tmp_dir, Some(owned_tmp_dir.clone()));
+    let temp_file = caches.cficaches.tempfile().unwrap();
+    assert!(temp_file.path().starts_with(&owned_tmp_dir));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_tmp_dir_other_filesystem() {
+    let basedir = tempdir().unwrap();
+    let cachedir = basedir.path().join("cache");
+
+    // `/proc` is never on the same filesystem as the cache
+    let config = Config {
+        cache_dir: Some(cachedir.clone()),
+        tmp_dir: Some(PathBuf::from("/proc/self")),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Cficaches,
+        &config,
+        CacheConfig::Derived(Default::default()),
+        Default::default(),
+        1024,
+    )
+    .unwrap();
+
+    // temporary files fall back to the directory next to the cache
+    assert_eq!(cache.tmp_dir, Some(cachedir.join("tmp")));
+    let temp_file = cache.tempfile().unwrap();
+    assert!(temp_file.path().starts_with(cachedir.join("tmp")));
+}
+
 #[test]
 fn test_caches_tmp_created() {
     let basedir = tempdir().unwrap();
@@ -125,6 +230,325 @@ fn test_max_unused_for() -> Result<()> {
     Ok(())
 }
 
+/// Makes sure that the cleanup expires compressed files without decompressing them, which only
+/// happens once they are read.
+#[test]
+fn test_cleanup_compressed() -> Result<()> {
+    let tempdir = tempdir()?;
+    let mut config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    config.caches.compression = CacheCompression::Zstd;
+    let objects_dir = tempdir.path().join("objects");
+    fs::create_dir_all(&objects_dir)?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Derived(DerivedCacheConfig {
+            max_unused_for: Some(Duration::from_millis(50)),
+            ..Default::default()
+        }),
+        Default::default(),
+        1024,
+    )?;
+
+    // not a valid zstd stream, which would fail to decompress
+    let mut corrupt = ZSTD_HEADER.to_vec();
+    corrupt.extend_from_slice(b"garbage");
+    fs::write(objects_dir.join("killthis"), corrupt)?;
+    sleep(Duration::from_millis(100));
+
+    let mut compressed = ZSTD_HEADER.to_vec();
+    compressed.extend(zstd::encode_all(&b"hi"[..], 0)?);
+    fs::write(objects_dir.join("keepthis"), compressed)?;
+    cache.cleanup()?;
+
+    let basenames: Vec<_> = fs::read_dir(&objects_dir)?
+        .map(|x| x.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(basenames, vec!["keepthis"]);
+
+    let (entry, _expiration) = cache
+        .open_cachefile(&objects_dir.join("keepthis"))?
+        .unwrap();
+    assert_eq!(entry.unwrap().as_slice(), b"hi");
+
+    Ok(())
+}
+
+#[test]
+fn test_max_age() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    fs::create_dir_all(tempdir.path().join("objects"))?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(DownloadedCacheConfig {
+            max_age: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }),
+        Default::default(),
+        1024,
+    )?;
+
+    let stale = tempdir.path().join("objects/stale");
+    File::create(&stale)?.write_all(b"hi")?;
+    sleep(Duration::from_millis(200));
+
+    let fresh = tempdir.path().join("objects/fresh");
+    File::create(&fresh)?.write_all(b"hi")?;
+
+    assert!(cache.open_cachefile(&stale)?.is_none());
+    let (entry, expiration) = cache.open_cachefile(&fresh)?.unwrap();
+    assert_eq!(entry.unwrap().as_slice(), b"hi");
+    assert!(matches!(expiration, ExpirationTime::TouchIn(d) if d <= Duration::from_millis(100)));
+
+    cache.cleanup()?;
+
+    let basenames: Vec<_> = fs::read_dir(tempdir.path().join("objects"))?
+        .map(|x| x.unwrap().file_name().into_string().unwrap())
+        .collect();
+
+    assert_eq!(basenames, vec!["fresh"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_max_cache_size() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache_dir = tempdir.path().join("objects");
+    fs::create_dir_all(cache_dir.join("aa"))?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Derived(DerivedCacheConfig {
+            max_cache_size: Some(25),
+            ..Default::default()
+        }),
+        Default::default(),
+        1024,
+    )?;
+
+    let now = SystemTime::now();
+    let files = [
+        ("aa/oldest", 3600),
+        ("aa/oldest.txt", 3600),
+        ("old", 1800),
+        ("aa/recent", 60),
+        ("newest", 0),
+    ];
+    for (name, age) in files {
+        let path = cache_dir.join(name);
+        File::create(&path)?.write_all(b"0123456789")?;
+        let mtime = now - Duration::from_secs(age);
+        filetime::set_file_mtime(&path, FileTime::from_system_time(mtime))?;
+    }
+
+    cache.cleanup()?;
+
+    // 50 bytes in total, evicting the oldest two items (along with the metadata file) gets us
+    // down to 20 bytes, below the 25 byte limit
+    assert!(!cache_dir.join("aa/oldest").exists());
+    assert!(!cache_dir.join("aa/oldest.txt").exists());
+    assert!(!cache_dir.join("old").exists());
+    assert!(cache_dir.join("aa/recent").exists());
+    assert!(cache_dir.join("newest").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_max_cache_size_orphaned_metadata() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache_dir = tempdir.path().join("objects");
+    fs::create_dir_all(&cache_dir)?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Derived(DerivedCacheConfig {
+            max_cache_size: Some(25),
+            ..Default::default()
+        }),
+        Default::default(),
+        1024,
+    )?;
+
+    let now = SystemTime::now();
+    let files = [("old", 1800), ("recent", 60), ("orphan.txt", 0)];
+    for (name, age) in files {
+        let path = cache_dir.join(name);
+        File::create(&path)?.write_all(b"0123456789")?;
+        let mtime = now - Duration::from_secs(age);
+        filetime::set_file_mtime(&path, FileTime::from_system_time(mtime))?;
+    }
+
+    cache.cleanup()?;
+
+    // the metadata file without a cache item goes first, even though it is the newest file,
+    // which gets the 30 bytes down to 20 bytes without evicting any cache item
+    assert!(!cache_dir.join("orphan.txt").exists());
+    assert!(cache_dir.join("old").exists());
+    assert!(cache_dir.join("recent").exists());
+
+    let stats = cache.stats()?;
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.bytes, 20);
+
+    Ok(())
+}
+
+#[test]
+fn test_prune() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let caches = Caches::from_config(&config)?;
+    fs::create_dir_all(tempdir.path().join("objects/aa"))?;
+
+    let now = SystemTime::now();
+    let day = 24 * 3600;
+    let files = [
+        ("objects/aa/ancient", 40 * day),
+        ("objects/aa/ancient.txt", 40 * day),
+        ("symcaches/old", 1800),
+        ("objects/aa/recent", 60),
+        ("symcaches/newest", 0),
+    ];
+    for (name, age) in files {
+        let path = tempdir.path().join(name);
+        File::create(&path)?.write_all(b"0123456789")?;
+        let mtime = now - Duration::from_secs(age);
+        filetime::set_file_mtime(&path, FileTime::from_system_time(mtime))?;
+    }
+
+    let options = PruneOptions {
+        max_age: Some(Duration::from_secs(30 * day)),
+        max_size: Some(25),
+        dry_run: true,
+    };
+
+    // the item unused for too long goes first, along with its metadata file, then the least
+    // recently used item across all caches brings them down to 20 bytes
+    let expected = vec![
+        PrunedFile {
+            path: tempdir.path().join("objects/aa/ancient"),
+            size: 20,
+        },
+        PrunedFile {
+            path: tempdir.path().join("symcaches/old"),
+            size: 10,
+        },
+    ];
+
+    assert_eq!(caches.prune(options)?, expected);
+    for (name, _) in files {
+        assert!(tempdir.path().join(name).exists());
+    }
+
+    let options = PruneOptions {
+        dry_run: false,
+        ..options
+    };
+    assert_eq!(caches.prune(options)?, expected);
+    assert!(!tempdir.path().join("objects/aa/ancient").exists());
+    assert!(!tempdir.path().join("objects/aa/ancient.txt").exists());
+    assert!(!tempdir.path().join("symcaches/old").exists());
+    assert!(tempdir.path().join("objects/aa/recent").exists());
+    assert!(tempdir.path().join("symcaches/newest").exists());
+
+    // nothing is left to prune
+    assert_eq!(caches.prune(options)?, vec![]);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_evict_for_min_free_space() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        caches: CacheConfigs {
+            // no volume has all of its space free, so everything is evicted
+            min_free_space: Some(FreeSpace::Percent(100.0)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let cache_dir = tempdir.path().join("objects");
+    fs::create_dir_all(&cache_dir)?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(Default::default()),
+        Default::default(),
+        1024,
+    )?;
+
+    File::create(cache_dir.join("evictthis"))?.write_all(b"0123456789")?;
+    File::create(cache_dir.join("evictthis.txt"))?.write_all(b"0123456789")?;
+
+    cache.cleanup()?;
+
+    assert!(!cache_dir.join("evictthis").exists());
+    assert!(!cache_dir.join("evictthis.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_max_cache_size_not_exceeded() -> Result<()> {
+    let tempdir = tempdir()?;
+    let config = Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache_dir = tempdir.path().join("objects");
+    fs::create_dir_all(&cache_dir)?;
+
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::Downloaded(DownloadedCacheConfig {
+            max_cache_size: Some(20),
+            ..Default::default()
+        }),
+        Default::default(),
+        1024,
+    )?;
+
+    File::create(cache_dir.join("keepthis"))?.write_all(b"0123456789")?;
+    File::create(cache_dir.join("keepthis2"))?.write_all(b"0123456789")?;
+
+    cache.cleanup()?;
+
+    assert!(cache_dir.join("keepthis").exists());
+    assert!(cache_dir.join("keepthis2").exists());
+
+    Ok(())
+}
+
 #[test]
 fn test_retry_misses_after() -> Result<()> {
     let tempdir = tempdir()?;
@@ -457,24 +881,103 @@ fn test_cleanup() {
     assert!(!diagnostics_entry.is_file());
 }
 
-#[tokio::test]
-async fn test_cache_error_write_negative() -> Result<()> {
-    let dir = tempdir()?;
-    let path = dir.path().join("honk");
+#[test]
+fn test_purge_scope() -> Result<()> {
+    let tempdir = tempdir()?;
+    let caches = Caches::from_config(&Config {
+        cache_dir: Some(tempdir.path().to_path_buf()),
+        ..Default::default()
+    })?;
+
+    let create = |name: &str, scope: &Scope| {
+        let key = CacheKey::scoped_builder(scope).build();
+        let path = tempdir.path().join(name).join(key.cache_path(1));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "contents").unwrap();
+        fs::write(path.with_extension("txt"), key.metadata()).unwrap();
+        path
+    };
 
-    // copying what compute does here instead of just using
-    // tokio::fs::File::create() directly
-    let sync_file = File::create(&path)?;
-    let mut async_file = tokio::fs::File::from_std(sync_file);
-    let error = CacheError::NotFound;
-    error.write(&mut async_file).await?;
+    let scope = Scope::Scoped("myscope".into());
+    let other_scope = Scope::Scoped("myscope2".into());
 
-    // make sure write leaves the cursor at the end
-    let current_pos = async_file.stream_position().await?;
-    assert_eq!(current_pos, 0);
+    let objects_entry = create("objects", &scope);
+    let symcaches_entry = create("symcaches", &scope);
+    let other_entry = create("objects", &other_scope);
+    let global_entry = create("objects", &Scope::Global);
 
-    let contents = fs::read(&path)?;
-    assert_eq!(contents, b"");
+    assert_eq!(caches.purge_scope(&scope)?, 2);
+
+    assert!(!objects_entry.exists());
+    assert!(!objects_entry.with_extension("txt").exists());
+    assert!(!symcaches_entry.exists());
+    assert!(other_entry.exists());
+    assert!(global_entry.exists());
+
+    // purging the global scope is refused, no matter how it is spelled
+    assert!(caches.purge_scope(&Scope::Global).is_err());
+    assert!(caches.purge_scope(&Scope::Scoped("global".into())).is_err());
+    assert!(global_entry.exists());
+
+    Ok(())
+}
+
+/// Makes sure that purging a scope drops its items from memory, even when purging through a
+/// clone of the caches.
+#[tokio::test]
+async fn test_purge_scope_in_memory() {
+    test::setup();
+
+    let config = Config {
+        cache_dir: None,
+        ..Default::default()
+    };
+    let caches = Caches::from_config(&config).unwrap();
+    let cacher = Cacher::new(caches.symcaches.clone(), Default::default());
+
+    let request = TestCacheItem::new();
+    let scope = Scope::Scoped("myscope".into());
+    let key = CacheKey::scoped_builder(&scope).build();
+    let other_key = CacheKey::scoped_builder(&Scope::Scoped("myscope2".into())).build();
+
+    for key in [&key, &other_key] {
+        cacher
+            .compute_memoized(request.clone(), key.clone())
+            .await
+            .unwrap();
+    }
+    assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+
+    assert_eq!(caches.clone().purge_scope(&scope).unwrap(), 0);
+
+    // only the item of the purged scope is computed again
+    for key in [&key, &other_key] {
+        cacher
+            .compute_memoized(request.clone(), key.clone())
+            .await
+            .unwrap();
+    }
+    assert_eq!(request.computations.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_cache_error_write_negative() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("honk");
+
+    // copying what compute does here instead of just using
+    // tokio::fs::File::create() directly
+    let sync_file = File::create(&path)?;
+    let mut async_file = tokio::fs::File::from_std(sync_file);
+    let error = CacheError::NotFound;
+    error.write(&mut async_file).await?;
+
+    // make sure write leaves the cursor at the end
+    let current_pos = async_file.stream_position().await?;
+    assert_eq!(current_pos, 0);
+
+    let contents = fs::read(&path)?;
+    assert_eq!(contents, b"");
 
     Ok(())
 }
@@ -743,13 +1246,12 @@ fn test_cache_entry() {
 #[derive(Clone, Default)]
 struct TestCacheItem {
     computations: Arc<AtomicUsize>,
+    temp_paths: Arc<Mutex<Vec<PathBuf>>>,
 }
 
 impl TestCacheItem {
     fn new() -> Self {
-        Self {
-            computations: Default::default(),
-        }
+        Self::default()
     }
 }
 
@@ -763,6 +1265,8 @@ impl CacheItemRequest for TestCacheItem {
 
     fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
         self.computations.fetch_add(1, Ordering::SeqCst);
+        let temp_path = temp_file.path().to_owned();
+        self.temp_paths.lock().unwrap().push(temp_path);
 
         Box::pin(async move {
             tokio::time::sleep(Duration::from_millis(100)).await;
@@ -823,6 +1327,647 @@ async fn test_cache_fallback() {
     assert_eq!(request.computations.load(Ordering::SeqCst), 1);
 }
 
+/// Makes sure that the metadata of the cache key is written next to the cache file, if enabled.
+#[tokio::test]
+async fn test_cache_metadata_file() {
+    test::setup();
+
+    for write_metadata in [true, false] {
+        let cache_dir = test::tempdir();
+
+        let request = TestCacheItem::new();
+        let key = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+
+        let mut config = Config {
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        config.caches.write_metadata = write_metadata;
+        let cache = Cache::from_config(
+            CacheName::Objects,
+            &config,
+            CacheConfig::from(CacheConfigs::default().derived),
+            Arc::new(AtomicIsize::new(1)),
+            1024,
+        )
+        .unwrap();
+        let cacher = Cacher::new(cache, Default::default());
+
+        let result = cacher.compute_memoized(request, key.clone()).await;
+        assert_eq!(result.unwrap().as_str(), "some new cached contents");
+
+        let cache_file = cache_dir.path().join("objects").join(key.cache_path(1));
+        assert!(cache_file.exists());
+
+        let metadata_file = cache_file.with_extension("txt");
+        if write_metadata {
+            assert_eq!(fs::read_to_string(metadata_file).unwrap(), key.metadata());
+        } else {
+            assert!(!metadata_file.exists());
+        }
+    }
+}
+
+/// Waits for the `condition` to hold, as items are evicted from memory in the background.
+async fn wait_for(condition: impl Fn() -> bool) {
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while !condition() {
+        assert!(std::time::Instant::now() < deadline, "condition never held");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Makes sure that a cache limited to a number of in-memory entries serves hits from memory, and
+/// evicts entries beyond that number.
+#[tokio::test]
+async fn test_in_memory_entries() {
+    test::setup();
+
+    // Without a cache directory, items which are not held in memory are computed again.
+    let mut config = Config {
+        cache_dir: None,
+        ..Default::default()
+    };
+    config.caches.in_memory.symcache_memory_entries = Some(1);
+    let caches = Caches::from_config(&config).unwrap();
+    assert_eq!(caches.symcaches.in_memory_entries, Some(1));
+    let cacher = Cacher::new(caches.symcaches, Default::default());
+
+    let request = TestCacheItem::new();
+    let first_key = CacheKey::for_testing("global/first");
+    let second_key = CacheKey::for_testing("global/second");
+
+    for _ in 0..2 {
+        let result = cacher
+            .compute_memoized(request.clone(), first_key.clone())
+            .await;
+        assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    }
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+
+    cacher
+        .compute_memoized(request.clone(), second_key.clone())
+        .await
+        .unwrap();
+    assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+
+    // Only one of the two items fits, the other one is computed again.
+    wait_for(|| !(cacher.is_cached(&first_key) && cacher.is_cached(&second_key))).await;
+    let (kept_key, evicted_key) = match cacher.is_cached(&first_key) {
+        true => (first_key, second_key),
+        false => (second_key, first_key),
+    };
+    assert!(cacher.is_cached(&kept_key));
+
+    cacher
+        .compute_memoized(request.clone(), kept_key)
+        .await
+        .unwrap();
+    assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+    cacher
+        .compute_memoized(request.clone(), evicted_key)
+        .await
+        .unwrap();
+    assert_eq!(request.computations.load(Ordering::SeqCst), 3);
+
+    // Without the limit, the symcaches are limited by their size in bytes.
+    config.caches.in_memory.symcache_memory_entries = None;
+    let caches = Caches::from_config(&config).unwrap();
+    assert_eq!(caches.symcaches.in_memory_entries, None);
+}
+
+/// Makes sure that nothing is written to disk without a cache directory, and that items are only
+/// kept in memory if enabled.
+#[tokio::test]
+async fn test_no_disk_cache() {
+    test::setup();
+
+    for file_caches in [true, false] {
+        let mut config = Config {
+            cache_dir: None,
+            ..Default::default()
+        };
+        config.caches.in_memory.file_caches = file_caches;
+        let caches = Caches::from_config(&config).unwrap();
+        caches.clear_tmp(&config).unwrap();
+        assert!(caches.symcaches.cache_dir().is_none());
+        let cacher = Cacher::new(caches.symcaches, Default::default());
+
+        let request = TestCacheItem::new();
+        let key = CacheKey::for_testing("global/some_cache_key");
+        for _ in 0..2 {
+            let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+            assert_eq!(result.unwrap().as_str(), "some new cached contents");
+            if file_caches {
+                assert!(cacher.is_cached(&key));
+            } else {
+                wait_for(|| !cacher.is_cached(&key)).await;
+            }
+        }
+
+        let expected_computations = if file_caches { 1 } else { 2 };
+        assert_eq!(
+            request.computations.load(Ordering::SeqCst),
+            expected_computations
+        );
+
+        // the temporary files the items were computed into are gone
+        let temp_paths = request.temp_paths.lock().unwrap();
+        assert_eq!(temp_paths.len(), expected_computations);
+        assert!(temp_paths.iter().all(|path| !path.exists()));
+    }
+}
+
+/// Makes sure that bypassing a cache neither reads nor writes the cached item, and that refreshing
+/// it replaces the cached item.
+#[tokio::test]
+async fn test_cache_modes() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let request = TestCacheItem::new();
+    let key = CacheKey::for_testing("global/some_cache_key");
+
+    let cache_file = cache_dir.path().join("objects").join(key.cache_path(1));
+    fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+    fs::write(&cache_file, "some old cached contents").unwrap();
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::from(CacheConfigs::default().derived),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    let cacher = Cacher::new(cache, Default::default());
+
+    let compute = |mode| cacher.compute_memoized_with_mode(request.clone(), key.clone(), mode);
+
+    let result = compute(CacheMode::Use).await;
+    assert_eq!(result.unwrap().as_str(), "some old cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 0);
+
+    let result = compute(CacheMode::Bypass).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        fs::read_to_string(&cache_file).unwrap(),
+        "some old cached contents"
+    );
+
+    // the bypassed item did not replace the one in memory either
+    let result = compute(CacheMode::Use).await;
+    assert_eq!(result.unwrap().as_str(), "some old cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+
+    let result = compute(CacheMode::Refresh).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        fs::read_to_string(&cache_file).unwrap(),
+        "some new cached contents"
+    );
+
+    let result = compute(CacheMode::Use).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+}
+
+/// Makes sure that a read-only cache still serves its existing files, and returns computed items
+/// without writing them.
+///
+/// The cache is switched into read-only mode directly, as permissions are not enforced for root.
+/// Detecting read-only directories is covered by `test_read_only_objects_dir`.
+#[tokio::test]
+async fn test_read_only_cache() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let request = TestCacheItem::new();
+    let cached_key = CacheKey::for_testing("global/cached");
+    let missing_key = CacheKey::for_testing("global/missing");
+
+    let objects_dir = cache_dir.path().join("objects");
+    let cache_file = objects_dir.join(cached_key.cache_path(1));
+    fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+    fs::write(&cache_file, "some old cached contents").unwrap();
+    // the file is due to be touched
+    let mtime = SystemTime::now() - Duration::from_secs(7200);
+    filetime::set_file_mtime(&cache_file, FileTime::from_system_time(mtime)).unwrap();
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::from(CacheConfigs::default().derived),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    cache.set_read_only(&std::io::ErrorKind::PermissionDenied.into());
+    assert!(cache.is_read_only());
+    let cacher = Cacher::new(cache, Default::default());
+
+    let cached = cacher.compute_memoized(request.clone(), cached_key).await;
+    let computed = cacher
+        .compute_memoized(request.clone(), missing_key.clone())
+        .await;
+
+    assert_eq!(cached.unwrap().as_str(), "some old cached contents");
+    assert_eq!(computed.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+    assert!(!objects_dir.join(missing_key.cache_path(1)).exists());
+    // the existing file is not touched either
+    let modified = fs::metadata(&cache_file).unwrap().modified().unwrap();
+    assert!(modified.elapsed().unwrap() > Duration::from_secs(3600));
+}
+
+/// Makes sure that a config with an existing read-only `objects_dir` is valid, and that the
+/// service starts with it.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_read_only_objects_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    test::setup();
+    let cache_dir = test::tempdir();
+    let objects_dir = test::tempdir();
+    let objects_path = objects_dir.path().join("objects");
+    fs::create_dir(&objects_path).unwrap();
+    fs::set_permissions(&objects_path, fs::Permissions::from_mode(0o555)).unwrap();
+
+    let mut config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    config.caches.objects_dir = Some(objects_path.clone());
+
+    let validated = config.validate();
+    let service = crate::services::create_service(&config, tokio::runtime::Handle::current());
+    let read_only_caches = Caches::from_config(&config).unwrap().read_only();
+    fs::set_permissions(&objects_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    validated.unwrap();
+    service.unwrap();
+    // Permissions are not enforced for root, in which case the cache stays writable.
+    if !read_only_caches.is_empty() {
+        assert_eq!(read_only_caches, [CacheName::Objects]);
+    }
+}
+
+/// Makes sure that concurrent requests for the same item share a single computation.
+#[tokio::test]
+async fn test_concurrent_computations() {
+    test::setup();
+
+    let config = Config {
+        cache_dir: Some(test::tempdir().path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::from(CacheConfigs::default().derived),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    let cacher = Cacher::new(cache, Default::default());
+
+    let request = TestCacheItem::new();
+    let key = CacheKey::for_testing("global/some_cache_key");
+
+    let results = futures::future::join_all(
+        (0..10).map(|_| cacher.compute_memoized(request.clone(), key.clone())),
+    )
+    .await;
+
+    for result in results {
+        assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    }
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+}
+
+/// A cache item whose first computation fails with a download error.
+#[derive(Clone, Default)]
+struct FlakyCacheItem {
+    computations: Arc<AtomicUsize>,
+}
+
+impl CacheItemRequest for FlakyCacheItem {
+    type Item = String;
+
+    const VERSIONS: CacheVersions = CacheVersions {
+        current: 1,
+        fallbacks: &[],
+    };
+
+    fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
+        let computation = self.computations.fetch_add(1, Ordering::SeqCst);
+
+        Box::pin(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            if computation == 0 {
+                return Err(CacheError::DownloadError("connection reset".into()));
+            }
+            fs::write(temp_file.path(), "some new cached contents")?;
+            Ok(())
+        })
+    }
+
+    fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item> {
+        Ok(std::str::from_utf8(data.as_slice()).unwrap().to_owned())
+    }
+}
+
+/// Makes sure that the failure of a shared computation is either shared with, or retried by the
+/// concurrent requests waiting for it, depending on the config.
+#[tokio::test]
+async fn test_concurrent_computation_failures() {
+    test::setup();
+
+    for failed_computations in [FailedComputations::Share, FailedComputations::Retry] {
+        let config = Config {
+            cache_dir: Some(test::tempdir().path().to_path_buf()),
+            caches: CacheConfigs {
+                failed_computations,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cache = Cache::from_config(
+            CacheName::Objects,
+            &config,
+            CacheConfig::from(CacheConfigs::default().derived),
+            Arc::new(AtomicIsize::new(1)),
+            1024,
+        )
+        .unwrap();
+        let cacher = Cacher::new(cache, Default::default());
+
+        let request = FlakyCacheItem::default();
+        let key = CacheKey::for_testing("global/some_cache_key");
+
+        let results = futures::future::join_all(
+            (0..10).map(|_| cacher.compute_memoized(request.clone(), key.clone())),
+        )
+        .await;
+
+        let failed = results.iter().filter(|result| result.is_err()).count();
+        match failed_computations {
+            // everyone gets the error of the single computation
+            FailedComputations::Share => {
+                assert_eq!(failed, 10);
+                assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+            }
+            // only the request which computed the item gets the error, the others share a retry
+            FailedComputations::Retry => {
+                assert_eq!(failed, 1);
+                assert_eq!(request.computations.load(Ordering::SeqCst), 2);
+            }
+        }
+    }
+}
+
+/// Makes sure that compressed cache files round-trip, and that uncompressed files written before
+/// compression was turned on can still be read.
+#[tokio::test]
+async fn test_cache_compression() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let compressed_key = CacheKey::for_testing("global/compressed");
+    let legacy_key = CacheKey::for_testing("global/legacy");
+
+    let legacy_file = cache_dir
+        .path()
+        .join("objects")
+        .join(legacy_key.cache_path(1));
+    fs::create_dir_all(legacy_file.parent().unwrap()).unwrap();
+    fs::write(&legacy_file, "some old cached contents").unwrap();
+
+    let mut config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    config.caches.compression = CacheCompression::Zstd;
+    let create_cacher = || {
+        let cache = Cache::from_config(
+            CacheName::Objects,
+            &config,
+            CacheConfig::from(CacheConfigs::default().derived),
+            Arc::new(AtomicIsize::new(1)),
+            1024,
+        )
+        .unwrap();
+        Cacher::new(cache, Default::default())
+    };
+
+    let request = TestCacheItem::new();
+    let cacher = create_cacher();
+    let result = cacher
+        .compute_memoized(request.clone(), compressed_key.clone())
+        .await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+
+    let compressed_file = cache_dir
+        .path()
+        .join("objects")
+        .join(compressed_key.cache_path(1));
+    assert!(fs::read(compressed_file).unwrap().starts_with(ZSTD_HEADER));
+
+    // A fresh cacher has nothing in memory and has to read both files from disk.
+    let cacher = create_cacher();
+    let result = cacher
+        .compute_memoized(request.clone(), compressed_key)
+        .await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    let result = cacher.compute_memoized(request.clone(), legacy_key).await;
+    assert_eq!(result.unwrap().as_str(), "some old cached contents");
+
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+}
+
+/// Makes sure that an item computed by one instance is reused by another instance which uses the
+/// same shared cache, but has its own local cache.
+#[tokio::test]
+async fn test_shared_cache_reuse() {
+    test::setup();
+    let shared_dir = test::tempdir();
+
+    let key = CacheKey::for_testing("scope: global\n\nsource: foo\n");
+
+    let create_cacher = |cache_dir: &Path| {
+        let config = Config {
+            cache_dir: Some(cache_dir.to_path_buf()),
+            ..Default::default()
+        };
+        let cache = Cache::from_config(
+            CacheName::Objects,
+            &config,
+            CacheConfig::from(CacheConfigs::default().derived),
+            Arc::new(AtomicIsize::new(1)),
+            1024,
+        )
+        .unwrap();
+        let shared_cache_config = SharedCacheConfig {
+            max_concurrent_uploads: 10,
+            max_upload_queue_size: 10,
+            namespace: None,
+            backend: SharedCacheBackendConfig::Filesystem(FilesystemSharedCacheConfig {
+                path: shared_dir.path().to_path_buf(),
+            }),
+        };
+        let shared_cache =
+            SharedCacheService::new(Some(shared_cache_config), tokio::runtime::Handle::current());
+        (Cacher::new(cache, shared_cache.clone()), shared_cache)
+    };
+
+    // waits for `check` to become true, as the shared cache is initialized and written to
+    // in the background
+    async fn wait_for(check: impl Fn() -> bool) {
+        for _ in 0..300 {
+            if check() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out waiting for the shared cache");
+    }
+
+    let first_dir = test::tempdir();
+    let (first, first_shared) = create_cacher(first_dir.path());
+    wait_for(|| first_shared.get().is_some()).await;
+
+    let request = TestCacheItem::new();
+    let result = first.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+
+    // the shared cache uses the same path as the local cache
+    let shared_path = shared_dir.path().join("objects").join(key.cache_path(1));
+    wait_for(|| shared_path.exists()).await;
+
+    let second_dir = test::tempdir();
+    let (second, second_shared) = create_cacher(second_dir.path());
+    wait_for(|| second_shared.get().is_some()).await;
+
+    let request = TestCacheItem::new();
+    let result = second.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 0);
+
+    // the item fetched from the shared cache is also persisted to the local cache
+    let local_path = second_dir.path().join("objects").join(key.cache_path(1));
+    assert_eq!(
+        fs::read_to_string(local_path).unwrap(),
+        "some new cached contents"
+    );
+}
+
+#[test]
+fn test_cache_versions_lookup_order() {
+    let versions = CacheVersions {
+        current: 3,
+        fallbacks: &[2, 0],
+    };
+    assert_eq!(versions.lookup_order().collect::<Vec<_>>(), [3, 2, 0]);
+}
+
+/// Makes sure that the `current` cache version is looked up before any of the fallbacks.
+#[tokio::test]
+async fn test_cache_current_before_fallback() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let request = TestCacheItem::new();
+    let key = CacheKey::for_testing("global/some_cache_key");
+
+    {
+        let cache_dir = cache_dir.path().join("objects");
+        for (version, contents) in [(0, "some old cached contents"), (1, "some cached contents")] {
+            let cache_file = cache_dir.join(key.cache_path(version));
+            fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+            fs::write(cache_file, contents).unwrap();
+        }
+    }
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::from(CacheConfigs::default().derived),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    let cacher = Cacher::new(cache, Default::default());
+
+    let result = cacher.compute_memoized(request.clone(), key).await;
+    assert_eq!(result.unwrap().as_str(), "some cached contents");
+
+    // neither a computation nor a background refresh should have been started
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(request.computations.load(Ordering::SeqCst), 0);
+}
+
+/// Makes sure that an interrupted write never ends up being served from the cache.
+#[tokio::test]
+async fn test_cache_interrupted_write() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let request = TestCacheItem::new();
+    let key = CacheKey::for_testing("global/some_cache_key");
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::from(CacheConfigs::default().derived),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+
+    // simulate a crash in the middle of writing a cache file, leaving the temp file behind
+    let mut temp_file = cache.tempfile().unwrap();
+    temp_file.write_all(b"some partial").unwrap();
+    let (_, partial_path) = temp_file.keep().unwrap();
+
+    let cache_file = cache_dir.path().join("objects").join(key.cache_path(1));
+    assert!(!cache_file.exists());
+
+    let cacher = Cacher::new(cache, Default::default());
+    let result = cacher.compute_memoized(request.clone(), key).await;
+    assert_eq!(result.unwrap().as_str(), "some new cached contents");
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        fs::read_to_string(cache_file).unwrap(),
+        "some new cached contents"
+    );
+
+    // the leftover temp file is cleaned up on startup
+    let caches = Caches::from_config(&config).unwrap();
+    caches.clear_tmp(&config).unwrap();
+    assert!(!partial_path.exists());
+}
+
 /// Makes sure that a `NotFound` result does not fall back to older cache versions.
 #[tokio::test]
 async fn test_cache_fallback_notfound() {
@@ -920,3 +2065,227 @@ async fn test_lazy_computation_limit() {
 
     assert_eq!(num_outdated, 2);
 }
+
+/// Makes sure that a cache miss followed by a cache hit are reported as metrics.
+#[test]
+fn test_cache_hit_miss_metrics() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let cache = Cache::from_config(
+        CacheName::Objects,
+        &config,
+        CacheConfig::from(CacheConfigs::default().derived),
+        Arc::new(AtomicIsize::new(1)),
+        1024,
+    )
+    .unwrap();
+    let cacher = Cacher::new(cache, Default::default());
+
+    let request = TestCacheItem::new();
+    let key = CacheKey::for_testing("global/some_cache_key");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let captured = crate::metrics::with_capturing_test_client(|| {
+        runtime.block_on(async {
+            let first_result = cacher.compute_memoized(request.clone(), key.clone()).await;
+            assert_eq!(first_result.unwrap().as_str(), "some new cached contents");
+
+            let second_result = cacher.compute_memoized(request.clone(), key).await;
+            assert_eq!(second_result.unwrap().as_str(), "some new cached contents");
+        })
+    });
+
+    let cache_metrics: Vec<_> = captured
+        .iter()
+        .map(String::as_str)
+        .filter(|metric| metric.starts_with("cache."))
+        .collect();
+    assert_eq!(
+        cache_metrics,
+        [
+            "cache.miss:1|c|#cache:objects",
+            "cache.hit:1|c|#cache:objects"
+        ]
+    );
+    assert_eq!(request.computations.load(Ordering::SeqCst), 1);
+}
+
+/// A cache item downloaded from a source which supports conditional requests.
+#[derive(Clone, Default)]
+struct RevalidatedCacheItem {
+    /// The contents and `ETag` of the file at the source.
+    upstream: Arc<Mutex<(&'static str, &'static str)>>,
+    downloads: Arc<AtomicUsize>,
+    revalidations: Arc<AtomicUsize>,
+}
+
+impl CacheItemRequest for RevalidatedCacheItem {
+    type Item = String;
+
+    const VERSIONS: CacheVersions = CacheVersions {
+        current: 1,
+        fallbacks: &[],
+    };
+
+    fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
+        let future = self.compute_conditional(temp_file, None);
+        Box::pin(async move { future.await.map(|_| ()) })
+    }
+
+    fn compute_conditional<'a>(
+        &'a self,
+        temp_file: &'a mut NamedTempFile,
+        validators: Option<&'a Validators>,
+    ) -> BoxFuture<'a, CacheEntry<Revalidation>> {
+        let (contents, etag) = *self.upstream.lock().unwrap();
+        Box::pin(async move {
+            if let Some(validators) = validators {
+                self.revalidations.fetch_add(1, Ordering::SeqCst);
+                if validators.etag.as_deref() == Some(etag) {
+                    return Ok(Revalidation::NotModified);
+                }
+            }
+
+            self.downloads.fetch_add(1, Ordering::SeqCst);
+            fs::write(temp_file.path(), contents)?;
+            Ok(Revalidation::Modified(Some(Validators {
+                etag: Some(etag.into()),
+                last_modified: None,
+            })))
+        })
+    }
+
+    fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item> {
+        Ok(std::str::from_utf8(data.as_slice()).unwrap().to_owned())
+    }
+}
+
+/// Makes sure that expired items are revalidated with their validators, and are only replaced
+/// if they were modified.
+#[tokio::test]
+async fn test_revalidation() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let config = Config {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let create_cacher = || {
+        let cache = Cache::from_config(
+            CacheName::Objects,
+            &config,
+            CacheConfig::Downloaded(DownloadedCacheConfig {
+                max_age: Some(Duration::from_millis(500)),
+                ..Default::default()
+            }),
+            Arc::new(AtomicIsize::new(1)),
+            1024,
+        )
+        .unwrap();
+        Cacher::new(cache, Default::default())
+    };
+
+    let request = RevalidatedCacheItem::default();
+    *request.upstream.lock().unwrap() = ("some old contents", "\"v1\"");
+    let key = CacheKey::for_testing("global/revalidated");
+    let cache_file = cache_dir.path().join("objects").join(key.cache_path(1));
+    let read_validators = || Validators::read(&cache_file).and_then(|v| v.etag);
+
+    let cacher = create_cacher();
+    let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(result.unwrap().as_str(), "some old contents");
+    assert_eq!(read_validators().as_deref(), Some("\"v1\""));
+
+    // once expired, the unmodified item is revalidated instead of being downloaded again
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(result.unwrap().as_str(), "some old contents");
+    assert_eq!(request.downloads.load(Ordering::SeqCst), 1);
+    assert_eq!(request.revalidations.load(Ordering::SeqCst), 1);
+
+    // the revalidated file is fresh again, so a new cacher uses it as is
+    let result = create_cacher()
+        .compute_memoized(request.clone(), key.clone())
+        .await;
+    assert_eq!(result.unwrap().as_str(), "some old contents");
+    assert_eq!(request.revalidations.load(Ordering::SeqCst), 1);
+
+    // a modified item replaces the expired one, along with its validators
+    *request.upstream.lock().unwrap() = ("some new contents", "\"v2\"");
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let result = cacher.compute_memoized(request.clone(), key.clone()).await;
+    assert_eq!(result.unwrap().as_str(), "some new contents");
+    assert_eq!(
+        fs::read_to_string(&cache_file).unwrap(),
+        "some new contents"
+    );
+    assert_eq!(read_validators().as_deref(), Some("\"v2\""));
+    assert_eq!(request.downloads.load(Ordering::SeqCst), 2);
+    assert_eq!(request.revalidations.load(Ordering::SeqCst), 2);
+}
+
+/// Makes sure that cache files are inspected along with their metadata.
+#[test]
+fn test_inspect() {
+    test::setup();
+    let cache_dir = test::tempdir();
+
+    let key = CacheKey::for_testing("scope: global\n\nsource: local\n");
+    let object_file = cache_dir.path().join("objects").join(key.cache_path(1));
+    fs::create_dir_all(object_file.parent().unwrap()).unwrap();
+    fs::copy(
+        test::fixture("symbols/crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb"),
+        &object_file,
+    )
+    .unwrap();
+    fs::write(object_file.with_extension("txt"), key.metadata()).unwrap();
+
+    let info = inspect(&object_file).unwrap();
+    assert_eq!(info.metadata.as_deref(), Some(key.metadata()));
+    assert_eq!(info.hash_matches, Some(true));
+    assert!(!info.compressed);
+    assert_eq!(
+        info.contents,
+        CacheFileContents::Object(symbolic::debuginfo::FileFormat::Pdb)
+    );
+    assert!(info.is_valid(), "{info}");
+
+    // the metadata of another key does not match the path of the file
+    let other_key = CacheKey::for_testing("scope: global\n\nsource: other\n");
+    fs::write(object_file.with_extension("txt"), other_key.metadata()).unwrap();
+    let info = inspect(&object_file).unwrap();
+    assert_eq!(info.hash_matches, Some(false));
+    assert!(!info.is_valid());
+
+    // broken objects are detected, but fail to parse
+    fs::write(&object_file, b"\x7fELF\0\0\0\0garbage").unwrap();
+    fs::remove_file(object_file.with_extension("txt")).unwrap();
+    let info = inspect(&object_file).unwrap();
+    assert_eq!(info.metadata, None);
+    assert_eq!(info.hash_matches, None);
+    assert_eq!(
+        info.contents,
+        CacheFileContents::Object(symbolic::debuginfo::FileFormat::Elf)
+    );
+    assert!(info.parse_error.is_some());
+    assert!(!info.is_valid());
+
+    // cached errors are valid entries
+    fs::write(&object_file, "").unwrap();
+    let info = inspect(&object_file).unwrap();
+    assert_eq!(
+        info.contents,
+        CacheFileContents::Error(CacheError::NotFound)
+    );
+    assert!(info.is_valid());
+}
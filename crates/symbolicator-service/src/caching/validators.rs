@@ -0,0 +1,68 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::fs::catch_not_found;
+
+/// The extension of the file holding the [`Validators`] of a cache item.
+pub(super) const VALIDATORS_EXTENSION: &str = "validators";
+
+/// The validators of a downloaded file, as returned by its source.
+///
+/// These are stored alongside the cache item, and allow to revalidate it with a conditional
+/// request once it expires, instead of downloading it again.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Validators {
+    /// The `ETag` of the file, sent as `If-None-Match`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// The `Last-Modified` date of the file, sent as `If-Modified-Since`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Whether there is any validator at all.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Returns the path of the validators of the cache item at `cache_path`.
+    pub(super) fn path(cache_path: &Path) -> PathBuf {
+        cache_path.with_extension(VALIDATORS_EXTENSION)
+    }
+
+    /// Reads the validators of the cache item at `cache_path`, if there are any.
+    pub(super) fn read(cache_path: &Path) -> Option<Self> {
+        let path = Self::path(cache_path);
+        let contents = match catch_not_found(|| fs::read(&path)) {
+            Ok(contents) => contents?,
+            Err(err) => {
+                tracing::error!(error = &err as &dyn std::error::Error);
+                return None;
+            }
+        };
+        serde_json::from_slice(&contents).ok()
+    }
+
+    /// Writes the validators of the cache item at `cache_path`, or removes them if empty.
+    pub(super) fn write(validators: Option<&Self>, cache_path: &Path) -> io::Result<()> {
+        let path = Self::path(cache_path);
+        match validators.filter(|validators| !validators.is_empty()) {
+            Some(validators) => fs::write(path, serde_json::to_vec(validators)?),
+            None => catch_not_found(|| fs::remove_file(path)).map(|_| ()),
+        }
+    }
+}
+
+/// The outcome of computing a cache item while revalidating an expired one, see
+/// [`CacheItemRequest::compute_conditional`](super::CacheItemRequest::compute_conditional).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Revalidation {
+    /// The expired item is still up to date, and is used again.
+    NotModified,
+    /// The item was computed anew, along with the validators of its source, if any.
+    Modified(Option<Validators>),
+}
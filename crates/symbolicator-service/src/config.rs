@@ -1,7 +1,9 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
@@ -11,9 +13,11 @@ use sentry::types::Dsn;
 use serde::{de, Deserialize, Deserializer};
 use tracing::level_filters::LevelFilter;
 
-use symbolicator_sources::SourceConfig;
+use symbolicator_sources::{redact_url, SourceConfig};
+use url::Url;
 
-use crate::caching::SharedCacheConfig;
+use crate::caching::{is_read_only_error, SharedCacheConfig};
+use crate::services::symbolication::source_context::DEFAULT_CONTEXT_LINES;
 
 /// Controls the log format
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
@@ -95,6 +99,42 @@ pub struct Metrics {
     ///
     /// These tags will be appended to every metric.
     pub custom_tags: BTreeMap<String, String>,
+    /// Configuration for exposing metrics to Prometheus.
+    pub prometheus: PrometheusMetrics,
+}
+
+/// Controls the export of traces via OpenTelemetry.
+///
+/// Traces are only exported if symbolicator is built with the `opentelemetry` feature.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Tracing {
+    /// The OTLP/HTTP endpoint to export traces to, such as `http://localhost:4318/v1/traces`.
+    ///
+    /// Defaults to not exporting traces.
+    pub otlp_endpoint: Option<String>,
+    /// The fraction of requests to trace, between `0` and `1`.
+    ///
+    /// Requests carrying a `traceparent` header follow the sampling decision of their parent.
+    pub sample_rate: f64,
+}
+
+impl Default for Tracing {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            sample_rate: 1.0,
+        }
+    }
+}
+
+/// Control the Prometheus metrics.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PrometheusMetrics {
+    /// Whether all metrics should be exposed in the Prometheus text format on the `/metrics`
+    /// endpoint. This can be enabled alongside statsd.
+    pub enabled: bool,
 }
 
 impl Default for Metrics {
@@ -108,6 +148,7 @@ impl Default for Metrics {
             hostname_tag: None,
             environment_tag: None,
             custom_tags: BTreeMap::new(),
+            prometheus: PrometheusMetrics::default(),
         }
     }
 }
@@ -122,8 +163,17 @@ pub struct DownloadedCacheConfig {
     #[serde(with = "humantime_serde")]
     pub max_unused_for: Option<Duration>,
 
-    /// Maximum duration since creation of negative cache item (item age).
+    /// Maximum duration since creation of positive cache item (item age).
+    ///
+    /// This requires the filesystem of the cache to record file creation times, otherwise the
+    /// age is measured from the last use of the item.
     #[serde(with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+
+    /// Maximum duration since creation of negative cache item (item age).
+    ///
+    /// Can also be configured as `negative_cache_ttl`.
+    #[serde(with = "humantime_serde", alias = "negative_cache_ttl")]
     pub retry_misses_after: Option<Duration>,
 
     /// Maximum duration since creation of malformed cache item (item age).
@@ -132,15 +182,22 @@ pub struct DownloadedCacheConfig {
 
     /// Maximum number of lazy re-downloads
     pub max_lazy_redownloads: isize,
+
+    /// Maximum total size in bytes of the cache directory.
+    ///
+    /// When exceeded, the least recently used cache items are evicted during cleanup.
+    pub max_cache_size: Option<u64>,
 }
 
 impl Default for DownloadedCacheConfig {
     fn default() -> Self {
         Self {
             max_unused_for: Some(Duration::from_secs(3600 * 24)),
+            max_age: None,
             retry_misses_after: Some(Duration::from_secs(3600)),
             retry_malformed_after: Some(Duration::from_secs(3600 * 24)),
             max_lazy_redownloads: 50,
+            max_cache_size: None,
         }
     }
 }
@@ -155,8 +212,17 @@ pub struct DerivedCacheConfig {
     #[serde(with = "humantime_serde")]
     pub max_unused_for: Option<Duration>,
 
-    /// Maximum duration since creation of negative cache item (item age).
+    /// Maximum duration since creation of positive cache item (item age).
+    ///
+    /// This requires the filesystem of the cache to record file creation times, otherwise the
+    /// age is measured from the last use of the item.
     #[serde(with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+
+    /// Maximum duration since creation of negative cache item (item age).
+    ///
+    /// Can also be configured as `negative_cache_ttl`.
+    #[serde(with = "humantime_serde", alias = "negative_cache_ttl")]
     pub retry_misses_after: Option<Duration>,
 
     /// Maximum duration since creation of malformed cache item (item age).
@@ -165,15 +231,22 @@ pub struct DerivedCacheConfig {
 
     /// Maximum number of lazy re-computations
     pub max_lazy_recomputations: isize,
+
+    /// Maximum total size in bytes of the cache directory.
+    ///
+    /// When exceeded, the least recently used cache items are evicted during cleanup.
+    pub max_cache_size: Option<u64>,
 }
 
 impl Default for DerivedCacheConfig {
     fn default() -> Self {
         Self {
             max_unused_for: Some(Duration::from_secs(3600 * 24 * 7)),
+            max_age: None,
             retry_misses_after: Some(Duration::from_secs(3600)),
             retry_malformed_after: Some(Duration::from_secs(3600 * 24)),
             max_lazy_recomputations: 20,
+            max_cache_size: None,
         }
     }
 }
@@ -212,6 +285,14 @@ impl CacheConfig {
         }
     }
 
+    pub fn max_age(&self) -> Option<Duration> {
+        match self {
+            Self::Downloaded(cfg) => cfg.max_age,
+            Self::Derived(cfg) => cfg.max_age,
+            Self::Diagnostics(_cfg) => None,
+        }
+    }
+
     pub fn retry_misses_after(&self) -> Option<Duration> {
         match self {
             Self::Downloaded(cfg) => cfg.retry_misses_after,
@@ -220,6 +301,14 @@ impl CacheConfig {
         }
     }
 
+    pub fn max_cache_size(&self) -> Option<u64> {
+        match self {
+            Self::Downloaded(cfg) => cfg.max_cache_size,
+            Self::Derived(cfg) => cfg.max_cache_size,
+            Self::Diagnostics(_cfg) => None,
+        }
+    }
+
     pub fn retry_malformed_after(&self) -> Option<Duration> {
         match self {
             Self::Downloaded(cfg) => cfg.retry_malformed_after,
@@ -307,6 +396,41 @@ pub struct InMemoryCacheConfig {
     ///
     /// Defaults to `600 MiB (= 629_145_600)`.
     pub cficaches_capacity: u64,
+
+    /// Maximum number of symcaches kept in memory.
+    ///
+    /// Symcaches are `mmap`-ed, so their size in memory does not reflect their real footprint.
+    /// If set, this limits the number of symcaches instead of their size in bytes. Each entry
+    /// keeps a file descriptor open.
+    ///
+    /// Defaults to `null`, limiting the symcaches by their size in bytes.
+    pub symcache_memory_entries: Option<u64>,
+
+    /// Whether the items of the file system caches, such as objects and symcaches, are also kept
+    /// in memory.
+    ///
+    /// When disabled, items are only shared between requests which need them at the same time.
+    /// Together with an unset `cache_dir`, all downloads and conversions happen anew for every
+    /// request.
+    ///
+    /// Defaults to `true`.
+    pub file_caches: bool,
+
+    /// The TTL for cached symbolication responses.
+    ///
+    /// Responses to identical `symbolicate` requests, such as client retries, are served from
+    /// this cache without symbolicating the stack traces again. Requests using the `refresh` or
+    /// `bypass` cache modes never read cached responses.
+    ///
+    /// Defaults to `0`, which disables the response cache.
+    #[serde(with = "humantime_serde")]
+    pub response_ttl: Duration,
+
+    /// Maximum size in bytes of the symbolication responses kept in memory, measured by the size
+    /// of their JSON.
+    ///
+    /// Defaults to `100 MiB (= 104_857_600)`.
+    pub response_capacity: u64,
 }
 
 impl Default for InMemoryCacheConfig {
@@ -319,11 +443,15 @@ impl Default for InMemoryCacheConfig {
             s3_client_capacity: 100,
             object_meta_capacity: 100 * meg,
             cficaches_capacity: 400 * meg,
+            symcache_memory_entries: None,
+            file_caches: true,
+            response_ttl: Duration::ZERO,
+            response_capacity: 100 * meg,
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(default)]
 pub struct CacheConfigs {
     /// Configure how long downloads are cached for.
@@ -337,6 +465,244 @@ pub struct CacheConfigs {
 
     /// Configuration of various in-memory caches.
     pub in_memory: InMemoryCacheConfig,
+
+    /// Whether to write the metadata of the cache key into a `.txt` file next to each cache file.
+    ///
+    /// This makes the cache files self-describing, and is required to purge the caches of a
+    /// scope.
+    pub write_metadata: bool,
+
+    /// How cache files are compressed on disk.
+    pub compression: CacheCompression,
+
+    /// The directory of the objects cache, overriding `<cache_dir>/objects`.
+    pub objects_dir: Option<PathBuf>,
+
+    /// The directory of the symcaches, overriding `<cache_dir>/symcaches`.
+    pub symcaches_dir: Option<PathBuf>,
+
+    /// The minimum free space on the volumes of the caches.
+    ///
+    /// Below this, the readiness check fails and the cleanup evicts cache files until enough
+    /// space is free again.
+    pub min_free_space: Option<FreeSpace>,
+
+    /// What concurrent requests for an item receive when its shared computation fails.
+    pub failed_computations: FailedComputations,
+
+    /// A version written into the keys of all symcaches.
+    ///
+    /// Bumping this version invalidates all symcaches, for example after a change to their
+    /// conversion, as they are then looked up at new paths. The previous files are left behind
+    /// and removed by the cache cleanup once they expire. Defaults to `0`, which keeps the keys
+    /// unchanged.
+    pub symcache_key_version: u32,
+}
+
+impl Default for CacheConfigs {
+    fn default() -> Self {
+        Self {
+            downloaded: Default::default(),
+            derived: Default::default(),
+            diagnostics: Default::default(),
+            in_memory: Default::default(),
+            write_metadata: true,
+            compression: Default::default(),
+            objects_dir: None,
+            symcaches_dir: None,
+            min_free_space: None,
+            failed_computations: Default::default(),
+            symcache_key_version: 0,
+        }
+    }
+}
+
+/// An amount of free disk space.
+///
+/// This is configured either as a number of bytes, or as a percentage of the total space of a
+/// volume, like `"10%"`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FreeSpace {
+    /// A number of bytes.
+    Bytes(u64),
+    /// A percentage of the total space.
+    Percent(f64),
+}
+
+impl FreeSpace {
+    /// Returns the number of bytes this amounts to on a volume of `total` bytes.
+    pub fn bytes(self, total: u64) -> u64 {
+        match self {
+            FreeSpace::Bytes(bytes) => bytes,
+            FreeSpace::Percent(percent) => (total as f64 * percent / 100.0) as u64,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FreeSpace {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Bytes(u64),
+            Percent(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Bytes(bytes) => Ok(FreeSpace::Bytes(bytes)),
+            Repr::Percent(value) => value
+                .strip_suffix('%')
+                .and_then(|percent| percent.trim().parse().ok())
+                .filter(|percent| (0.0..=100.0).contains(percent))
+                .map(FreeSpace::Percent)
+                .ok_or_else(|| {
+                    de::Error::custom(format!(
+                        "invalid free space `{value}`, expected a number of bytes or a percentage"
+                    ))
+                }),
+        }
+    }
+}
+
+/// The compression of cache files on disk.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheCompression {
+    /// Cache files are stored as they are.
+    #[default]
+    None,
+    /// Cache files are compressed using zstd.
+    Zstd,
+}
+
+/// How requests deal with the failure of a computation they waited for.
+///
+/// Concurrent requests for the same cache item share one computation. This only applies to
+/// transient failures, like download errors and timeouts.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailedComputations {
+    /// All waiting requests receive the error of the failed computation.
+    #[default]
+    Share,
+    /// The waiting requests retry the computation once, again sharing a single computation.
+    Retry,
+}
+
+/// The rate at which symbolication requests of a scope are admitted.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct RateLimit {
+    /// The number of requests admitted per second, on average.
+    pub requests_per_second: f64,
+    /// The number of requests admitted at once after the scope has been idle.
+    pub burst: u32,
+}
+
+/// Rate limits of symbolication requests, per scope.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ScopeRateLimits {
+    /// The rate limit of all scopes without a rate limit of their own.
+    ///
+    /// A value of `None` indicates no limit.
+    pub default: Option<RateLimit>,
+    /// The rate limits of individual scopes, by the name of the scope.
+    pub scopes: BTreeMap<String, RateLimit>,
+}
+
+impl ScopeRateLimits {
+    /// Returns the rate limit which applies to the scope with the given name, if any.
+    pub fn get(&self, scope: &str) -> Option<RateLimit> {
+        self.scopes.get(scope).copied().or(self.default)
+    }
+
+    /// Whether any rate limits are configured.
+    pub fn is_enabled(&self) -> bool {
+        self.default.is_some() || !self.scopes.is_empty()
+    }
+}
+
+/// Limits of uploaded minidumps, which are rejected before processing if they exceed any of them.
+///
+/// These guard against crafted minidumps that would make parsing them allocate huge amounts of
+/// memory. A value of `None` indicates no limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct MinidumpLimits {
+    /// The maximum number of modules in the module list.
+    pub max_modules: Option<usize>,
+    /// The maximum number of threads in the thread list.
+    pub max_threads: Option<usize>,
+    /// The maximum size of a single memory region, in bytes.
+    pub max_memory_region_size: Option<u64>,
+}
+
+impl Default for MinidumpLimits {
+    fn default() -> Self {
+        Self {
+            max_modules: Some(16_384),
+            max_threads: Some(16_384),
+            max_memory_region_size: Some(64 * 1024 * 1024),
+        }
+    }
+}
+
+/// The proxy for outgoing requests to sources.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// The URL of the proxy, which is used for requests to HTTP, GCS and Sentry sources.
+    ///
+    /// HTTP sources can opt out of the proxy with `bypass_proxy: true`. S3 sources do not use the
+    /// proxy. As the proxy resolves the addresses of the sources, this requires
+    /// `connect_to_reserved_ips`.
+    pub url: Option<Url>,
+    /// The username to authenticate with the proxy.
+    pub username: Option<String>,
+    /// The password to authenticate with the proxy.
+    pub password: Option<String>,
+    /// Whether to use the proxies of the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment
+    /// variables for all outgoing requests, unless a `url` is configured.
+    pub from_env: bool,
+}
+
+impl ProxyConfig {
+    /// Creates the proxy for the configured `url`, if any.
+    pub fn proxy(&self) -> Option<reqwest::Result<reqwest::Proxy>> {
+        let url = self.url.as_ref()?;
+        let proxy = reqwest::Proxy::all(url.clone()).map(|proxy| match &self.username {
+            Some(username) => {
+                proxy.basic_auth(username, self.password.as_deref().unwrap_or_default())
+            }
+            None => proxy,
+        });
+        Some(proxy)
+    }
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            username: None,
+            password: None,
+            from_env: true,
+        }
+    }
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url.as_ref().map(redact_url))
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "[redacted]"))
+            .field("from_env", &self.from_env)
+            .finish()
+    }
 }
 
 /// See docs/index.md for more information on config values.
@@ -344,10 +710,28 @@ pub struct CacheConfigs {
 #[serde(default)]
 pub struct Config {
     /// Which directory to use when caching. Default is not to cache.
+    ///
+    /// Without a cache directory, nothing is persisted on disk. Downloads and conversions are
+    /// written to temporary files which are removed once they are no longer used, and their
+    /// results are only kept in the bounded in-memory caches, see
+    /// [`InMemoryCacheConfig::file_caches`].
     pub cache_dir: Option<PathBuf>,
 
-    /// Host and port to bind the HTTP webserver to.
-    pub bind: String,
+    /// Which directory to use for temporary files of the caches, such as downloads and
+    /// conversions in progress.
+    ///
+    /// This must be on the same filesystem as the cache directories, as temporary files are
+    /// moved into the caches by renaming them. Caches on a different filesystem use a `tmp`
+    /// directory next to them instead. Only a `symbolicator-tmp` directory within it is used and
+    /// cleared on startup. Defaults to `tmp` within the `cache_dir`.
+    pub tmp_dir: Option<PathBuf>,
+
+    /// Addresses to bind the HTTP webserver to.
+    ///
+    /// This is either a single address or a list of addresses, each of which is parsed as a
+    /// [`BindAddress`]. The webserver listens on all of them at once.
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    pub bind: Vec<String>,
 
     /// Host and port to bind the HTTPS webserver to.
     #[cfg(feature = "https")]
@@ -359,6 +743,9 @@ pub struct Config {
     /// Configuration for reporting metrics to a statsd instance.
     pub metrics: Metrics,
 
+    /// Configuration for exporting traces of requests.
+    pub tracing: Tracing,
+
     #[cfg(feature = "https")]
     pub server_config: ServerConfig,
 
@@ -377,6 +764,25 @@ pub struct Config {
     /// Allow reserved IP addresses for requests to sources.
     pub connect_to_reserved_ips: bool,
 
+    /// The maximum number of redirects followed when downloading from a source.
+    ///
+    /// Downloads exceeding this, or running into a redirect loop, fail for that source.
+    pub max_redirects: usize,
+
+    /// The proxy for outgoing requests to sources.
+    pub proxy: ProxyConfig,
+
+    /// Whether sources may redirect downloads to other hosts.
+    ///
+    /// This does not apply to requests to Sentry, which are trusted.
+    pub allow_cross_host_redirects: bool,
+
+    /// The `User-Agent` sent with requests to sources.
+    ///
+    /// Defaults to `symbolicator/<version>`. HTTP sources can override it with a `User-Agent`
+    /// in their `headers`.
+    pub user_agent: Option<String>,
+
     /// The maximum timeout for downloads.
     ///
     /// This is the upper limit the download service will take for downloading from a single
@@ -411,6 +817,15 @@ pub struct Config {
     #[serde(with = "humantime_serde")]
     pub deny_list_block_time: Duration,
 
+    /// The number of consecutive failed lookups after which a source is skipped.
+    ///
+    /// `None` disables the circuit breaker, so sources are never skipped.
+    pub circuit_breaker_threshold: Option<usize>,
+
+    /// The duration for which a failing source is skipped before it is retried.
+    #[serde(with = "humantime_serde")]
+    pub circuit_breaker_open_time: Duration,
+
     /// The timeout per GB for streaming downloads.
     ///
     /// For downloads with a known size, this timeout applies per individual
@@ -421,11 +836,103 @@ pub struct Config {
     #[serde(with = "humantime_serde")]
     pub streaming_timeout: Duration,
 
+    /// The maximum time to wait for new data while streaming a download.
+    ///
+    /// This detects downloads that stall. It applies to each individual download
+    /// attempt, and can be overridden per source.
+    #[serde(with = "humantime_serde")]
+    pub read_timeout: Duration,
+
+    /// The maximum size of downloaded files, in bytes.
+    ///
+    /// Larger files are rejected as soon as their size is known, either from the
+    /// `Content-Length` of the response or while streaming them. This can be overridden per
+    /// source. A value of `None` indicates no limit.
+    pub max_download_size: Option<u64>,
+
+    /// The maximum number of times a failed download is retried.
+    ///
+    /// Only transient failures, such as connection errors, server errors or timeouts are
+    /// retried, using an exponential backoff between attempts.
+    pub max_download_retries: usize,
+
+    /// The maximum number of downloads that symbolicator will run concurrently.
+    ///
+    /// This limit is shared across all requests. Downloads beyond this limit are queued.
+    /// A value of `None` indicates no limit.
+    pub max_concurrent_downloads: Option<usize>,
+
     /// The maximum number of requests that symbolicator will process concurrently.
     ///
     /// A value of `None` indicates no limit.
     pub max_concurrent_requests: Option<usize>,
 
+    /// The maximum number of requests in a single batch symbolication request.
+    ///
+    /// A batch is admitted as a single request, so this bounds the work it can cause.
+    /// A value of `None` indicates no limit.
+    pub max_batch_size: Option<usize>,
+
+    /// The rate limits of symbolication requests, per scope.
+    ///
+    /// Requests of a scope exceeding its rate limit are rejected, so that a single scope can
+    /// not use up the capacity of all others.
+    pub rate_limits: ScopeRateLimits,
+
+    /// Limits of uploaded minidumps, see [`MinidumpLimits`].
+    pub minidump_limits: MinidumpLimits,
+
+    /// The default for the maximum time spent symbolicating a request.
+    ///
+    /// Once this time is exceeded, the frames whose debug files have not been fetched yet are
+    /// reported as timed out, instead of waiting for them. Requests can set their own timeout.
+    /// A value of `None` indicates no limit.
+    #[serde(with = "humantime_serde")]
+    pub symbolication_timeout: Option<Duration>,
+
+    /// The upper bound for the maximum time spent symbolicating a request.
+    ///
+    /// This caps both the [`symbolication_timeout`](Self::symbolication_timeout) and the
+    /// timeouts of individual requests. A value of `None` indicates no limit.
+    #[serde(with = "humantime_serde")]
+    pub max_symbolication_timeout: Option<Duration>,
+
+    /// The number of source lines returned before and after the line of a frame.
+    pub source_context_lines: usize,
+
+    /// The maximum number of frames returned in a symbolication response, across all threads.
+    ///
+    /// Frames beyond this limit are removed, starting with the last thread. The requesting thread
+    /// keeps its frames before any other thread. A value of `None` indicates no limit.
+    pub max_response_frames: Option<usize>,
+
+    /// The maximum time a completed request can still be polled for its result.
+    ///
+    /// After this time, the result is evicted and polling the request responds with a `404`.
+    #[serde(with = "humantime_serde")]
+    pub max_poll_delay: Duration,
+
+    /// The number of threads of the runtime used for CPU-bound work, such as symbolication
+    /// and the conversion of debug files.
+    ///
+    /// A value of `0` uses one thread per CPU core.
+    pub cpu_threads: usize,
+
+    /// The number of threads of the runtime used for IO-bound work, such as downloads.
+    ///
+    /// A value of `0` uses one thread per CPU core.
+    pub io_threads: usize,
+
+    /// The maximum number of symcache conversions that symbolicator will run concurrently.
+    ///
+    /// This limit is shared across all requests. Conversions beyond this limit are queued.
+    /// A value of `0` allows one conversion per CPU core.
+    pub symcache_conversion_concurrency: usize,
+
+    /// The maximum time to wait for in-flight requests to complete when shutting down.
+    #[serde(with = "humantime_serde")]
+    pub shutdown_timeout: Duration,
+
     /// An optional shared cache between multiple symbolicators.
     ///
     /// If configured this cache location is queried whenever a cache item is not found in
@@ -440,6 +947,10 @@ pub struct Config {
     /// cached on disk. The path is created if it doesn't exist. Path must be UTF-8.
     #[serde(default)]
     pub _crash_db: Option<PathBuf>,
+
+    /// The path of the file this config was loaded from, which is used to reload it.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
 }
 
 impl Config {
@@ -457,6 +968,214 @@ impl Config {
     pub fn default_sources(&self) -> Arc<[SourceConfig]> {
         self.sources.clone()
     }
+
+    /// Returns the number of threads to use for CPU-bound work, resolving the `0` default.
+    pub fn num_cpu_threads(&self) -> usize {
+        resolve_num_threads(self.cpu_threads)
+    }
+
+    /// Returns the number of threads to use for IO-bound work, resolving the `0` default.
+    pub fn num_io_threads(&self) -> usize {
+        resolve_num_threads(self.io_threads)
+    }
+
+    /// Returns the number of symcache conversions to run concurrently, resolving the `0` default.
+    pub fn num_symcache_conversions(&self) -> usize {
+        resolve_num_threads(self.symcache_conversion_concurrency)
+    }
+}
+
+/// An error returned when validating the [`Config`], listing all of its problems.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid configuration: {}", .problems.join("; "))]
+pub struct ConfigError {
+    /// Descriptions of all the problems of the configuration.
+    pub problems: Vec<String>,
+}
+
+impl Config {
+    /// Checks the configuration for problems that would otherwise only surface while handling
+    /// requests.
+    ///
+    /// This makes sure that the `cache_dir`, the `tmp_dir` and the overridden cache directories
+    /// are writable, that all sources have a unique `id`, that the bind addresses parse, that the
+    /// proxy URL is supported, that the shared cache namespace is valid, and that all the
+    /// configured limits are non-zero. All problems are reported at once.
+    ///
+    /// Cache directories which exist but are read-only are only warned about, as the caches still
+    /// serve their existing files in that case. Checking the directories does not modify the
+    /// file system, missing directories are checked through their closest existing parent.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = vec![];
+
+        let cache_dirs = [
+            ("cache_dir", &self.cache_dir),
+            ("tmp_dir", &self.tmp_dir),
+            ("caches.objects_dir", &self.caches.objects_dir),
+            ("caches.symcaches_dir", &self.caches.symcaches_dir),
+        ];
+        for (name, cache_dir) in cache_dirs {
+            let Some(cache_dir) = cache_dir else {
+                continue;
+            };
+            match check_writable_dir(cache_dir) {
+                Ok(_) => {}
+                // Existing read-only caches still serve their files, and are reported as degraded.
+                Err(err) if cache_dir.is_dir() && is_read_only_error(&err) => {
+                    tracing::warn!(
+                        error = &err as &dyn std::error::Error,
+                        "{name} `{}` is read-only, new items are not cached in it",
+                        cache_dir.display()
+                    );
+                }
+                Err(err) => problems.push(format!(
+                    "{name} `{}` is not writable: {err}",
+                    cache_dir.display()
+                )),
+            }
+        }
+
+        let mut source_ids = HashSet::new();
+        for source in self.sources.iter() {
+            if !source_ids.insert(source.id()) {
+                problems.push(format!("duplicate source id `{}`", source.id()));
+            }
+        }
+
+        match self.proxy.proxy() {
+            Some(Err(err)) => problems.push(format!("proxy.url is invalid: {err}")),
+            // Reserved IPs are filtered on the connection, which only reaches the proxy.
+            Some(Ok(_)) if !self.connect_to_reserved_ips => problems.push(
+                "proxy.url requires connect_to_reserved_ips, reserved IPs of sources cannot be \
+                 blocked behind a proxy"
+                    .to_owned(),
+            ),
+            _ => {}
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            if let Err(err) = reqwest::header::HeaderValue::from_str(user_agent) {
+                problems.push(format!("user_agent `{user_agent}` is invalid: {err}"));
+            }
+        }
+
+        if self.bind.is_empty() {
+            problems.push("bind must contain at least one address".to_owned());
+        }
+        for bind in &self.bind {
+            if let Err(err) = bind.parse::<BindAddress>() {
+                problems.push(format!("bind address `{bind}` is invalid: {err}"));
+            }
+        }
+        #[cfg(feature = "https")]
+        if let Some(bind_https) = &self.bind_https {
+            if let Err(err) = bind_https.parse::<SocketAddr>() {
+                problems.push(format!(
+                    "bind_https address `{bind_https}` is invalid: {err}"
+                ));
+            }
+            match &self.server_config.https {
+                Some(https) => {
+                    let pem_files = [
+                        ("certificate_path", &https.certificate_path),
+                        ("key_path", &https.key_path),
+                    ];
+                    for (name, path) in pem_files {
+                        if let Err(err) = check_pem_file(path) {
+                            problems.push(format!(
+                                "server_config.https.{name} `{}` is invalid: {err}",
+                                path.display()
+                            ));
+                        }
+                    }
+                }
+                None => problems.push("bind_https requires server_config.https".to_owned()),
+            }
+        }
+
+        let namespace = self
+            .shared_cache
+            .as_ref()
+            .and_then(|cfg| cfg.namespace.as_deref());
+        if let Some(namespace) = namespace {
+            let is_valid = !matches!(namespace, "" | "." | "..")
+                && !namespace
+                    .chars()
+                    .any(|c| c == '/' || c == '\\' || c.is_control());
+            if !is_valid {
+                problems.push(format!(
+                    "shared_cache.namespace `{}` must be a non-empty name without slashes or control characters",
+                    namespace.escape_debug()
+                ));
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.tracing.sample_rate) {
+            problems.push(format!(
+                "tracing.sample_rate must be between 0 and 1, got {}",
+                self.tracing.sample_rate
+            ));
+        }
+
+        let limits = [
+            ("max_concurrent_requests", self.max_concurrent_requests),
+            ("max_batch_size", self.max_batch_size),
+            ("max_concurrent_downloads", self.max_concurrent_downloads),
+            ("circuit_breaker_threshold", self.circuit_breaker_threshold),
+            (
+                "minidump_limits.max_modules",
+                self.minidump_limits.max_modules,
+            ),
+            (
+                "minidump_limits.max_threads",
+                self.minidump_limits.max_threads,
+            ),
+        ];
+        for (name, limit) in limits {
+            if limit == Some(0) {
+                problems.push(format!(
+                    "{name} must be greater than 0, or `null` for no limit"
+                ));
+            }
+        }
+        if self.minidump_limits.max_memory_region_size == Some(0) {
+            problems.push(
+                "minidump_limits.max_memory_region_size must be greater than 0, or `null` for no limit"
+                    .into(),
+            );
+        }
+        let rate_limits = (self.rate_limits.default.iter())
+            .map(|limit| ("rate_limits.default".to_owned(), limit))
+            .chain(
+                (self.rate_limits.scopes.iter())
+                    .map(|(scope, limit)| (format!("rate_limits.scopes.{scope}"), limit)),
+            );
+        for (name, limit) in rate_limits {
+            if !(limit.requests_per_second > 0.0 && limit.requests_per_second.is_finite()) {
+                problems.push(format!("{name}.requests_per_second must be greater than 0"));
+            }
+            if limit.burst == 0 {
+                problems.push(format!("{name}.burst must be greater than 0"));
+            }
+        }
+        if self.deny_list_bucket_size.is_zero() {
+            problems.push("deny_list_bucket_size must be greater than 0".into());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+}
+
+/// Resolves a configured number of threads, where `0` means one thread per CPU core.
+fn resolve_num_threads(threads: usize) -> usize {
+    match threads {
+        0 => std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    }
 }
 
 /// Checks if we are running in docker.
@@ -470,6 +1189,66 @@ fn is_docker() -> bool {
         .unwrap_or(false)
 }
 
+/// Checks whether files can be created in `dir`, without modifying the file system.
+///
+/// A missing `dir` can be created if its closest existing ancestor is a writable directory.
+fn check_writable_dir(dir: &Path) -> std::io::Result<()> {
+    let existing = dir
+        .ancestors()
+        .map(|path| match path.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => path,
+        })
+        .find(|path| path.exists())
+        .unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("`{}` is not a directory", existing.display()),
+        ));
+    }
+    check_access(existing)
+}
+
+/// Checks whether the current user may create files in the directory at `path`.
+#[cfg(unix)]
+fn check_access(path: &Path) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `path` is a valid C string.
+    let result = unsafe {
+        libc::faccessat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::W_OK | libc::X_OK,
+            libc::AT_EACCESS,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Checks whether the current user may create files in the directory at `path`.
+#[cfg(not(unix))]
+fn check_access(path: &Path) -> std::io::Result<()> {
+    match fs::metadata(path)?.permissions().readonly() {
+        false => Ok(()),
+        true => Err(std::io::ErrorKind::PermissionDenied.into()),
+    }
+}
+
+/// Checks that the file at `path` is readable and contains a PEM block.
+#[cfg(feature = "https")]
+fn check_pem_file(path: &Path) -> Result<()> {
+    let contents = fs::read_to_string(path)?;
+    anyhow::ensure!(contents.contains("-----BEGIN "), "not a PEM file");
+    Ok(())
+}
+
 /// Default value for the "bind" configuration.
 fn default_bind() -> String {
     if is_docker() {
@@ -494,55 +1273,277 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             cache_dir: default_cache_dir(),
-            bind: default_bind(),
+            tmp_dir: None,
+            bind: vec![default_bind()],
             #[cfg(feature = "https")]
             bind_https: None,
             logging: Logging::default(),
             #[cfg(feature = "https")]
             server_config: ServerConfig::default(),
             metrics: Metrics::default(),
+            tracing: Tracing::default(),
             sentry_dsn: None,
             caches: CacheConfigs::default(),
             symstore_proxy: true,
             sources: Arc::from(vec![]),
             connect_to_reserved_ips: false,
+            max_redirects: 5,
+            proxy: ProxyConfig::default(),
+            allow_cross_host_redirects: true,
+            user_agent: None,
             // Allow a 4MB/s connection to download 2GB without timing out
             max_download_timeout: Duration::from_secs(315),
             connect_timeout: Duration::from_secs(15),
             // Allow a 4MB/s connection to download 1GB without timing out
             streaming_timeout: Duration::from_secs(250),
+            read_timeout: Duration::from_secs(60),
+            max_download_size: None,
+            max_download_retries: 2,
+            max_concurrent_downloads: None,
             deny_list_time_window: Duration::from_secs(60),
             deny_list_bucket_size: Duration::from_secs(5),
             deny_list_threshold: 20,
             deny_list_block_time: Duration::from_secs(24 * 60 * 60),
+            circuit_breaker_threshold: None,
+            circuit_breaker_open_time: Duration::from_secs(60),
             max_concurrent_requests: Some(120),
+            max_batch_size: Some(1000),
+            rate_limits: ScopeRateLimits::default(),
+            minidump_limits: MinidumpLimits::default(),
+            symbolication_timeout: None,
+            max_symbolication_timeout: None,
+            source_context_lines: DEFAULT_CONTEXT_LINES,
+            max_response_frames: None,
+            max_poll_delay: Duration::from_secs(90),
+            shutdown_timeout: Duration::from_secs(30),
+            cpu_threads: 0,
+            io_threads: 0,
+            symcache_conversion_concurrency: 0,
             shared_cache: None,
             _crash_db: None,
+            path: None,
+        }
+    }
+}
+
+/// An address to bind the webserver to, see [`Config::bind`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BindAddress {
+    /// A TCP socket, given as `host:port`.
+    Tcp(SocketAddr),
+    /// A Unix domain socket, given as `unix:/path/to.sock`.
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for BindAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_prefix("unix:") {
+            Some("") => anyhow::bail!("missing path of the unix socket"),
+            Some(path) => Ok(Self::Unix(PathBuf::from(path))),
+            None => Ok(Self::Tcp(s.parse()?)),
+        }
+    }
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
         }
     }
 }
 
+/// Deserializes either a single value or a list of values.
+fn deserialize_one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    match Repr::deserialize(deserializer)? {
+        Repr::One(value) => Ok(vec![value]),
+        Repr::Many(values) => Ok(values),
+    }
+}
+
+/// The prefix of environment variables overriding config values.
+const ENV_PREFIX: &str = "SYMBOLICATOR_";
+
+/// The name of the directory next to the config file with additional source definitions.
+const SOURCES_DIR: &str = "sources.d";
+
 impl Config {
+    /// Loads the config from the file at `path`, or the default config if no `path` is given.
+    ///
+    /// Environment variables prefixed with `SYMBOLICATOR_` are layered on top of the loaded
+    /// config, see [`Config::apply_env_overrides`]. The sources of the fragments in the
+    /// `sources.d` directory next to the config file are appended to its `sources`.
     pub fn get(path: Option<&Path>) -> Result<Self> {
-        match path {
-            Some(path) => Self::from_reader(
-                fs::File::open(path).context("failed to open configuration file")?,
-            ),
-            None => Ok(Config::default()),
+        let value = match path {
+            Some(path) => {
+                Self::read_yaml(fs::File::open(path).context("failed to open configuration file")?)?
+            }
+            None => serde_yaml::Value::Mapping(Default::default()),
+        };
+        // make sure the file itself is valid before attributing errors to any overrides
+        serde_yaml::from_value::<Self>(value.clone()).context("failed to parse config YAML")?;
+
+        let mut config = Self::apply_env_overrides(value, env::vars())?;
+        if let Some(path) = path {
+            config.sources =
+                merge_source_fragments(&config.sources, &path.with_file_name(SOURCES_DIR))?;
         }
+        config.path = path.map(Path::to_owned);
+        Ok(config)
     }
 
-    fn from_reader(mut reader: impl std::io::Read) -> Result<Self> {
+    fn from_reader(reader: impl std::io::Read) -> Result<Self> {
+        let value = Self::read_yaml(reader)?;
+        serde_yaml::from_value(value).context("failed to parse config YAML")
+    }
+
+    fn read_yaml(mut reader: impl std::io::Read) -> Result<serde_yaml::Value> {
         let mut config = String::new();
         reader
             .read_to_string(&mut config)
             .context("failed reading config file")?;
+        // check for empty files explicitly
         if config.trim().is_empty() {
             anyhow::bail!("config file empty");
         }
-        // check for empty files explicitly
         serde_yaml::from_str(&config).context("failed to parse config YAML")
     }
+
+    /// Applies the `SYMBOLICATOR_`-prefixed variables in `vars` on top of the config `value`.
+    ///
+    /// The remainder of the variable name is lowercased, and nested keys are separated by double
+    /// underscores, so `SYMBOLICATOR_METRICS__STATSD` overrides `metrics.statsd`. Values are
+    /// parsed as YAML, falling back to a plain string if that fails.
+    ///
+    /// Overrides that don't result in a valid config are reported as a [`ConfigError`].
+    fn apply_env_overrides(
+        mut value: serde_yaml::Value,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let mut overrides: Vec<_> = vars
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+            .collect();
+        // apply overrides in a stable order, independent of the environment
+        overrides.sort();
+
+        let mut problems = vec![];
+        for (key, raw) in overrides {
+            let path: Vec<_> = key[ENV_PREFIX.len()..]
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+            if path.iter().any(String::is_empty) {
+                problems.push(format!("`{key}` does not name a valid config key"));
+                continue;
+            }
+
+            let new_value = serde_yaml::from_str(&raw)
+                .unwrap_or_else(|_| serde_yaml::Value::String(raw.clone()));
+            let mut candidate = value.clone();
+            set_yaml_path(&mut candidate, &path, new_value);
+
+            // check each override on its own, so that errors can name the offending variable
+            match serde_yaml::from_value::<Self>(candidate.clone()) {
+                Ok(_) => value = candidate,
+                Err(err) => problems.push(format!("`{key}` has an invalid value: {err}")),
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+        serde_yaml::from_value(value).map_err(|err| ConfigError {
+            problems: vec![err.to_string()],
+        })
+    }
+}
+
+/// Appends the sources of all `*.yml` fragments in `dir` to the `sources` of the config file.
+///
+/// Each fragment contains a list of sources. Fragments are merged in the order of their file
+/// names, and a missing `dir` contributes no sources. A source id must not be defined more than
+/// once, across the config file and all fragments.
+fn merge_source_fragments(sources: &[SourceConfig], dir: &Path) -> Result<Arc<[SourceConfig]>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(sources.into()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read `{}`", dir.display()));
+        }
+    };
+    let mut fragments = vec![];
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("failed to read `{}`", dir.display()))?
+            .path();
+        if path.is_file() && path.extension().map_or(false, |ext| ext == "yml") {
+            fragments.push(path);
+        }
+    }
+    fragments.sort();
+
+    let mut origins: BTreeMap<_, PathBuf> = BTreeMap::new();
+    let mut merged = sources.to_vec();
+    for fragment in fragments {
+        let contents = fs::read_to_string(&fragment)
+            .with_context(|| format!("failed to read `{}`", fragment.display()))?;
+        // empty fragments, for example with all sources commented out, contribute nothing
+        if contents.trim().is_empty() {
+            continue;
+        }
+        let fragment_sources: Vec<SourceConfig> = serde_yaml::from_str(&contents)
+            .with_context(|| format!("failed to parse sources in `{}`", fragment.display()))?;
+
+        for source in fragment_sources {
+            let id = source.id().clone();
+            if let Some(origin) = origins.get(&id) {
+                anyhow::bail!(
+                    "duplicate source id `{id}` in `{}`, already defined in `{}`",
+                    fragment.display(),
+                    origin.display()
+                );
+            }
+            if sources.iter().any(|source| source.id() == &id) {
+                anyhow::bail!(
+                    "duplicate source id `{id}` in `{}`, already defined in the config file",
+                    fragment.display()
+                );
+            }
+            origins.insert(id, fragment.clone());
+            merged.push(source);
+        }
+    }
+    Ok(merged.into())
+}
+
+/// Sets the value at the nested `path` within `root`, creating intermediate mappings as needed.
+fn set_yaml_path(root: &mut serde_yaml::Value, path: &[String], new_value: serde_yaml::Value) {
+    let mut current = root;
+    for key in path {
+        if !current.is_mapping() {
+            *current = serde_yaml::Value::Mapping(Default::default());
+        }
+        let mapping = current.as_mapping_mut().unwrap();
+        current = mapping
+            .entry(serde_yaml::Value::String(key.clone()))
+            .or_insert(serde_yaml::Value::Null);
+    }
+    *current = new_value;
 }
 
 #[derive(Debug)]
@@ -654,6 +1655,26 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_negative_cache_ttl_alias() {
+        let yaml = r#"
+            caches:
+              downloaded:
+                negative_cache_ttl: 5m
+              derived:
+                retry_misses_after: 10m
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(
+            cfg.caches.downloaded.retry_misses_after,
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            cfg.caches.derived.retry_misses_after,
+            Some(Duration::from_secs(600))
+        );
+    }
+
     #[test]
     fn test_unspecified_dl_timeouts() {
         let yaml = r#"
@@ -680,6 +1701,395 @@ mod tests {
         assert_eq!(cfg.streaming_timeout, Duration::from_secs(0));
     }
 
+    #[test]
+    fn test_num_threads() {
+        let yaml = r#"
+            cpu_threads: 3
+            io_threads: 0
+            symcache_conversion_concurrency: 2
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(cfg.num_cpu_threads(), 3);
+        assert_eq!(cfg.num_symcache_conversions(), 2);
+
+        let num_cores = std::thread::available_parallelism().unwrap().get();
+        assert_eq!(cfg.num_io_threads(), num_cores);
+        assert_eq!(Config::default().num_cpu_threads(), num_cores);
+        assert_eq!(Config::default().num_symcache_conversions(), num_cores);
+    }
+
+    #[test]
+    fn test_validate() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let yaml = format!(
+            r#"
+            cache_dir: {}
+            bind: "127.0.0.1:3021"
+            sources:
+              - id: one
+                type: http
+                url: https://example.com/
+              - id: two
+                type: http
+                url: https://example.com/
+        "#,
+            cache_dir.path().display()
+        );
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        cfg.validate().unwrap();
+
+        // missing directories are not created
+        let missing_dir = cache_dir.path().join("missing").join("cache");
+        let cfg = Config {
+            cache_dir: Some(missing_dir.clone()),
+            ..Config::default()
+        };
+        cfg.validate().unwrap();
+        assert!(!missing_dir.parent().unwrap().exists());
+    }
+
+    #[test]
+    fn test_validate_multiple_errors() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        // a file can not be used as a directory
+        let cache_file = cache_dir.path().join("file");
+        fs::write(&cache_file, "").unwrap();
+
+        let yaml = format!(
+            r#"
+            cache_dir: {}
+            bind: "not an address"
+            max_concurrent_requests: 0
+            sources:
+              - id: one
+                type: http
+                url: https://example.com/
+              - id: one
+                type: http
+                url: https://example.org/
+        "#,
+            cache_file.display()
+        );
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+
+        assert_eq!(err.problems.len(), 4);
+        assert!(err.problems[0].starts_with("cache_dir"));
+        assert_eq!(err.problems[1], "duplicate source id `one`");
+        assert!(err.problems[2].starts_with("bind address `not an address` is invalid"));
+        assert_eq!(
+            err.problems[3],
+            "max_concurrent_requests must be greater than 0, or `null` for no limit"
+        );
+    }
+
+    #[cfg(feature = "https")]
+    #[test]
+    fn test_validate_https() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        fs::write(
+            &cert_path,
+            "-----BEGIN CERTIFICATE-----\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let key_path = dir.path().join("key.pem");
+        fs::write(&key_path, "not a key").unwrap();
+
+        let yaml = format!(
+            r#"
+            bind_https: "127.0.0.1:3443"
+            server_config:
+              https:
+                certificate_path: {}
+                key_path: {}
+        "#,
+            cert_path.display(),
+            key_path.display()
+        );
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(err.problems.len(), 1);
+        assert!(err.problems[0].starts_with("server_config.https.key_path"));
+
+        let yaml = format!(
+            r#"
+            bind_https: "127.0.0.1:3443"
+            server_config:
+              https:
+                certificate_path: {}
+                key_path: {}
+        "#,
+            dir.path().join("missing.pem").display(),
+            cert_path.display()
+        );
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(err.problems.len(), 1);
+        assert!(err.problems[0].starts_with("server_config.https.certificate_path"));
+
+        let cfg = Config::from_reader(r#"bind_https: "127.0.0.1:3443""#.as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(err.problems, ["bind_https requires server_config.https"]);
+    }
+
+    #[test]
+    fn test_validate_shared_cache_namespace() {
+        let config = |namespace: &str| {
+            let yaml = format!(
+                r#"
+                shared_cache:
+                  namespace: {namespace:?}
+                  filesystem:
+                    path: /tmp/symbolicator-shared
+            "#
+            );
+            Config::from_reader(yaml.as_bytes()).unwrap()
+        };
+
+        let cfg = config("cluster-1");
+        assert_eq!(
+            cfg.shared_cache.as_ref().unwrap().namespace.as_deref(),
+            Some("cluster-1")
+        );
+        cfg.validate().unwrap();
+
+        for namespace in ["", "..", "a/b", "a\\b", "a\nb"] {
+            let err = config(namespace).validate().unwrap_err();
+            assert_eq!(err.problems.len(), 1, "{namespace:?}");
+            assert!(err.problems[0].starts_with("shared_cache.namespace"));
+        }
+    }
+
+    #[test]
+    fn test_bind_addresses() {
+        let cfg = Config::from_reader(r#"bind: "127.0.0.1:3021""#.as_bytes()).unwrap();
+        assert_eq!(cfg.bind, ["127.0.0.1:3021"]);
+
+        let yaml = r#"
+            bind:
+              - "127.0.0.1:3021"
+              - "[::1]:3021"
+              - "unix:/run/symbolicator.sock"
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        cfg.validate().unwrap();
+        let addresses: Vec<BindAddress> = cfg.bind.iter().map(|b| b.parse().unwrap()).collect();
+        assert_eq!(
+            addresses,
+            [
+                BindAddress::Tcp("127.0.0.1:3021".parse().unwrap()),
+                BindAddress::Tcp("[::1]:3021".parse().unwrap()),
+                BindAddress::Unix(PathBuf::from("/run/symbolicator.sock")),
+            ]
+        );
+        assert_eq!(addresses[1].to_string(), "[::1]:3021");
+        assert_eq!(addresses[2].to_string(), "unix:/run/symbolicator.sock");
+
+        let yaml = r#"
+            bind:
+              - "127.0.0.1:3021"
+              - "unix:"
+              - "::1"
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(err.problems.len(), 2);
+        assert!(err.problems[0].starts_with("bind address `unix:` is invalid"));
+        assert!(err.problems[1].starts_with("bind address `::1` is invalid"));
+
+        let cfg = Config::from_reader("bind: []".as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(err.problems, ["bind must contain at least one address"]);
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let yaml = r#"
+            rate_limits:
+              default:
+                requests_per_second: 10
+                burst: 20
+              scopes:
+                noisy:
+                  requests_per_second: 0.5
+                  burst: 0
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+
+        let default = RateLimit {
+            requests_per_second: 10.0,
+            burst: 20,
+        };
+        assert_eq!(cfg.rate_limits.get("global"), Some(default));
+        assert_eq!(cfg.rate_limits.get("other"), Some(default));
+        assert_eq!(
+            cfg.rate_limits.get("noisy"),
+            Some(RateLimit {
+                requests_per_second: 0.5,
+                burst: 0,
+            })
+        );
+
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(
+            err.problems,
+            ["rate_limits.scopes.noisy.burst must be greater than 0"]
+        );
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let cfg = Config::from_reader("user_agent: my-symbolicator/1.0".as_bytes()).unwrap();
+        assert_eq!(cfg.user_agent.as_deref(), Some("my-symbolicator/1.0"));
+        cfg.validate().unwrap();
+
+        let cfg = Config::from_reader("user_agent: \"line\\nbreak\"".as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(
+            err.problems,
+            ["user_agent `line\nbreak` is invalid: failed to parse header value"]
+        );
+    }
+
+    #[test]
+    fn test_min_free_space() {
+        let parse = |value: &str| {
+            let yaml = format!("caches:\n  min_free_space: {value}\n");
+            Config::from_reader(yaml.as_bytes()).map(|cfg| cfg.caches.min_free_space)
+        };
+
+        assert_eq!(parse("null").unwrap(), None);
+        assert_eq!(parse("1024").unwrap(), Some(FreeSpace::Bytes(1024)));
+        assert_eq!(parse("\"12.5%\"").unwrap(), Some(FreeSpace::Percent(12.5)));
+        assert!(parse("\"150%\"").is_err());
+        assert!(parse("\"lots\"").is_err());
+
+        assert_eq!(FreeSpace::Bytes(1024).bytes(100), 1024);
+        assert_eq!(FreeSpace::Percent(12.5).bytes(1000), 125);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let yaml = r#"
+            bind: "127.0.0.1:3021"
+            metrics:
+              statsd: "127.0.0.1:8125"
+              prefix: "symbolicator"
+        "#;
+        let value = Config::read_yaml(yaml.as_bytes()).unwrap();
+        let vars = [
+            ("SYMBOLICATOR_BIND", "0.0.0.0:4000"),
+            ("SYMBOLICATOR_METRICS__STATSD", "statsd.internal:8125"),
+            ("SYMBOLICATOR_MAX_CONCURRENT_REQUESTS", "10"),
+            ("SYMBOLICATOR_CACHE_DIR", "/tmp/symbolicator"),
+            ("UNRELATED", "foo"),
+        ]
+        .map(|(key, value)| (key.to_owned(), value.to_owned()));
+
+        let cfg = Config::apply_env_overrides(value, vars).unwrap();
+
+        assert_eq!(cfg.bind, ["0.0.0.0:4000"]);
+        assert_eq!(cfg.metrics.statsd.as_deref(), Some("statsd.internal:8125"));
+        // values not overridden are kept
+        assert_eq!(cfg.metrics.prefix, "symbolicator");
+        assert_eq!(cfg.max_concurrent_requests, Some(10));
+        assert_eq!(cfg.cache_dir, Some(PathBuf::from("/tmp/symbolicator")));
+    }
+
+    #[test]
+    fn test_env_overrides_invalid() {
+        let yaml = r#"
+            bind: "127.0.0.1:3021"
+        "#;
+        let value = Config::read_yaml(yaml.as_bytes()).unwrap();
+        let vars = [
+            ("SYMBOLICATOR_MAX_CONCURRENT_REQUESTS", "many"),
+            ("SYMBOLICATOR_CONNECT_TIMEOUT", "soon"),
+            ("SYMBOLICATOR_BIND", "0.0.0.0:4000"),
+        ]
+        .map(|(key, value)| (key.to_owned(), value.to_owned()));
+
+        let err = Config::apply_env_overrides(value, vars).unwrap_err();
+
+        assert_eq!(err.problems.len(), 2);
+        assert!(err.problems[0].starts_with("`SYMBOLICATOR_CONNECT_TIMEOUT` has an invalid value"));
+        assert!(err.problems[1]
+            .starts_with("`SYMBOLICATOR_MAX_CONCURRENT_REQUESTS` has an invalid value"));
+    }
+
+    #[test]
+    fn test_proxy_config() {
+        let yaml = r#"
+            connect_to_reserved_ips: true
+            proxy:
+              url: http://proxy.example.com:3128
+              username: user
+              password: secret-password
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert!(cfg.proxy.from_env);
+        assert!(matches!(cfg.proxy.proxy(), Some(Ok(_))));
+        assert!(!format!("{cfg:?}").contains("secret"));
+        cfg.validate().unwrap();
+
+        let cfg =
+            Config::from_reader("proxy:\n  url: http://proxy.example.com".as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(err.problems.len(), 1);
+        assert!(err.problems[0].starts_with("proxy.url requires connect_to_reserved_ips"));
+
+        let cfg = Config::from_reader("proxy:\n  url: ftp://proxy.example.com".as_bytes()).unwrap();
+        let err = cfg.validate().unwrap_err();
+        assert_eq!(err.problems.len(), 1);
+        assert!(err.problems[0].starts_with("proxy.url is invalid"));
+    }
+
+    #[test]
+    fn test_source_fragments() {
+        let yaml = r#"
+            sources:
+              - id: main
+                type: http
+                url: https://main.example.com/
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        // fragments are merged in the order of their file names
+        fs::write(
+            dir.path().join("20-second.yml"),
+            "- id: second\n  type: http\n  url: https://second.example.com/\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("10-first.yml"),
+            "- id: first\n  type: http\n  url: https://first.example.com/\n",
+        )
+        .unwrap();
+        // files without the `yml` extension are ignored
+        fs::write(dir.path().join("30-disabled.yml.bak"), "not: sources").unwrap();
+
+        let sources = merge_source_fragments(&cfg.sources, dir.path()).unwrap();
+        let ids: Vec<_> = sources.iter().map(|source| source.id().as_str()).collect();
+        assert_eq!(ids, ["main", "first", "second"]);
+
+        // a missing directory contributes no sources
+        let sources = merge_source_fragments(&cfg.sources, &dir.path().join("missing")).unwrap();
+        assert_eq!(sources.len(), 1);
+
+        fs::write(
+            dir.path().join("30-duplicate.yml"),
+            "- id: first\n  type: http\n  url: https://duplicate.example.com/\n",
+        )
+        .unwrap();
+        let err = merge_source_fragments(&cfg.sources, dir.path()).unwrap_err();
+        let err = err.to_string();
+        assert!(err.starts_with("duplicate source id `first` in"), "{err}");
+        assert!(err.contains("30-duplicate.yml"), "{err}");
+        assert!(err.contains("10-first.yml"), "{err}");
+    }
+
     #[test]
     fn test_unknown_fields() {
         // Unknown fields should not cause failure
@@ -1,18 +1,26 @@
 //! Provides access to the metrics sytem.
+use std::cell::RefCell;
 use std::collections::BTreeMap;
+use std::fmt::Write;
 use std::net::ToSocketAddrs;
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use cadence::{Metric, MetricBuilder, StatsdClient, UdpMetricSink};
 use parking_lot::RwLock;
 
 lazy_static::lazy_static! {
     static ref METRICS_CLIENT: RwLock<Option<Arc<MetricsClient>>> = RwLock::new(None);
+    static ref PROMETHEUS_REGISTRY: RwLock<Option<Arc<PrometheusRegistry>>> = RwLock::new(None);
 }
 
 thread_local! {
-    static CURRENT_CLIENT: Option<Arc<MetricsClient>> = METRICS_CLIENT.read().clone();
+    static CURRENT_CLIENT: RefCell<Option<Arc<MetricsClient>>> =
+        RefCell::new(METRICS_CLIENT.read().clone());
+    static CURRENT_REGISTRY: RefCell<Option<Arc<PrometheusRegistry>>> =
+        RefCell::new(PROMETHEUS_REGISTRY.read().clone());
 }
 
 /// The metrics prelude that is necessary to use the client.
@@ -88,7 +96,7 @@ where
     R: Default,
 {
     CURRENT_CLIENT.with(|client| {
-        if let Some(client) = client {
+        if let Some(client) = client.borrow().as_deref() {
             f(client)
         } else {
             Default::default()
@@ -96,7 +104,357 @@ where
     })
 }
 
+/// Captures all the statsd metrics emitted on the current thread while running `f`.
+///
+/// The metrics are returned in the statsd line format, without any prefix.
+#[cfg(test)]
+pub fn with_capturing_test_client(f: impl FnOnce()) -> Vec<String> {
+    let (rx, sink) = cadence::SpyMetricSink::new();
+    let client = MetricsClient {
+        statsd_client: StatsdClient::from_sink("", sink),
+        tags: Default::default(),
+    };
+
+    let previous = CURRENT_CLIENT.with(|client| client.replace(Some(Arc::new(client))));
+    f();
+    CURRENT_CLIENT.with(|client| client.replace(previous));
+
+    rx.try_iter()
+        .map(|metric| String::from_utf8(metric).unwrap())
+        .collect()
+}
+
+/// The type of a metric recorded into the [`PrometheusRegistry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    /// A value that is added up.
+    Counter,
+    /// A value that is overwritten by each new measurement.
+    Gauge,
+    /// A distribution of values, of which the sum and count are tracked.
+    Summary,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Summary => "summary",
+        }
+    }
+}
+
+/// Conversion of metric values into the floats used by Prometheus.
+pub trait ToMetricValue {
+    /// Converts the value into a float.
+    ///
+    /// [`Duration`]s are converted to seconds, as is customary for Prometheus.
+    fn to_metric_value(&self) -> f64;
+}
+
+macro_rules! impl_to_metric_value {
+    ($($ty:ty),*) => {
+        $(
+            impl ToMetricValue for $ty {
+                fn to_metric_value(&self) -> f64 {
+                    *self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_to_metric_value!(i32, i64, u32, u64, usize, f64);
+
+impl ToMetricValue for Duration {
+    fn to_metric_value(&self) -> f64 {
+        self.as_secs_f64()
+    }
+}
+
+/// A single time series of a [`MetricFamily`], which is updated without locking.
+#[derive(Debug, Default)]
+struct Series {
+    /// The bits of the `f64` value of the series.
+    value: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Series {
+    fn value(&self) -> f64 {
+        f64::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, value: f64) {
+        let _ = self
+            .value
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+
+    fn set(&self, value: f64) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// All the time series sharing a metric name.
+#[derive(Debug)]
+struct MetricFamily {
+    ty: MetricType,
+    /// Whether a counter was ever decremented, which makes it a gauge for Prometheus.
+    decremented: AtomicBool,
+    /// The series along with their unsanitized tags.
+    series: Vec<(Vec<(String, String)>, Series)>,
+}
+
+impl MetricFamily {
+    fn new(ty: MetricType) -> Self {
+        Self {
+            ty,
+            decremented: AtomicBool::new(false),
+            series: Vec::new(),
+        }
+    }
+
+    /// Looks up the series with exactly the given tags, in any order.
+    fn find(&self, tags: &[(&str, &str)]) -> Option<&Series> {
+        self.series
+            .iter()
+            .find(|(labels, _)| {
+                labels.len() == tags.len()
+                    && tags
+                        .iter()
+                        .all(|(k, v)| labels.iter().any(|(label, value)| label == k && value == v))
+            })
+            .map(|(_, series)| series)
+    }
+
+    fn update(&self, series: &Series, value: f64) {
+        match self.ty {
+            MetricType::Counter => {
+                if value < 0.0 {
+                    self.decremented.store(true, Ordering::Relaxed);
+                }
+                series.add(value)
+            }
+            MetricType::Gauge => series.set(value),
+            MetricType::Summary => {
+                series.add(value);
+                series.count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn rendered_type(&self) -> MetricType {
+        match self.ty {
+            MetricType::Counter if self.decremented.load(Ordering::Relaxed) => MetricType::Gauge,
+            ty => ty,
+        }
+    }
+}
+
+/// A registry of all metrics, which can be rendered in the Prometheus text format.
+///
+/// Recording a metric into an existing series only takes a read lock and updates atomics. Names
+/// and tags are sanitized when rendering, so recording does not allocate either.
+#[derive(Debug)]
+pub struct PrometheusRegistry {
+    /// The prefix that is added to all metric names.
+    prefix: String,
+
+    /// A collection of tags and values that will be added to every metric.
+    tags: BTreeMap<String, String>,
+
+    /// The metric families by their unsanitized names.
+    families: RwLock<BTreeMap<String, MetricFamily>>,
+}
+
+impl PrometheusRegistry {
+    /// Creates a new, empty registry.
+    pub fn new(prefix: &str, tags: BTreeMap<String, String>) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+            tags,
+            families: Default::default(),
+        }
+    }
+
+    /// Records a metric with the given tags.
+    ///
+    /// Counters which are decremented are exposed as gauges, as Prometheus counters only go up.
+    pub fn record(
+        &self,
+        ty: MetricType,
+        name: &str,
+        value: impl ToMetricValue,
+        tags: &[(&str, &str)],
+    ) {
+        let value = value.to_metric_value();
+
+        {
+            let families = self.families.read();
+            if let Some(family) = families.get(name) {
+                if let Some(series) = family.find(tags) {
+                    family.update(series, value);
+                    return;
+                }
+            }
+        }
+
+        let mut families = self.families.write();
+        let family = families
+            .entry(name.to_owned())
+            .or_insert_with(|| MetricFamily::new(ty));
+        if family.find(tags).is_none() {
+            let labels = tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            family.series.push((labels, Series::default()));
+        }
+        let family = &*family;
+        if let Some(series) = family.find(tags) {
+            family.update(series, value);
+        }
+    }
+
+    /// Renders all the recorded metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let families = self.families.read();
+
+        let mut families: Vec<_> = families
+            .iter()
+            .map(|(name, family)| (sanitize_name(&format!("{}.{}", self.prefix, name)), family))
+            .collect();
+        families.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut previous_name = None;
+        for (name, family) in families.iter() {
+            if previous_name != Some(name) {
+                writeln!(output, "# TYPE {name} {}", family.rendered_type().as_str()).unwrap();
+                previous_name = Some(name);
+            }
+
+            let mut series: Vec<_> = family
+                .series
+                .iter()
+                .map(|(labels, series)| {
+                    let mut labels: Vec<_> = labels
+                        .iter()
+                        .map(|(k, v)| (sanitize_name(k), v.as_str()))
+                        .collect();
+                    labels.sort();
+                    (labels, series)
+                })
+                .collect();
+            series.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (labels, series) in series {
+                let labels = self.render_labels(&labels);
+                match family.ty {
+                    MetricType::Counter | MetricType::Gauge => {
+                        writeln!(output, "{name}{labels} {}", series.value()).unwrap();
+                    }
+                    MetricType::Summary => {
+                        let count = series.count.load(Ordering::Relaxed);
+                        writeln!(output, "{name}_sum{labels} {}", series.value()).unwrap();
+                        writeln!(output, "{name}_count{labels} {count}").unwrap();
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    fn render_labels(&self, labels: &[(String, &str)]) -> String {
+        let global_tags = self
+            .tags
+            .iter()
+            .map(|(k, v)| (sanitize_name(k), v.as_str()))
+            .filter(|(k, _)| !labels.iter().any(|(label, _)| label == k));
+        let mut all_labels: Vec<_> = labels.iter().cloned().chain(global_tags).collect();
+        all_labels.sort();
+
+        if all_labels.is_empty() {
+            return String::new();
+        }
+
+        let mut output = String::from("{");
+        for (i, (k, v)) in all_labels.into_iter().enumerate() {
+            if i > 0 {
+                output.push(',');
+            }
+            let v = v
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            write!(output, "{k}=\"{v}\"").unwrap();
+        }
+        output.push('}');
+        output
+    }
+}
+
+/// Pairs a tag with its value, used by the [`metric!`](crate::metric) macro.
+#[doc(hidden)]
+pub fn tag<'a>(key: &'a str, value: &'a str) -> (&'a str, &'a str) {
+    (key, value)
+}
+
+/// Replaces all the characters that are not valid in Prometheus metric or label names.
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Tell the metrics system to also record all metrics for Prometheus.
+pub fn configure_prometheus(prefix: &str, tags: BTreeMap<String, String>) {
+    tracing::info!("Exposing metrics to Prometheus");
+    *PROMETHEUS_REGISTRY.write() = Some(Arc::new(PrometheusRegistry::new(prefix, tags)));
+}
+
+/// Returns the [`PrometheusRegistry`], if Prometheus metrics are enabled.
+pub fn prometheus_registry() -> Option<Arc<PrometheusRegistry>> {
+    CURRENT_REGISTRY.with(|registry| registry.borrow().clone())
+}
+
+/// Records all the metrics emitted on the current thread while running `f` into `registry`.
+///
+/// This is meant for tests, which should not configure the process-global registry.
+pub fn with_prometheus_registry<R>(registry: Arc<PrometheusRegistry>, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT_REGISTRY.with(|current| current.replace(Some(registry)));
+    let result = f();
+    CURRENT_REGISTRY.with(|current| current.replace(previous));
+    result
+}
+
+/// Invoke a callback with the [`PrometheusRegistry`].
+///
+/// If Prometheus metrics are not enabled the callback is not invoked. For the most part the
+/// [`metric!`](crate::metric) macro should be used instead.
+#[inline(always)]
+pub fn with_prometheus<F>(f: F)
+where
+    F: FnOnce(&PrometheusRegistry),
+{
+    CURRENT_REGISTRY.with(|registry| {
+        if let Some(registry) = registry.borrow().as_deref() {
+            f(registry)
+        }
+    })
+}
+
 /// Emits a metric.
+///
+/// The metric is sent to statsd, and recorded for Prometheus, if either of those is configured.
 #[macro_export]
 macro_rules! metric {
     // counters
@@ -107,6 +465,14 @@ macro_rules! metric {
                 client.count_with_tags($id, $value)
                     $(.with_tag($k, $v))*
             );
+        });
+        $crate::metrics::with_prometheus(|registry| {
+            registry.record(
+                $crate::metrics::MetricType::Counter,
+                $id,
+                $value,
+                &[$($crate::metrics::tag($k, $v)),*],
+            );
         })
     }};
     (counter($id:expr) -= $value:expr $(, $k:expr => $v:expr)* $(,)?) => {{
@@ -116,6 +482,15 @@ macro_rules! metric {
                 client.count_with_tags($id, -$value)
                     $(.with_tag($k, $v))*
              );
+        });
+        // decremented counters are exposed as gauges, see `PrometheusRegistry::record`
+        $crate::metrics::with_prometheus(|registry| {
+            registry.record(
+                $crate::metrics::MetricType::Counter,
+                $id,
+                -$value,
+                &[$($crate::metrics::tag($k, $v)),*],
+            );
         })
     }};
 
@@ -127,6 +502,14 @@ macro_rules! metric {
                 client.gauge_with_tags($id, $value)
                     $(.with_tag($k, $v))*
             );
+        });
+        $crate::metrics::with_prometheus(|registry| {
+            registry.record(
+                $crate::metrics::MetricType::Gauge,
+                $id,
+                $value,
+                &[$($crate::metrics::tag($k, $v)),*],
+            );
         })
     }};
 
@@ -138,6 +521,14 @@ macro_rules! metric {
                 client.time_with_tags($id, $value)
                     $(.with_tag($k, $v))*
             );
+        });
+        $crate::metrics::with_prometheus(|registry| {
+            registry.record(
+                $crate::metrics::MetricType::Summary,
+                $id,
+                $value,
+                &[$($crate::metrics::tag($k, $v)),*],
+            );
         })
     }};
 
@@ -149,6 +540,14 @@ macro_rules! metric {
                 client.time_with_tags($id, $value)
                     $(.with_tag($k, $v))*
             );
+        });
+        $crate::metrics::with_prometheus(|registry| {
+            registry.record(
+                $crate::metrics::MetricType::Summary,
+                $id,
+                $value,
+                &[$($crate::metrics::tag($k, $v)),*],
+            );
         })
     }};
 
@@ -160,6 +559,117 @@ macro_rules! metric {
                 client.histogram_with_tags($id, $value)
                     $(.with_tag($k, $v))*
             );
+        });
+        $crate::metrics::with_prometheus(|registry| {
+            registry.record(
+                $crate::metrics::MetricType::Summary,
+                $id,
+                $value,
+                &[$($crate::metrics::tag($k, $v)),*],
+            );
         })
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_render() {
+        let tags = BTreeMap::from([("host".to_owned(), "test".to_owned())]);
+        let registry = PrometheusRegistry::new("symbolicator", tags);
+
+        registry.record(MetricType::Counter, "caches.access", 1, &[]);
+        registry.record(
+            MetricType::Counter,
+            "caches.access",
+            2,
+            &[("cache", "objects")],
+        );
+        registry.record(
+            MetricType::Counter,
+            "caches.access",
+            3,
+            &[("cache", "objects")],
+        );
+        registry.record(MetricType::Gauge, "requests.in_flight", 5, &[]);
+        registry.record(MetricType::Gauge, "requests.in_flight", 3, &[]);
+        registry.record(
+            MetricType::Summary,
+            "requests.duration",
+            Duration::from_millis(500),
+            &[],
+        );
+        registry.record(
+            MetricType::Summary,
+            "requests.duration",
+            Duration::from_millis(1500),
+            &[],
+        );
+
+        let expected = r#"# TYPE symbolicator_caches_access counter
+symbolicator_caches_access{host="test"} 1
+symbolicator_caches_access{cache="objects",host="test"} 5
+# TYPE symbolicator_requests_duration summary
+symbolicator_requests_duration_sum{host="test"} 2
+symbolicator_requests_duration_count{host="test"} 2
+# TYPE symbolicator_requests_in_flight gauge
+symbolicator_requests_in_flight{host="test"} 3
+"#;
+        assert_eq!(registry.render(), expected);
+    }
+
+    #[test]
+    fn test_prometheus_tag_order() {
+        let registry = PrometheusRegistry::new("symbolicator", Default::default());
+
+        registry.record(
+            MetricType::Counter,
+            "caches.access",
+            1,
+            &[("cache", "objects"), ("hit", "true")],
+        );
+        registry.record(
+            MetricType::Counter,
+            "caches.access",
+            1,
+            &[("hit", "true"), ("cache", "objects")],
+        );
+
+        let expected = r#"# TYPE symbolicator_caches_access counter
+symbolicator_caches_access{cache="objects",hit="true"} 2
+"#;
+        assert_eq!(registry.render(), expected);
+    }
+
+    #[test]
+    fn test_prometheus_decremented_counter() {
+        let registry = PrometheusRegistry::new("symbolicator", Default::default());
+
+        registry.record(MetricType::Counter, "requests.in_flight", 3, &[]);
+        registry.record(MetricType::Counter, "requests.in_flight", -1, &[]);
+
+        let expected = r#"# TYPE symbolicator_requests_in_flight gauge
+symbolicator_requests_in_flight 2
+"#;
+        assert_eq!(registry.render(), expected);
+    }
+
+    #[test]
+    fn test_prometheus_escape_labels() {
+        let registry = PrometheusRegistry::new("symbolicator", Default::default());
+
+        registry.record(
+            MetricType::Counter,
+            "responses.status_code.200",
+            1,
+            &[("reason", "a \"quoted\"\nvalue")],
+        );
+
+        let expected = r#"# TYPE symbolicator_responses_status_code_200 counter
+symbolicator_responses_status_code_200{reason="a \"quoted\"\nvalue"} 1
+"#;
+        assert_eq!(registry.render(), expected);
+    }
+}
@@ -11,6 +11,14 @@
 //! * it is also possible to skip a version, in case a broken deploy needed to
 //!   be reverted which left behind broken cache files.
 //!
+//! Changes to the [`CacheKey`](crate::caching::CacheKey) of a cache move all of its files to new
+//! paths, so the previous files can no longer be found. The version is bumped in that case as
+//! well, to make the recomputation explicit, but the `fallbacks` are cleared.
+//!
+//! To invalidate only the symcaches of a deployment, without a new release, the
+//! `caches.symcache_key_version` config can be bumped instead. It is part of the cache key, and
+//! thus moves the symcaches to new paths, while the previous files are left to expire.
+//!
 //! Some of the versioned caches are also tied to format versions defined in [`symbolic`].
 //! For those cases, there are static assertions that are a reminder to also bump the cache version.
 
@@ -18,6 +26,8 @@ use crate::caching::CacheVersions;
 
 /// CFI cache, with the following versions:
 ///
+/// - `5`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `4`: Recomputation to use new `CacheKey` format.
 ///
 /// - `3`: Proactive bump, as a bug in shared cache could have potentially
@@ -29,13 +39,15 @@ use crate::caching::CacheVersions;
 ///
 /// - `0`: Initial version.
 pub const CFICACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 4,
+    current: 5,
     fallbacks: &[],
 };
 static_assert!(symbolic::cfi::CFICACHE_LATEST_VERSION == 2);
 
 /// SymCache, with the following versions:
 ///
+/// - `7`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `6`: Recomputation to use new `CacheKey` format.
 ///
 /// - `5`: Proactive bump, as a bug in shared cache could have potentially
@@ -58,41 +70,47 @@ static_assert!(symbolic::cfi::CFICACHE_LATEST_VERSION == 2);
 ///
 /// - `0`: Initial version.
 pub const SYMCACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 6,
+    current: 7,
     fallbacks: &[],
 };
 static_assert!(symbolic::symcache::SYMCACHE_VERSION == 8);
 
 /// Data / Objects cache, with the following versions:
 ///
+/// - `2`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `1`: Recomputation to use new `CacheKey` format.
 ///
 /// - `0`: Initial version.
 pub const OBJECTS_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 1,
+    current: 2,
     fallbacks: &[],
 };
 
 /// Objects Meta cache, with the following versions:
 ///
+/// - `2`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `1`: Recomputation to use new `CacheKey` format.
 ///
 /// - `0`: Initial version.
 pub const META_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 1,
+    current: 2,
     fallbacks: &[],
 };
 
 /// Portable PDB cache, with the following versions:
 ///
+/// - `4`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `3`: Skips hidden SequencePoints, and thus avoids outputting `lineno: 0`.
 ///
 /// - `2`: Recomputation to use new `CacheKey` format.
 ///
 /// - `1`: Initial version.
 pub const PPDB_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 3,
-    fallbacks: &[2],
+    current: 4,
+    fallbacks: &[],
 };
 
 /// SourceMapCache, with the following versions:
@@ -105,28 +123,34 @@ pub const SOURCEMAP_CACHE_VERSIONS: CacheVersions = CacheVersions {
 
 /// Il2cpp cache, with the following versions:
 ///
+/// - `2`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `1`: Recomputation to use new `CacheKey` format.
 ///
 /// - `0`: Initial version.
 pub const IL2CPP_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 1,
+    current: 2,
     fallbacks: &[],
 };
 
 /// Bitcode / Auxdif (plist / bcsymbolmap) cache, with the following versions:
 ///
+/// - `2`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `1`: Recomputation to use new `CacheKey` format.
 ///
 /// - `0`: Initial version.
 pub const BITCODE_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 1,
+    current: 2,
     fallbacks: &[],
 };
 
 /// Source Files Cache, with the following versions:
 ///
+/// - `2`: Recomputation to include the source type in the `CacheKey`.
+///
 /// - `1`: Initial version.
 pub const SOURCEFILES_CACHE_VERSIONS: CacheVersions = CacheVersions {
-    current: 1,
+    current: 2,
     fallbacks: &[],
 };
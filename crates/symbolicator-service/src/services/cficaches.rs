@@ -12,7 +12,8 @@ use symbolic::common::ByteView;
 use symbolicator_sources::{FileType, ObjectId, ObjectType, SourceConfig};
 
 use crate::caching::{
-    Cache, CacheEntry, CacheError, CacheItemRequest, CacheVersions, Cacher, SharedCacheRef,
+    Cache, CacheEntry, CacheError, CacheItemRequest, CacheMode, CacheVersions, Cacher,
+    SharedCacheRef,
 };
 use crate::services::objects::{
     FindObject, ObjectHandle, ObjectMetaHandle, ObjectPurpose, ObjectsActor,
@@ -130,6 +131,7 @@ impl CfiCacheActor {
                 sources: request.sources,
                 scope: request.scope,
                 purpose: ObjectPurpose::Unwind,
+                cache_mode: CacheMode::Use,
             })
             .await;
 
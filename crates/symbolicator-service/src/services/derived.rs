@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use crate::caching::{CacheEntry, CacheError};
+use symbolicator_sources::SourceId;
+
+use crate::caching::{CacheEntry, CacheError, CacheKey};
 use crate::services::objects::{FindResult, ObjectMetaHandle};
 use crate::types::{AllObjectCandidates, CandidateStatus, ObjectFeatures, ObjectUseInfo};
 
@@ -13,6 +15,17 @@ pub struct DerivedCache<T> {
     pub cache: CacheEntry<T>,
     pub candidates: AllObjectCandidates,
     pub features: ObjectFeatures,
+    /// The object file this cache was derived from, if it was derived successfully.
+    pub derived_from: Option<DerivedFrom>,
+}
+
+/// The object file a [`DerivedCache`] was derived from.
+#[derive(Clone, Debug)]
+pub struct DerivedFrom {
+    /// The id of the source the object file was found on.
+    pub source_id: SourceId,
+    /// The [`CacheKey`] of the object file.
+    pub cache_key: CacheKey,
 }
 
 /// Derives a [`DerivedCache`] from the provided object handle and derive function.
@@ -38,10 +51,11 @@ where
             cache: Err(CacheError::NotFound),
             candidates,
             features: ObjectFeatures::default(),
+            derived_from: None,
         };
     };
 
-    let (cache, object_info, features) = match meta.handle {
+    let (cache, object_info, features, derived_from) = match meta.handle {
         Ok(handle) => {
             // Fetch cache file from handle
             let derived_cache = derive(Arc::clone(&handle)).await;
@@ -57,7 +71,11 @@ where
                     details: e.to_string(),
                 },
             };
-            (derived_cache, object_info, handle.features())
+            let derived_from = derived_cache.is_ok().then(|| DerivedFrom {
+                source_id: meta.file_source.source_id().clone(),
+                cache_key: handle.cache_key(),
+            });
+            (derived_cache, object_info, handle.features(), derived_from)
         }
         Err(error) => {
             let object_info = match &error {
@@ -73,17 +91,18 @@ where
                 _ => ObjectUseInfo::Malformed,
             };
 
-            (Err(error), object_info, Default::default())
+            (Err(error), object_info, Default::default(), None)
         }
     };
 
     let source_id = meta.file_source.source_id();
-    let uri = meta.file_source.uri();
+    let uri = meta.file_source.redacted_uri();
     candidates.set_status(candidate_status, source_id, &uri, object_info);
 
     DerivedCache {
         cache,
         candidates,
         features,
+        derived_from,
     }
 }
@@ -13,11 +13,13 @@ use crate::caching::{CacheEntry, CacheError};
 
 /// Downloader implementation that supports the filesystem source.
 #[derive(Debug)]
-pub struct FilesystemDownloader {}
+pub struct FilesystemDownloader {
+    max_download_size: Option<u64>,
+}
 
 impl FilesystemDownloader {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(max_download_size: Option<u64>) -> Self {
+        Self { max_download_size }
     }
 
     /// Download from a filesystem source.
@@ -30,12 +32,15 @@ impl FilesystemDownloader {
         let abspath = file_source.path();
         tracing::debug!("Fetching debug file from {:?}", abspath);
 
-        fs::copy(abspath, dest)
-            .await
-            .map(|_| ())
-            .map_err(|e| match e.kind() {
-                io::ErrorKind::NotFound => CacheError::NotFound,
-                _ => e.into(),
-            })
+        let map_err = |e: io::Error| match e.kind() {
+            io::ErrorKind::NotFound => CacheError::NotFound,
+            _ => e.into(),
+        };
+
+        let max_download_size = file_source.source.files.max_download_size;
+        let size = fs::metadata(&abspath).await.map_err(map_err)?.len();
+        super::check_download_size(size, max_download_size.or(self.max_download_size))?;
+
+        fs::copy(abspath, dest).await.map(|_| ()).map_err(map_err)
     }
 }
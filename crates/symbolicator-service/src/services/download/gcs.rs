@@ -18,6 +18,8 @@ pub struct GcsDownloader {
     client: reqwest::Client,
     connect_timeout: std::time::Duration,
     streaming_timeout: std::time::Duration,
+    read_timeout: std::time::Duration,
+    max_download_size: Option<u64>,
 }
 
 impl GcsDownloader {
@@ -25,6 +27,8 @@ impl GcsDownloader {
         client: reqwest::Client,
         connect_timeout: std::time::Duration,
         streaming_timeout: std::time::Duration,
+        read_timeout: std::time::Duration,
+        max_download_size: Option<u64>,
         token_capacity: u64,
     ) -> Self {
         Self {
@@ -34,6 +38,8 @@ impl GcsDownloader {
             client,
             connect_timeout,
             streaming_timeout,
+            read_timeout,
+            max_download_size,
         }
     }
 
@@ -84,6 +90,8 @@ impl GcsDownloader {
             request,
             self.connect_timeout,
             self.streaming_timeout,
+            self.read_timeout,
+            self.max_download_size,
             destination,
         )
         .await
@@ -123,6 +131,8 @@ mod tests {
             Client::new(),
             std::time::Duration::from_secs(30),
             std::time::Duration::from_secs(30),
+            std::time::Duration::from_secs(30),
+            None,
             100.try_into().unwrap(),
         );
 
@@ -152,6 +162,8 @@ mod tests {
             Client::new(),
             std::time::Duration::from_secs(30),
             std::time::Duration::from_secs(30),
+            std::time::Duration::from_secs(30),
+            None,
             100.try_into().unwrap(),
         );
 
@@ -181,6 +193,8 @@ mod tests {
             Client::new(),
             std::time::Duration::from_secs(30),
             std::time::Duration::from_secs(30),
+            std::time::Duration::from_secs(30),
+            None,
             100.try_into().unwrap(),
         );
 
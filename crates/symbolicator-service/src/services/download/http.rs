@@ -5,40 +5,67 @@ use std::time::Duration;
 
 use reqwest::{header, Client};
 
-use symbolicator_sources::{HttpRemoteFile, RemoteFile};
+use symbolicator_sources::{redact_url, HttpRemoteFile, HttpSourceAuth, RemoteFile};
 
-use crate::caching::{CacheEntry, CacheError};
-
-use super::USER_AGENT;
+use crate::caching::{CacheEntry, CacheError, Revalidation, Validators};
 
 /// Downloader implementation that supports the HTTP source.
 #[derive(Debug)]
 pub struct HttpDownloader {
     client: Client,
+    /// The client for sources which bypass the proxy, if it differs from the `client`.
+    direct_client: Option<Client>,
     connect_timeout: Duration,
     streaming_timeout: Duration,
+    read_timeout: Duration,
+    max_download_size: Option<u64>,
 }
 
 impl HttpDownloader {
-    pub fn new(client: Client, connect_timeout: Duration, streaming_timeout: Duration) -> Self {
+    pub fn new(
+        client: Client,
+        connect_timeout: Duration,
+        streaming_timeout: Duration,
+        read_timeout: Duration,
+        max_download_size: Option<u64>,
+    ) -> Self {
         Self {
             client,
+            direct_client: None,
             connect_timeout,
             streaming_timeout,
+            read_timeout,
+            max_download_size,
         }
     }
 
+    /// Sets the client for sources which bypass the proxy of the default client.
+    pub fn with_direct_client(mut self, direct_client: Client) -> Self {
+        self.direct_client = Some(direct_client);
+        self
+    }
+
     /// Downloads a source hosted on an HTTP server.
+    ///
+    /// With `validators`, the request is conditional, and the file is not downloaded again if the
+    /// server reports that it was not modified.
     pub async fn download_source(
         &self,
         file_source: HttpRemoteFile,
         destination: &Path,
-    ) -> CacheEntry {
+        validators: Option<&Validators>,
+    ) -> CacheEntry<Revalidation> {
         let download_url = file_source.url().map_err(|_| CacheError::NotFound)?;
 
-        tracing::debug!("Fetching debug file from {}", download_url);
-        let mut builder = self.client.get(download_url.clone());
+        tracing::debug!("Fetching debug file from {}", redact_url(&download_url));
+        let client = match &self.direct_client {
+            Some(direct_client) if file_source.source.bypass_proxy => direct_client,
+            _ => &self.client,
+        };
+        let mut builder = client.get(download_url.clone());
 
+        // The static headers of the source take precedence over the default `User-Agent` of the
+        // client. They are not part of the cache key, just like the `auth` of the source.
         let headers = file_source
             .source
             .headers
@@ -50,14 +77,31 @@ impl HttpDownloader {
             }
         }
 
-        let request = builder.header(header::USER_AGENT, USER_AGENT);
+        builder = match &file_source.source.auth {
+            Some(HttpSourceAuth::Basic { username, password }) => {
+                builder.basic_auth(username, password.as_ref())
+            }
+            Some(HttpSourceAuth::Bearer { token }) => builder.bearer_auth(token),
+            None => builder,
+        };
+
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                builder = builder.header(header::IF_NONE_MATCH, etag.as_str());
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                builder = builder.header(header::IF_MODIFIED_SINCE, last_modified.as_str());
+            }
+        }
 
         let source = RemoteFile::from(file_source);
-        super::download_reqwest(
+        super::download_reqwest_conditional(
             &source,
-            request,
+            builder,
             self.connect_timeout,
             self.streaming_timeout,
+            self.read_timeout,
+            self.max_download_size,
             destination,
         )
         .await
@@ -68,9 +112,20 @@ impl HttpDownloader {
 mod tests {
     use super::*;
 
-    use symbolicator_sources::{SourceConfig, SourceLocation};
+    use std::sync::Arc;
 
+    use axum::body::StreamBody;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use futures::StreamExt;
+    use symbolicator_sources::{
+        CommonSourceConfig, HttpSourceConfig, SourceConfig, SourceId, SourceLocation,
+    };
+
+    use crate::caching::CacheKey;
     use crate::test;
+    use crate::types::Scope;
 
     #[tokio::test]
     async fn test_download_source() {
@@ -91,8 +146,10 @@ mod tests {
             Client::new(),
             Duration::from_secs(30),
             Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
         );
-        let download_status = downloader.download_source(file_source, dest).await;
+        let download_status = downloader.download_source(file_source, dest, None).await;
 
         assert!(download_status.is_ok());
 
@@ -119,9 +176,436 @@ mod tests {
             Client::new(),
             Duration::from_secs(30),
             Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
         );
-        let download_status = downloader.download_source(file_source, dest).await;
+        let download_status = downloader.download_source(file_source, dest, None).await;
 
         assert_eq!(download_status, Err(CacheError::NotFound));
     }
+
+    #[tokio::test]
+    async fn test_download_source_connect_timeout() {
+        test::setup();
+
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        let dest = tmpfile.path();
+
+        let server = test::Server::new();
+        let files = CommonSourceConfig {
+            connect_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let http_source = match server.source_with_config("delayed", "/delay/5s/symbols/", files) {
+            SourceConfig::Http(source) => source,
+            _ => panic!("unexpected source"),
+        };
+        let loc = SourceLocation::new("hello.txt");
+        let file_source = HttpRemoteFile::new(http_source, loc);
+
+        let downloader = HttpDownloader::new(
+            Client::new(),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+        );
+        let download_status = downloader.download_source(file_source, dest, None).await;
+
+        assert_eq!(
+            download_status,
+            Err(CacheError::Timeout(Duration::from_millis(100)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_source_read_timeout() {
+        test::setup();
+
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        let dest = tmpfile.path();
+
+        let router = Router::new().route(
+            "/stall/hello.txt",
+            get(|| async {
+                let first = futures::stream::once(async { Ok::<_, std::io::Error>("hello") });
+                let stalled = futures::stream::once(async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Ok(" world")
+                });
+                StreamBody::new(first.chain(stalled))
+            }),
+        );
+        let server = test::Server::with_router(router);
+        let files = CommonSourceConfig {
+            read_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let http_source = match server.source_with_config("stalling", "/stall/", files) {
+            SourceConfig::Http(source) => source,
+            _ => panic!("unexpected source"),
+        };
+        let loc = SourceLocation::new("hello.txt");
+        let file_source = HttpRemoteFile::new(http_source, loc);
+
+        let downloader = HttpDownloader::new(
+            Client::new(),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+        );
+        let download_status = downloader.download_source(file_source, dest, None).await;
+
+        assert_eq!(
+            download_status,
+            Err(CacheError::Timeout(Duration::from_millis(100)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_source_auth() {
+        test::setup();
+
+        let router = Router::new().route(
+            "/auth/hello.txt",
+            get(|headers: HeaderMap| async move {
+                let authorization = headers.get(header::AUTHORIZATION);
+                match authorization.and_then(|value| value.to_str().ok()) {
+                    // `Basic` with `user:pass`
+                    Some("Basic dXNlcjpwYXNz") | Some("Bearer secret-token") => Ok("hello world"),
+                    _ => Err(StatusCode::UNAUTHORIZED),
+                }
+            }),
+        );
+        let server = test::Server::with_router(router);
+
+        let downloader = HttpDownloader::new(
+            Client::new(),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+        );
+
+        let auths = [
+            (None, false),
+            (
+                Some(HttpSourceAuth::Basic {
+                    username: "user".into(),
+                    password: Some("pass".into()),
+                }),
+                true,
+            ),
+            (
+                Some(HttpSourceAuth::Bearer {
+                    token: "secret-token".into(),
+                }),
+                true,
+            ),
+            (
+                Some(HttpSourceAuth::Bearer {
+                    token: "wrong-token".into(),
+                }),
+                false,
+            ),
+        ];
+        for (auth, authorized) in auths {
+            let http_source = Arc::new(HttpSourceConfig {
+                id: SourceId::new("auth"),
+                url: server.url("/auth/"),
+                headers: Default::default(),
+                auth,
+                bypass_proxy: false,
+                files: Default::default(),
+            });
+            let file_source = HttpRemoteFile::new(http_source, SourceLocation::new("hello.txt"));
+
+            let tmpfile = tempfile::NamedTempFile::new().unwrap();
+            let download_status = downloader
+                .download_source(file_source, tmpfile.path(), None)
+                .await;
+
+            if authorized {
+                assert!(download_status.is_ok());
+            } else {
+                assert!(matches!(
+                    download_status,
+                    Err(CacheError::PermissionDenied(_))
+                ));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_source_headers() {
+        test::setup();
+
+        let router = Router::new().route(
+            "/headers/hello.txt",
+            get(|headers: HeaderMap| async move {
+                let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+                format!(
+                    "{} {}",
+                    header("user-agent").unwrap_or_default(),
+                    header("x-custom").unwrap_or_default()
+                )
+            }),
+        );
+        let server = test::Server::with_router(router);
+
+        let config = crate::config::Config {
+            user_agent: Some("custom-agent/1.0".into()),
+            ..Default::default()
+        };
+        let downloader = HttpDownloader::new(
+            crate::utils::http::create_client(&config, true),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+        );
+
+        let sources = [
+            (Default::default(), "custom-agent/1.0 "),
+            (
+                [("X-Custom".to_owned(), "custom-value".to_owned())].into(),
+                "custom-agent/1.0 custom-value",
+            ),
+            (
+                [("User-Agent".to_owned(), "source-agent/2.0".to_owned())].into(),
+                "source-agent/2.0 ",
+            ),
+        ];
+        for (headers, expected) in sources {
+            let http_source = Arc::new(HttpSourceConfig {
+                id: SourceId::new("headers"),
+                url: server.url("/headers/"),
+                headers,
+                auth: None,
+                bypass_proxy: false,
+                files: Default::default(),
+            });
+            let file_source = HttpRemoteFile::new(http_source, SourceLocation::new("hello.txt"));
+
+            let tmpfile = tempfile::NamedTempFile::new().unwrap();
+            let download_status = downloader
+                .download_source(file_source, tmpfile.path(), None)
+                .await;
+
+            assert!(download_status.is_ok());
+            let content = std::fs::read_to_string(tmpfile.path()).unwrap();
+            assert_eq!(content, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_source_proxy() {
+        test::setup();
+
+        let server = test::Server::with_router(
+            Router::new().route("/symbols/hello.txt", get(|| async { "direct" })),
+        );
+        let proxy = test::Server::with_router(
+            Router::new().route("/symbols/hello.txt", get(|| async { "proxied" })),
+        );
+
+        let config = crate::config::Config {
+            connect_to_reserved_ips: true,
+            proxy: crate::config::ProxyConfig {
+                url: Some(proxy.url("/")),
+                from_env: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let downloader = HttpDownloader::new(
+            crate::utils::http::create_client(&config, false),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+        )
+        .with_direct_client(crate::utils::http::create_direct_client(&config));
+
+        let mut cache_keys = vec![];
+        for (bypass_proxy, expected) in [(false, "proxied"), (true, "direct")] {
+            let http_source = Arc::new(HttpSourceConfig {
+                id: SourceId::new("proxied"),
+                url: server.url("/symbols/"),
+                headers: Default::default(),
+                auth: None,
+                bypass_proxy,
+                files: Default::default(),
+            });
+            let file_source = HttpRemoteFile::new(http_source, SourceLocation::new("hello.txt"));
+            let remote_file = RemoteFile::from(file_source.clone());
+            cache_keys.push(CacheKey::from_scoped_file(&Scope::Global, &remote_file));
+
+            let tmpfile = tempfile::NamedTempFile::new().unwrap();
+            let download_status = downloader
+                .download_source(file_source, tmpfile.path(), None)
+                .await;
+
+            assert!(download_status.is_ok());
+            let content = std::fs::read_to_string(tmpfile.path()).unwrap();
+            assert_eq!(content, expected);
+        }
+
+        assert_eq!(proxy.accesses(), 1);
+        assert_eq!(server.accesses(), 1);
+        // the proxy does not affect the cache key
+        assert_eq!(cache_keys[0], cache_keys[1]);
+    }
+
+    #[tokio::test]
+    async fn test_download_source_max_size() {
+        test::setup();
+
+        let router = Router::new()
+            .route("/sized/hello.txt", get(|| async { "hello world" }))
+            .route(
+                "/streamed/hello.txt",
+                get(|| async {
+                    let chunks =
+                        futures::stream::iter(["hello", " world"]).map(Ok::<_, std::io::Error>);
+                    let stalled = futures::stream::once(async {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        Ok("!")
+                    });
+                    StreamBody::new(chunks.chain(stalled))
+                }),
+            );
+        let server = test::Server::with_router(router);
+
+        let downloader = HttpDownloader::new(
+            Client::new(),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Some(8),
+        );
+        let download = |path, max_download_size| {
+            let http_source = Arc::new(HttpSourceConfig {
+                id: SourceId::new("sized"),
+                url: server.url(path),
+                headers: Default::default(),
+                auth: None,
+                bypass_proxy: false,
+                files: CommonSourceConfig {
+                    max_download_size,
+                    ..Default::default()
+                },
+            });
+            let file_source = HttpRemoteFile::new(http_source, SourceLocation::new("hello.txt"));
+            let downloader = &downloader;
+            async move {
+                let tmpfile = tempfile::NamedTempFile::new().unwrap();
+                let status = downloader
+                    .download_source(file_source, tmpfile.path(), None)
+                    .await;
+                (status, std::fs::metadata(tmpfile.path()).unwrap().len())
+            }
+        };
+        let too_large = Err(CacheError::Malformed(
+            "download exceeds the maximum size of 8 bytes".into(),
+        ));
+
+        // the `Content-Length` exceeds the limit, so nothing is downloaded at all
+        let (status, size) = download("/sized/", None).await;
+        assert_eq!(status, too_large);
+        assert_eq!(size, 0);
+
+        // without a `Content-Length`, the download is aborted once it exceeds the limit
+        let (status, _) = download("/streamed/", None).await;
+        assert_eq!(status, too_large);
+
+        // the source can raise the limit
+        let (status, size) = download("/sized/", Some(100)).await;
+        assert!(status.is_ok());
+        assert_eq!(size, 11);
+    }
+
+    #[tokio::test]
+    async fn test_download_source_conditional() {
+        test::setup();
+
+        let router = Router::new().route(
+            "/conditional/hello.txt",
+            get(|headers: HeaderMap| async move {
+                let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+                let validators = [
+                    (header::ETAG, "\"v1\""),
+                    (header::LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT"),
+                ];
+                let not_modified = header("if-none-match") == Some("\"v1\"")
+                    || header("if-modified-since") == Some("Wed, 21 Oct 2015 07:28:00 GMT");
+                if not_modified {
+                    (StatusCode::NOT_MODIFIED, validators, "")
+                } else {
+                    (StatusCode::OK, validators, "hello world")
+                }
+            }),
+        );
+        let server = test::Server::with_router(router);
+
+        let downloader = HttpDownloader::new(
+            Client::new(),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+        );
+        let http_source = Arc::new(HttpSourceConfig {
+            id: SourceId::new("conditional"),
+            url: server.url("/conditional/"),
+            headers: Default::default(),
+            auth: None,
+            bypass_proxy: false,
+            files: Default::default(),
+        });
+        let download = |validators| {
+            let file_source =
+                HttpRemoteFile::new(http_source.clone(), SourceLocation::new("hello.txt"));
+            let downloader = &downloader;
+            async move {
+                let tmpfile = tempfile::NamedTempFile::new().unwrap();
+                let status = downloader
+                    .download_source(file_source, tmpfile.path(), validators)
+                    .await;
+                (status, std::fs::read_to_string(tmpfile.path()).unwrap())
+            }
+        };
+
+        let expected = Validators {
+            etag: Some("\"v1\"".into()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+        };
+
+        // without validators, the file is downloaded along with its validators
+        let (status, content) = download(None).await;
+        assert_eq!(status, Ok(Revalidation::Modified(Some(expected.clone()))));
+        assert_eq!(content, "hello world");
+
+        // matching validators are answered with `304 Not Modified`, without any download
+        let (status, content) = download(Some(&expected)).await;
+        assert_eq!(status, Ok(Revalidation::NotModified));
+        assert_eq!(content, "");
+
+        let etag_only = Validators {
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+        };
+        let (status, _) = download(Some(&etag_only)).await;
+        assert_eq!(status, Ok(Revalidation::NotModified));
+
+        // outdated validators download the file again
+        let outdated = Validators {
+            etag: Some("\"v0\"".into()),
+            last_modified: Some("Tue, 20 Oct 2015 07:28:00 GMT".into()),
+        };
+        let (status, content) = download(Some(&outdated)).await;
+        assert_eq!(status, Ok(Revalidation::Modified(Some(expected))));
+        assert_eq!(content, "hello world");
+    }
 }
@@ -16,6 +16,8 @@ use futures::prelude::*;
 use reqwest::StatusCode;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
 
 pub use symbolicator_sources::{
     DirectoryLayout, FileType, ObjectId, ObjectType, RemoteFile, RemoteFileUri, SourceConfig,
@@ -26,7 +28,7 @@ use symbolicator_sources::{
     SourceLocationIter,
 };
 
-use crate::caching::{CacheEntry, CacheError};
+use crate::caching::{CacheEntry, CacheError, Revalidation, Validators};
 use crate::config::{CacheConfigs, Config, InMemoryCacheConfig};
 use crate::utils::futures::{m, measure, CancelOnDrop};
 use crate::utils::gcs::GcsError;
@@ -45,13 +47,10 @@ impl ConfigureScope for RemoteFile {
         scope.set_tag("source.id", self.source_id());
         scope.set_tag("source.type", self.source_metric_key());
         scope.set_tag("source.is_public", self.is_public());
-        scope.set_tag("source.uri", self.uri());
+        scope.set_tag("source.uri", self.redacted_uri());
     }
 }
 
-/// HTTP User-Agent string to use.
-const USER_AGENT: &str = concat!("symbolicator/", env!("CARGO_PKG_VERSION"));
-
 impl CacheError {
     fn download_error(mut error: &dyn Error) -> Self {
         while let Some(src) = error.source() {
@@ -218,6 +217,8 @@ impl HostDenyList {
 pub struct DownloadService {
     runtime: tokio::runtime::Handle,
     max_download_timeout: Duration,
+    max_download_retries: usize,
+    download_permits: Option<Semaphore>,
     sentry: sentry::SentryDownloader,
     http: http::HttpDownloader,
     s3: s3::S3Downloader,
@@ -231,10 +232,13 @@ impl DownloadService {
     pub fn new(config: &Config, runtime: tokio::runtime::Handle) -> Arc<Self> {
         let trusted_client = crate::utils::http::create_client(config, true);
         let restricted_client = crate::utils::http::create_client(config, false);
+        let direct_client = crate::utils::http::create_direct_client(config);
 
         let Config {
             connect_timeout,
             streaming_timeout,
+            read_timeout,
+            max_download_size,
             caches: CacheConfigs { ref in_memory, .. },
             deny_list_time_window,
             deny_list_bucket_size,
@@ -252,20 +256,33 @@ impl DownloadService {
         Arc::new(Self {
             runtime: runtime.clone(),
             max_download_timeout: config.max_download_timeout,
+            max_download_retries: config.max_download_retries,
+            download_permits: config.max_concurrent_downloads.map(Semaphore::new),
             sentry: sentry::SentryDownloader::new(trusted_client, runtime, config),
             http: http::HttpDownloader::new(
                 restricted_client.clone(),
                 connect_timeout,
                 streaming_timeout,
+                read_timeout,
+                max_download_size,
+            )
+            .with_direct_client(direct_client),
+            s3: s3::S3Downloader::new(
+                connect_timeout,
+                streaming_timeout,
+                read_timeout,
+                max_download_size,
+                *s3_client_capacity,
             ),
-            s3: s3::S3Downloader::new(connect_timeout, streaming_timeout, *s3_client_capacity),
             gcs: gcs::GcsDownloader::new(
                 restricted_client,
                 connect_timeout,
                 streaming_timeout,
+                read_timeout,
+                max_download_size,
                 *gcs_token_capacity,
             ),
-            fs: filesystem::FilesystemDownloader::new(),
+            fs: filesystem::FilesystemDownloader::new(max_download_size),
             host_deny_list: HostDenyList::new(
                 deny_list_time_window,
                 deny_list_bucket_size,
@@ -276,16 +293,25 @@ impl DownloadService {
     }
 
     /// Dispatches downloading of the given file to the appropriate source.
-    async fn dispatch_download(&self, source: &RemoteFile, destination: &Path) -> CacheEntry {
-        let result = retry(|| async {
-            match source {
+    async fn dispatch_download(
+        &self,
+        source: &RemoteFile,
+        destination: &Path,
+        validators: Option<&Validators>,
+    ) -> CacheEntry<Revalidation> {
+        let result = retry(self.max_download_retries, || async {
+            let downloaded = match source {
                 RemoteFile::Sentry(inner) => {
                     self.sentry
                         .download_source(inner.clone(), destination)
                         .await
                 }
+                // Only HTTP sources are revalidated with conditional requests.
                 RemoteFile::Http(inner) => {
-                    self.http.download_source(inner.clone(), destination).await
+                    return self
+                        .http
+                        .download_source(inner.clone(), destination, validators)
+                        .await
                 }
                 RemoteFile::S3(inner) => self.s3.download_source(inner.clone(), destination).await,
                 RemoteFile::Gcs(inner) => {
@@ -294,7 +320,8 @@ impl DownloadService {
                 RemoteFile::Filesystem(inner) => {
                     self.fs.download_source(inner.clone(), destination).await
                 }
-            }
+            };
+            downloaded.map(|()| Revalidation::Modified(None))
         });
 
         let result = result.await;
@@ -320,6 +347,23 @@ impl DownloadService {
         source: RemoteFile,
         destination: PathBuf,
     ) -> CacheEntry {
+        self.download_conditional(source, destination, None)
+            .await
+            .map(|_| ())
+    }
+
+    /// Download a file from a source like [`download`](Self::download), unless it was not
+    /// modified since it was downloaded with the given `validators`.
+    ///
+    /// For sources supporting conditional requests, [`Revalidation::NotModified`] is returned
+    /// without writing to `destination` if the file is unchanged. Otherwise, the file is
+    /// downloaded along with its new validators.
+    pub async fn download_conditional(
+        self: &Arc<Self>,
+        source: RemoteFile,
+        destination: PathBuf,
+        validators: Option<Validators>,
+    ) -> CacheEntry<Revalidation> {
         let host = source.host();
 
         // Check whether `source` is an internal Sentry source. We don't ever
@@ -338,10 +382,28 @@ impl DownloadService {
             ));
         }
 
+        // Wait for our turn in case too many downloads are running concurrently.
+        let _permit = match self.download_permits {
+            Some(ref permits) => {
+                let queued_at = Instant::now();
+                let permit = permits
+                    .acquire()
+                    .await
+                    .map_err(|_| CacheError::InternalError)?;
+                metric!(timer("service.download.queue_wait") = queued_at.elapsed());
+                Some(permit)
+            }
+            None => None,
+        };
+
         let slf = self.clone();
 
-        let job = async move { slf.dispatch_download(&source, &destination).await };
-        let job = CancelOnDrop::new(self.runtime.spawn(job.bind_hub(::sentry::Hub::current())));
+        let job = async move {
+            slf.dispatch_download(&source, &destination, validators.as_ref())
+                .await
+        };
+        let job = job.in_current_span().bind_hub(::sentry::Hub::current());
+        let job = CancelOnDrop::new(self.runtime.spawn(job));
         let job = tokio::time::timeout(self.max_download_timeout, job);
         let job = measure("service.download", m::timed_result, job);
 
@@ -353,6 +415,10 @@ impl DownloadService {
             Ok(Ok(res)) => res,
         };
 
+        if matches!(result, Err(CacheError::Timeout(_))) {
+            metric!(counter("service.download.timeout") += 1, "source" => &source_metric_key);
+        }
+
         if source_is_external
             && matches!(
                 result,
@@ -465,40 +531,80 @@ impl DownloadService {
     }
 }
 
-/// Try to run a future up to 3 times with 20 millisecond delays on failure.
-pub async fn retry<G, F, T>(mut task_gen: G) -> CacheEntry<T>
+/// The delay before the first retry of a failed download, doubled for every subsequent retry.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(20);
+
+/// Try to run a future, retrying it up to `max_retries` times on transient failures.
+///
+/// Only the failures for which [`is_transient_download_error`] holds are retried. Retries use an
+/// exponential backoff with jitter, see [`retry_delay`].
+pub async fn retry<G, F, T>(max_retries: usize, mut task_gen: G) -> CacheEntry<T>
 where
     G: FnMut() -> F,
     F: Future<Output = CacheEntry<T>>,
 {
-    let mut tries = 0;
+    let mut retries = 0;
     loop {
-        tries += 1;
         let result = task_gen().await;
 
-        // its highly unlikely we get a different result when retrying these
-        let should_not_retry = matches!(
-            result,
-            Ok(_) | Err(CacheError::NotFound | CacheError::PermissionDenied(_))
-        );
+        let should_retry = matches!(&result, Err(err) if is_transient_download_error(err));
 
-        if should_not_retry || tries >= 3 {
+        if !should_retry || retries >= max_retries {
             break result;
         }
 
-        tokio::time::sleep(Duration::from_millis(20)).await;
+        tokio::time::sleep(retry_delay(retries)).await;
+        retries += 1;
     }
 }
 
+/// Whether a failed download may succeed when retrying it.
+///
+/// These are timeouts, connection errors and `5xx` responses. Client errors, exceeded size or
+/// redirect limits and malformed files fail the same way again.
+fn is_transient_download_error(error: &CacheError) -> bool {
+    match error {
+        CacheError::Timeout(_) => true,
+        // The details are either a status code, or the error of the client.
+        CacheError::DownloadError(details) => {
+            let status = details.split(' ').next().and_then(|s| s.parse().ok());
+            match status.and_then(|status| StatusCode::from_u16(status).ok()) {
+                Some(status) => status.is_server_error(),
+                // see `RedirectError`
+                None => !details.contains("redirect"),
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Computes the delay before the given (zero-based) retry.
+///
+/// The delay is [`RETRY_BASE_DELAY`] doubled for every previous retry, plus a random jitter of
+/// up to half of that.
+fn retry_delay(retry: usize) -> Duration {
+    let delay = RETRY_BASE_DELAY.saturating_mul(1 << retry.min(16));
+    let jitter = rand::random::<f64>() * 0.5;
+    delay + delay.mul_f64(jitter)
+}
+
 /// Download the source from a stream.
 ///
 /// This is common functionality used by many downloaders.
+///
+/// The download fails with a timeout if it overall takes longer than `timeout`, or if no new data
+/// arrives for `read_timeout`, unless the source of the file overrides it. It is aborted once
+/// more than `max_download_size` bytes arrive, which the source of the file can override as well.
 async fn download_stream(
     source: &RemoteFile,
     stream: impl Stream<Item = Result<impl AsRef<[u8]>, CacheError>>,
     destination: &Path,
     timeout: Option<Duration>,
+    read_timeout: Duration,
+    max_download_size: Option<u64>,
 ) -> CacheEntry {
+    let read_timeout = source.read_timeout().unwrap_or(read_timeout);
+    let max_download_size = source.max_download_size().or(max_download_size);
     // All file I/O in this function is blocking!
     tracing::trace!("Downloading from {}", source);
     let future = async {
@@ -508,10 +614,19 @@ async fn download_stream(
         let mut throughput_recorder =
             MeasureSourceDownloadGuard::new("source.download.stream", source.source_metric_key());
         let result: CacheEntry = async {
-            while let Some(chunk) = stream.next().await {
+            let mut size = 0;
+            loop {
+                let chunk = tokio::time::timeout(read_timeout, stream.next())
+                    .await
+                    .map_err(|_| CacheError::Timeout(read_timeout))?;
+                let Some(chunk) = chunk else {
+                    break;
+                };
                 let chunk = chunk?;
                 let chunk = chunk.as_ref();
                 throughput_recorder.add_bytes_transferred(chunk.len() as u64);
+                size += chunk.len() as u64;
+                check_download_size(size, max_download_size)?;
                 file.write_all(chunk).await?;
             }
             Ok(())
@@ -532,13 +647,65 @@ async fn download_stream(
     }
 }
 
+/// Fails if a download of `size` bytes exceeds the `max_download_size`.
+///
+/// Files exceeding the limit are treated as malformed, so that they are neither retried nor
+/// downloaded again until the cached error expires.
+fn check_download_size(size: u64, max_download_size: Option<u64>) -> CacheEntry {
+    match max_download_size {
+        Some(max_download_size) if size > max_download_size => {
+            metric!(counter("service.download.too_large") += 1);
+            Err(CacheError::Malformed(format!(
+                "download exceeds the maximum size of {max_download_size} bytes"
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
 async fn download_reqwest(
     source: &RemoteFile,
     builder: reqwest::RequestBuilder,
     connect_timeout: Duration,
     streaming_timeout: Duration,
+    read_timeout: Duration,
+    max_download_size: Option<u64>,
     destination: &Path,
 ) -> CacheEntry {
+    let downloaded = download_reqwest_conditional(
+        source,
+        builder,
+        connect_timeout,
+        streaming_timeout,
+        read_timeout,
+        max_download_size,
+        destination,
+    )
+    .await?;
+
+    match downloaded {
+        Revalidation::Modified(_) => Ok(()),
+        // only conditional requests may be answered with `304 Not Modified`
+        Revalidation::NotModified => Err(CacheError::DownloadError(
+            StatusCode::NOT_MODIFIED.to_string(),
+        )),
+    }
+}
+
+/// Downloads the response of a conditional request, see [`download_reqwest`].
+///
+/// Returns [`Revalidation::NotModified`] for a `304 Not Modified` response, and the
+/// [`Validators`] of the downloaded file otherwise.
+async fn download_reqwest_conditional(
+    source: &RemoteFile,
+    builder: reqwest::RequestBuilder,
+    connect_timeout: Duration,
+    streaming_timeout: Duration,
+    read_timeout: Duration,
+    max_download_size: Option<u64>,
+    destination: &Path,
+) -> CacheEntry<Revalidation> {
+    let connect_timeout = source.connect_timeout().unwrap_or(connect_timeout);
     let request = builder.send();
 
     let request = tokio::time::timeout(connect_timeout, request);
@@ -557,10 +724,38 @@ async fn download_reqwest(
             .and_then(|hv| hv.to_str().ok())
             .and_then(|s| s.parse::<i64>().ok());
 
+        if let Some(content_length) = content_length {
+            let max_download_size = source.max_download_size().or(max_download_size);
+            check_download_size(content_length.max(0) as u64, max_download_size)?;
+        }
+
+        let header = |name: reqwest::header::HeaderName| {
+            let value = response.headers().get(name)?.to_str().ok()?;
+            Some(value.to_owned())
+        };
+        let validators = Validators {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        };
+
         let timeout = content_length.map(|cl| content_length_timeout(cl, streaming_timeout));
         let stream = response.bytes_stream().map_err(CacheError::from);
 
-        download_stream(source, stream, destination, timeout).await
+        download_stream(
+            source,
+            stream,
+            destination,
+            timeout,
+            read_timeout,
+            max_download_size,
+        )
+        .await?;
+
+        Ok(Revalidation::Modified(Some(validators)))
+    } else if status == StatusCode::NOT_MODIFIED {
+        tracing::trace!("`{}` was not modified", source);
+
+        Ok(Revalidation::NotModified)
     } else if matches!(status, StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED) {
         tracing::debug!(
             "Insufficient permissions to download `{}`: {}",
@@ -711,12 +906,46 @@ mod tests {
     // Actual implementation is tested in the sub-modules, this only needs to
     // ensure the service interface works correctly.
 
-    use symbolicator_sources::{HttpRemoteFile, ObjectType, SourceConfig};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::extract;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use symbolicator_sources::{
+        CommonSourceConfig, DirectoryLayoutType, HttpRemoteFile, ObjectType, SourceConfig,
+    };
 
     use super::*;
 
     use crate::test;
 
+    /// Creates a server responding with the given status codes in order, and with `hello world`
+    /// afterwards.
+    fn flaky_server(statuses: &'static [StatusCode]) -> (test::Server, RemoteFile) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route(
+            "/flaky/hello.txt",
+            get(move || {
+                let requests = requests.clone();
+                async move {
+                    match statuses.get(requests.fetch_add(1, Ordering::SeqCst)) {
+                        Some(status) => Err(*status),
+                        None => Ok("hello world"),
+                    }
+                }
+            }),
+        );
+        let server = test::Server::with_router(router);
+        let file_source = match server.source("flaky", "/flaky/") {
+            SourceConfig::Http(source) => {
+                HttpRemoteFile::new(source, SourceLocation::new("hello.txt")).into()
+            }
+            _ => panic!("unexpected source"),
+        };
+        (server, file_source)
+    }
+
     #[tokio::test]
     async fn test_download() {
         test::setup();
@@ -747,6 +976,190 @@ mod tests {
         assert_eq!(content, "hello world\n")
     }
 
+    #[tokio::test]
+    async fn test_download_retry_transient() {
+        test::setup();
+
+        let (server, file_source) = flaky_server(&[
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ]);
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            max_download_retries: 2,
+            ..Config::default()
+        };
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        service
+            .download(file_source, temp_file.path().to_owned())
+            .await
+            .unwrap();
+
+        let content = std::fs::read_to_string(temp_file.path()).unwrap();
+        assert_eq!(content, "hello world");
+        assert_eq!(server.accesses(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_download_retry_exhausted() {
+        test::setup();
+
+        let (server, file_source) =
+            flaky_server(&[StatusCode::INTERNAL_SERVER_ERROR, StatusCode::BAD_GATEWAY]);
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            max_download_retries: 1,
+            ..Config::default()
+        };
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let result = service
+            .download(file_source, temp_file.path().to_owned())
+            .await;
+
+        assert_eq!(
+            result,
+            Err(CacheError::DownloadError("502 Bad Gateway".into()))
+        );
+        assert_eq!(server.accesses(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_download_no_retry_not_found() {
+        test::setup();
+
+        let (server, file_source) = flaky_server(&[StatusCode::NOT_FOUND]);
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            max_download_retries: 2,
+            ..Config::default()
+        };
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let result = service
+            .download(file_source, temp_file.path().to_owned())
+            .await;
+
+        assert_eq!(result, Err(CacheError::NotFound));
+        assert_eq!(server.accesses(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_download_no_retry_too_large() {
+        test::setup();
+
+        let (server, file_source) = flaky_server(&[]);
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            max_download_retries: 2,
+            max_download_size: Some(5),
+            ..Config::default()
+        };
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let result = service
+            .download(file_source, temp_file.path().to_owned())
+            .await;
+
+        assert!(matches!(result, Err(CacheError::Malformed(_))));
+        assert_eq!(server.accesses(), 1);
+    }
+
+    #[test]
+    fn test_is_transient_download_error() {
+        assert!(is_transient_download_error(&CacheError::Timeout(
+            Duration::from_secs(1)
+        )));
+        assert!(is_transient_download_error(&CacheError::DownloadError(
+            "502 Bad Gateway".into()
+        )));
+        assert!(is_transient_download_error(&CacheError::DownloadError(
+            "error trying to connect: Connection refused".into()
+        )));
+
+        assert!(!is_transient_download_error(&CacheError::NotFound));
+        assert!(!is_transient_download_error(&CacheError::DownloadError(
+            "400 Bad Request".into()
+        )));
+        assert!(!is_transient_download_error(&CacheError::DownloadError(
+            "too many redirects, the maximum is 2".into()
+        )));
+        assert!(!is_transient_download_error(&CacheError::Malformed(
+            "download exceeds the maximum size of 5 bytes".into()
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_downloads() {
+        test::setup();
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let router = {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            Router::new().route(
+                "/slow/hello.txt",
+                get(move || {
+                    let in_flight = in_flight.clone();
+                    let max_in_flight = max_in_flight.clone();
+                    async move {
+                        let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_in_flight.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        "hello world"
+                    }
+                }),
+            )
+        };
+        let server = test::Server::with_router(router);
+        let file_source: RemoteFile = match server.source("slow", "/slow/") {
+            SourceConfig::Http(source) => {
+                HttpRemoteFile::new(source, SourceLocation::new("hello.txt")).into()
+            }
+            _ => panic!("unexpected source"),
+        };
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            max_concurrent_downloads: Some(2),
+            ..Config::default()
+        };
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+
+        let temp_dir = test::tempdir();
+        let downloads = (0..10)
+            .map(|i| service.download(file_source.clone(), temp_dir.path().join(i.to_string())));
+        let results = future::join_all(downloads).await;
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(server.accesses(), 10);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        for retry in 0..4 {
+            let base = RETRY_BASE_DELAY * 2u32.pow(retry as u32);
+            let delay = retry_delay(retry);
+            assert!(delay >= base);
+            assert!(delay <= base.mul_f64(1.5));
+        }
+
+        // large retry counts do not overflow
+        retry_delay(1000);
+    }
+
     #[tokio::test]
     async fn test_list_files() {
         test::setup();
@@ -772,6 +1185,82 @@ mod tests {
         assert_eq!(item.source_id(), source.id());
     }
 
+    #[tokio::test]
+    async fn test_debuginfod_source() {
+        test::setup();
+
+        const BUILD_ID: &str = "dfb85de42daffd09640c8fe377d572de3e168920";
+
+        // a mocked debuginfod server, which only knows about a single build id
+        let router = Router::new().route(
+            "/buildid/:build_id/:kind",
+            get(
+                |extract::Path((build_id, kind)): extract::Path<(String, String)>| async move {
+                    if build_id == BUILD_ID {
+                        Ok(kind)
+                    } else {
+                        Err(StatusCode::NOT_FOUND)
+                    }
+                },
+            ),
+        );
+        let server = test::Server::with_router(router);
+        let files = CommonSourceConfig::with_layout(DirectoryLayoutType::Debuginfod);
+        let source = server.source_with_config("debuginfod", "/buildid/", files);
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            ..Config::default()
+        };
+        let service = DownloadService::new(&config, tokio::runtime::Handle::current());
+        let filetypes = [FileType::ElfDebug, FileType::ElfCode];
+
+        let objid = ObjectId {
+            code_id: Some(BUILD_ID.parse().unwrap()),
+            object_type: ObjectType::Elf,
+            ..Default::default()
+        };
+        let file_list = service
+            .list_files(&[source.clone()], &filetypes, &objid)
+            .await;
+        let uris: Vec<_> = file_list
+            .iter()
+            .map(|file| file.uri().to_string())
+            .collect();
+        assert_eq!(
+            uris,
+            [
+                server
+                    .url(&format!("/buildid/{BUILD_ID}/debuginfo"))
+                    .to_string(),
+                server
+                    .url(&format!("/buildid/{BUILD_ID}/executable"))
+                    .to_string(),
+            ]
+        );
+
+        let temp_dir = test::tempdir();
+        for file in file_list {
+            let dest = temp_dir.path().join("file");
+            service.download(file, dest.clone()).await.unwrap();
+            let content = std::fs::read_to_string(dest).unwrap();
+            assert!(content == "debuginfo" || content == "executable");
+        }
+
+        // unknown build ids are not found on this source
+        let objid = ObjectId {
+            code_id: Some("0123456789abcdef0123456789abcdef01234567".parse().unwrap()),
+            object_type: ObjectType::Elf,
+            ..Default::default()
+        };
+        let file_list = service.list_files(&[source], &filetypes, &objid).await;
+        for file in file_list {
+            let dest = temp_dir.path().join("file");
+            let result = service.download(file, dest).await;
+            assert_eq!(result, Err(CacheError::NotFound));
+        }
+    }
+
     #[test]
     fn test_content_length_timeout() {
         let timeout_per_gb = Duration::from_secs(30);
@@ -29,6 +29,8 @@ pub struct S3Downloader {
     client_cache: ClientCache,
     connect_timeout: Duration,
     streaming_timeout: Duration,
+    read_timeout: Duration,
+    max_download_size: Option<u64>,
 }
 
 impl fmt::Debug for S3Downloader {
@@ -36,6 +38,8 @@ impl fmt::Debug for S3Downloader {
         f.debug_struct(type_name::<Self>())
             .field("connect_timeout", &self.connect_timeout)
             .field("streaming_timeout", &self.streaming_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("max_download_size", &self.max_download_size)
             .finish()
     }
 }
@@ -44,12 +48,16 @@ impl S3Downloader {
     pub fn new(
         connect_timeout: Duration,
         streaming_timeout: Duration,
+        read_timeout: Duration,
+        max_download_size: Option<u64>,
         s3_client_capacity: u64,
     ) -> Self {
         Self {
             client_cache: ClientCache::new(s3_client_capacity),
             connect_timeout,
             streaming_timeout,
+            read_timeout,
+            max_download_size,
         }
     }
 
@@ -125,12 +133,13 @@ impl S3Downloader {
         let request = client.get_object().bucket(&bucket).key(&key).send();
 
         let source = RemoteFile::from(file_source);
-        let request = tokio::time::timeout(self.connect_timeout, request);
+        let connect_timeout = source.connect_timeout().unwrap_or(self.connect_timeout);
+        let request = tokio::time::timeout(connect_timeout, request);
         let request = super::measure_download_time(source.source_metric_key(), request);
 
         let response = request
             .await
-            .map_err(|_| CacheError::Timeout(self.connect_timeout))?; // Timeout
+            .map_err(|_| CacheError::Timeout(connect_timeout))?; // Timeout
 
         let response = match response {
             Ok(response) => response,
@@ -188,6 +197,9 @@ impl S3Downloader {
             }
         };
 
+        let max_download_size = source.max_download_size().or(self.max_download_size);
+        super::check_download_size(response.content_length().max(0) as u64, max_download_size)?;
+
         let timeout = Some(content_length_timeout(
             response.content_length(),
             self.streaming_timeout,
@@ -202,7 +214,15 @@ impl S3Downloader {
                 .map_err(|err| CacheError::download_error(&err))
         };
 
-        super::download_stream(&source, stream, destination, timeout).await
+        super::download_stream(
+            &source,
+            stream,
+            destination,
+            timeout,
+            self.read_timeout,
+            self.max_download_size,
+        )
+        .await
     }
 }
 
@@ -363,7 +383,13 @@ mod tests {
         setup_bucket(source_key.clone()).await;
 
         let source = s3_source(source_key);
-        let downloader = S3Downloader::new(Duration::from_secs(30), Duration::from_secs(30), 100);
+        let downloader = S3Downloader::new(
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+            100,
+        );
 
         let tempdir = test::tempdir();
         let target_path = tempdir.path().join("myfile");
@@ -389,7 +415,13 @@ mod tests {
         setup_bucket(source_key.clone()).await;
 
         let source = s3_source(source_key);
-        let downloader = S3Downloader::new(Duration::from_secs(30), Duration::from_secs(30), 100);
+        let downloader = S3Downloader::new(
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+            100,
+        );
 
         let tempdir = test::tempdir();
         let target_path = tempdir.path().join("myfile");
@@ -414,7 +446,13 @@ mod tests {
             secret_key: "".into(),
         };
         let source = s3_source(broken_key);
-        let downloader = S3Downloader::new(Duration::from_secs(30), Duration::from_secs(30), 100);
+        let downloader = S3Downloader::new(
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            Duration::from_secs(30),
+            None,
+            100,
+        );
 
         let tempdir = test::tempdir();
         let target_path = tempdir.path().join("myfile");
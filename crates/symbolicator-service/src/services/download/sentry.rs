@@ -15,10 +15,11 @@ use serde::Deserialize;
 use url::Url;
 
 use symbolicator_sources::{
-    HttpRemoteFile, ObjectId, RemoteFile, SentryFileId, SentryRemoteFile, SentrySourceConfig,
+    redact_url, HttpRemoteFile, ObjectId, RemoteFile, SentryFileId, SentryRemoteFile,
+    SentrySourceConfig,
 };
 
-use super::{FileType, USER_AGENT};
+use super::FileType;
 use crate::caching::{CacheEntry, CacheError};
 use crate::config::Config;
 use crate::utils::futures::CancelOnDrop;
@@ -30,6 +31,15 @@ struct SearchResult {
     symbol_type: SentryFileType,
 }
 
+/// The maximum number of pages that are fetched for a single Sentry API query.
+const MAX_PAGES: usize = 10;
+
+/// The maximum number of times a rate limited Sentry API request is retried.
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+
+/// The maximum duration to wait for before retrying a rate limited Sentry API request.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
 /// The is almost the same as [`FileType`], except it does not treat MachO, Elf and Wasm
 /// Code / Debug differently.
 /// All the formats Sentry itself knows about are listed here:
@@ -130,6 +140,9 @@ pub struct SentryDownloader {
     js_cache: SentryJsCache,
     connect_timeout: Duration,
     streaming_timeout: Duration,
+    read_timeout: Duration,
+    max_download_size: Option<u64>,
+    max_download_retries: usize,
 }
 
 impl fmt::Debug for SentryDownloader {
@@ -158,10 +171,17 @@ impl SentryDownloader {
             js_cache,
             connect_timeout: config.connect_timeout,
             streaming_timeout: config.streaming_timeout,
+            read_timeout: config.read_timeout,
+            max_download_size: config.max_download_size,
+            max_download_retries: config.max_download_retries,
         }
     }
 
     /// Make a request to sentry, parse the result as a JSON SearchResult list.
+    ///
+    /// This follows the pagination `Link` headers returned by the Sentry API, up to [`MAX_PAGES`]
+    /// pages. Pages on a different origin than the `index_url` are not followed, as the token
+    /// would be sent to them.
     #[tracing::instrument(skip_all)]
     async fn fetch_sentry_json<T>(
         client: &reqwest::Client,
@@ -170,26 +190,81 @@ impl SentryDownloader {
     where
         T: DeserializeOwned,
     {
-        let mut request = client
-            .get(query.index_url.clone())
-            .bearer_auth(&query.token)
-            .header("Accept-Encoding", "identity")
-            .header("User-Agent", USER_AGENT);
-        if let Some(span) = sentry::configure_scope(|scope| scope.get_span()) {
-            for (k, v) in span.iter_headers() {
-                request = request.header(k, v);
+        let mut results = Vec::new();
+        let mut page_url = Some(query.index_url.clone());
+        let mut pages = 0;
+
+        while let Some(url) = page_url.take() {
+            let response = Self::send_sentry_request(client, url.clone(), &query.token).await?;
+            page_url = next_page_url(&url, response.headers());
+            if let Some(next_url) = &page_url {
+                if next_url.origin() != query.index_url.origin() {
+                    tracing::warn!(
+                        query = %redact_url(&query.index_url),
+                        next = %redact_url(next_url),
+                        "Sentry API returned a next page on a different origin"
+                    );
+                    page_url = None;
+                }
+            }
+
+            let page: Vec<T> = response.json().await?;
+            results.extend(page);
+
+            pages += 1;
+            if pages >= MAX_PAGES && page_url.is_some() {
+                tracing::error!(query = %redact_url(&query.index_url), "Sentry API Query exceeded {MAX_PAGES} pages");
+                break;
             }
         }
 
-        let response = request.send().await?;
+        tracing::trace!("Success fetching from Sentry API");
+        Ok(results.into())
+    }
+
+    /// Sends a single request to the Sentry API.
+    ///
+    /// Rate limited requests are retried up to [`MAX_RATE_LIMIT_RETRIES`] times, respecting the
+    /// `Retry-After` header.
+    async fn send_sentry_request(
+        client: &reqwest::Client,
+        url: Url,
+        token: &str,
+    ) -> CacheEntry<reqwest::Response> {
+        let mut rate_limited = 0;
+        loop {
+            let mut request = client
+                .get(url.clone())
+                .bearer_auth(token)
+                .header("Accept-Encoding", "identity");
+            if let Some(span) = sentry::configure_scope(|scope| scope.get_span()) {
+                for (k, v) in span.iter_headers() {
+                    request = request.header(k, v);
+                }
+            }
+
+            let response = request.send().await?;
+            let status = response.status();
 
-        if response.status().is_success() {
-            tracing::trace!("Success fetching from Sentry API");
-            Ok(response.json().await?)
-        } else {
-            tracing::warn!("Sentry API returned status code {}", response.status());
-            let details = response.status().to_string();
-            Err(CacheError::DownloadError(details))
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                && rate_limited < MAX_RATE_LIMIT_RETRIES
+            {
+                rate_limited += 1;
+                metric!(counter("source.sentry.rate_limited") += 1);
+
+                let retry_after = retry_after(response.headers()).min(MAX_RETRY_AFTER);
+                tracing::debug!("Sentry API rate limited, retrying in {:?}", retry_after);
+                tokio::time::sleep(retry_after).await;
+                continue;
+            }
+
+            tracing::warn!("Sentry API returned status code {}", status);
+            let details = status.to_string();
+            return Err(CacheError::DownloadError(details));
         }
     }
 
@@ -236,23 +311,16 @@ impl SentryDownloader {
             let future = {
                 let client = self.client.clone();
                 let query = query.clone();
-                async move { super::retry(|| Self::fetch_sentry_json(&client, &query)).await }
+                let max_retries = self.max_download_retries;
+                async move {
+                    super::retry(max_retries, || Self::fetch_sentry_json(&client, &query)).await
+                }
             };
 
             let future =
                 CancelOnDrop::new(self.runtime.spawn(future.bind_hub(sentry::Hub::current())));
 
-            let result = future.await.map_err(|_| CacheError::InternalError)?;
-
-            if let Ok(result) = &result {
-                // TODO(flub): These queries do not handle pagination.  But sentry only starts to
-                // paginate at 20 results so we get away with this for now.
-                if result.len() >= 20 {
-                    tracing::error!(query = ?query.index_url, "Sentry API Query returned 20 results");
-                }
-            }
-
-            result
+            future.await.map_err(|_| CacheError::InternalError)?
         });
 
         let entries = self
@@ -315,7 +383,10 @@ impl SentryDownloader {
             let future = {
                 let client = self.client.clone();
                 let query = query.clone();
-                async move { super::retry(|| Self::fetch_sentry_json(&client, &query)).await }
+                let max_retries = self.max_download_retries;
+                async move {
+                    super::retry(max_retries, || Self::fetch_sentry_json(&client, &query)).await
+                }
             };
 
             let future =
@@ -358,12 +429,14 @@ impl SentryDownloader {
         file_source: SentryRemoteFile,
         destination: &Path,
     ) -> CacheEntry {
-        tracing::debug!("Fetching Sentry artifact from {}", file_source.url());
+        tracing::debug!(
+            "Fetching Sentry artifact from {}",
+            redact_url(&file_source.url())
+        );
 
         let request = self
             .client
             .get(file_source.url())
-            .header("User-Agent", USER_AGENT)
             .bearer_auth(&file_source.source.token);
         let source = RemoteFile::from(file_source);
 
@@ -372,12 +445,61 @@ impl SentryDownloader {
             request,
             self.connect_timeout,
             self.streaming_timeout,
+            self.read_timeout,
+            self.max_download_size,
             destination,
         )
         .await
     }
 }
 
+/// Returns the URL of the next page from the `Link` header of a Sentry API response.
+///
+/// The Sentry API uses headers of the form
+/// `<url>; rel="previous"; results="false"; cursor="...", <url>; rel="next"; results="true"; cursor="..."`.
+/// The next page is only returned if there are actually `results` on it.
+fn next_page_url(current: &Url, headers: &reqwest::header::HeaderMap) -> Option<Url> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+
+    for part in link.split(',') {
+        let mut params = part.split(';').map(str::trim);
+        let Some(url) = params
+            .next()
+            .and_then(|url| url.strip_prefix('<')?.strip_suffix('>'))
+        else {
+            continue;
+        };
+
+        let mut is_next = false;
+        let mut has_results = true;
+        for param in params {
+            match param.split_once('=') {
+                Some(("rel", rel)) => is_next = rel.trim_matches('"') == "next",
+                Some(("results", results)) => has_results = results.trim_matches('"') != "false",
+                _ => {}
+            }
+        }
+
+        if is_next && has_results {
+            return current.join(url).ok();
+        }
+    }
+
+    None
+}
+
+/// Returns the duration to wait for from the `Retry-After` header of a rate limited response.
+///
+/// Only the delay in seconds format is supported, defaulting to one second otherwise.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Duration {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(1))
+}
+
 /// Transforms the given `url` into a [`RemoteFile`].
 ///
 /// Depending on the `source`, this creates either a [`SentryRemoteFile`], or a
@@ -403,8 +525,32 @@ fn make_remote_file(
 mod tests {
     use super::*;
 
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::extract::RawQuery;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use reqwest::header::{HeaderMap, HeaderValue};
     use symbolicator_sources::{RemoteFileUri, SourceId};
 
+    use crate::test;
+
+    fn sentry_downloader() -> SentryDownloader {
+        SentryDownloader::new(
+            reqwest::Client::new(),
+            tokio::runtime::Handle::current(),
+            &Config::default(),
+        )
+    }
+
+    fn object_id() -> ObjectId {
+        ObjectId {
+            debug_id: Some("3249d99d-0c40-4931-8610-f4e4fb0b6936-1".parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_download_url() {
         let source = SentrySourceConfig {
@@ -433,4 +579,195 @@ mod tests {
             RemoteFileUri::new("sentry://project_debug_file/abc123")
         );
     }
+
+    #[test]
+    fn test_next_page_url() {
+        let current = Url::parse("https://example.net/endpoint/?debug_id=abc").unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(next_page_url(&current, &headers), None);
+
+        headers.insert(
+            reqwest::header::LINK,
+            HeaderValue::from_static(
+                r#"<https://example.net/endpoint/?debug_id=abc&cursor=0:0:1>; rel="previous"; results="false"; cursor="0:0:1", <https://example.net/endpoint/?debug_id=abc&cursor=0:20:0>; rel="next"; results="true"; cursor="0:20:0""#,
+            ),
+        );
+        assert_eq!(
+            next_page_url(&current, &headers).unwrap().as_str(),
+            "https://example.net/endpoint/?debug_id=abc&cursor=0:20:0"
+        );
+
+        headers.insert(
+            reqwest::header::LINK,
+            HeaderValue::from_static(
+                r#"<https://example.net/endpoint/?cursor=0:20:0>; rel="next"; results="false"; cursor="0:20:0""#,
+            ),
+        );
+        assert_eq!(next_page_url(&current, &headers), None);
+
+        headers.insert(
+            reqwest::header::LINK,
+            HeaderValue::from_static(r#"</endpoint/?cursor=1>; rel="next""#),
+        );
+        assert_eq!(
+            next_page_url(&current, &headers).unwrap().as_str(),
+            "https://example.net/endpoint/?cursor=1"
+        );
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), Duration::from_secs(1));
+
+        headers.insert(reqwest::header::RETRY_AFTER, HeaderValue::from_static("5"));
+        assert_eq!(retry_after(&headers), Duration::from_secs(5));
+
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_list_files_paginated() {
+        test::setup();
+
+        let router = Router::new().route(
+            "/files/",
+            get(|RawQuery(query): RawQuery| async move {
+                let query = query.unwrap_or_default();
+                let (link, id) = if query.contains("cursor=1") {
+                    (r#"</files/?cursor=2>; rel="next"; results="false""#, "def")
+                } else {
+                    (r#"</files/?cursor=1>; rel="next"; results="true""#, "abc")
+                };
+                let body = serde_json::json!([
+                    {"id": id, "symbolType": "pdb"},
+                    {"id": format!("{id}-pe"), "symbolType": "pe"},
+                ]);
+                ([("Link", link)], Json(body))
+            }),
+        );
+        let server = test::Server::with_router(router);
+        let source = Arc::new(SentrySourceConfig {
+            id: SourceId::new("sentry:project"),
+            url: server.url("/files/"),
+            token: "token".into(),
+        });
+
+        let files = sentry_downloader()
+            .list_files(source, &object_id(), &[FileType::Pdb])
+            .await
+            .unwrap();
+
+        let uris: Vec<_> = files.iter().map(|file| file.uri()).collect();
+        assert_eq!(
+            uris,
+            [
+                RemoteFileUri::new("sentry://project_debug_file/abc"),
+                RemoteFileUri::new("sentry://project_debug_file/def"),
+            ]
+        );
+        assert_eq!(server.accesses(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_list_files_cross_origin_page() {
+        test::setup();
+
+        let other_server = test::Server::with_router(Router::new().route(
+            "/files/",
+            get(|| async { Json(serde_json::json!([{"id": "def", "symbolType": "pdb"}])) }),
+        ));
+        let link = format!(
+            r#"<{}>; rel="next"; results="true""#,
+            other_server.url("/files/?cursor=1")
+        );
+        let router = Router::new().route(
+            "/files/",
+            get(|| async move {
+                let body = serde_json::json!([{"id": "abc", "symbolType": "pdb"}]);
+                ([("Link", link)], Json(body))
+            }),
+        );
+        let server = test::Server::with_router(router);
+        let source = Arc::new(SentrySourceConfig {
+            id: SourceId::new("sentry:project"),
+            url: server.url("/files/"),
+            token: "token".into(),
+        });
+
+        let files = sentry_downloader()
+            .list_files(source, &object_id(), &[FileType::Pdb])
+            .await
+            .unwrap();
+
+        // the token is not sent to the other origin
+        let uris: Vec<_> = files.iter().map(|file| file.uri()).collect();
+        assert_eq!(
+            uris,
+            [RemoteFileUri::new("sentry://project_debug_file/abc")]
+        );
+        assert_eq!(server.accesses(), 1);
+        assert_eq!(other_server.accesses(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_list_files_no_retry_client_error() {
+        test::setup();
+
+        let router = Router::new().route("/files/", get(|| async { StatusCode::BAD_REQUEST }));
+        let server = test::Server::with_router(router);
+        let source = Arc::new(SentrySourceConfig {
+            id: SourceId::new("sentry:project"),
+            url: server.url("/files/"),
+            token: "token".into(),
+        });
+
+        let err = sentry_downloader()
+            .list_files(source, &object_id(), &[FileType::Pdb])
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, CacheError::DownloadError("400 Bad Request".into()));
+        assert_eq!(server.accesses(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_files_rate_limited() {
+        test::setup();
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route(
+            "/files/",
+            get(move || {
+                let requests = requests.clone();
+                async move {
+                    if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                        Err((StatusCode::TOO_MANY_REQUESTS, [("Retry-After", "0")]))
+                    } else {
+                        Ok(Json(
+                            serde_json::json!([{"id": "abc", "symbolType": "pdb"}]),
+                        ))
+                    }
+                }
+            }),
+        );
+        let server = test::Server::with_router(router);
+        let source = Arc::new(SentrySourceConfig {
+            id: SourceId::new("sentry:project"),
+            url: server.url("/files/"),
+            token: "token".into(),
+        });
+
+        let files = sentry_downloader()
+            .list_files(source, &object_id(), &[FileType::Pdb])
+            .await
+            .unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(server.accesses(), 2);
+    }
 }
@@ -4,7 +4,7 @@ use std::sync::Arc;
 use symbolicator_sources::RemoteFile;
 use tempfile::NamedTempFile;
 
-use crate::caching::{CacheEntry, CacheError};
+use crate::caching::{CacheEntry, CacheError, Revalidation, Validators};
 use crate::services::download::DownloadService;
 use crate::utils::compression::maybe_decompress_file;
 
@@ -20,14 +20,36 @@ pub async fn fetch_file(
     file_id: RemoteFile,
     temp_file: &mut NamedTempFile,
 ) -> CacheEntry {
-    downloader
-        .download(file_id, temp_file.path().to_owned())
+    fetch_file_conditional(downloader, file_id, temp_file, None)
+        .await
+        .map(|_| ())
+}
+
+/// Downloads the given [`RemoteFile`] like [`fetch_file`], unless it was not modified since it
+/// was downloaded with the given `validators`.
+///
+/// If the file was not modified, [`Revalidation::NotModified`] is returned and the `temp_file`
+/// is left untouched.
+#[tracing::instrument(skip(downloader, temp_file, validators), fields(%file_id))]
+pub async fn fetch_file_conditional(
+    downloader: Arc<DownloadService>,
+    file_id: RemoteFile,
+    temp_file: &mut NamedTempFile,
+    validators: Option<&Validators>,
+) -> CacheEntry<Revalidation> {
+    let downloaded = downloader
+        .download_conditional(file_id, temp_file.path().to_owned(), validators.cloned())
         .await?;
     tracing::trace!("Finished download");
 
+    if downloaded == Revalidation::NotModified {
+        return Ok(downloaded);
+    }
+
     // Treat decompression errors as malformed files. It is more likely that
     // the error comes from a corrupt file than a local file system error.
     maybe_decompress_file(temp_file).map_err(|e| CacheError::Malformed(e.to_string()))?;
 
-    Ok(temp_file.as_file().rewind()?)
+    temp_file.as_file().rewind()?;
+    Ok(downloaded)
 }
@@ -0,0 +1,187 @@
+//! Checks of uploaded minidumps against the configured [`MinidumpLimits`].
+//!
+//! The counts and sizes are read from the raw streams of the minidump, without parsing the
+//! modules, threads or memory regions themselves. Parsing those is what allocates memory
+//! proportional to the counts and sizes declared in the minidump.
+
+use std::convert::TryInto;
+
+use thiserror::Error;
+
+use crate::config::MinidumpLimits;
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const MEMORY64_LIST_STREAM: u32 = 9;
+
+/// The size of a `MINIDUMP_MEMORY_DESCRIPTOR` and a `MINIDUMP_MEMORY_DESCRIPTOR64`.
+const MEMORY_DESCRIPTOR_SIZE: usize = 16;
+
+/// A minidump exceeding one of the [`MinidumpLimits`].
+#[derive(Debug, Error)]
+pub enum MinidumpLimitError {
+    #[error("minidump has {count} modules, the maximum is {max}")]
+    TooManyModules { count: u64, max: usize },
+    #[error("minidump has {count} threads, the maximum is {max}")]
+    TooManyThreads { count: u64, max: usize },
+    #[error("minidump has a memory region of {size} bytes, the maximum is {max}")]
+    MemoryRegionTooLarge { size: u64, max: u64 },
+}
+
+impl MinidumpLimitError {
+    /// The name of the exceeded limit, for use in metrics.
+    pub fn limit(&self) -> &'static str {
+        match self {
+            Self::TooManyModules { .. } => "max_modules",
+            Self::TooManyThreads { .. } => "max_threads",
+            Self::MemoryRegionTooLarge { .. } => "max_memory_region_size",
+        }
+    }
+}
+
+/// Reads integers from the raw streams of a minidump, in the byte order of the minidump.
+#[derive(Debug, Clone, Copy)]
+struct Reader {
+    little_endian: bool,
+}
+
+impl Reader {
+    fn u32(self, data: &[u8], offset: usize) -> Option<u32> {
+        let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match self.little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn u64(self, data: &[u8], offset: usize) -> Option<u64> {
+        let bytes = data.get(offset..offset + 8)?.try_into().ok()?;
+        Some(match self.little_endian {
+            true => u64::from_le_bytes(bytes),
+            false => u64::from_be_bytes(bytes),
+        })
+    }
+}
+
+/// Checks the minidump in `data` against the `limits`.
+///
+/// Data which is not a valid minidump passes the check, it is rejected once it is processed.
+pub fn check_minidump_limits(
+    data: &[u8],
+    limits: &MinidumpLimits,
+) -> Result<(), MinidumpLimitError> {
+    let Ok(minidump) = minidump::Minidump::read(data) else {
+        return Ok(());
+    };
+    // The signature was validated while reading the minidump, and is reversed in big-endian ones.
+    let reader = Reader {
+        little_endian: data.starts_with(b"MDMP"),
+    };
+    let stream = |stream_type| minidump.get_raw_stream(stream_type).ok();
+
+    if let (Some(max), Some(stream)) = (limits.max_modules, stream(MODULE_LIST_STREAM)) {
+        let count = reader.u32(stream, 0).unwrap_or_default().into();
+        if count > max as u64 {
+            return Err(MinidumpLimitError::TooManyModules { count, max });
+        }
+    }
+
+    if let (Some(max), Some(stream)) = (limits.max_threads, stream(THREAD_LIST_STREAM)) {
+        let count = reader.u32(stream, 0).unwrap_or_default().into();
+        if count > max as u64 {
+            return Err(MinidumpLimitError::TooManyThreads { count, max });
+        }
+    }
+
+    if let Some(max) = limits.max_memory_region_size {
+        let mut sizes = vec![];
+        if let Some(stream) = stream(MEMORY_LIST_STREAM) {
+            let count = reader.u32(stream, 0).unwrap_or_default() as usize;
+            // Some writers pad the count to 8 bytes.
+            let padded = stream.len() == 8 + count.saturating_mul(MEMORY_DESCRIPTOR_SIZE);
+            let offset = if padded { 8 } else { 4 };
+            let descriptors = stream.get(offset..).unwrap_or_default();
+            sizes.extend(
+                descriptors
+                    .chunks_exact(MEMORY_DESCRIPTOR_SIZE)
+                    .take(count)
+                    .filter_map(|descriptor| reader.u32(descriptor, 8))
+                    .map(u64::from),
+            );
+        }
+        if let Some(stream) = stream(MEMORY64_LIST_STREAM) {
+            let count = reader.u64(stream, 0).unwrap_or_default();
+            let descriptors = stream.get(16..).unwrap_or_default();
+            sizes.extend(
+                descriptors
+                    .chunks_exact(MEMORY_DESCRIPTOR_SIZE)
+                    .take(count.try_into().unwrap_or(usize::MAX))
+                    .filter_map(|descriptor| reader.u64(descriptor, 8)),
+            );
+        }
+        if let Some(size) = sizes.into_iter().find(|size| *size > max) {
+            return Err(MinidumpLimitError::MemoryRegionTooLarge { size, max });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test;
+
+    #[test]
+    fn test_minidump_limits() {
+        let minidump = test::read_fixture("windows.dmp");
+        check_minidump_limits(&minidump, &MinidumpLimits::default()).unwrap();
+
+        let limits = MinidumpLimits {
+            max_modules: Some(1),
+            ..Default::default()
+        };
+        let err = check_minidump_limits(&minidump, &limits).unwrap_err();
+        assert!(matches!(
+            err,
+            MinidumpLimitError::TooManyModules { max: 1, .. }
+        ));
+
+        let limits = MinidumpLimits {
+            max_threads: Some(1),
+            ..Default::default()
+        };
+        let err = check_minidump_limits(&minidump, &limits).unwrap_err();
+        assert!(matches!(
+            err,
+            MinidumpLimitError::TooManyThreads { max: 1, .. }
+        ));
+
+        let limits = MinidumpLimits {
+            max_memory_region_size: Some(1),
+            ..Default::default()
+        };
+        let err = check_minidump_limits(&minidump, &limits).unwrap_err();
+        assert!(matches!(
+            err,
+            MinidumpLimitError::MemoryRegionTooLarge { max: 1, .. }
+        ));
+
+        // without limits, every minidump passes
+        let limits = MinidumpLimits {
+            max_modules: None,
+            max_threads: None,
+            max_memory_region_size: None,
+        };
+        check_minidump_limits(&minidump, &limits).unwrap();
+
+        // invalid minidumps are rejected when they are processed
+        let limits = MinidumpLimits {
+            max_modules: Some(1),
+            ..Default::default()
+        };
+        check_minidump_limits(b"MDMP not really a minidump", &limits).unwrap();
+    }
+}
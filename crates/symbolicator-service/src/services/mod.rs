@@ -24,6 +24,7 @@ pub mod download;
 mod fetch_file;
 pub mod il2cpp;
 mod minidump;
+mod minidump_limits;
 mod module_lookup;
 pub mod objects;
 pub mod ppdb_caches;
@@ -37,18 +38,38 @@ use self::caches::SourceFilesCache;
 use self::cficaches::CfiCacheActor;
 use self::download::DownloadService;
 use self::il2cpp::Il2cppService;
-use self::objects::ObjectsActor;
+use self::objects::{ObjectsActor, SourceCircuitBreaker};
 use self::ppdb_caches::PortablePdbCacheActor;
 use self::sourcemap::SourceMapService;
 use self::symbolication::SymbolicationActor;
 use self::symcaches::SymCacheActor;
-pub use fetch_file::fetch_file;
+pub use fetch_file::{fetch_file, fetch_file_conditional};
+pub use minidump_limits::{check_minidump_limits, MinidumpLimitError};
+pub use module_lookup::object_id_from_object_info;
 
 pub fn create_service(
     config: &Config,
     io_pool: tokio::runtime::Handle,
 ) -> Result<(SymbolicationActor, ObjectsActor)> {
     let caches = Caches::from_config(config).context("failed to create local caches")?;
+    create_service_with_caches(config, caches, io_pool)
+}
+
+/// Creates the services on top of the given local `caches`.
+///
+/// Clones of the `caches` share their state with the ones used by the services, so they can be
+/// kept to inspect and maintain the caches of the running services.
+pub fn create_service_with_caches(
+    config: &Config,
+    caches: Caches,
+    io_pool: tokio::runtime::Handle,
+) -> Result<(SymbolicationActor, ObjectsActor)> {
+    if config.cache_dir.is_none() {
+        tracing::info!(
+            in_memory = config.caches.in_memory.file_caches,
+            "No cache_dir configured, caches are not persisted on disk"
+        );
+    }
     caches
         .clear_tmp(config)
         .context("failed to clear tmp caches")?;
@@ -63,11 +84,16 @@ pub fn create_service(
         downloader.clone(),
     ));
 
+    let circuit_breaker = SourceCircuitBreaker::new(
+        config.circuit_breaker_threshold,
+        config.circuit_breaker_open_time,
+    );
     let objects = ObjectsActor::new(
         caches.object_meta,
         caches.objects,
         shared_cache.clone(),
         downloader.clone(),
+        circuit_breaker,
     );
 
     let bitcode = BitcodeService::new(caches.auxdifs, shared_cache.clone(), downloader.clone());
@@ -80,6 +106,8 @@ pub fn create_service(
         objects.clone(),
         bitcode,
         il2cpp,
+        config.num_symcache_conversions(),
+        config.caches.symcache_key_version,
     );
 
     let cficaches = CfiCacheActor::new(caches.cficaches, shared_cache.clone(), objects.clone());
@@ -103,6 +131,7 @@ pub fn create_service(
         caches.diagnostics,
         sourcemaps,
         sourcefiles_cache,
+        config.source_context_lines,
     );
 
     Ok((symbolication, objects))
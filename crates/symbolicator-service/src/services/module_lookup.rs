@@ -1,14 +1,17 @@
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Instant;
 
 use futures::future;
 use sentry::{Hub, SentryFutureExt};
 
+use symbolic::common::Arch;
 use symbolic::debuginfo::ObjectDebugSession;
 use symbolicator_sources::{FileType, ObjectId, ObjectType, SourceConfig};
 
-use crate::caching::{CacheEntry, CacheError};
-use crate::services::derived::DerivedCache;
+use crate::caching::{CacheEntry, CacheError, CacheMode};
+use crate::services::derived::{DerivedCache, DerivedFrom};
 use crate::services::objects::{FindObject, FindResult, ObjectHandle, ObjectPurpose, ObjectsActor};
 use crate::services::ppdb_caches::{
     FetchPortablePdbCache, OwnedPortablePdbCache, PortablePdbCacheActor,
@@ -16,13 +19,15 @@ use crate::services::ppdb_caches::{
 use crate::services::symcaches::{FetchSymCache, OwnedSymCache, SymCacheActor};
 use crate::types::{
     AllObjectCandidates, CompleteObjectInfo, CompleteStacktrace, ObjectFeatures, ObjectFileStatus,
-    RawFrame, RawObjectInfo, RawStacktrace, Scope,
+    RawFrame, RawObjectInfo, RawStacktrace, Scope, SymbolicatedWith,
 };
 use crate::utils::addr::AddrMode;
+use crate::utils::futures::with_deadline;
 
 use super::symbolication::source_context::get_context_lines;
 
-fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
+/// Creates the [`ObjectId`] used to look up the DIFs of the given module.
+pub fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
     ObjectId {
         debug_id: match object_info.debug_id.as_deref() {
             None | Some("") => None,
@@ -63,6 +68,19 @@ pub struct CacheFile {
     file: CacheEntry<CacheFileEntry>,
     candidates: AllObjectCandidates,
     features: ObjectFeatures,
+    derived_from: Option<DerivedFrom>,
+}
+
+impl CacheFile {
+    /// The status of fetching this cache file.
+    pub fn status(&self) -> ObjectFileStatus {
+        object_file_status_from_cache_entry(&self.file)
+    }
+
+    /// The error that occurred while fetching this cache file, if any.
+    pub fn error(&self) -> Option<&CacheError> {
+        self.file.as_ref().err()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -71,6 +89,10 @@ pub struct CacheLookupResult<'a> {
     pub object_info: &'a CompleteObjectInfo,
     pub cache: &'a CacheEntry<CacheFileEntry>,
     pub relative_addr: Option<u64>,
+    /// Whether fetching the cache was given up because the deadline of the request passed.
+    pub deadline_exceeded: bool,
+    /// The reduced debug information the cache was built from, if any.
+    pub symbolicated_with: Option<SymbolicatedWith>,
 }
 
 impl<'a> CacheLookupResult<'a> {
@@ -101,7 +123,13 @@ struct ModuleEntry {
     module_index: usize,
     object_info: CompleteObjectInfo,
     cache: CacheEntry<CacheFileEntry>,
+    /// Whether the `cache` was fetched, successfully or not.
+    cache_fetched: bool,
+    deadline_exceeded: bool,
+    symbolicated_with: Option<SymbolicatedWith>,
     source_object: CacheEntry<Arc<ObjectHandle>>,
+    /// Whether the `source_object` was fetched, successfully or not.
+    source_fetched: bool,
 }
 
 pub struct ModuleLookup {
@@ -123,7 +151,11 @@ impl ModuleLookup {
                 module_index,
                 object_info,
                 cache: Err(CacheError::NotFound),
+                cache_fetched: false,
+                deadline_exceeded: false,
+                symbolicated_with: None,
                 source_object: Err(CacheError::NotFound),
+                source_fetched: false,
             })
             .collect();
 
@@ -168,14 +200,8 @@ impl ModuleLookup {
             .collect()
     }
 
-    /// Fetches all the SymCaches for the modules referenced by the `stacktraces`.
-    #[tracing::instrument(skip_all)]
-    pub async fn fetch_caches(
-        &mut self,
-        symcache_actor: SymCacheActor,
-        ppdb_cache_actor: PortablePdbCacheActor,
-        stacktraces: &[RawStacktrace],
-    ) {
+    /// Returns the indices of all the modules referenced by the `stacktraces`.
+    fn referenced_objects(&self, stacktraces: &[RawStacktrace]) -> HashSet<usize> {
         let mut referenced_objects = HashSet::new();
         for stacktrace in stacktraces {
             for frame in &stacktrace.frames {
@@ -186,73 +212,129 @@ impl ModuleLookup {
                 }
             }
         }
+        referenced_objects
+    }
+
+    /// Returns all the modules referenced by the `stacktraces`.
+    pub fn referenced_modules(
+        &self,
+        stacktraces: &[RawStacktrace],
+    ) -> impl Iterator<Item = &CompleteObjectInfo> {
+        let referenced_objects = self.referenced_objects(stacktraces);
+        self.modules
+            .iter()
+            .filter(move |entry| referenced_objects.contains(&entry.module_index))
+            .map(|entry| &entry.object_info)
+    }
+
+    /// Fetches the SymCache, or the PortablePdbCache for .NET objects, of the given object.
+    pub fn fetch_cache_file(
+        symcache_actor: SymCacheActor,
+        ppdb_cache_actor: PortablePdbCacheActor,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        object_info: &RawObjectInfo,
+        cache_mode: CacheMode,
+    ) -> impl Future<Output = CacheFile> + Send + 'static {
+        let identifier = object_id_from_object_info(object_info);
+        let object_type = object_info.ty;
+
+        let fut = async move {
+            match object_type {
+                ObjectType::PeDotnet => {
+                    let request = FetchPortablePdbCache {
+                        identifier,
+                        sources,
+                        scope,
+                    };
+
+                    let DerivedCache {
+                        cache,
+                        candidates,
+                        features,
+                        derived_from,
+                    } = ppdb_cache_actor.fetch(request).await;
+
+                    CacheFile {
+                        file: cache.map(CacheFileEntry::PortablePdbCache),
+                        candidates,
+                        features,
+                        derived_from,
+                    }
+                }
+                _ => {
+                    let request = FetchSymCache {
+                        object_type,
+                        identifier,
+                        sources,
+                        scope,
+                        cache_mode,
+                    };
+
+                    let DerivedCache {
+                        cache,
+                        candidates,
+                        features,
+                        derived_from,
+                    } = symcache_actor.fetch(request).await;
+
+                    CacheFile {
+                        file: cache.map(CacheFileEntry::SymCache),
+                        candidates,
+                        features,
+                        derived_from,
+                    }
+                }
+            }
+        };
+
+        fut.bind_hub(Hub::new_from_top(Hub::current()))
+    }
+
+    /// Fetches all the SymCaches for the modules referenced by the `stacktraces`.
+    ///
+    /// The caches which are not fetched by the given `deadline` are given up on, and their
+    /// modules are marked as timed out. Caches fetched by a previous call are kept, so this can
+    /// be called for one stack trace after the other.
+    #[tracing::instrument(skip_all)]
+    pub async fn fetch_caches(
+        &mut self,
+        symcache_actor: SymCacheActor,
+        ppdb_cache_actor: PortablePdbCacheActor,
+        stacktraces: &[RawStacktrace],
+        deadline: Option<Instant>,
+        cache_mode: CacheMode,
+    ) {
+        let referenced_objects = self.referenced_objects(stacktraces);
 
         let futures = self
             .modules
             .iter_mut()
             .enumerate()
             .filter_map(|(idx, entry)| {
+                if entry.cache_fetched {
+                    return None;
+                }
                 let is_used = referenced_objects.contains(&entry.module_index);
                 if !is_used {
                     entry.object_info.debug_status = ObjectFileStatus::Unused;
                     return None;
                 }
-                let symcache_actor = symcache_actor.clone();
-                let ppdb_cache_actor = ppdb_cache_actor.clone();
-                let identifier = object_id_from_object_info(&entry.object_info.raw);
-                let sources = self.sources.clone();
-                let scope = self.scope.clone();
-                let object_type = entry.object_info.raw.ty;
 
-                let fut = async move {
-                    match object_type {
-                        ObjectType::PeDotnet => {
-                            let request = FetchPortablePdbCache {
-                                identifier,
-                                sources,
-                                scope,
-                            };
-
-                            let DerivedCache {
-                                cache,
-                                candidates,
-                                features,
-                            } = ppdb_cache_actor.fetch(request).await;
-
-                            let cache_file = CacheFile {
-                                file: cache.map(CacheFileEntry::PortablePdbCache),
-                                candidates,
-                                features,
-                            };
-
-                            (idx, cache_file)
-                        }
-                        _ => {
-                            let request = FetchSymCache {
-                                object_type,
-                                identifier,
-                                sources,
-                                scope,
-                            };
-
-                            let DerivedCache {
-                                cache,
-                                candidates,
-                                features,
-                            } = symcache_actor.fetch(request).await;
-
-                            let cache_file = CacheFile {
-                                file: cache.map(CacheFileEntry::SymCache),
-                                candidates,
-                                features,
-                            };
-
-                            (idx, cache_file)
-                        }
-                    }
-                };
-
-                Some(fut.bind_hub(Hub::new_from_top(Hub::current())))
+                let fut = Self::fetch_cache_file(
+                    symcache_actor.clone(),
+                    ppdb_cache_actor.clone(),
+                    self.scope.clone(),
+                    self.sources.clone(),
+                    &entry.object_info.raw,
+                    cache_mode,
+                );
+
+                Some(async move {
+                    let started = Instant::now();
+                    let cache_file = with_deadline(deadline, fut).await;
+                    (idx, cache_file.ok_or_else(|| started.elapsed()))
+                })
             });
 
         for (idx, cache_file) in future::join_all(futures).await {
@@ -260,16 +342,40 @@ impl ModuleLookup {
                 file,
                 candidates,
                 features,
-            } = cache_file;
+                derived_from,
+            } = match cache_file {
+                Ok(cache_file) => cache_file,
+                Err(elapsed) => {
+                    if let Some(entry) = self.modules.get_mut(idx) {
+                        entry.cache_fetched = true;
+                        entry.object_info.debug_status = ObjectFileStatus::Timeout;
+                        entry.cache = Err(CacheError::Timeout(elapsed));
+                        entry.deadline_exceeded = true;
+                    }
+                    continue;
+                }
+            };
 
             if let Some(entry) = self.modules.get_mut(idx) {
-                entry.object_info.arch = Default::default();
+                entry.cache_fetched = true;
                 entry.object_info.features.merge(features);
                 entry.object_info.candidates.merge(&candidates);
                 entry.object_info.debug_status = object_file_status_from_cache_entry(&file);
+                if let Some(derived_from) = derived_from {
+                    entry.object_info.debug_source = Some(derived_from.source_id);
+                    entry.object_info.debug_cache_key = Some(derived_from.cache_key.to_string());
+                }
 
+                // Keep the architecture reported by the client if the debug file does not know it.
                 if let Ok(CacheFileEntry::SymCache(ref symcache)) = file {
-                    entry.object_info.arch = symcache.get().arch();
+                    let arch = symcache.get().arch();
+                    if arch != Arch::Unknown {
+                        entry.object_info.arch = arch;
+                    }
+
+                    // Objects without debug info are only selected if there is none with it, in
+                    // which case the SymCache falls back to the symbol table of the object.
+                    entry.symbolicated_with = SymbolicatedWith::from_features(&features);
                 }
 
                 entry.cache = file;
@@ -278,6 +384,9 @@ impl ModuleLookup {
     }
 
     /// Fetches all the sources for the modules referenced by the `stacktraces`.
+    ///
+    /// Sources fetched by a previous call are kept, so this can be called for one stack trace
+    /// after the other.
     #[tracing::instrument(skip_all)]
     pub async fn fetch_sources(
         &mut self,
@@ -300,9 +409,15 @@ impl ModuleLookup {
             .iter_mut()
             .enumerate()
             .filter_map(|(idx, entry)| {
+                if entry.source_fetched {
+                    return None;
+                }
                 let is_used = referenced_objects.contains(&entry.module_index);
                 if !is_used {
-                    entry.object_info.debug_status = ObjectFileStatus::Unused;
+                    // modules used by previous stack traces keep their status
+                    if !entry.cache_fetched {
+                        entry.object_info.debug_status = ObjectFileStatus::Unused;
+                    }
                     entry.source_object = Err(CacheError::NotFound);
                     return None;
                 }
@@ -314,6 +429,7 @@ impl ModuleLookup {
                     identifier: object_id_from_object_info(&entry.object_info.raw),
                     sources: self.sources.clone(),
                     scope: self.scope.clone(),
+                    cache_mode: CacheMode::Use,
                 };
 
                 let fut = async move {
@@ -335,6 +451,7 @@ impl ModuleLookup {
 
         for (idx, source_object, candidates) in future::join_all(futures).await {
             if let Some(entry) = self.modules.get_mut(idx) {
+                entry.source_fetched = true;
                 entry.source_object = source_object;
                 entry.object_info.candidates.merge(&candidates);
 
@@ -357,6 +474,8 @@ impl ModuleLookup {
                 object_info: &entry.object_info,
                 cache: &entry.cache,
                 relative_addr,
+                deadline_exceeded: entry.deadline_exceeded,
+                symbolicated_with: entry.symbolicated_with,
             }
         })
     }
@@ -389,6 +508,7 @@ impl ModuleLookup {
         &self,
         debug_sessions: &HashMap<usize, Option<ObjectDebugSession<'_>>>,
         frame: &mut RawFrame,
+        context_lines: usize,
     ) -> Option<url::Url> {
         let abs_path = frame.abs_path.as_ref()?;
 
@@ -416,7 +536,7 @@ impl ModuleLookup {
 
         if let Some(text) = source_descriptor.contents() {
             // Set the actual source code, if embedded in the file.
-            Self::set_source_context(text, frame);
+            Self::set_source_context(text, frame, context_lines);
             None
         } else {
             // Let caller know this source code may be resolved from a remote URL.
@@ -424,9 +544,17 @@ impl ModuleLookup {
         }
     }
 
-    pub(crate) fn set_source_context(source: &str, frame: &mut RawFrame) -> Option<()> {
-        let (pre_context, context_line, post_context) =
-            get_context_lines(source, frame.lineno?.try_into().ok()?, None, None)?;
+    pub(crate) fn set_source_context(
+        source: &str,
+        frame: &mut RawFrame,
+        context_lines: usize,
+    ) -> Option<()> {
+        let (pre_context, context_line, post_context) = get_context_lines(
+            source,
+            frame.lineno?.try_into().ok()?,
+            None,
+            Some(context_lines),
+        )?;
         frame.pre_context = pre_context;
         frame.context_line = Some(context_line);
         frame.post_context = post_context;
@@ -561,6 +689,7 @@ mod tests {
             debug_checksum: None,
             image_addr: HexValue(42),
             image_size: Some(0),
+            arch: None,
         });
 
         let lookup = ModuleLookup::new(Scope::Global, Arc::new([]), std::iter::once(info.clone()));
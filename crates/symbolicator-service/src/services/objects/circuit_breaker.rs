@@ -0,0 +1,348 @@
+//! A circuit breaker which temporarily skips sources that keep failing.
+//!
+//! Each source starts out `closed`, and is looked up as usual. After `failure_threshold`
+//! consecutive failed lookups, its breaker `opens` and the source is skipped for `open_time`.
+//! The next lookup after that is let through as a trial, while the breaker is `half_open`. A
+//! successful trial closes the breaker again, a failed one opens it for another `open_time`.
+//!
+//! Breakers are tracked per source configuration rather than per source id, as different
+//! requests may send different sources under the same id.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use symbolicator_sources::{SourceConfig, SourceId};
+
+/// The state of the circuit breaker of a single source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakerState {
+    /// The source is looked up, and has failed the given number of times in a row.
+    Closed { consecutive_failures: usize },
+    /// The source is skipped until the given time.
+    Open { until: Instant },
+    /// A trial lookup of the source was started at the given time.
+    HalfOpen { since: Instant },
+}
+
+/// Identifies a source by its id along with a hash of its complete configuration.
+///
+/// Computing the key serializes the whole source, so it is computed once per lookup, see
+/// [`SourceCircuitBreaker::key`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceKey {
+    id: SourceId,
+    config_hash: u64,
+}
+
+impl SourceKey {
+    fn new(source: &SourceConfig) -> Self {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_vec(source)
+            .expect("source configs serialize to JSON")
+            .hash(&mut hasher);
+        Self {
+            id: source.id().clone(),
+            config_hash: hasher.finish(),
+        }
+    }
+}
+
+/// The publicly reported state of the circuit breaker of a source, see [`SourceCircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitBreakerStatus {
+    /// The source is looked up as usual.
+    Closed,
+    /// The source is skipped because of repeated failures.
+    Open,
+    /// The source is being retried after it was skipped.
+    HalfOpen,
+}
+
+/// Tracks consecutive lookup failures per source, and skips sources which keep failing.
+///
+/// See the [module docs](self) for the transitions between the states. The breaker is disabled
+/// without a `failure_threshold`, in which case no source is ever skipped.
+#[derive(Debug, Clone, Default)]
+pub struct SourceCircuitBreaker {
+    failure_threshold: Option<usize>,
+    open_time: Duration,
+    states: Arc<Mutex<HashMap<SourceKey, BreakerState>>>,
+}
+
+impl SourceCircuitBreaker {
+    /// Creates a breaker which opens after `failure_threshold` consecutive failures of a source,
+    /// skipping the source for `open_time`.
+    pub fn new(failure_threshold: Option<usize>, open_time: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_time,
+            states: Default::default(),
+        }
+    }
+
+    /// Whether the breaker is tracking sources at all.
+    pub fn is_enabled(&self) -> bool {
+        self.failure_threshold.is_some()
+    }
+
+    /// Returns the key under which the breaker of the `source` is tracked.
+    ///
+    /// This is `None` if the breaker is disabled, in which case sources are always looked up.
+    pub fn key(&self, source: &SourceConfig) -> Option<SourceKey> {
+        self.is_enabled().then(|| SourceKey::new(source))
+    }
+
+    /// Returns whether the `source` with the given `key` should be looked up.
+    ///
+    /// Once the `open_time` of an open breaker has passed, this lets a single trial lookup
+    /// through, whose outcome must be reported with [`record`](Self::record).
+    pub fn allow(&self, source: &SourceConfig, key: &SourceKey) -> bool {
+        self.allow_at(source, key, Instant::now())
+    }
+
+    fn allow_at(&self, source: &SourceConfig, key: &SourceKey, now: Instant) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        let mut states = self.states.lock().unwrap();
+        let Some(state) = states.get_mut(key) else {
+            return true;
+        };
+
+        // A trial which never reported its outcome is retried after another `open_time`.
+        let retry_at = match *state {
+            BreakerState::Closed { .. } => return true,
+            BreakerState::Open { until } => until,
+            BreakerState::HalfOpen { since } => since + self.open_time,
+        };
+        if now < retry_at {
+            metric!(
+                counter("objects.circuit_breaker.skipped") += 1,
+                "source" => metric_key(source)
+            );
+            return false;
+        }
+
+        tracing::info!(source = %source.id(), "Retrying source after repeated failures");
+        *state = BreakerState::HalfOpen { since: now };
+        true
+    }
+
+    /// Records the outcome of a lookup of the `source` with the given `key`, opening or closing
+    /// its breaker.
+    ///
+    /// Sources which responded, even if they did not have the requested file, are successful.
+    pub fn record(&self, source: &SourceConfig, key: &SourceKey, success: bool) {
+        self.record_at(source, key, success, Instant::now())
+    }
+
+    fn record_at(&self, source: &SourceConfig, key: &SourceKey, success: bool, now: Instant) {
+        let Some(failure_threshold) = self.failure_threshold else {
+            return;
+        };
+        let mut states = self.states.lock().unwrap();
+
+        if success {
+            if let Some(BreakerState::Open { .. } | BreakerState::HalfOpen { .. }) =
+                states.remove(key)
+            {
+                tracing::info!(source = %source.id(), "Closing circuit breaker of source");
+                metric!(
+                    counter("objects.circuit_breaker.closed") += 1,
+                    "source" => metric_key(source)
+                );
+            }
+            return;
+        }
+
+        let state = states.entry(key.clone()).or_insert(BreakerState::Closed {
+            consecutive_failures: 0,
+        });
+        let open = match state {
+            BreakerState::Closed {
+                consecutive_failures,
+            } => {
+                *consecutive_failures += 1;
+                *consecutive_failures >= failure_threshold
+            }
+            BreakerState::HalfOpen { .. } => true,
+            // Lookups which were started before the breaker opened keep it open.
+            BreakerState::Open { .. } => false,
+        };
+        if open {
+            tracing::info!(
+                source = %source.id(),
+                open_time = %humantime::format_duration(self.open_time),
+                "Opening circuit breaker of source due to repeated failures"
+            );
+            metric!(
+                counter("objects.circuit_breaker.opened") += 1,
+                "source" => metric_key(source)
+            );
+            *state = BreakerState::Open {
+                until: now + self.open_time,
+            };
+        }
+    }
+
+    /// Returns the state of the breaker of the `source`.
+    ///
+    /// This is `None` if the breaker is disabled.
+    pub fn status(&self, source: &SourceConfig) -> Option<CircuitBreakerStatus> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let key = SourceKey::new(source);
+        let status = match self.states.lock().unwrap().get(&key) {
+            None | Some(BreakerState::Closed { .. }) => CircuitBreakerStatus::Closed,
+            Some(BreakerState::Open { .. }) => CircuitBreakerStatus::Open,
+            Some(BreakerState::HalfOpen { .. }) => CircuitBreakerStatus::HalfOpen,
+        };
+        Some(status)
+    }
+}
+
+/// Collects the outcomes of the downloads done while looking up files.
+///
+/// Only downloads which actually reach a source are collected. Lookups which are served from the
+/// caches, including cached download errors, say nothing about the current state of a source.
+#[derive(Debug, Default)]
+pub(super) struct DownloadOutcomes(Mutex<Vec<(SourceId, bool)>>);
+
+impl DownloadOutcomes {
+    /// Records whether a download from the source with the given `id` succeeded.
+    pub(super) fn record(&self, id: &SourceId, success: bool) {
+        self.0.lock().unwrap().push((id.clone(), success));
+    }
+
+    /// Returns whether any download from the `source` succeeded, or `None` if it had no downloads.
+    pub(super) fn any_success(&self, source: &SourceConfig) -> Option<bool> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| id == source.id())
+            .fold(None, |any, (_, success)| {
+                Some(any.unwrap_or(false) || *success)
+            })
+    }
+}
+
+/// Returns the metric tag of the `source`, which is only its id for the built-in sources, to keep
+/// the cardinality low.
+fn metric_key(source: &SourceConfig) -> &str {
+    let id = source.id().as_str();
+    if id.starts_with("sentry:") {
+        id
+    } else {
+        source.type_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let source: SourceConfig = serde_json::from_value(serde_json::json!({
+            "id": "flaky",
+            "type": "http",
+            "url": "https://example.com/",
+        }))
+        .unwrap();
+        let key = SourceKey::new(&source);
+        let open_time = Duration::from_secs(60);
+        let breaker = SourceCircuitBreaker::new(Some(2), open_time);
+        let start = Instant::now();
+
+        // a success resets the consecutive failures
+        breaker.record_at(&source, &key, false, start);
+        breaker.record_at(&source, &key, true, start);
+        breaker.record_at(&source, &key, false, start);
+        assert!(breaker.allow_at(&source, &key, start));
+        assert_eq!(breaker.status(&source), Some(CircuitBreakerStatus::Closed));
+
+        // closed -> open
+        breaker.record_at(&source, &key, false, start);
+        assert_eq!(breaker.status(&source), Some(CircuitBreakerStatus::Open));
+        assert!(!breaker.allow_at(&source, &key, start + open_time / 2));
+
+        // open -> half-open, letting through a single trial
+        let retry = start + open_time;
+        assert!(breaker.allow_at(&source, &key, retry));
+        assert_eq!(
+            breaker.status(&source),
+            Some(CircuitBreakerStatus::HalfOpen)
+        );
+        assert!(!breaker.allow_at(&source, &key, retry));
+
+        // a failed trial opens the breaker again
+        breaker.record_at(&source, &key, false, retry);
+        assert_eq!(breaker.status(&source), Some(CircuitBreakerStatus::Open));
+        assert!(!breaker.allow_at(&source, &key, retry + open_time / 2));
+
+        // half-open -> closed
+        let retry = retry + open_time;
+        assert!(breaker.allow_at(&source, &key, retry));
+        breaker.record_at(&source, &key, true, retry);
+        assert_eq!(breaker.status(&source), Some(CircuitBreakerStatus::Closed));
+        assert!(breaker.allow_at(&source, &key, retry));
+
+        // a disabled breaker never skips sources
+        let breaker = SourceCircuitBreaker::default();
+        breaker.record_at(&source, &key, false, start);
+        assert!(breaker.allow_at(&source, &key, start));
+        assert_eq!(breaker.status(&source), None);
+    }
+
+    #[test]
+    fn test_circuit_breaker_source_identity() {
+        let source = |url: &str| -> SourceConfig {
+            serde_json::from_value(serde_json::json!({
+                "id": "shared",
+                "type": "http",
+                "url": url,
+            }))
+            .unwrap()
+        };
+        let flaky = source("https://flaky.example.com/");
+        let healthy = source("https://healthy.example.com/");
+        let flaky_key = SourceKey::new(&flaky);
+        let breaker = SourceCircuitBreaker::new(Some(1), Duration::from_secs(60));
+        let now = Instant::now();
+
+        breaker.record_at(&flaky, &flaky_key, false, now);
+        assert_eq!(breaker.status(&flaky), Some(CircuitBreakerStatus::Open));
+        assert!(!breaker.allow_at(&flaky, &flaky_key, now));
+
+        // a source with the same id but a different location is tracked separately
+        assert_eq!(breaker.status(&healthy), Some(CircuitBreakerStatus::Closed));
+        assert!(breaker.allow_at(&healthy, &SourceKey::new(&healthy), now));
+    }
+
+    #[test]
+    fn test_download_outcomes() {
+        let source = |id: &str| -> SourceConfig {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "type": "http",
+                "url": "https://example.com/",
+            }))
+            .unwrap()
+        };
+        let outcomes = DownloadOutcomes::default();
+        outcomes.record(source("a").id(), false);
+        outcomes.record(source("a").id(), true);
+        outcomes.record(source("b").id(), false);
+
+        assert_eq!(outcomes.any_success(&source("a")), Some(true));
+        assert_eq!(outcomes.any_success(&source("b")), Some(false));
+        assert_eq!(outcomes.any_success(&source("c")), None);
+    }
+}
@@ -10,7 +10,7 @@ use std::cmp;
 use std::fmt;
 use std::io;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::future::BoxFuture;
 use sentry::{Hub, SentryFutureExt};
@@ -18,14 +18,16 @@ use symbolic::common::SelfCell;
 use tempfile::NamedTempFile;
 
 use symbolic::common::ByteView;
-use symbolic::debuginfo::{Archive, Object};
-use symbolicator_sources::{ObjectId, RemoteFile};
+use symbolic::debuginfo::{Archive, FileFormat, Object};
+use symbolicator_sources::{ObjectId, ObjectType, RemoteFile};
 
 use crate::caching::CacheVersions;
-use crate::caching::{CacheEntry, CacheError, CacheItemRequest, CacheKey};
+use crate::caching::{
+    CacheEntry, CacheError, CacheItemRequest, CacheKey, Revalidation, Validators,
+};
 use crate::services::caches::versions::OBJECTS_CACHE_VERSIONS;
 use crate::services::download::DownloadService;
-use crate::services::fetch_file;
+use crate::services::fetch_file_conditional;
 use crate::types::Scope;
 use crate::utils::compression::tempfile_in_parent;
 use crate::utils::futures::{m, measure};
@@ -120,28 +122,67 @@ impl fmt::Display for ObjectHandle {
 /// This is the actual implementation of [`CacheItemRequest::compute`] for
 /// [`FetchFileDataRequest`] but outside of the trait so it can be written as async/await
 /// code.
+#[tracing::instrument(name = "download", skip_all, fields(source = %file_id.source_id()))]
 async fn fetch_object_file(
     object_id: &ObjectId,
     file_id: RemoteFile,
     downloader: Arc<DownloadService>,
     temp_file: &mut NamedTempFile,
-) -> CacheEntry {
+    validators: Option<&Validators>,
+) -> CacheEntry<Revalidation> {
     sentry::configure_scope(|scope| {
         file_id.to_scope(scope);
         object_id.to_scope(scope);
     });
 
-    fetch_file(downloader, file_id, temp_file).await?;
+    let verify_ids = file_id.verify_ids();
+    let source_metric_key = file_id.source_metric_key().to_string();
+    let uri = file_id.redacted_uri();
+
+    let downloaded = fetch_file_conditional(downloader, file_id, temp_file, validators).await?;
+    if downloaded == Revalidation::NotModified {
+        return Ok(downloaded);
+    }
+
+    let view = ByteView::map_file_ref(temp_file.as_file())?;
+
+    // Misbehaving sources may answer with an error page and a successful status code. Reject
+    // those so that the next source is tried, instead of caching them as malformed objects.
+    if is_html_page(&view) {
+        tracing::warn!(
+            "Object at {} is an HTML page, treating it as not found",
+            uri
+        );
+        metric!(counter("objects.html_page") += 1, "source" => &source_metric_key);
+        return Err(CacheError::NotFound);
+    }
 
     // Since objects in Sentry (and potentially also other sources) might be
     // multi-arch files (e.g. FatMach), we parse as Archive and try to
     // extract the wanted file.
-    let view = ByteView::map_file_ref(temp_file.as_file())?;
     let archive = match Archive::parse(&view) {
         Ok(archive) => archive,
         Err(e) => return Err(CacheError::Malformed(e.to_string())),
     };
 
+    // The format is detected from the contents of the file, and all further processing is based
+    // on that. Sources might serve files of another type than the one declared for the object,
+    // which is only worth a warning as long as the file itself is usable.
+    let format = archive.file_format();
+    if !format_matches_type(format, object_id.object_type) {
+        tracing::warn!(
+            "Object at {} is a {} file, which does not match the declared type {}",
+            uri,
+            format,
+            object_id.object_type
+        );
+        metric!(
+            counter("objects.format_mismatch") += 1,
+            "source" => &source_metric_key,
+            "format" => format.name()
+        );
+    }
+
     if archive.is_multi() {
         let object_opt = archive
             .objects()
@@ -154,6 +195,8 @@ async fn fetch_object_file(
                 if let Some(Err(err)) = archive.objects().find(|r| r.is_err()) {
                     return Err(CacheError::Malformed(err.to_string()));
                 } else {
+                    // None of the architectures in a fat file match the requested object.
+                    tracing::debug!("No object matching {} in multi-object archive", object_id);
                     return Err(CacheError::NotFound);
                 }
             }
@@ -167,12 +210,62 @@ async fn fetch_object_file(
     } else {
         // Attempt to parse the object to capture errors. The result can be
         // discarded as the object's data is the entire ByteView.
-        if let Err(err) = archive.object_by_index(0) {
-            return Err(CacheError::Malformed(err.to_string()));
+        let object = match archive.object_by_index(0) {
+            Ok(object) => object,
+            Err(err) => return Err(CacheError::Malformed(err.to_string())),
+        };
+
+        // Sources may serve a different file than the one requested, for example when two
+        // files share the same path. Reject those so that the next source is tried.
+        if let Some(object) = object {
+            if verify_ids && !object_matches_id(&object, object_id) {
+                tracing::warn!(
+                    "Object at {} does not match the requested {}",
+                    uri,
+                    object_id
+                );
+                metric!(counter("objects.id_mismatch") += 1, "source" => &source_metric_key);
+                return Err(CacheError::NotFound);
+            }
         }
     };
 
-    Ok(())
+    Ok(downloaded)
+}
+
+/// Whether the `data` of a downloaded file is an HTML page, which is never a valid object file.
+///
+/// This only looks at the start of the file, ignoring a byte order mark and leading whitespace.
+fn is_html_page(data: &[u8]) -> bool {
+    const TAGS: &[&[u8]] = &[b"<!doctype", b"<html"];
+
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let start = data
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    let data = &data[start..];
+    TAGS.iter().any(|tag| {
+        data.get(..tag.len())
+            .map_or(false, |prefix| prefix.eq_ignore_ascii_case(tag))
+    })
+}
+
+/// Whether an object file of the detected `format` can be used for an object of the declared
+/// `object_type`.
+///
+/// Breakpad symbols and source bundles can be created for objects of any type.
+fn format_matches_type(format: FileFormat, object_type: ObjectType) -> bool {
+    match (object_type, format) {
+        (_, FileFormat::Breakpad | FileFormat::SourceBundle) => true,
+        (ObjectType::Unknown | ObjectType::SourceMap, _) => true,
+        (ObjectType::Elf, FileFormat::Elf) => true,
+        (ObjectType::Macho, FileFormat::MachO) => true,
+        (ObjectType::Pe | ObjectType::PeDotnet, FileFormat::Pe | FileFormat::Pdb) => true,
+        (ObjectType::Pe | ObjectType::PeDotnet, FileFormat::PortablePdb) => true,
+        (ObjectType::Wasm, FileFormat::Wasm) => true,
+        _ => false,
+    }
 }
 
 /// Validates that the object matches expected identifiers.
@@ -207,6 +300,15 @@ impl CacheItemRequest for FetchFileDataRequest {
     const VERSIONS: CacheVersions = OBJECTS_CACHE_VERSIONS;
 
     fn compute<'a>(&'a self, temp_file: &'a mut NamedTempFile) -> BoxFuture<'a, CacheEntry> {
+        let future = self.compute_conditional(temp_file, None);
+        Box::pin(async move { future.await.map(|_| ()) })
+    }
+
+    fn compute_conditional<'a>(
+        &'a self,
+        temp_file: &'a mut NamedTempFile,
+        validators: Option<&'a Validators>,
+    ) -> BoxFuture<'a, CacheEntry<Revalidation>> {
         let cache_key = CacheKey::from_scoped_file(&self.0.scope, &self.0.file_source);
         tracing::trace!("Fetching file data for {}", cache_key);
         let future = fetch_object_file(
@@ -214,13 +316,27 @@ impl CacheItemRequest for FetchFileDataRequest {
             self.0.file_source.clone(),
             self.0.download_svc.clone(),
             temp_file,
+            validators,
         )
         .bind_hub(Hub::current());
 
         let timeout = Duration::from_secs(600);
         let future = tokio::time::timeout(timeout, future);
         let future = measure("objects", m::timed_result, future);
-        Box::pin(async move { future.await.map_err(|_| CacheError::Timeout(timeout))? })
+        Box::pin(async move {
+            let start = Instant::now();
+            let result = future.await;
+            metric!(timer("symbolication.phase") = start.elapsed(), "phase" => "download");
+            let result = result.unwrap_or(Err(CacheError::Timeout(timeout)));
+            if let Some(outcomes) = &self.0.download_outcomes {
+                let success = !matches!(
+                    result,
+                    Err(CacheError::DownloadError(_) | CacheError::Timeout(_))
+                );
+                outcomes.record(self.0.file_source.source_id(), success);
+            }
+            result
+        })
     }
 
     fn load(&self, data: ByteView<'static>) -> CacheEntry<Self::Item> {
@@ -247,20 +363,33 @@ mod tests {
     use std::sync::Arc;
     use std::time::Duration;
 
-    use symbolicator_sources::FileType;
+    use symbolicator_sources::{
+        CommonSourceConfig, FileType, FilesystemSourceConfig, SourceConfig, SourceId,
+    };
 
     use super::*;
-    use crate::caching::{Cache, CacheName};
+    use crate::caching::{Cache, CacheMode, CacheName};
     use crate::config::{CacheConfig, CacheConfigs, Config};
     use crate::services::download::DownloadService;
     use crate::services::objects::data_cache::Scope;
-    use crate::services::objects::{FindObject, ObjectPurpose, ObjectsActor};
+    use crate::services::objects::{
+        CircuitBreakerStatus, FindObject, ObjectPurpose, ObjectsActor, SourceCircuitBreaker,
+    };
     use crate::test::{self, tempdir};
 
-    use symbolic::common::DebugId;
+    use axum::response::Html;
+    use axum::Router;
+    use symbolic::common::{Arch, DebugId};
     use tempfile::TempDir;
 
     async fn make_objects_actor(tempdir: &TempDir) -> ObjectsActor {
+        make_objects_actor_with_breaker(tempdir, Default::default()).await
+    }
+
+    async fn make_objects_actor_with_breaker(
+        tempdir: &TempDir,
+        circuit_breaker: SourceCircuitBreaker,
+    ) -> ObjectsActor {
         let config = Config {
             connect_to_reserved_ips: true,
             max_download_timeout: Duration::from_millis(100),
@@ -287,7 +416,13 @@ mod tests {
         .unwrap();
 
         let download_svc = DownloadService::new(&config, tokio::runtime::Handle::current());
-        ObjectsActor::new(meta_cache, data_cache, Default::default(), download_svc)
+        ObjectsActor::new(
+            meta_cache,
+            data_cache,
+            Default::default(),
+            download_svc,
+            circuit_breaker,
+        )
     }
 
     #[tokio::test]
@@ -304,6 +439,7 @@ mod tests {
             scope: Scope::Global,
             identifier: DebugId::default().into(),
             sources: Arc::new([]),
+            cache_mode: CacheMode::Use,
         };
 
         // for each of the different symbol sources, we assert that:
@@ -359,6 +495,7 @@ mod tests {
             scope: Scope::Global,
             identifier: DebugId::default().into(),
             sources: Arc::new([]),
+            cache_mode: CacheMode::Use,
         };
 
         // for each of the different symbol sources, we assert that:
@@ -408,6 +545,7 @@ mod tests {
             scope: Scope::Global,
             identifier: DebugId::default().into(),
             sources: Arc::new([]),
+            cache_mode: CacheMode::Use,
         };
 
         // for each of the different symbol sources, we assert that:
@@ -459,6 +597,7 @@ mod tests {
             scope: Scope::Global,
             identifier: DebugId::default().into(),
             sources: Arc::new([]),
+            cache_mode: CacheMode::Use,
         };
 
         // for each of the different symbol sources, we assert that:
@@ -494,4 +633,380 @@ mod tests {
         assert_eq!(result, err);
         assert_eq!(hitcounter.accesses(), 0);
     }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_ignores_cached_errors() {
+        test::setup();
+
+        let hitcounter = test::Server::new();
+        let cachedir = tempdir();
+        let circuit_breaker = SourceCircuitBreaker::new(Some(2), Duration::from_secs(60));
+
+        let source = hitcounter.source("rejected", "/respond_statuscode/500/");
+        let find_object = FindObject {
+            filetypes: &[FileType::MachCode],
+            purpose: ObjectPurpose::Debug,
+            scope: Scope::Global,
+            identifier: DebugId::default().into(),
+            sources: Arc::new([source.clone()]),
+            cache_mode: CacheMode::Use,
+        };
+
+        // the first lookup fails to download
+        let objects_actor =
+            make_objects_actor_with_breaker(&cachedir, circuit_breaker.clone()).await;
+        objects_actor.find(find_object.clone()).await;
+        assert_eq!(hitcounter.accesses(), 3);
+
+        // the cached download errors do not count as further failures of the source
+        for _ in 0..2 {
+            // NOTE: creating a fresh instance to avoid in-memory cache
+            let objects_actor =
+                make_objects_actor_with_breaker(&cachedir, circuit_breaker.clone()).await;
+            let result = objects_actor.find(find_object.clone()).await;
+            assert_eq!(
+                result.meta.unwrap().handle.unwrap_err(),
+                CacheError::DownloadError("500 Internal Server Error".into())
+            );
+            assert_eq!(hitcounter.accesses(), 0);
+        }
+        assert_eq!(
+            circuit_breaker.status(&source),
+            Some(CircuitBreakerStatus::Closed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_downloads() {
+        test::setup();
+
+        let (server, source) = test::symbol_server();
+        let cachedir = tempdir();
+        let objects_actor = make_objects_actor(&cachedir).await;
+
+        let debug_id: DebugId = "502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap();
+        let find_object = FindObject {
+            filetypes: &[FileType::MachDebug],
+            purpose: ObjectPurpose::Debug,
+            scope: Scope::Global,
+            identifier: debug_id.into(),
+            sources: Arc::new([source]),
+            cache_mode: CacheMode::Use,
+        };
+
+        // all concurrent requests for the same file share a single download
+        let results =
+            futures::future::join_all((0..10).map(|_| objects_actor.find(find_object.clone())))
+                .await;
+        for result in results {
+            let handle = result.meta.unwrap().handle.unwrap();
+            assert_eq!(handle.object_id.debug_id, Some(debug_id));
+        }
+
+        let hits = server.all_hits();
+        assert!(!hits.is_empty());
+        for (path, hits) in hits {
+            assert_eq!(hits, 1, "{path}");
+        }
+    }
+
+    /// Builds a fat Mach-O file out of the given `(cputype, cpusubtype, data)` slices.
+    fn fat_macho(slices: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        const ALIGN: u32 = 14;
+
+        let mut header = Vec::new();
+        header.extend(0xcafe_babe_u32.to_be_bytes());
+        header.extend((slices.len() as u32).to_be_bytes());
+
+        let mut data = vec![0; 8 + 20 * slices.len()];
+        for (cputype, cpusubtype, slice) in slices {
+            let mask = (1 << ALIGN) - 1;
+            let offset = (data.len() as u32 + mask) & !mask;
+            data.resize(offset as usize, 0);
+            data.extend_from_slice(slice);
+
+            for value in [*cputype, *cpusubtype, offset, slice.len() as u32, ALIGN] {
+                header.extend(value.to_be_bytes());
+            }
+        }
+
+        data[..header.len()].copy_from_slice(&header);
+        data
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let fixtures = [
+            (
+                "symbols/91/9e06d606e3c443d723d295bb30b02ddc3a02f8",
+                FileFormat::Elf,
+                ObjectType::Elf,
+            ),
+            (
+                "symbols/502F/C0A5/1EC1/3E47/9998/684FA139DCA7",
+                FileFormat::MachO,
+                ObjectType::Macho,
+            ),
+            (
+                "symbols/crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb",
+                FileFormat::Pdb,
+                ObjectType::Pe,
+            ),
+            (
+                "symbols/portable-embedded.pdb/B6919861510C48879994943F64F70C37ffffffff/portable-embedded.pdb",
+                FileFormat::PortablePdb,
+                ObjectType::PeDotnet,
+            ),
+            (
+                "symbols/bd/a18fd85d4a4eb893022d6bfad846b1.debug",
+                FileFormat::Wasm,
+                ObjectType::Wasm,
+            ),
+            (
+                "symbols/7f/883fcdc55336d0a809b0150f09500b.src.zip",
+                FileFormat::SourceBundle,
+                ObjectType::Macho,
+            ),
+        ];
+
+        for (fixture, format, object_type) in fixtures {
+            let data = test::read_fixture(fixture);
+            let archive = Archive::parse(&data).unwrap();
+            assert_eq!(archive.file_format(), format, "{fixture}");
+
+            assert!(format_matches_type(format, object_type), "{fixture}");
+            assert!(
+                format_matches_type(format, ObjectType::Unknown),
+                "{fixture}"
+            );
+        }
+
+        let breakpad = b"MODULE mac x86_64 502FC0A51EC13E479998684FA139DCA70 crash\n";
+        let archive = Archive::parse(breakpad).unwrap();
+        assert_eq!(archive.file_format(), FileFormat::Breakpad);
+        assert!(format_matches_type(FileFormat::Breakpad, ObjectType::Elf));
+
+        // files which are mislabeled by their source
+        assert!(!format_matches_type(FileFormat::Elf, ObjectType::Macho));
+        assert!(!format_matches_type(FileFormat::MachO, ObjectType::Pe));
+        assert!(!format_matches_type(FileFormat::Pdb, ObjectType::Elf));
+        assert!(!format_matches_type(FileFormat::Wasm, ObjectType::Elf));
+    }
+
+    #[tokio::test]
+    async fn test_fat_macho_slices() {
+        test::setup();
+
+        let x86_64 = test::read_fixture("symbols/502F/C0A5/1EC1/3E47/9998/684FA139DCA7");
+        let arm64 = test::read_fixture("symbols/2d10c42f-591d-3265-b147-78ba0868073f.dwarf-hidden");
+        let fat = fat_macho(&[(0x0100_0007, 3, &x86_64), (0x0100_000c, 0, &arm64)]);
+
+        let x86_64_id: DebugId = "502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap();
+        let arm64_id: DebugId = "2d10c42f-591d-3265-b147-78ba0868073f".parse().unwrap();
+        let missing_id: DebugId = "67e9247c-814e-392b-a027-dbde6748fcbf".parse().unwrap();
+
+        // serve the fat file for every requested id, using the `native` layout
+        let symbols_dir = tempdir();
+        for debug_id in [x86_64_id, arm64_id, missing_id] {
+            let uuid = debug_id.uuid().as_simple().to_string().to_uppercase();
+            let path = symbols_dir.path().join(format!(
+                "{}/{}/{}/{}/{}/{}",
+                &uuid[..4],
+                &uuid[4..8],
+                &uuid[8..12],
+                &uuid[12..16],
+                &uuid[16..20],
+                &uuid[20..]
+            ));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, &fat).unwrap();
+        }
+        let source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("fat"),
+            path: symbols_dir.path().to_owned(),
+            files: Default::default(),
+        }));
+
+        let cachedir = tempdir();
+        let objects_actor = make_objects_actor(&cachedir).await;
+
+        let find_object = |debug_id: DebugId| FindObject {
+            filetypes: &[FileType::MachDebug],
+            purpose: ObjectPurpose::Debug,
+            scope: Scope::Global,
+            identifier: debug_id.into(),
+            sources: Arc::new([source.clone()]),
+            cache_mode: CacheMode::Use,
+        };
+
+        // each architecture resolves to its own slice
+        for (debug_id, arch) in [(x86_64_id, Arch::Amd64), (arm64_id, Arch::Arm64)] {
+            let meta = objects_actor
+                .find(find_object(debug_id))
+                .await
+                .meta
+                .unwrap();
+            let object = objects_actor.fetch(meta.handle.unwrap()).await.unwrap();
+
+            assert_eq!(object.object().debug_id(), debug_id);
+            assert_eq!(object.object().arch(), arch);
+        }
+
+        // an architecture that is not part of the fat file is not found
+        let meta = objects_actor
+            .find(find_object(missing_id))
+            .await
+            .meta
+            .unwrap();
+        assert_eq!(meta.handle.unwrap_err(), CacheError::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_object_id_mismatch() {
+        test::setup();
+
+        let x86_64 = test::read_fixture("symbols/502F/C0A5/1EC1/3E47/9998/684FA139DCA7");
+        let matching_id: DebugId = "502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap();
+        let mismatching_id: DebugId = "67e9247c-814e-392b-a027-dbde6748fcbf".parse().unwrap();
+
+        // serve the same file for both ids, using the `native` layout
+        let symbols_dir = tempdir();
+        for debug_id in [matching_id, mismatching_id] {
+            let uuid = debug_id.uuid().as_simple().to_string().to_uppercase();
+            let path = symbols_dir.path().join(format!(
+                "{}/{}/{}/{}/{}/{}",
+                &uuid[..4],
+                &uuid[4..8],
+                &uuid[8..12],
+                &uuid[12..16],
+                &uuid[16..20],
+                &uuid[20..]
+            ));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, &x86_64).unwrap();
+        }
+        let source = |verify_ids: bool| {
+            SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+                id: SourceId::new(format!("verify-{verify_ids}")),
+                path: symbols_dir.path().to_owned(),
+                files: CommonSourceConfig {
+                    verify_ids,
+                    ..Default::default()
+                },
+            }))
+        };
+
+        let cachedir = tempdir();
+        let objects_actor = make_objects_actor(&cachedir).await;
+
+        let find_object = |debug_id: DebugId, source: SourceConfig| FindObject {
+            filetypes: &[FileType::MachDebug],
+            purpose: ObjectPurpose::Debug,
+            scope: Scope::Global,
+            identifier: debug_id.into(),
+            sources: Arc::new([source]),
+            cache_mode: CacheMode::Use,
+        };
+
+        // the file matching the requested id is used
+        let meta = objects_actor
+            .find(find_object(matching_id, source(true)))
+            .await
+            .meta
+            .unwrap();
+        let object = objects_actor.fetch(meta.handle.unwrap()).await.unwrap();
+        assert_eq!(object.object().debug_id(), matching_id);
+
+        // a file with a different id is rejected
+        let meta = objects_actor
+            .find(find_object(mismatching_id, source(true)))
+            .await
+            .meta
+            .unwrap();
+        assert_eq!(meta.handle.unwrap_err(), CacheError::NotFound);
+
+        // unless the verification is turned off for the source
+        let meta = objects_actor
+            .find(find_object(mismatching_id, source(false)))
+            .await
+            .meta
+            .unwrap();
+        let object = objects_actor.fetch(meta.handle.unwrap()).await.unwrap();
+        assert_eq!(object.object().debug_id(), matching_id);
+    }
+
+    /// Returns the contents of all files within `dir`, recursively.
+    fn read_all_files(dir: &std::path::Path) -> Vec<Vec<u8>> {
+        let mut contents = vec![];
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                contents.extend(read_all_files(&path));
+            } else {
+                contents.push(std::fs::read(path).unwrap());
+            }
+        }
+        contents
+    }
+
+    #[test]
+    fn test_is_html_page() {
+        assert!(is_html_page(b"<!DOCTYPE html>\n<html></html>"));
+        assert!(is_html_page(
+            b"\xef\xbb\xbf\r\n  <HTML><body>Not Found</body></HTML>"
+        ));
+        assert!(!is_html_page(
+            b"MODULE mac x86_64 502FC0A51EC13E479998684FA139DCA70 crash\n"
+        ));
+        assert!(!is_html_page(b"<htm"));
+        assert!(!is_html_page(b""));
+    }
+
+    #[tokio::test]
+    async fn test_html_page() {
+        test::setup();
+
+        // serves an error page with a `200` status for every file
+        let html_server = test::Server::with_router(Router::new().fallback(|| async {
+            Html("<!DOCTYPE html>\n<html><body><h1>404 Not Found</h1></body></html>")
+        }));
+        let html_source = html_server.source_with_config("html", "/", Default::default());
+        let (_symbol_server, source) = test::symbol_server();
+
+        let cachedir = tempdir();
+        let objects_actor = make_objects_actor(&cachedir).await;
+
+        let debug_id: DebugId = "502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap();
+        let find_object = |sources: Vec<SourceConfig>| FindObject {
+            filetypes: &[FileType::MachDebug],
+            purpose: ObjectPurpose::Debug,
+            scope: Scope::Global,
+            identifier: debug_id.into(),
+            sources: sources.into(),
+            cache_mode: CacheMode::Use,
+        };
+
+        // the page is treated as not found, instead of a malformed object
+        let meta = objects_actor
+            .find(find_object(vec![html_source.clone()]))
+            .await
+            .meta
+            .unwrap();
+        assert_eq!(meta.handle.unwrap_err(), CacheError::NotFound);
+        assert!(html_server.accesses() > 0);
+
+        // and it is not cached
+        for contents in read_all_files(cachedir.path()) {
+            assert!(!contents.windows(5).any(|window| window == b"<html"));
+        }
+
+        // the lookup falls through to the next source
+        let meta = objects_actor
+            .find(find_object(vec![html_source, source]))
+            .await
+            .meta
+            .unwrap();
+        let object = objects_actor.fetch(meta.handle.unwrap()).await.unwrap();
+        assert_eq!(object.object().debug_id(), debug_id);
+    }
 }
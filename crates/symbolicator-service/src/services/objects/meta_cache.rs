@@ -16,11 +16,12 @@ use symbolicator_sources::{ObjectId, RemoteFile};
 use tempfile::NamedTempFile;
 
 use crate::caching::{
-    CacheEntry, CacheItemRequest, CacheKey, CacheKeyBuilder, CacheVersions, Cacher,
+    CacheEntry, CacheItemRequest, CacheKey, CacheKeyBuilder, CacheMode, CacheVersions, Cacher,
 };
 use crate::services::caches::versions::META_CACHE_VERSIONS;
 use crate::types::{ObjectFeatures, Scope};
 
+use super::circuit_breaker::DownloadOutcomes;
 use super::FetchFileDataRequest;
 
 /// This requests metadata of a single file at a specific path/url.
@@ -31,12 +32,16 @@ pub(super) struct FetchFileMetaRequest {
     /// Source-type specific attributes.
     pub(super) file_source: RemoteFile,
     pub(super) object_id: ObjectId,
+    /// How the object file is looked up in the data cache.
+    pub(super) cache_mode: CacheMode,
 
     // XXX: This kind of state is not request data. We should find a different way to get this into
     // `<FetchFileMetaRequest as CacheItemRequest>::compute`, e.g. make the Cacher hold arbitrary
     // state for computing.
     pub(super) data_cache: Arc<Cacher<FetchFileDataRequest>>,
     pub(super) download_svc: Arc<crate::services::download::DownloadService>,
+    /// Collects the outcomes of downloads for the circuit breaker, if it is enabled.
+    pub(super) download_outcomes: Option<Arc<DownloadOutcomes>>,
 }
 
 /// Handle to local metadata file of an object.
@@ -104,7 +109,11 @@ impl FetchFileMetaRequest {
 
         let object_handle = self
             .data_cache
-            .compute_memoized(FetchFileDataRequest(self.clone()), cache_key.clone())
+            .compute_memoized_with_mode(
+                FetchFileDataRequest(self.clone()),
+                cache_key.clone(),
+                self.cache_mode,
+            )
             .await?;
 
         let object = object_handle.object();
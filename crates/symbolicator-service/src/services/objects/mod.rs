@@ -6,16 +6,21 @@ use sentry::{Hub, SentryFutureExt};
 
 use symbolicator_sources::{FileType, ObjectId, RemoteFile, RemoteFileUri, SourceConfig, SourceId};
 
-use crate::caching::{Cache, CacheEntry, CacheError, CacheKey, Cacher, SharedCacheRef};
+use crate::caching::{Cache, CacheEntry, CacheError, CacheKey, CacheMode, Cacher, SharedCacheRef};
 use crate::services::download::DownloadService;
-use crate::types::{AllObjectCandidates, ObjectCandidate, ObjectDownloadInfo, Scope};
+use crate::types::{
+    AllObjectCandidates, LookupCandidate, ObjectCandidate, ObjectDownloadInfo, Scope,
+};
 
+use circuit_breaker::DownloadOutcomes;
 use data_cache::FetchFileDataRequest;
 use meta_cache::FetchFileMetaRequest;
 
+pub use circuit_breaker::{CircuitBreakerStatus, SourceCircuitBreaker, SourceKey};
 pub use data_cache::ObjectHandle;
 pub use meta_cache::ObjectMetaHandle;
 
+mod circuit_breaker;
 mod data_cache;
 mod meta_cache;
 
@@ -43,6 +48,8 @@ pub struct FindObject {
     pub scope: Scope,
     pub identifier: ObjectId,
     pub sources: Arc<[SourceConfig]>,
+    /// How the object files are looked up in the caches.
+    pub cache_mode: CacheMode,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -77,6 +84,7 @@ pub struct ObjectsActor {
     meta_cache: Arc<Cacher<FetchFileMetaRequest>>,
     data_cache: Arc<Cacher<FetchFileDataRequest>>,
     download_svc: Arc<DownloadService>,
+    circuit_breaker: SourceCircuitBreaker,
 }
 
 impl ObjectsActor {
@@ -85,29 +93,49 @@ impl ObjectsActor {
         data_cache: Cache,
         shared_cache: SharedCacheRef,
         download_svc: Arc<DownloadService>,
+        circuit_breaker: SourceCircuitBreaker,
     ) -> Self {
         ObjectsActor {
             meta_cache: Arc::new(Cacher::new(meta_cache, Arc::clone(&shared_cache))),
             data_cache: Arc::new(Cacher::new(data_cache, shared_cache)),
             download_svc,
+            circuit_breaker,
         }
     }
 
+    /// The circuit breaker which skips sources that keep failing.
+    pub fn circuit_breaker(&self) -> &SourceCircuitBreaker {
+        &self.circuit_breaker
+    }
+
     /// Returns the requested object file.
     ///
     /// This fetches the requested object, re-downloading it from the source if it is no
     /// longer in the cache.
     pub async fn fetch(&self, file_handle: Arc<ObjectMetaHandle>) -> CacheEntry<Arc<ObjectHandle>> {
+        self.fetch_with_mode(file_handle, CacheMode::Use).await
+    }
+
+    /// Returns the requested object file, using the caches according to the [`CacheMode`].
+    pub async fn fetch_with_mode(
+        &self,
+        file_handle: Arc<ObjectMetaHandle>,
+        cache_mode: CacheMode,
+    ) -> CacheEntry<Arc<ObjectHandle>> {
         let cache_key = CacheKey::from_scoped_file(&file_handle.scope, &file_handle.file_source);
         let request = FetchFileDataRequest(FetchFileMetaRequest {
             scope: file_handle.scope.clone(),
             file_source: file_handle.file_source.clone(),
             object_id: file_handle.object_id.clone(),
+            cache_mode,
             data_cache: self.data_cache.clone(),
             download_svc: self.download_svc.clone(),
+            download_outcomes: None,
         });
 
-        self.data_cache.compute_memoized(request, cache_key).await
+        self.data_cache
+            .compute_memoized_with_mode(request, cache_key, cache_mode)
+            .await
     }
 
     /// Fetches matching objects and returns the metadata of the most suitable object.
@@ -119,6 +147,10 @@ impl ObjectsActor {
     /// Asking for the objects metadata from the data cache also triggers a download of each
     /// object, which will then be cached in the data cache.  The metadata itself is cached
     /// in the metadata cache which usually lives longer.
+    ///
+    /// Sources whose [`SourceCircuitBreaker`] is open are skipped, and are listed as erroneous
+    /// candidates.
+    #[tracing::instrument(name = "find_object", skip_all)]
     pub async fn find(&self, request: FindObject) -> FindResult {
         let FindObject {
             filetypes,
@@ -126,20 +158,101 @@ impl ObjectsActor {
             identifier,
             sources,
             purpose,
+            cache_mode,
         } = request;
+        let (sources, skipped_sources): (Vec<_>, Vec<_>) = prioritize_sources(&sources)
+            .into_iter()
+            .map(|source| {
+                let key = self.circuit_breaker.key(&source);
+                (source, key)
+            })
+            .partition(|(source, key)| {
+                key.as_ref()
+                    .map_or(true, |key| self.circuit_breaker.allow(source, key))
+            });
+        let (sources, source_keys): (Vec<_>, Vec<_>) = sources.into_iter().unzip();
+        let skipped_sources: Vec<_> = skipped_sources
+            .into_iter()
+            .map(|(source, _)| source)
+            .collect();
         let file_ids = self
             .download_svc
             .list_files(&sources, filetypes, &identifier)
             .await;
 
-        let file_metas = self.fetch_file_metas(file_ids, &identifier, scope).await;
+        let download_outcomes = self
+            .circuit_breaker
+            .is_enabled()
+            .then(|| Arc::new(DownloadOutcomes::default()));
+        let file_metas = self
+            .fetch_file_metas(
+                file_ids,
+                &identifier,
+                scope,
+                cache_mode,
+                download_outcomes.clone(),
+            )
+            .await;
+        if let Some(download_outcomes) = download_outcomes {
+            self.record_source_outcomes(&sources, &source_keys, &download_outcomes);
+        }
 
-        let candidates = create_candidates(&sources, &file_metas);
+        let candidates = create_candidates(&sources, &skipped_sources, &file_metas);
         let meta = select_meta(file_metas, purpose);
 
         FindResult { meta, candidates }
     }
 
+    /// Lists the files which [`find`](Self::find) would look up, without fetching them.
+    ///
+    /// The files are listed in the order of preference, along with whether their metadata
+    /// is already cached.  Listing the files of some sources, like Sentry, requires a request
+    /// to the source, but none of the files are downloaded.
+    pub async fn explain(&self, request: FindObject) -> Vec<LookupCandidate> {
+        let FindObject {
+            filetypes,
+            scope,
+            identifier,
+            sources,
+            ..
+        } = request;
+        let sources = prioritize_sources(&sources);
+        let file_ids = self
+            .download_svc
+            .list_files(&sources, filetypes, &identifier)
+            .await;
+
+        file_ids
+            .into_iter()
+            .map(|file_source| {
+                let cache_key =
+                    CacheKey::from_scoped_file(&file_scope(&file_source, &scope), &file_source);
+                LookupCandidate {
+                    source: file_source.source_id().clone(),
+                    location: file_source.redacted_uri(),
+                    cached: self.meta_cache.is_cached(&cache_key),
+                }
+            })
+            .collect()
+    }
+
+    /// Reports the outcome of looking up files on the `sources` to the circuit breaker.
+    ///
+    /// A source fails if all of its downloads failed. Sources which were not downloaded from,
+    /// because their lookups were served from the caches, are not reported.
+    fn record_source_outcomes(
+        &self,
+        sources: &[SourceConfig],
+        keys: &[Option<SourceKey>],
+        outcomes: &DownloadOutcomes,
+    ) {
+        for (source, key) in sources.iter().zip(keys) {
+            if let (Some(key), Some(success)) = (key, outcomes.any_success(source)) {
+                self.circuit_breaker.record(source, key, success);
+            }
+        }
+    }
+
     /// Fetch all [`ObjectMetaHandle`]s for the files.
     ///
     /// This concurrently looks up the file IDs in the meta-cache and returns all results.
@@ -151,24 +264,27 @@ impl ObjectsActor {
         file_sources: Vec<RemoteFile>,
         identifier: &ObjectId,
         scope: Scope,
+        cache_mode: CacheMode,
+        download_outcomes: Option<Arc<DownloadOutcomes>>,
     ) -> Vec<FoundMeta> {
         let queries = file_sources.into_iter().map(|file_source| {
-            let scope = if file_source.is_public() {
-                Scope::Global
-            } else {
-                scope.clone()
-            };
+            let scope = file_scope(&file_source, &scope);
             let cache_key = CacheKey::from_scoped_file(&scope, &file_source);
             let request = FetchFileMetaRequest {
                 scope,
                 file_source: file_source.clone(),
                 object_id: identifier.clone(),
+                cache_mode,
                 data_cache: self.data_cache.clone(),
                 download_svc: self.download_svc.clone(),
+                download_outcomes: download_outcomes.clone(),
             };
 
             async move {
-                let handle = self.meta_cache.compute_memoized(request, cache_key).await;
+                let handle = self
+                    .meta_cache
+                    .compute_memoized_with_mode(request, cache_key, cache_mode)
+                    .await;
                 FoundMeta {
                     file_source,
                     handle,
@@ -181,6 +297,30 @@ impl ObjectsActor {
     }
 }
 
+/// Returns the [`Scope`] in which the given file is cached.
+///
+/// Files of public sources are shared across all scopes.
+fn file_scope(file_source: &RemoteFile, scope: &Scope) -> Scope {
+    if file_source.is_public() {
+        Scope::Global
+    } else {
+        scope.clone()
+    }
+}
+
+/// Returns the enabled `sources`, in the order in which they are preferred.
+///
+/// Sources are sorted by ascending priority, and by their id if the priority is the same.
+fn prioritize_sources(sources: &[SourceConfig]) -> Vec<SourceConfig> {
+    let mut sources: Vec<_> = sources
+        .iter()
+        .filter(|source| source.is_enabled())
+        .cloned()
+        .collect();
+    sources.sort_by(|a, b| (a.priority(), a.id()).cmp(&(b.priority(), b.id())));
+    sources
+}
+
 /// Select the best [ObjectMetaHandle`] out of all lookups from the meta-cache.
 ///
 /// The lookups are expected to be in order or preference, so if two files are equally good
@@ -245,11 +385,17 @@ fn object_has_features(meta_handle: &ObjectMetaHandle, purpose: ObjectPurpose) -
 /// If there were any sources which did not return any [`DownloadService::list_files`]
 /// results they will get a [`ObjectDownloadInfo::NotFound`] entry with a location of `*`.
 /// In practice this will only affect the `sentry` source for now as all other sources
-/// always return [`DownloadService::list_files`] results.
-fn create_candidates(sources: &[SourceConfig], lookups: &[FoundMeta]) -> AllObjectCandidates {
+/// always return [`DownloadService::list_files`] results. The `skipped_sources` get an
+/// [`ObjectDownloadInfo::Error`] entry.
+fn create_candidates(
+    sources: &[SourceConfig],
+    skipped_sources: &[SourceConfig],
+    lookups: &[FoundMeta],
+) -> AllObjectCandidates {
     let mut source_ids: BTreeSet<SourceId> =
         sources.iter().map(|source| source.id()).cloned().collect();
-    let mut candidates: Vec<ObjectCandidate> = Vec::with_capacity(lookups.len() + source_ids.len());
+    let mut candidates: Vec<ObjectCandidate> =
+        Vec::with_capacity(lookups.len() + source_ids.len() + skipped_sources.len());
 
     for meta_lookup in lookups.iter() {
         let source_id = meta_lookup.file_source.source_id();
@@ -269,13 +415,26 @@ fn create_candidates(sources: &[SourceConfig], lookups: &[FoundMeta]) -> AllObje
         candidates.push(info);
     }
 
+    for source in skipped_sources {
+        let info = ObjectCandidate {
+            source: source.id().clone(),
+            location: RemoteFileUri::new("Source skipped after repeated failures"),
+            download: ObjectDownloadInfo::Error {
+                details: "circuit breaker is open".into(),
+            },
+            unwind: Default::default(),
+            debug: Default::default(),
+        };
+        candidates.push(info);
+    }
+
     candidates.into()
 }
 
 /// Build the [`ObjectCandidate`] info for the provided meta lookup result.
 fn create_candidate_info(meta_lookup: &FoundMeta) -> ObjectCandidate {
     let source = meta_lookup.file_source.source_id().clone();
-    let location = meta_lookup.file_source.uri();
+    let location = meta_lookup.file_source.redacted_uri();
     let download = match &meta_lookup.handle {
         Ok(handle) => ObjectDownloadInfo::Ok {
             features: handle.features(),
@@ -300,3 +459,34 @@ fn create_candidate_info(meta_lookup: &FoundMeta) -> ObjectCandidate {
         debug: Default::default(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(id: &str, extra: &str) -> SourceConfig {
+        serde_json::from_str(&format!(
+            r#"{{"id": "{id}", "type": "http", "url": "https://example.com/" {extra}}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_prioritize_sources() {
+        let sources = [
+            source("b", ""),
+            source("fallback", r#", "priority": 10"#),
+            source("disabled", r#", "enabled": false, "priority": -10"#),
+            source("a", ""),
+            source("first", r#", "priority": -1"#),
+        ];
+
+        let ids: Vec<_> = prioritize_sources(&sources)
+            .iter()
+            .map(|source| source.id().to_string())
+            .collect();
+
+        // sources with the same priority are ordered by id, and disabled sources are skipped
+        assert_eq!(ids, ["first", "a", "b", "fallback"]);
+    }
+}
@@ -12,7 +12,8 @@ use symbolic::ppdb::{PortablePdbCache, PortablePdbCacheConverter};
 use symbolicator_sources::{FileType, ObjectId, SourceConfig};
 
 use crate::caching::{
-    Cache, CacheEntry, CacheError, CacheItemRequest, CacheVersions, Cacher, SharedCacheRef,
+    Cache, CacheEntry, CacheError, CacheItemRequest, CacheMode, CacheVersions, Cacher,
+    SharedCacheRef,
 };
 use crate::types::{CandidateStatus, Scope};
 use crate::utils::futures::{m, measure};
@@ -64,6 +65,7 @@ impl PortablePdbCacheActor {
                 sources: request.sources,
                 scope: request.scope,
                 purpose: ObjectPurpose::Debug,
+                cache_mode: CacheMode::Use,
             })
             .await;
         derive_from_object_handle(found_object, CandidateStatus::Debug, |object_meta| {
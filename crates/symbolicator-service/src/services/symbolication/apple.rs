@@ -9,10 +9,12 @@ use regex::Regex;
 use symbolic::common::{Arch, CodeId, DebugId};
 use symbolicator_sources::{ObjectType, SourceConfig};
 
+use crate::caching::CacheMode;
 use crate::types::{
     CompleteObjectInfo, CompletedSymbolicationResponse, RawFrame, RawObjectInfo, RawStacktrace,
     Scope, SystemInfo,
 };
+use crate::utils::arch::normalize_arch;
 use crate::utils::hex::HexValue;
 
 use super::{StacktraceOrigin, SymbolicateStacktraces, SymbolicationActor};
@@ -33,9 +35,15 @@ impl SymbolicationActor {
             .code_type
             .as_ref()
             .and_then(|code_type| code_type.split(' ').next())
-            .and_then(|word| word.parse().ok())
+            .map(normalize_arch)
             .unwrap_or_default();
 
+        // Frames of arm64e processes may still carry pointer authentication codes.
+        let is_arm64e = report
+            .binary_images
+            .iter()
+            .any(|image| normalize_arch(&image.arch) == Arch::Arm64e);
+
         let modules = report
             .binary_images
             .into_iter()
@@ -56,7 +64,11 @@ impl SymbolicationActor {
                 .frames
                 .into_iter()
                 .map(|frame| RawFrame {
-                    instruction_addr: HexValue(frame.instruction_addr.0),
+                    instruction_addr: HexValue(if is_arm64e {
+                        strip_pointer_authentication_code(frame.instruction_addr.0)
+                    } else {
+                        frame.instruction_addr.0
+                    }),
                     package: frame.module,
                     ..RawFrame::default()
                 })
@@ -78,6 +90,9 @@ impl SymbolicationActor {
             origin: StacktraceOrigin::AppleCrashReport,
             signal: None,
             stacktraces,
+            deadline: None,
+            apply_source_context: true,
+            cache_mode: CacheMode::Use,
         };
 
         let mut system_info = SystemInfo {
@@ -163,6 +178,17 @@ impl AppleCrashReportState {
     }
 }
 
+/// The bits of an arm64e pointer that make up the actual address.
+///
+/// User space addresses on Apple platforms fit into 36 bits, the bits above that may hold a
+/// pointer authentication code.
+const ARM64E_ADDRESS_MASK: u64 = 0x0000_000f_ffff_ffff;
+
+/// Strips the pointer authentication code from an arm64e instruction address.
+fn strip_pointer_authentication_code(addr: u64) -> u64 {
+    addr & ARM64E_ADDRESS_MASK
+}
+
 fn map_apple_binary_image(image: apple_crash_report_parser::BinaryImage) -> CompleteObjectInfo {
     let code_id = CodeId::from_binary(&image.uuid.as_bytes()[..]);
     let debug_id = DebugId::from_uuid(image.uuid);
@@ -179,7 +205,27 @@ fn map_apple_binary_image(image: apple_crash_report_parser::BinaryImage) -> Comp
             0 => None,
             size => Some(size),
         },
+        arch: Some(image.arch),
     };
 
     raw_info.into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_pointer_authentication_code() {
+        // a signed return address, as found in arm64e crash reports
+        assert_eq!(
+            strip_pointer_authentication_code(0x8d4f_0001_8a3b_4c5c),
+            0x0000_0001_8a3b_4c5c
+        );
+        // addresses without a pointer authentication code are unchanged
+        assert_eq!(
+            strip_pointer_authentication_code(0x0000_0001_0264_1f3c),
+            0x0000_0001_0264_1f3c
+        );
+    }
+}
@@ -1,14 +1,17 @@
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use futures::future;
+use futures::{future, Sink, SinkExt};
+use serde::Serialize;
 use symbolic::common::{split_path, DebugId, InstructionInfo, Language, Name};
 use symbolic::demangle::{Demangle, DemangleOptions};
 use symbolic::ppdb::PortablePdbCache;
 use symbolic::symcache::{Function, SymCache};
 use symbolicator_sources::{HttpRemoteFile, ObjectType, SourceConfig};
 
-use crate::caching::{Cache, CacheError};
+use crate::caching::{Cache, CacheError, CacheMode};
 use crate::services::caches::SourceFilesCache;
 use crate::services::cficaches::CfiCacheActor;
 use crate::services::module_lookup::{CacheFileEntry, CacheLookupResult, ModuleLookup};
@@ -18,9 +21,11 @@ use crate::services::sourcemap::SourceMapService;
 use crate::services::symcaches::SymCacheActor;
 use crate::types::{
     CompleteObjectInfo, CompleteStacktrace, CompletedSymbolicationResponse, FrameStatus,
-    FrameTrust, ObjectFileStatus, RawFrame, RawStacktrace, Registers, Scope, Signal,
-    SymbolicatedFrame,
+    FrameTrust, MissingDebugFile, ObjectFileStatus, RawFrame, RawObjectInfo, RawStacktrace,
+    Registers, Scope, Signal, SymbolicatedFrame,
 };
+use crate::utils::arch::normalize_arch;
+use crate::utils::futures::with_deadline;
 use crate::utils::hex::HexValue;
 
 mod apple;
@@ -88,6 +93,7 @@ pub struct SymbolicationActor {
     diagnostics_cache: Cache,
     sourcemaps: SourceMapService,
     sourcefiles_cache: Arc<SourceFilesCache>,
+    source_context_lines: usize,
 }
 
 impl SymbolicationActor {
@@ -99,6 +105,7 @@ impl SymbolicationActor {
         diagnostics_cache: Cache,
         sourcemaps: SourceMapService,
         sourcefiles_cache: Arc<SourceFilesCache>,
+        source_context_lines: usize,
     ) -> Self {
         let demangle_cache = DemangleCache::builder()
             .max_capacity(10 * 1024 * 1024) // 10 MiB, considering key and value:
@@ -114,6 +121,7 @@ impl SymbolicationActor {
             diagnostics_cache,
             sourcemaps,
             sourcefiles_cache,
+            source_context_lines,
         }
     }
 
@@ -129,18 +137,23 @@ impl SymbolicationActor {
             signal,
             origin,
             modules,
-            ..
+            deadline,
+            apply_source_context,
+            cache_mode,
         } = request;
 
-        let mut module_lookup = ModuleLookup::new(scope.clone(), sources, modules.into_iter());
-        module_lookup
-            .fetch_caches(
-                self.symcaches.clone(),
-                self.ppdb_caches.clone(),
+        let mut module_lookup = self
+            .lookup_modules(
+                scope.clone(),
+                sources,
+                modules,
                 &stacktraces,
+                deadline,
+                cache_mode,
             )
             .await;
 
+        let symbolize_start = Instant::now();
         let mut metrics = StacktraceMetrics::default();
         let mut stacktraces: Vec<_> = stacktraces
             .into_iter()
@@ -154,21 +167,198 @@ impl SymbolicationActor {
                 )
             })
             .collect();
+        metric!(
+            timer("symbolication.phase") = symbolize_start.elapsed(),
+            "phase" => "symbolize"
+        );
+
+        if apply_source_context {
+            self.apply_source_context(&mut module_lookup, &mut stacktraces, &scope, deadline)
+                .await
+        }
+
+        // bring modules back into the original order
+        let modules = module_lookup.into_inner();
+        let num_frames = stacktraces.iter().map(|s| s.frames.len()).sum();
+        record_symbolication_metrics(origin, metrics, &modules, stacktraces.len(), num_frames);
+        let missing_debug_files = missing_debug_files(&modules);
+
+        Ok(CompletedSymbolicationResponse {
+            signal,
+            stacktraces,
+            modules,
+            missing_debug_files,
+            ..Default::default()
+        })
+    }
 
+    /// Symbolicates the stack traces of the `request` like [`symbolicate`](Self::symbolicate),
+    /// sending each of them to `emit` as soon as it is complete.
+    ///
+    /// The stack traces are symbolicated and emitted one after the other in the order of the
+    /// request, each with its source context. The caches and sources of a module are fetched once
+    /// the first stack trace referencing it is symbolicated, and shared with all later ones.
+    /// Symbolication waits while `emit` is not ready, and stops emitting once it fails. The
+    /// returned response contains everything else, and has no stack traces.
+    #[tracing::instrument(skip_all)]
+    pub async fn symbolicate_incremental(
+        &self,
+        request: SymbolicateStacktraces,
+        mut emit: impl Sink<CompleteStacktrace> + Unpin + Send,
+    ) -> anyhow::Result<CompletedSymbolicationResponse> {
+        let SymbolicateStacktraces {
+            stacktraces,
+            sources,
+            scope,
+            signal,
+            origin,
+            modules,
+            deadline,
+            apply_source_context,
+            cache_mode,
+        } = request;
+
+        let mut module_lookup = self.module_lookup(scope.clone(), sources, modules);
+
+        let num_stacktraces = stacktraces.len();
+        let mut num_frames = 0;
+        let mut symbolize_time = Duration::ZERO;
+        let mut metrics = StacktraceMetrics::default();
+        let mut emitting = true;
+        for trace in stacktraces {
+            module_lookup
+                .fetch_caches(
+                    self.symcaches.clone(),
+                    self.ppdb_caches.clone(),
+                    std::slice::from_ref(&trace),
+                    deadline,
+                    cache_mode,
+                )
+                .await;
+
+            let symbolize_start = Instant::now();
+            let mut stacktrace = symbolicate_stacktrace(
+                &self.demangle_cache,
+                trace,
+                &module_lookup,
+                &mut metrics,
+                signal,
+            );
+            symbolize_time += symbolize_start.elapsed();
+
+            if apply_source_context {
+                let stacktraces = std::slice::from_mut(&mut stacktrace);
+                self.apply_source_context(&mut module_lookup, stacktraces, &scope, deadline)
+                    .await;
+            }
+            num_frames += stacktrace.frames.len();
+            // the sink fails once nobody receives the stack traces anymore
+            emitting = emitting && emit.send(stacktrace).await.is_ok();
+        }
+        metric!(
+            timer("symbolication.phase") = symbolize_time,
+            "phase" => "symbolize"
+        );
+
+        let modules = module_lookup.into_inner();
+        record_symbolication_metrics(origin, metrics, &modules, num_stacktraces, num_frames);
+        let missing_debug_files = missing_debug_files(&modules);
+
+        Ok(CompletedSymbolicationResponse {
+            signal,
+            modules,
+            missing_debug_files,
+            ..Default::default()
+        })
+    }
+
+    /// Creates the [`ModuleLookup`] for the `modules` of a request.
+    fn module_lookup(
+        &self,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<CompleteObjectInfo>,
+    ) -> ModuleLookup {
+        // Clients use various aliases for architectures, which are normalized up front. The
+        // architecture of the debug file takes precedence once it is found.
+        let modules = modules.into_iter().map(|mut module| {
+            if let Some(arch) = module.raw.arch.as_deref() {
+                module.arch = normalize_arch(arch);
+            }
+            module
+        });
+        ModuleLookup::new(scope, sources, modules)
+    }
+
+    /// Creates the [`ModuleLookup`] for the `modules` of a request, and fetches the caches of all
+    /// the modules referenced by the `stacktraces`.
+    async fn lookup_modules(
+        &self,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<CompleteObjectInfo>,
+        stacktraces: &[RawStacktrace],
+        deadline: Option<Instant>,
+        cache_mode: CacheMode,
+    ) -> ModuleLookup {
+        let mut module_lookup = self.module_lookup(scope, sources, modules);
         module_lookup
-            .fetch_sources(self.objects.clone(), &stacktraces)
+            .fetch_caches(
+                self.symcaches.clone(),
+                self.ppdb_caches.clone(),
+                stacktraces,
+                deadline,
+                cache_mode,
+            )
             .await;
+        module_lookup
+    }
+
+    /// Adds the source context to the `stacktraces`, if the sources are available.
+    ///
+    /// Missing source files are not an error, the frames are left without source context instead.
+    async fn apply_source_context(
+        &self,
+        module_lookup: &mut ModuleLookup,
+        stacktraces: &mut [CompleteStacktrace],
+        scope: &Scope,
+        deadline: Option<Instant>,
+    ) {
+        // Source context is optional, so it is skipped for all the modules once the deadline
+        // has passed.
+        with_deadline(
+            deadline,
+            module_lookup.fetch_sources(self.objects.clone(), stacktraces),
+        )
+        .await;
+
+        self.set_source_context(module_lookup, stacktraces, scope, deadline)
+            .await
+    }
+
+    /// Adds the source context to the `stacktraces` from the sources fetched by
+    /// [`ModuleLookup::fetch_sources`], downloading the remote sources of in-app frames.
+    async fn set_source_context(
+        &self,
+        module_lookup: &ModuleLookup,
+        stacktraces: &mut [CompleteStacktrace],
+        scope: &Scope,
+        deadline: Option<Instant>,
+    ) {
+        let context_lines = self.source_context_lines;
 
         // Map collected source contexts to frames and collect URLs for remote source links.
         let mut remote_sources: HashMap<url::Url, Vec<&mut RawFrame>> = HashMap::new();
         {
             let debug_sessions = module_lookup.prepare_debug_sessions();
 
-            for trace in &mut stacktraces {
+            for trace in stacktraces.iter_mut() {
                 for frame in &mut trace.frames {
-                    if let Some(url) =
-                        module_lookup.try_set_source_context(&debug_sessions, &mut frame.raw)
-                    {
+                    if let Some(url) = module_lookup.try_set_source_context(
+                        &debug_sessions,
+                        &mut frame.raw,
+                        context_lines,
+                    ) {
                         // Only resolve source context from URLs if the frame is "in-app".
                         if frame.raw.in_app.unwrap_or(false) {
                             if let Some(vec) = remote_sources.get_mut(&url) {
@@ -187,30 +377,117 @@ impl SymbolicationActor {
             let cache = self.sourcefiles_cache.as_ref();
             let futures = remote_sources.into_iter().map(|(url, frames)| async {
                 if let Ok(source) = cache
-                    .fetch_file(&scope, HttpRemoteFile::from_url(url).into())
+                    .fetch_file(scope, HttpRemoteFile::from_url(url).into())
                     .await
                 {
                     for frame in frames {
-                        ModuleLookup::set_source_context(&source, frame);
+                        ModuleLookup::set_source_context(&source, frame, context_lines);
                     }
                 }
             });
+            with_deadline(deadline, future::join_all(futures)).await;
+        }
+    }
+
+    /// Fetches the caches for all the modules referenced by any of the `requests`.
+    ///
+    /// The modules are deduplicated across all the `requests`, so that each debug file is only
+    /// resolved once. Symbolicating the `requests` afterwards is then served from the in-memory
+    /// caches.
+    ///
+    /// Requests which do not use the caches are skipped, as their caches are fetched anew anyway.
+    pub fn prefetch_caches(
+        &self,
+        requests: &[SymbolicateStacktraces],
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let mut unique_modules = HashMap::new();
+        for request in requests {
+            if request.cache_mode != CacheMode::Use {
+                continue;
+            }
+            let module_lookup = ModuleLookup::new(
+                request.scope.clone(),
+                request.sources.clone(),
+                request.modules.iter().cloned(),
+            );
+            for object_info in module_lookup.referenced_modules(&request.stacktraces) {
+                let raw = &object_info.raw;
+                let key = (
+                    raw.ty,
+                    raw.code_id.clone(),
+                    raw.code_file.clone(),
+                    raw.debug_id.clone(),
+                    raw.debug_file.clone(),
+                    raw.debug_checksum.clone(),
+                );
+                unique_modules.entry(key).or_insert_with(|| {
+                    ModuleLookup::fetch_cache_file(
+                        self.symcaches.clone(),
+                        self.ppdb_caches.clone(),
+                        request.scope.clone(),
+                        request.sources.clone(),
+                        raw,
+                        CacheMode::Use,
+                    )
+                });
+            }
+        }
+
+        let futures = unique_modules.into_values();
+        async move {
             future::join_all(futures).await;
         }
+    }
 
-        // bring modules back into the original order
-        let modules = module_lookup.into_inner();
-        record_symbolication_metrics(origin, metrics, &modules, &stacktraces);
+    /// Eagerly downloads the debug files of the given `modules` and converts them to SymCaches.
+    ///
+    /// This goes through the same caches as symbolication, so that subsequent requests for these
+    /// `modules` are served from the caches. Returns the outcome for each of the `modules`.
+    pub async fn warm_caches(
+        &self,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+    ) -> Vec<WarmedModule> {
+        let futures = modules.into_iter().map(|module| {
+            let fetch = ModuleLookup::fetch_cache_file(
+                self.symcaches.clone(),
+                self.ppdb_caches.clone(),
+                scope.clone(),
+                sources.clone(),
+                &module,
+                CacheMode::Use,
+            );
+            async move {
+                let cache_file = fetch.await;
+                WarmedModule {
+                    debug_id: module.debug_id,
+                    code_id: module.code_id,
+                    status: cache_file.status(),
+                    error: cache_file.error().map(ToString::to_string),
+                }
+            }
+        });
 
-        Ok(CompletedSymbolicationResponse {
-            signal,
-            stacktraces,
-            modules,
-            ..Default::default()
-        })
+        future::join_all(futures).await
     }
 }
 
+/// The outcome of warming the caches of a single module, see
+/// [`SymbolicationActor::warm_caches`].
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmedModule {
+    /// The debug id of the module, as given in the request.
+    pub debug_id: Option<String>,
+    /// The code id of the module, as given in the request.
+    pub code_id: Option<String>,
+    /// Whether the caches of the module could be created.
+    pub status: ObjectFileStatus,
+    /// The reason the caches of the module could not be created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 /// A request for symbolication of multiple stack traces.
 pub struct SymbolicateStacktraces {
@@ -238,6 +515,18 @@ pub struct SymbolicateStacktraces {
     /// [`stacktraces`](Self::stacktraces). If a frame is not covered by any image, the frame cannot
     /// be symbolicated as it is not clear which debug file to load.
     pub modules: Vec<CompleteObjectInfo>,
+
+    /// The point in time after which symbolication stops waiting for debug files.
+    ///
+    /// Frames whose debug files have not been fetched by then are reported with
+    /// [`FrameStatus::Timeout`]. If this is `None`, symbolication waits for all debug files.
+    pub deadline: Option<Instant>,
+
+    /// Whether to add the surrounding source lines to the frames, if the sources are available.
+    pub apply_source_context: bool,
+
+    /// How the caches are used to fetch the debug files of the [`modules`](Self::modules).
+    pub cache_mode: CacheMode,
 }
 
 fn symbolicate_frame(
@@ -258,14 +547,8 @@ fn symbolicate_frame(
     match lookup_result.cache {
         Ok(CacheFileEntry::SymCache(symcache)) => {
             let symcache = symcache.get();
-            let relative_addr = get_relative_caller_addr(
-                symcache,
-                &lookup_result,
-                registers,
-                signal,
-                index,
-                adjustment,
-            )?;
+            let relative_addr =
+                get_relative_caller_addr(&lookup_result, registers, signal, index, adjustment)?;
             symbolicate_native_frame(
                 demangle_cache,
                 symcache,
@@ -279,6 +562,7 @@ fn symbolicate_frame(
             symbolicate_dotnet_frame(ppdb_cache.get(), frame, index)
         }
         Err(CacheError::Malformed(_)) => Err(FrameStatus::Malformed),
+        Err(_) if lookup_result.deadline_exceeded => Err(FrameStatus::Timeout),
         _ => Err(FrameStatus::Missing),
     }
 }
@@ -301,6 +585,8 @@ fn symbolicate_dotnet_frame(
     let result = SymbolicatedFrame {
         status: FrameStatus::Symbolicated,
         original_index: Some(index),
+        is_inline: false,
+        symbolicated_with: None,
         raw: RawFrame {
             lang: Some(line_info.file_lang),
             filename: Some(filename.to_string()),
@@ -313,8 +599,11 @@ fn symbolicate_dotnet_frame(
     Ok(vec![result])
 }
 
+/// Applies the caller address heuristics to the frame's instruction address.
+///
+/// These depend on the architecture of the module, which is taken from its SymCache, or is the one
+/// reported by the client if the SymCache does not know it.
 fn get_relative_caller_addr(
-    symcache: &SymCache,
     lookup_result: &CacheLookupResult,
     registers: &Registers,
     signal: Option<Signal>,
@@ -326,11 +615,10 @@ fn get_relative_caller_addr(
         // In cases where this is not possible we skip this part entirely and use the relative
         // address calculated by the lookup result as lookup address in the module.
         if let Some(absolute_addr) = lookup_result.object_info.rel_to_abs_addr(addr) {
+            let arch = lookup_result.object_info.arch;
             let is_crashing_frame = index == 0;
             let ip_register_value = if is_crashing_frame {
-                symcache
-                    .arch()
-                    .cpu_family()
+                arch.cpu_family()
                     .ip_register_name()
                     .and_then(|ip_reg_name| registers.get(ip_reg_name))
                     .map(|x| x.0)
@@ -338,7 +626,7 @@ fn get_relative_caller_addr(
                 None
             };
 
-            let mut instruction_info = InstructionInfo::new(symcache.arch(), absolute_addr);
+            let mut instruction_info = InstructionInfo::new(arch, absolute_addr);
             let instruction_info = instruction_info
                 .is_crashing_frame(is_crashing_frame)
                 .signal(signal.map(|signal| signal.0))
@@ -408,6 +696,8 @@ fn symbolicate_native_frame(
         rv.push(SymbolicatedFrame {
             status: FrameStatus::Symbolicated,
             original_index: Some(index),
+            is_inline: true,
+            symbolicated_with: lookup_result.symbolicated_with,
             raw: RawFrame {
                 package: lookup_result.object_info.raw.code_file.clone(),
                 addr_mode: lookup_result.preferred_addr_mode(),
@@ -440,6 +730,7 @@ fn symbolicate_native_frame(
 
     if let Some(last_frame) = rv.last_mut() {
         last_frame.raw.sym_addr = sym_addr;
+        last_frame.is_inline = false;
     }
 
     if rv.is_empty() {
@@ -578,11 +869,23 @@ fn is_likely_base_frame(frame: &SymbolicatedFrame) -> bool {
     false
 }
 
+/// Lists the debug files of the `modules` which could not be found on any source, deduplicated.
+fn missing_debug_files(modules: &[CompleteObjectInfo]) -> Vec<MissingDebugFile> {
+    let mut missing = Vec::new();
+    for file in modules.iter().filter_map(MissingDebugFile::from_module) {
+        if !missing.contains(&file) {
+            missing.push(file);
+        }
+    }
+    missing
+}
+
 fn record_symbolication_metrics(
     origin: StacktraceOrigin,
     metrics: StacktraceMetrics,
     modules: &[CompleteObjectInfo],
-    stacktraces: &[CompleteStacktrace],
+    num_stacktraces: usize,
+    num_frames: usize,
 ) {
     let origin = origin.to_string();
 
@@ -641,7 +944,7 @@ fn record_symbolication_metrics(
     );
 
     metric!(
-        time_raw("symbolication.num_stacktraces") = stacktraces.len() as u64,
+        time_raw("symbolication.num_stacktraces") = num_stacktraces as u64,
         "platform" => &platform, "origin" => &origin
     );
     metric!(
@@ -658,8 +961,7 @@ fn record_symbolication_metrics(
     );
 
     metric!(
-        time_raw("symbolication.num_frames") =
-            stacktraces.iter().map(|s| s.frames.len() as u64).sum::<u64>(),
+        time_raw("symbolication.num_frames") = num_frames as u64,
         "platform" => &platform, "origin" => &origin
     );
     metric!(
@@ -789,6 +1091,8 @@ fn symbolicate_stacktrace(
                 symbolicated_frames.push(SymbolicatedFrame {
                     status,
                     original_index: Some(index),
+                    is_inline: false,
+                    symbolicated_with: None,
                     raw: frame,
                 });
             }
@@ -820,6 +1124,7 @@ fn symbolicate_stacktrace(
         is_requesting: thread.is_requesting,
         registers: thread.registers,
         frames: symbolicated_frames,
+        truncated: false,
     }
 }
 
@@ -843,3 +1148,78 @@ impl std::fmt::Display for StacktraceOrigin {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::services::create_service;
+    use crate::test;
+
+    use super::*;
+
+    /// Makes sure that the timers of the download, convert and symbolize phases are all emitted
+    /// while symbolicating a frame.
+    ///
+    /// Unlike the other symbolication tests in `tests/integration`, this captures the metrics
+    /// with the crate-internal test client, which is why it lives here.
+    #[test]
+    fn test_phase_metrics() {
+        test::setup();
+        let cache_dir = test::tempdir();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let captured = crate::metrics::with_capturing_test_client(|| {
+            runtime.block_on(async {
+                let config = Config {
+                    cache_dir: Some(cache_dir.path().to_owned()),
+                    ..Default::default()
+                };
+                let handle = tokio::runtime::Handle::current();
+                let (symbolication, _objects) = create_service(&config, handle).unwrap();
+
+                let module: RawObjectInfo = serde_json::from_value(serde_json::json!({
+                    "type": "macho",
+                    "debug_id": "502fc0a5-1ec1-3e47-9998-684fa139dca7",
+                    "code_id": "502fc0a51ec13e479998684fa139dca7",
+                    "image_addr": "0x100000000",
+                    "image_size": 4096,
+                }))
+                .unwrap();
+                let stacktraces = serde_json::from_value(serde_json::json!([{
+                    "frames": [{"instruction_addr": "0x100000fa0"}],
+                }]))
+                .unwrap();
+
+                let request = SymbolicateStacktraces {
+                    modules: vec![module.into()],
+                    stacktraces,
+                    signal: None,
+                    origin: StacktraceOrigin::Symbolicate,
+                    sources: Arc::from([test::local_source()]),
+                    scope: Default::default(),
+                    deadline: None,
+                    apply_source_context: true,
+                    cache_mode: CacheMode::Use,
+                };
+                let response = symbolication.symbolicate(request).await.unwrap();
+                let frame = &response.stacktraces[0].frames[0];
+                assert_eq!(frame.status, FrameStatus::Symbolicated);
+            })
+        });
+
+        for phase in ["download", "convert", "symbolize"] {
+            let tag = format!("#phase:{phase}");
+            assert!(
+                captured
+                    .iter()
+                    .any(|metric| metric.starts_with("symbolication.phase:")
+                        && metric.ends_with(&tag)),
+                "missing timer of the {phase} phase"
+            );
+        }
+    }
+}
@@ -20,6 +20,7 @@ use tempfile::TempPath;
 use symbolic::common::{Arch, ByteView, CodeId, DebugId};
 use symbolicator_sources::{ObjectId, ObjectType, SourceConfig};
 
+use crate::caching::CacheMode;
 use crate::services::cficaches::{CfiCacheActor, FetchCfiCache, FetchedCfiCache};
 use crate::services::minidump::parse_stacktraces_from_minidump;
 use crate::services::module_lookup::object_file_status_from_cache_entry;
@@ -265,6 +266,7 @@ fn object_info_from_minidump_module(ty: ObjectType, module: &MinidumpModule) ->
             0 => None,
             size => Some(size),
         },
+        arch: None,
     })
 }
 
@@ -473,6 +475,9 @@ impl SymbolicationActor {
             origin: StacktraceOrigin::Minidump,
             signal: None,
             stacktraces,
+            deadline: None,
+            apply_source_context: true,
+            cache_mode: CacheMode::Use,
         };
 
         Ok((request, minidump_state))
@@ -580,6 +585,7 @@ mod tests {
             identifier: Default::default(),
             sources: Arc::from_iter([]),
             scope: Scope::Global,
+            cache_mode: CacheMode::Use,
         };
         let fut = obj.find(req);
         let size = dbg!(std::mem::size_of_val(&fut));
@@ -609,6 +615,7 @@ mod tests {
             identifier: Default::default(),
             sources: Arc::from_iter([]),
             scope: Scope::Global,
+            cache_mode: CacheMode::Use,
         };
         let fut = sym.symcaches.fetch(req);
         let size = dbg!(std::mem::size_of_val(&fut));
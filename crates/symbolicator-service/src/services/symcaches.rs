@@ -2,18 +2,20 @@ use std::fmt::Write;
 use std::fs::File;
 use std::io::{self, BufWriter};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::future::BoxFuture;
 use sentry::{Hub, SentryFutureExt};
 use tempfile::NamedTempFile;
+use tokio::sync::Semaphore;
 
 use symbolic::common::{ByteView, SelfCell};
 use symbolic::symcache::{SymCache, SymCacheConverter};
 use symbolicator_sources::{FileType, ObjectId, ObjectType, SourceConfig};
 
 use crate::caching::{
-    Cache, CacheEntry, CacheError, CacheItemRequest, CacheVersions, Cacher, SharedCacheRef,
+    Cache, CacheEntry, CacheError, CacheItemRequest, CacheKey, CacheKeyBuilder, CacheMode,
+    CacheVersions, Cacher, SharedCacheRef,
 };
 use crate::services::bitcode::BitcodeService;
 use crate::services::objects::{
@@ -45,6 +47,10 @@ pub struct SymCacheActor {
     objects: ObjectsActor,
     bitcode_svc: BitcodeService,
     il2cpp_svc: Il2cppService,
+    /// Limits the number of concurrent conversions, see [`limit_conversion`].
+    conversion_permits: Arc<Semaphore>,
+    /// The version written into the symcache keys, see [`symcache_key`].
+    key_version: u32,
 }
 
 impl SymCacheActor {
@@ -54,12 +60,16 @@ impl SymCacheActor {
         objects: ObjectsActor,
         bitcode_svc: BitcodeService,
         il2cpp_svc: Il2cppService,
+        max_conversions: usize,
+        key_version: u32,
     ) -> Self {
         SymCacheActor {
             symcaches: Arc::new(Cacher::new(cache, shared_cache)),
             objects,
             bitcode_svc,
             il2cpp_svc,
+            conversion_permits: Arc::new(Semaphore::new(max_conversions)),
+            key_version,
         }
     }
 }
@@ -74,6 +84,12 @@ struct FetchSymCacheInternal {
 
     /// ObjectMeta handle of the original DIF object to fetch.
     object_meta: Arc<ObjectMetaHandle>,
+
+    /// How the original DIF object is looked up in the caches.
+    cache_mode: CacheMode,
+
+    /// Limits the number of concurrent conversions.
+    conversion_permits: Arc<Semaphore>,
 }
 
 /// Fetches the needed DIF objects and spawns symcache computation.
@@ -91,10 +107,38 @@ async fn fetch_difs_and_compute_symcache(
     objects_actor: &ObjectsActor,
     object_meta: Arc<ObjectMetaHandle>,
     secondary_sources: SecondarySymCacheSources,
+    cache_mode: CacheMode,
+    conversion_permits: &Semaphore,
 ) -> CacheEntry {
-    let object_handle = objects_actor.fetch(object_meta.clone()).await?;
+    let object_handle = objects_actor
+        .fetch_with_mode(object_meta.clone(), cache_mode)
+        .await?;
+
+    limit_conversion(conversion_permits, || {
+        let start = Instant::now();
+        let result = write_symcache(temp_file.as_file_mut(), &object_handle, secondary_sources);
+        metric!(timer("symbolication.phase") = start.elapsed(), "phase" => "convert");
+        result
+    })
+    .await
+}
+
+/// Runs the CPU-bound `convert` once one of the `permits` is available.
+///
+/// Conversions are gated separately from downloads, so that the conversions of many
+/// concurrently downloaded objects do not starve each other of CPU time.
+async fn limit_conversion<T>(
+    permits: &Semaphore,
+    convert: impl FnOnce() -> CacheEntry<T>,
+) -> CacheEntry<T> {
+    let queued_at = Instant::now();
+    let _permit = permits
+        .acquire()
+        .await
+        .map_err(|_| CacheError::InternalError)?;
+    metric!(timer("symcaches.conversion.queue_wait") = queued_at.elapsed());
 
-    write_symcache(temp_file.as_file_mut(), &object_handle, secondary_sources)
+    convert()
 }
 
 impl CacheItemRequest for FetchSymCacheInternal {
@@ -108,6 +152,8 @@ impl CacheItemRequest for FetchSymCacheInternal {
             &self.objects_actor,
             self.object_meta.clone(),
             self.secondary_sources.clone(),
+            self.cache_mode,
+            &self.conversion_permits,
         );
 
         let timeout = Duration::from_secs(1200);
@@ -128,9 +174,13 @@ pub struct FetchSymCache {
     pub identifier: ObjectId,
     pub sources: Arc<[SourceConfig]>,
     pub scope: Scope,
+    /// Whether the symcache and the object it is created from are read from, and written to,
+    /// the caches.
+    pub cache_mode: CacheMode,
 }
 
 impl SymCacheActor {
+    #[tracing::instrument(name = "fetch_symcache", skip_all)]
     pub async fn fetch(&self, request: FetchSymCache) -> DerivedCache<OwnedSymCache> {
         let found_object = self
             .objects
@@ -140,6 +190,7 @@ impl SymCacheActor {
                 sources: request.sources.clone(),
                 scope: request.scope.clone(),
                 purpose: ObjectPurpose::Debug,
+                cache_mode: request.cache_mode,
             })
             .await;
 
@@ -183,34 +234,61 @@ impl SymCacheActor {
             let (bcsymbolmap_handle, il2cpp_handle) =
                 futures::future::join(fetch_bcsymbolmap, fetch_il2cpp).await;
 
-            let mut builder = handle.cache_key_builder();
-            if let Some(handle) = &bcsymbolmap_handle {
-                builder.write_str("\nbcsymbolmap:\n").unwrap();
-                builder.write_file_meta(&handle.file).unwrap();
-            }
-            if let Some(handle) = &il2cpp_handle {
-                builder.write_str("\nil2cpp:\n").unwrap();
-                builder.write_file_meta(&handle.file).unwrap();
-            }
-
-            let cache_key = builder.build();
-
             let secondary_sources = SecondarySymCacheSources {
                 bcsymbolmap_handle,
                 il2cpp_handle,
             };
+            let cache_key = symcache_key(
+                handle.cache_key_builder(),
+                self.key_version,
+                &secondary_sources,
+            );
 
-            let request = FetchSymCacheInternal {
+            // A refreshed object meta has already refreshed the object file itself, so there is
+            // no need to download it a second time.
+            let object_cache_mode = match request.cache_mode {
+                CacheMode::Bypass => CacheMode::Bypass,
+                CacheMode::Use | CacheMode::Refresh => CacheMode::Use,
+            };
+            let request_internal = FetchSymCacheInternal {
                 objects_actor: self.objects.clone(),
                 secondary_sources,
                 object_meta: Arc::clone(&handle),
+                cache_mode: object_cache_mode,
+                conversion_permits: Arc::clone(&self.conversion_permits),
             };
-            self.symcaches.compute_memoized(request, cache_key).await
+            self.symcaches
+                .compute_memoized_with_mode(request_internal, cache_key, request.cache_mode)
+                .await
         })
         .await
     }
 }
 
+/// Builds the [`CacheKey`] of a symcache, given the `builder` of the key of its object.
+///
+/// A non-zero `key_version` is written into the key, so that bumping it moves all symcaches to
+/// new cache paths, leaving the previous files to expire. A version of `0` keeps the keys
+/// unchanged.
+fn symcache_key(
+    mut builder: CacheKeyBuilder,
+    key_version: u32,
+    secondary_sources: &SecondarySymCacheSources,
+) -> CacheKey {
+    if key_version != 0 {
+        builder.write_attribute("version", key_version).unwrap();
+    }
+    if let Some(handle) = &secondary_sources.bcsymbolmap_handle {
+        builder.write_str("\nbcsymbolmap:\n").unwrap();
+        builder.write_file_meta(&handle.file).unwrap();
+    }
+    if let Some(handle) = &secondary_sources.il2cpp_handle {
+        builder.write_str("\nil2cpp:\n").unwrap();
+        builder.write_file_meta(&handle.file).unwrap();
+    }
+    builder.build()
+}
+
 /// Encapsulation of all the source artifacts that are being used to create SymCaches.
 #[derive(Clone, Debug, Default)]
 struct SecondarySymCacheSources {
@@ -293,6 +371,7 @@ fn write_symcache(
 mod tests {
     use std::fs;
     use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
 
     use symbolic::common::{DebugId, Uuid};
@@ -303,15 +382,18 @@ mod tests {
     use crate::services::bitcode::BitcodeService;
     use crate::services::DownloadService;
     use crate::test::{self, fixture};
+    use crate::types::ObjectDownloadInfo;
     use symbolicator_sources::{
         CommonSourceConfig, DirectoryLayoutType, FilesystemSourceConfig, SourceConfig, SourceId,
     };
 
     /// Creates a `SymCacheActor` with the given cache directory
-    /// and timeout for download cache misses.
+    /// and timeout for download cache misses and malformed caches.
     async fn symcache_actor(cache_dir: PathBuf, timeout: Duration) -> SymCacheActor {
         let mut cache_config = CacheConfigs::default();
         cache_config.downloaded.retry_misses_after = Some(timeout);
+        cache_config.downloaded.retry_malformed_after = Some(timeout);
+        cache_config.derived.retry_malformed_after = Some(timeout);
 
         let config = Config {
             cache_dir: Some(cache_dir),
@@ -329,11 +411,70 @@ mod tests {
             caches.objects,
             shared_cache.clone(),
             downloader.clone(),
+            Default::default(),
         );
         let bitcode = BitcodeService::new(caches.auxdifs, shared_cache.clone(), downloader.clone());
         let il2cpp = Il2cppService::new(caches.il2cpp, shared_cache.clone(), downloader);
 
-        SymCacheActor::new(caches.symcaches, shared_cache, objects, bitcode, il2cpp)
+        SymCacheActor::new(
+            caches.symcaches,
+            shared_cache,
+            objects,
+            bitcode,
+            il2cpp,
+            1,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_symcache_key_version() {
+        let secondary_sources = SecondarySymCacheSources::default();
+        let key = |key_version| {
+            let builder = CacheKey::scoped_builder(&Scope::Global);
+            symcache_key(builder, key_version, &secondary_sources)
+        };
+
+        // the default version keeps the keys of existing symcaches
+        let unversioned = CacheKey::scoped_builder(&Scope::Global).build();
+        assert_eq!(key(0).cache_path(0), unversioned.cache_path(0));
+
+        // bumping the version moves the symcaches to new paths
+        assert_ne!(key(1).cache_path(0), key(0).cache_path(0));
+        assert_ne!(key(2).cache_path(0), key(1).cache_path(0));
+        assert!(key(2).metadata().contains("version: 2\n"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_limit_conversion() {
+        let permits = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let conversions = (0..8).map(|i| {
+            let permits = Arc::clone(&permits);
+            let running = Arc::clone(&running);
+            let max_running = Arc::clone(&max_running);
+            tokio::spawn(async move {
+                limit_conversion(&permits, || {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(i)
+                })
+                .await
+            })
+        });
+        let results: Vec<_> = futures::future::join_all(conversions)
+            .await
+            .into_iter()
+            .map(|result| result.unwrap().unwrap())
+            .collect();
+
+        // all conversions completed, but never more than two at once
+        assert_eq!(results, (0..8).collect::<Vec<_>>());
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
     }
 
     /// Tests that a symcache is regenerated when it was created without a BcSymbolMap
@@ -389,6 +530,7 @@ mod tests {
             identifier,
             sources: Arc::new([source]),
             scope: Scope::Global,
+            cache_mode: CacheMode::Use,
         };
 
         let symcache_actor = symcache_actor(cache_dir.path().to_owned(), TIMEOUT).await;
@@ -453,4 +595,71 @@ mod tests {
         );
         assert_eq!(sl.function().name(), "-[SentryMessage initWithFormatted:]");
     }
+
+    /// Tests that a malformed object is not downloaded again until its malformed cache entry
+    /// expires, even if it has been fixed in the meantime.
+    #[tokio::test]
+    async fn test_symcache_malformed() {
+        test::setup();
+
+        const TIMEOUT: Duration = Duration::from_secs(2);
+
+        let cache_dir = test::tempdir();
+        let symbol_dir = test::tempdir();
+
+        let macho_dir = symbol_dir.path().join("2d/10c42f591d3265b14778ba0868073f/");
+        fs::create_dir_all(&macho_dir).unwrap();
+        fs::write(macho_dir.join("debuginfo"), "definitely not an object file").unwrap();
+
+        let source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("local"),
+            path: symbol_dir.path().to_owned(),
+            files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
+        }));
+
+        let identifier = ObjectId::from(DebugId::from_uuid(
+            Uuid::parse_str("2d10c42f-591d-3265-b147-78ba0868073f").unwrap(),
+        ));
+
+        let fetch_symcache = FetchSymCache {
+            object_type: ObjectType::Macho,
+            identifier,
+            sources: Arc::new([source]),
+            scope: Scope::Global,
+            cache_mode: CacheMode::Use,
+        };
+
+        let symcache_actor = symcache_actor(cache_dir.path().to_owned(), TIMEOUT).await;
+
+        let is_malformed = |symcache: &DerivedCache<OwnedSymCache>| {
+            symcache
+                .candidates
+                .0
+                .iter()
+                .any(|candidate| candidate.download == ObjectDownloadInfo::Malformed)
+        };
+
+        let symcache = symcache_actor.fetch(fetch_symcache.clone()).await;
+        assert!(symcache.cache.is_err());
+        assert!(is_malformed(&symcache));
+
+        // Fix the object file. As the object is still cached as malformed, it is not downloaded
+        // again and the symcache can not be created.
+        fs::copy(
+            fixture("symbols/2d10c42f-591d-3265-b147-78ba0868073f.dwarf-hidden"),
+            macho_dir.join("debuginfo"),
+        )
+        .unwrap();
+
+        let symcache = symcache_actor.fetch(fetch_symcache.clone()).await;
+        assert!(symcache.cache.is_err());
+        assert!(is_malformed(&symcache));
+
+        // Sleep long enough for the malformed cache entry to become invalid.
+        std::thread::sleep(TIMEOUT);
+
+        let symcache = symcache_actor.fetch(fetch_symcache).await;
+        assert!(symcache.cache.is_ok());
+        assert!(!is_malformed(&symcache));
+    }
 }
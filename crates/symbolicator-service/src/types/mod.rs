@@ -10,7 +10,7 @@ use std::fmt;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use symbolic::common::{Arch, CodeId, DebugId, Language};
-use symbolicator_sources::ObjectType;
+use symbolicator_sources::{ObjectType, SourceId};
 
 use crate::utils::addr::AddrMode;
 use crate::utils::hex::HexValue;
@@ -18,7 +18,8 @@ use crate::utils::hex::HexValue;
 mod objects;
 
 pub use objects::{
-    AllObjectCandidates, CandidateStatus, ObjectCandidate, ObjectDownloadInfo, ObjectUseInfo,
+    AllObjectCandidates, CandidateStatus, LookupCandidate, ObjectCandidate, ObjectDownloadInfo,
+    ObjectUseInfo,
 };
 
 /// OS-specific crash signal value.
@@ -274,6 +275,19 @@ pub struct RawObjectInfo {
     /// The size is infered from the module list if not specified.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub image_size: Option<u64>,
+
+    /// Architecture of the module, as reported by the client.
+    ///
+    /// This accepts the various aliases of architectures, such as `amd64` for `x86_64`. It is
+    /// returned unchanged as `original_arch`, as the normalized architecture is reported as the
+    /// [`arch`](CompleteObjectInfo::arch) of the module.
+    #[serde(
+        default,
+        rename(serialize = "original_arch"),
+        alias = "original_arch",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub arch: Option<String>,
 }
 
 /// Information on the symbolication status of this frame.
@@ -292,6 +306,8 @@ pub enum FrameStatus {
     Missing,
     /// The retrieved debug file could not be processed.
     Malformed,
+    /// The debug file could not be retrieved before the symbolication timeout of the request.
+    Timeout,
 }
 
 /// A potentially symbolicated frame in the symbolication response.
@@ -309,10 +325,41 @@ pub struct SymbolicatedFrame {
     ///     this might occur within recursion, so the instruction address is not a good
     pub original_index: Option<usize>,
 
+    /// Whether this frame was inlined into the frame following it.
+    ///
+    /// An instruction address expands into the chain of inlined frames from the innermost to the
+    /// outermost function. All of them except the outermost one are marked as inline.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_inline: bool,
+
+    /// The kind of debug information this frame was symbolicated with, if it was not full debug
+    /// info.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbolicated_with: Option<SymbolicatedWith>,
+
     #[serde(flatten)]
     pub raw: RawFrame,
 }
 
+/// Reduced debug information a frame was symbolicated with, see
+/// [`SymbolicatedFrame::symbolicated_with`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolicatedWith {
+    /// Only the symbol table of the object was available, such as for stripped binaries.
+    ///
+    /// The frame has a function name, but no file and line information.
+    Symtab,
+}
+
+impl SymbolicatedWith {
+    /// Determines the kind of debug information a cache built from an object with the given
+    /// `features` provides, or `None` if it has full debug info.
+    pub fn from_features(features: &ObjectFeatures) -> Option<Self> {
+        (!features.has_debug_info && features.has_symbols).then_some(Self::Symtab)
+    }
+}
+
 /// A symbolicated stacktrace.
 ///
 /// Frames in this request may or may not be symbolicated. The status field contains information on
@@ -340,6 +387,10 @@ pub struct CompleteStacktrace {
 
     /// Frames of this stack trace.
     pub frames: Vec<SymbolicatedFrame>,
+
+    /// Whether frames at the bottom of this stack trace were removed to limit the response size.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
 /// Information on a debug information file.
@@ -419,10 +470,22 @@ pub struct CompleteObjectInfo {
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub unwind_status: Option<ObjectFileStatus>,
 
+    /// The id of the source which provided the file with debug info.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub debug_source: Option<SourceId>,
+
+    /// The cache key of the file with debug info.
+    ///
+    /// This is the same identifier that is used for this file in Symbolicator's logs.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub debug_cache_key: Option<String>,
+
     /// Features available during symbolication.
     pub features: ObjectFeatures,
 
     /// Actual architecture of this debug file.
+    ///
+    /// Until the debug file is found, this is the normalized architecture reported by the client.
     pub arch: Arch,
 
     /// More information on the object file.
@@ -491,6 +554,8 @@ impl From<RawObjectInfo> for CompleteObjectInfo {
         CompleteObjectInfo {
             debug_status: ObjectFileStatus::Unused,
             unwind_status: None,
+            debug_source: None,
+            debug_cache_key: None,
             features: ObjectFeatures::default(),
             arch: Arch::Unknown,
             raw,
@@ -571,6 +636,45 @@ pub struct CompletedSymbolicationResponse {
 
     /// A list of images, extended with status information.
     pub modules: Vec<CompleteObjectInfo>,
+
+    /// The debug files of all modules which could not be found on any source, deduplicated.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_debug_files: Vec<MissingDebugFile>,
+}
+
+/// A debug file which could not be found on any of the sources of a request.
+///
+/// These are the debug files that need to be uploaded to fully symbolicate the request.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MissingDebugFile {
+    /// Identifier of the debug file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_id: Option<String>,
+
+    /// Identifier of the code file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_id: Option<String>,
+
+    /// The type of the module.
+    pub filetype: ObjectType,
+
+    /// The architecture of the module.
+    pub arch: Arch,
+}
+
+impl MissingDebugFile {
+    /// Returns the debug file of the `module`, if it was not found on any source.
+    pub fn from_module(module: &CompleteObjectInfo) -> Option<Self> {
+        if module.debug_status != ObjectFileStatus::Missing {
+            return None;
+        }
+        Some(Self {
+            debug_id: module.raw.debug_id.clone(),
+            code_id: module.raw.code_id.clone(),
+            filetype: module.raw.ty,
+            arch: module.arch,
+        })
+    }
 }
 
 // Some of the renames are there only to make it synchronized
@@ -49,6 +49,22 @@ pub struct ObjectCandidate {
     pub debug: ObjectUseInfo,
 }
 
+/// A file which would be tried when looking up a DIF object.
+///
+/// This is returned when explaining a lookup without actually performing it, so only the
+/// location of the file is known, but not whether it exists or is usable.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LookupCandidate {
+    /// The ID of the object source where this DIF would be looked up.
+    pub source: SourceId,
+    /// The location of this DIF on the object source.
+    pub location: RemoteFileUri,
+    /// Whether the result of looking up this DIF is already cached.
+    ///
+    /// Cached files are not fetched from the source again until the cache entry expires.
+    pub cached: bool,
+}
+
 /// Information about downloading of a DIF object.
 ///
 /// This is part of the larger [`ObjectCandidate`] struct.
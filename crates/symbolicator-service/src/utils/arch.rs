@@ -0,0 +1,45 @@
+use symbolic::common::Arch;
+
+/// Parses the name of a CPU architecture, accepting the aliases used by the various platforms.
+///
+/// Names are matched case-insensitively, so that for example `amd64`, `x86_64` and `X86-64` all
+/// resolve to [`Arch::Amd64`]. `arm64e` stays distinct from `arm64`, as its instruction
+/// addresses may carry pointer authentication codes. Unknown names resolve to
+/// [`Arch::Unknown`].
+pub fn normalize_arch(name: &str) -> Arch {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "x86_64" | "x86-64" | "amd64" | "x64" => Arch::Amd64,
+        "x86" | "i386" | "i486" | "i586" | "i686" | "ia32" => Arch::X86,
+        "arm64" | "arm-64" | "aarch64" => Arch::Arm64,
+        "arm64e" => Arch::Arm64e,
+        name => name.parse().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_arch() {
+        let cases = [
+            ("x86_64", Arch::Amd64),
+            ("amd64", Arch::Amd64),
+            ("X86-64", Arch::Amd64),
+            ("x64", Arch::Amd64),
+            ("i386", Arch::X86),
+            ("x86", Arch::X86),
+            ("arm64", Arch::Arm64),
+            ("aarch64", Arch::Arm64),
+            ("ARM-64", Arch::Arm64),
+            ("arm64e", Arch::Arm64e),
+            ("armv7", Arch::ArmV7),
+            ("", Arch::Unknown),
+            ("definitely-not-an-arch", Arch::Unknown),
+        ];
+
+        for (name, arch) in cases {
+            assert_eq!(normalize_arch(name), arch, "{name}");
+        }
+    }
+}
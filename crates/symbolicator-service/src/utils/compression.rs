@@ -22,6 +22,12 @@ pub fn maybe_decompress_file(src: &mut NamedTempFile) -> io::Result<()> {
     // kinds of downloaded files, not only "objects".
     metric!(time_raw("objects.size") = metadata.len());
 
+    // Files shorter than any of the magic bytes can not be compressed.
+    if metadata.len() < 4 {
+        metric!(counter("compression") += 1, "type" => "none");
+        return Ok(());
+    }
+
     file.rewind()?;
     let mut magic_bytes: [u8; 4] = [0, 0, 0, 0];
     file.read_exact(&mut magic_bytes)?;
@@ -106,3 +112,68 @@ pub fn tempfile_in_parent(file: &NamedTempFile) -> io::Result<NamedTempFile> {
         .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
     NamedTempFile::new_in(dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    const CONTENTS: &[u8] = b"MODULE Linux x86_64 E45DB8DFAF2D09FD640C8FE377D572DE0 libm-2.23.so\n";
+
+    fn decompress(bytes: &[u8]) -> Vec<u8> {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        maybe_decompress_file(&mut file).unwrap();
+        std::fs::read(file.path()).unwrap()
+    }
+
+    #[test]
+    fn test_decompress_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(CONTENTS).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(decompress(&compressed), CONTENTS);
+    }
+
+    #[test]
+    fn test_decompress_zstd() {
+        let compressed = zstd::encode_all(CONTENTS, 0).unwrap();
+
+        assert_eq!(decompress(&compressed), CONTENTS);
+    }
+
+    #[test]
+    fn test_decompress_cab() {
+        // cab files are extracted with the external `cabextract` tool
+        if Command::new("cabextract")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            println!("Skipping due to missing cabextract");
+            return;
+        }
+
+        let compressed = crate::test::read_fixture("compression/libm.sym.cab");
+        assert_eq!(decompress(&compressed), CONTENTS);
+    }
+
+    #[test]
+    fn test_decompress_malformed_cab() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"MSCF garbage").unwrap();
+        assert!(maybe_decompress_file(&mut file).is_err());
+    }
+
+    #[test]
+    fn test_decompress_uncompressed() {
+        assert_eq!(decompress(CONTENTS), CONTENTS);
+        // files shorter than the magic bytes are kept as well
+        assert_eq!(decompress(b"ab"), b"ab");
+    }
+}
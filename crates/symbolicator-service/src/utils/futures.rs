@@ -5,6 +5,16 @@ use tokio::task::JoinHandle;
 
 use crate::metrics::{self, prelude::*};
 
+/// Runs the future `f` until it completes or the `deadline` is reached.
+///
+/// Returns `None` if the `deadline` was reached first, in which case `f` is dropped.
+pub async fn with_deadline<F: Future>(deadline: Option<Instant>, f: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), f).await.ok(),
+        None => Some(f.await),
+    }
+}
+
 /// Execute a callback on dropping of the container type.
 ///
 /// The callback must not panic under any circumstance. Since it is called while dropping an item,
@@ -1,6 +1,8 @@
 use std::net::IpAddr;
 
 use ipnetwork::Ipv4Network;
+use reqwest::redirect;
+use url::Url;
 
 use crate::config::Config;
 
@@ -37,18 +39,99 @@ fn is_external_ip(ip: std::net::IpAddr) -> bool {
     true
 }
 
+/// The reason a redirect is not followed.
+#[derive(Debug, thiserror::Error)]
+enum RedirectError {
+    #[error("too many redirects, the maximum is {0}")]
+    TooMany(usize),
+    #[error("redirect loop at {0}")]
+    Loop(Url),
+    #[error("redirect to another host: {0}")]
+    CrossHost(Url),
+}
+
+/// Creates the policy for following redirects.
+///
+/// Up to `max_redirects` redirects are followed, and redirect loops are rejected right away.
+/// Unless `cross_host` is set, redirects to another host than the one of the original request are
+/// rejected as well.
+fn redirect_policy(max_redirects: usize, cross_host: bool) -> redirect::Policy {
+    redirect::Policy::custom(move |attempt| {
+        let previous = attempt.previous();
+        if previous.len() > max_redirects {
+            return attempt.error(RedirectError::TooMany(max_redirects));
+        }
+        if previous.contains(attempt.url()) {
+            let url = attempt.url().clone();
+            return attempt.error(RedirectError::Loop(url));
+        }
+        let original_host = previous.first().and_then(Url::host_str);
+        if !cross_host && attempt.url().host_str() != original_host {
+            let url = attempt.url().clone();
+            return attempt.error(RedirectError::CrossHost(url));
+        }
+        attempt.follow()
+    })
+}
+
+/// The default HTTP User-Agent, used unless [`Config::user_agent`] is configured.
+pub const DEFAULT_USER_AGENT: &str = concat!("symbolicator/", env!("CARGO_PKG_VERSION"));
+
+/// Creates the client for outgoing requests.
+///
+/// All requests go through the configured [`proxy`](Config::proxy). Without a proxy URL, the
+/// proxies of the environment are used unless this is turned off.
 pub fn create_client(config: &Config, trusted: bool) -> reqwest::Client {
-    let mut builder = reqwest::ClientBuilder::new().gzip(true).trust_dns(true);
+    let mut builder = client_builder(config, trusted);
+
+    match config.proxy.proxy() {
+        Some(Ok(proxy)) => builder = builder.proxy(proxy),
+        Some(Err(err)) => {
+            tracing::error!(error = %err, "Invalid proxy, sending requests without it");
+            builder = builder.no_proxy();
+        }
+        _ if !config.proxy.from_env => builder = builder.no_proxy(),
+        _ => {}
+    }
+
+    builder.build().unwrap()
+}
+
+/// Creates the client for requests to sources which opt out of the proxy.
+///
+/// This ignores the proxies of the environment as well.
+pub fn create_direct_client(config: &Config) -> reqwest::Client {
+    client_builder(config, false).no_proxy().build().unwrap()
+}
+
+fn client_builder(config: &Config, trusted: bool) -> reqwest::ClientBuilder {
+    let user_agent = config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+    let mut builder = reqwest::ClientBuilder::new()
+        .gzip(true)
+        .trust_dns(true)
+        .user_agent(user_agent)
+        .redirect(redirect_policy(
+            config.max_redirects,
+            trusted || config.allow_cross_host_redirects,
+        ));
 
     if !(trusted || config.connect_to_reserved_ips) {
         builder = builder.ip_filter(is_external_ip);
     }
 
-    builder.build().unwrap()
+    builder
 }
 
 #[cfg(test)]
 mod tests {
+    use axum::http::{header, HeaderMap, Uri};
+    use axum::response::Redirect;
+    use axum::routing::get;
+    use axum::Router;
+
+    use crate::caching::CacheError;
+    use crate::config::ProxyConfig;
+
     use super::*;
 
     #[tokio::test]
@@ -131,4 +214,143 @@ mod tests {
         let text = response.text().await.unwrap();
         assert_eq!(text, "OK");
     }
+
+    #[tokio::test]
+    async fn test_redirect_limit() {
+        symbolicator_test::setup();
+
+        let server = symbolicator_test::Server::new();
+        let config = Config {
+            max_redirects: 2,
+            ..Config::default()
+        };
+        let client = create_client(&config, true);
+
+        let response = client
+            .get(server.url("/redirect/redirect/garbage_data/OK"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "OK");
+
+        let err = client
+            .get(server.url("/redirect/redirect/redirect/garbage_data/OK"))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_redirect());
+        assert_eq!(
+            CacheError::from(err),
+            CacheError::DownloadError("too many redirects, the maximum is 2".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirect_loop() {
+        symbolicator_test::setup();
+
+        let router = Router::new()
+            .route("/ping", get(|| async { Redirect::to("/pong") }))
+            .route("/pong", get(|| async { Redirect::to("/ping") }));
+        let server = symbolicator_test::Server::with_router(router);
+
+        let err = create_client(&Config::default(), true)
+            .get(server.url("/ping"))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_redirect());
+        // the loop is detected before running into the limit
+        assert_eq!(server.accesses(), 2);
+        assert_eq!(
+            CacheError::from(err),
+            CacheError::DownloadError(format!("redirect loop at {}", server.url("/ping")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cross_host_redirect() {
+        symbolicator_test::setup();
+
+        // redirects from `localhost` to `127.0.0.1`
+        let router = Router::new()
+            .route(
+                "/elsewhere",
+                get(|headers: HeaderMap| async move {
+                    let host = headers[header::HOST].to_str().unwrap();
+                    let port = host.rsplit(':').next().unwrap();
+                    Redirect::to(&format!("http://127.0.0.1:{port}/ok"))
+                }),
+            )
+            .route("/ok", get(|| async { "OK" }));
+        let server = symbolicator_test::Server::with_router(router);
+
+        for allow_cross_host_redirects in [true, false] {
+            let config = Config {
+                connect_to_reserved_ips: true,
+                allow_cross_host_redirects,
+                ..Config::default()
+            };
+
+            let result = create_client(&config, false) // untrusted
+                .get(server.url("/elsewhere"))
+                .send()
+                .await;
+
+            match result {
+                Ok(response) => {
+                    assert!(allow_cross_host_redirects);
+                    assert_eq!(response.text().await.unwrap(), "OK");
+                }
+                Err(err) => {
+                    assert!(!allow_cross_host_redirects);
+                    assert!(err.is_redirect());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        symbolicator_test::setup();
+
+        // the proxy echoes the requested URL and the credentials sent to it
+        let router = Router::new().route(
+            "/symbols/hello.txt",
+            get(|uri: Uri, headers: HeaderMap| async move {
+                let auth = headers
+                    .get(header::PROXY_AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_owned();
+                format!("{uri} {auth}")
+            }),
+        );
+        let proxy = symbolicator_test::Server::with_router(router);
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            proxy: ProxyConfig {
+                url: Some(proxy.url("/")),
+                username: Some("user".into()),
+                password: Some("secret".into()),
+                from_env: false,
+            },
+            ..Config::default()
+        };
+
+        // both the untrusted client for sources and the trusted one for Sentry use the proxy
+        for trusted in [false, true] {
+            let response = create_client(&config, trusted)
+                .get("http://symbols.example.com/symbols/hello.txt")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(
+                response.text().await.unwrap(),
+                "http://symbols.example.com/symbols/hello.txt Basic dXNlcjpzZWNyZXQ="
+            );
+            assert_eq!(proxy.accesses(), 1);
+        }
+    }
 }
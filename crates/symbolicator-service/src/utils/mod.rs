@@ -1,7 +1,9 @@
 pub mod addr;
+pub mod arch;
 pub mod compression;
 pub mod futures;
 pub mod gcs;
 pub mod hex;
 pub mod http;
+pub mod rate_limit;
 pub mod sentry;
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{RateLimit, ScopeRateLimits};
+use crate::types::Scope;
+
+/// A token bucket, which holds up to `burst` tokens and is refilled at `requests_per_second`.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Adds the tokens which accrued since the bucket was last updated.
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+/// Limits the rate of requests per [`Scope`], using a token bucket for each of them.
+///
+/// The buckets are held in memory, and are created as requests of a scope come in. Buckets
+/// which have been refilled completely are equivalent to new ones, so they are removed by
+/// [`remove_idle`](Self::remove_idle).
+#[derive(Debug)]
+pub struct ScopeRateLimiter {
+    limits: ScopeRateLimits,
+    buckets: Mutex<HashMap<Scope, Bucket>>,
+}
+
+impl ScopeRateLimiter {
+    /// Creates a new rate limiter enforcing the given `limits`.
+    pub fn new(limits: ScopeRateLimits) -> Self {
+        Self {
+            limits,
+            buckets: Default::default(),
+        }
+    }
+
+    /// Whether any rate limits are enforced.
+    pub fn is_enabled(&self) -> bool {
+        self.limits.is_enabled()
+    }
+
+    /// Takes a token for a request of the given `scope`.
+    ///
+    /// Returns the time after which the next token is available if the scope has exceeded its
+    /// rate limit.
+    pub fn try_acquire(&self, scope: &Scope) -> Result<(), Duration> {
+        self.try_acquire_at(scope, Instant::now())
+    }
+
+    fn try_acquire_at(&self, scope: &Scope, now: Instant) -> Result<(), Duration> {
+        let Some(limit) = self.limits.get(scope.as_ref()) else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(scope.clone()).or_insert_with(|| Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(missing / limit.requests_per_second))
+        }
+    }
+
+    /// Removes the buckets of scopes which have been idle long enough to be refilled completely.
+    pub fn remove_idle(&self) {
+        self.remove_idle_at(Instant::now())
+    }
+
+    fn remove_idle_at(&self, now: Instant) {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|scope, bucket| match self.limits.get(scope.as_ref()) {
+            Some(limit) => {
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst as f64
+            }
+            None => false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> ScopeRateLimiter {
+        ScopeRateLimiter::new(ScopeRateLimits {
+            default: Some(RateLimit {
+                requests_per_second: 1.0,
+                burst: 2,
+            }),
+            scopes: [(
+                "unlimited".to_owned(),
+                RateLimit {
+                    requests_per_second: 1000.0,
+                    burst: 1000,
+                },
+            )]
+            .into(),
+        })
+    }
+
+    #[test]
+    fn test_burst_and_refill() {
+        let limiter = limiter();
+        let scope = Scope::Scoped("noisy".into());
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire_at(&scope, now).is_ok());
+        assert!(limiter.try_acquire_at(&scope, now).is_ok());
+        assert_eq!(
+            limiter.try_acquire_at(&scope, now),
+            Err(Duration::from_secs(1))
+        );
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(
+            limiter.try_acquire_at(&scope, later),
+            Err(Duration::from_millis(500))
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.try_acquire_at(&scope, later).is_ok());
+        assert!(limiter.try_acquire_at(&scope, later).is_err());
+    }
+
+    #[test]
+    fn test_scopes_are_independent() {
+        let limiter = limiter();
+        let noisy = Scope::Scoped("noisy".into());
+        let now = Instant::now();
+
+        while limiter.try_acquire_at(&noisy, now).is_ok() {}
+
+        assert!(limiter.try_acquire_at(&Scope::Global, now).is_ok());
+        assert!(limiter
+            .try_acquire_at(&Scope::Scoped("quiet".into()), now)
+            .is_ok());
+        for _ in 0..100 {
+            assert!(limiter
+                .try_acquire_at(&Scope::Scoped("unlimited".into()), now)
+                .is_ok());
+        }
+        assert!(limiter.try_acquire_at(&noisy, now).is_err());
+    }
+
+    #[test]
+    fn test_remove_idle() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        limiter
+            .try_acquire_at(&Scope::Scoped("idle".into()), now)
+            .unwrap();
+        let later = now + Duration::from_millis(500);
+        limiter
+            .try_acquire_at(&Scope::Scoped("busy".into()), later)
+            .unwrap();
+
+        limiter.remove_idle_at(now + Duration::from_secs(1));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(
+            buckets.keys().collect::<Vec<_>>(),
+            [&Scope::Scoped("busy".into())]
+        );
+    }
+}
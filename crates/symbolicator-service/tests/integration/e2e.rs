@@ -48,6 +48,87 @@ async fn test_no_sources() {
     assert!(!cache_dir.path().join("symcaches/global").exists());
 }
 
+/// Tests that disabled sources are skipped entirely
+#[tokio::test]
+async fn test_disabled_source() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+    let hitcounter = Server::new();
+
+    let mut files = source_config(DirectoryLayoutType::Symstore, vec![FileType::Pdb]);
+    files.enabled = false;
+    let source = hitcounter.source_with_config("disabled", "/msdl/", files);
+
+    let request = request_fixture(vec![source]);
+    let mut response = symbolication.symbolicate(request).await.unwrap();
+
+    assert_eq!(hitcounter.accesses(), 0);
+
+    let module = response.modules.pop().unwrap();
+    assert_eq!(module.debug_status, ObjectFileStatus::Missing);
+    assert!(module.candidates.0.is_empty());
+    assert_eq!(
+        response.stacktraces[0].frames[0].status,
+        FrameStatus::Missing
+    );
+}
+
+/// Tests that sources supplied with one request are not used for another request, even if they
+/// share the same id
+#[tokio::test]
+async fn test_request_sources_isolated() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+    let hitcounter = Server::new();
+
+    let files = source_config(DirectoryLayoutType::Symstore, vec![FileType::Pdb]);
+    let found = hitcounter.source_with_config("custom", "/msdl/", files.clone());
+    let not_found = hitcounter.source_with_config("custom", "/respond_statuscode/404/", files);
+
+    let mut request = request_fixture(vec![found]);
+    request.scope = Scope::Scoped("first".into());
+    let response = symbolication.symbolicate(request).await.unwrap();
+    assert_eq!(
+        response.stacktraces[0].frames[0].status,
+        FrameStatus::Symbolicated
+    );
+
+    let mut request = request_fixture(vec![not_found]);
+    request.scope = Scope::Scoped("second".into());
+    let mut response = symbolication.symbolicate(request).await.unwrap();
+    assert_eq!(
+        response.stacktraces[0].frames[0].status,
+        FrameStatus::Missing
+    );
+    let module = response.modules.pop().unwrap();
+    assert_eq!(module.debug_status, ObjectFileStatus::Missing);
+
+    // the second request has actually looked up its own source, instead of using the cache
+    let hits = hitcounter.all_hits();
+    assert!(hits
+        .iter()
+        .any(|(path, _)| path.starts_with("/respond_statuscode/404/")));
+}
+
+/// Tests that the module reports the source its debug file was found on
+#[tokio::test]
+async fn test_debug_source() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+    let hitcounter = Server::new();
+
+    let files = source_config(DirectoryLayoutType::Symstore, vec![FileType::Pdb]);
+    let sources = vec![
+        hitcounter.source_with_config("not-found", "/respond_statuscode/404/", files.clone()),
+        hitcounter.source_with_config("found", "/msdl/", files),
+    ];
+
+    let request = request_fixture(sources);
+    let mut response = symbolication.symbolicate(request).await.unwrap();
+
+    let module = response.modules.pop().unwrap();
+    assert_eq!(module.debug_status, ObjectFileStatus::Found);
+    assert_eq!(module.debug_source, Some(SourceId::new("found")));
+    assert!(module.debug_cache_key.is_some());
+}
+
 /// Tests that source file types are correctly filtered
 #[tokio::test]
 async fn test_sources_filetypes() {
@@ -203,6 +284,8 @@ async fn test_reserved_ip_addresses() {
         id: SourceId::new("getsentry"),
         url: url.clone(),
         headers: Default::default(),
+        auth: None,
+        bypass_proxy: false,
         files: files.clone(),
     })));
 
@@ -211,6 +294,8 @@ async fn test_reserved_ip_addresses() {
         id: SourceId::new("ip"),
         url: url.clone(),
         headers: Default::default(),
+        auth: None,
+        bypass_proxy: false,
         files: files.clone(),
     })));
 
@@ -219,6 +304,8 @@ async fn test_reserved_ip_addresses() {
         id: SourceId::new("localhost"),
         url,
         headers: Default::default(),
+        auth: None,
+        bypass_proxy: false,
         files,
     })));
 
@@ -3,10 +3,29 @@ use std::sync::Arc;
 
 use tempfile::NamedTempFile;
 
-use symbolicator_service::types::Scope;
+use symbolicator_service::types::{
+    CompletedSymbolicationResponse, FrameTrust, ObjectFileStatus, Scope,
+};
+use symbolicator_sources::SourceConfig;
 
 use crate::{assert_snapshot, read_fixture, setup_service, symbol_server};
 
+/// Processes the given minidump fixture with the given `sources`.
+async fn process_minidump(
+    path: &str,
+    sources: Arc<[SourceConfig]>,
+) -> CompletedSymbolicationResponse {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    let minidump = read_fixture(path);
+    let mut minidump_file = NamedTempFile::new().unwrap();
+    minidump_file.write_all(&minidump).unwrap();
+    symbolication
+        .process_minidump(Scope::Global, minidump_file.into_temp_path(), sources)
+        .await
+        .unwrap()
+}
+
 macro_rules! stackwalk_minidump {
     ($path:expr) => {
         async {
@@ -43,3 +62,47 @@ async fn test_minidump_macos() {
 async fn test_minidump_linux() {
     stackwalk_minidump!("linux.dmp").await
 }
+
+/// Makes sure that the CFI of the modules is fetched and used to walk the stacks of a minidump of
+/// a release build, which omits frame pointers.
+#[tokio::test]
+async fn test_minidump_uses_cfi() {
+    let (_symsrv, source) = symbol_server();
+
+    let cfi_frames = |response: &CompletedSymbolicationResponse| {
+        response
+            .stacktraces
+            .iter()
+            .flat_map(|stacktrace| &stacktrace.frames)
+            .filter(|frame| frame.raw.trust == FrameTrust::Cfi)
+            .count()
+    };
+    let crash_exe_unwind_status = |response: &CompletedSymbolicationResponse| {
+        response
+            .modules
+            .iter()
+            .find(|module| {
+                module
+                    .raw
+                    .code_file
+                    .as_deref()
+                    .map_or(false, |file| file.ends_with("crash.exe"))
+            })
+            .and_then(|module| module.unwind_status)
+    };
+
+    let response = process_minidump("windows.dmp", Arc::new([source])).await;
+    assert_eq!(
+        crash_exe_unwind_status(&response),
+        Some(ObjectFileStatus::Found)
+    );
+    assert!(cfi_frames(&response) > 0);
+
+    // Without CFI, the stack walker has to fall back to frame pointers and scanning.
+    let response = process_minidump("windows.dmp", Arc::new([])).await;
+    assert_eq!(
+        crash_exe_unwind_status(&response),
+        Some(ObjectFileStatus::Missing)
+    );
+    assert_eq!(cfi_frames(&response), 0);
+}
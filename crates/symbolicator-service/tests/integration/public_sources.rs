@@ -22,6 +22,8 @@ async fn test_nuget_source() {
             .parse()
             .unwrap(),
         headers: Default::default(),
+        auth: None,
+        bypass_proxy: false,
         files: source_config(DirectoryLayoutType::Symstore, vec![FileType::PortablePdb]),
     }));
 
@@ -56,6 +58,8 @@ async fn test_ubuntu_source() {
         id: SourceId::new("ubuntu"),
         url: "https://debuginfod.ubuntu.com/buildid/".parse().unwrap(),
         headers: Default::default(),
+        auth: None,
+        bypass_proxy: false,
         files: source_config(
             DirectoryLayoutType::Debuginfod,
             vec![FileType::ElfCode, FileType::ElfDebug],
@@ -1,11 +1,16 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use symbolicator_sources::{DirectoryLayoutType, FileType};
 
 use symbolicator_service::types::{
     CompletedSymbolicationResponse, FrameStatus, ObjectDownloadInfo, ObjectFileStatus,
     ObjectUseInfo,
 };
 
-use crate::{example_request, setup_service, Server};
+use crate::{
+    example_request, make_symbolication_request, setup_service, source_config, symbol_server,
+    Server,
+};
 
 #[tokio::test]
 async fn test_download_errors() {
@@ -269,3 +274,52 @@ async fn test_deny_list() {
     // 14 requests
     assert_eq!(hitcounter.accesses(), 14);
 }
+
+#[tokio::test]
+async fn test_deadline() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    let (_symsrv, source) = symbol_server();
+    // The slow source only serves the wasm module, so the mach module is served by the fast one.
+    let hitcounter = Server::new();
+    let config = source_config(
+        DirectoryLayoutType::Native,
+        vec![FileType::WasmCode, FileType::WasmDebug],
+    );
+    let slow_source = hitcounter.source_with_config("slow", "/delay/1h/", config);
+
+    let mut request = make_symbolication_request(
+        vec![source, slow_source],
+        r#"[{
+          "type":"macho",
+          "debug_id":"502fc0a5-1ec1-3e47-9998-684fa139dca7",
+          "code_id":"502fc0a51ec13e479998684fa139dca7",
+          "image_addr": "0x100000000",
+          "image_size": 4096
+        },{
+          "type":"wasm",
+          "debug_id":"67e9247c-814e-392b-a027-dbde6748fcbf",
+          "image_addr": "0x200000000",
+          "image_size": 4096
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr":"0x100000fa0"
+          },{
+            "instruction_addr":"0x200000010"
+          }]
+        }]"#,
+    );
+    let started = Instant::now();
+    request.deadline = Some(started + Duration::from_millis(500));
+
+    let response = symbolication.symbolicate(request).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    // The frame of the mach module is symbolicated, the one of the wasm module timed out.
+    let frames = &response.stacktraces[0].frames;
+    assert_eq!(frames[0].status, FrameStatus::Symbolicated);
+    assert_eq!(frames.last().unwrap().status, FrameStatus::Timeout);
+    assert_eq!(response.modules[0].debug_status, ObjectFileStatus::Found);
+    assert_eq!(response.modules[1].debug_status, ObjectFileStatus::Timeout);
+}
@@ -1,10 +1,22 @@
 use std::sync::Arc;
 
-use symbolicator_service::types::Scope;
+use axum::http::{StatusCode, Uri};
+use axum::Router;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use symbolic::common::Arch;
+use symbolicator_service::types::{
+    FrameStatus, MissingDebugFile, ObjectFileStatus, Scope, SymbolicatedWith,
+};
+use symbolicator_service::utils::addr::AddrMode;
+use symbolicator_sources::{
+    CommonSourceConfig, DirectoryLayoutType, FilesystemSourceConfig, ObjectType, SourceConfig,
+    SourceId,
+};
 
 use crate::{
-    assert_snapshot, example_request, fixture, make_symbolication_request, setup_service,
-    symbol_server,
+    assert_snapshot, example_request, fixture, local_source, make_symbolication_request,
+    setup_service, symbol_server, Server,
 };
 
 #[tokio::test]
@@ -45,6 +57,57 @@ async fn test_add_bucket() {
     assert_snapshot!(response.unwrap());
 }
 
+/// Makes sure that a stack trace is emitted as soon as it is symbolicated, before the debug files
+/// of later stack traces are fetched.
+#[tokio::test]
+async fn test_symbolicate_incremental() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+    let (_symsrv, source) = symbol_server();
+
+    // the debug file of the second module never arrives
+    let hanging_server = Server::with_router(Router::new().fallback(|uri: Uri| async move {
+        if uri.path().contains("ffffffff") {
+            std::future::pending::<()>().await;
+        }
+        StatusCode::NOT_FOUND
+    }));
+
+    let request = make_symbolication_request(
+        vec![source, hanging_server.source("hanging", "/")],
+        r#"[{
+          "type":"macho",
+          "debug_id":"502fc0a5-1ec1-3e47-9998-684fa139dca7",
+          "code_id":"502fc0a51ec13e479998684fa139dca7",
+          "image_addr": "0x100000000",
+          "image_size": 4096
+        }, {
+          "type":"macho",
+          "debug_id":"ffffffff-ffff-ffff-ffff-ffffffffffff",
+          "image_addr": "0x200000000",
+          "image_size": 4096
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr":"0x100000fa0"
+          }]
+        }, {
+          "frames":[{
+            "instruction_addr":"0x200000fa0"
+          }]
+        }]"#,
+    );
+
+    let (sender, mut receiver) = mpsc::channel(1);
+    let symbolicate = symbolication.symbolicate_incremental(request, sender);
+    let stacktrace = tokio::select! {
+        _ = symbolicate => panic!("the debug file of the second module was fetched"),
+        stacktrace = receiver.next() => stacktrace.unwrap(),
+    };
+
+    assert_eq!(stacktrace.frames[0].status, FrameStatus::Symbolicated);
+    assert_eq!(stacktrace.frames[0].raw.function.as_deref(), Some("main"));
+}
+
 #[tokio::test]
 async fn test_apple_crash_report() {
     let (symbolication, _cache_dir) = setup_service(|_| ());
@@ -176,6 +239,67 @@ async fn test_dotnet_embedded_sources() {
     assert_snapshot!(response.unwrap());
 }
 
+#[tokio::test]
+async fn test_source_context_lines() {
+    let (symbolication, _cache_dir) = setup_service(|config| config.source_context_lines = 2);
+    let (_srv, source) = symbol_server();
+
+    let request = make_symbolication_request(
+        vec![source],
+        r#"[{
+          "type":"pe_dotnet",
+          "debug_file":"portable-embedded.pdb",
+          "debug_id":"b6919861-510c-4887-9994-943f64f70c37-870b9ef9"
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr": 47,
+            "function_id": 5,
+            "addr_mode":"rel:0"
+          }]
+        }]"#,
+    );
+
+    let response = symbolication.symbolicate(request.clone()).await.unwrap();
+    let frame = &response.stacktraces[0].frames[0].raw;
+    assert_eq!(
+        frame.pre_context,
+        [
+            "",
+            "// The following unhandled exception will be captured and sent to Sentry."
+        ]
+    );
+    assert_eq!(
+        frame.context_line.as_deref(),
+        Some("throw new Exception(\"test\");")
+    );
+    assert!(frame.post_context.is_empty());
+
+    let mut request = request;
+    request.apply_source_context = false;
+    let response = symbolication.symbolicate(request).await.unwrap();
+    let frame = &response.stacktraces[0].frames[0].raw;
+    assert_eq!(frame.lineno, Some(13));
+    assert!(frame.pre_context.is_empty());
+    assert_eq!(frame.context_line, None);
+}
+
+/// Frames whose source files are not available are symbolicated without source context.
+#[tokio::test]
+async fn test_source_context_missing() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+    let (_symsrv, source) = symbol_server();
+
+    let request = example_request(vec![source]);
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let frame = &response.stacktraces[0].frames[0];
+    assert_eq!(frame.status, FrameStatus::Symbolicated);
+    assert!(frame.raw.lineno.is_some());
+    assert_eq!(frame.raw.context_line, None);
+    assert!(!response.modules[0].features.has_sources);
+}
+
 #[tokio::test]
 async fn test_dotnet_source_links_in_app() {
     let (symbolication, _cache_dir) = setup_service(|_| ());
@@ -277,3 +401,296 @@ async fn test_dotnet_only_source_links_3rd_party() {
 
     assert_snapshot!(response.unwrap());
 }
+
+/// Makes sure that an address within inlined functions expands into a frame for each of them,
+/// from the innermost to the outermost one.
+#[tokio::test]
+async fn test_inline_frames() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    // At `0x5f00`, this file has `__hidden#146_` inlined into `__hidden#160_` at line 1533.
+    let symbol_dir = symbolicator_test::tempdir();
+    let macho_dir = symbol_dir.path().join("2d/10c42f591d3265b14778ba0868073f/");
+    std::fs::create_dir_all(&macho_dir).unwrap();
+    std::fs::copy(
+        fixture("symbols/2d10c42f-591d-3265-b147-78ba0868073f.dwarf-hidden"),
+        macho_dir.join("debuginfo"),
+    )
+    .unwrap();
+    let source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+        id: SourceId::new("local"),
+        path: symbol_dir.path().to_owned(),
+        files: CommonSourceConfig::with_layout(DirectoryLayoutType::Unified),
+    }));
+
+    let request = make_symbolication_request(
+        vec![source],
+        r#"[{
+          "type":"macho",
+          "debug_id":"2d10c42f-591d-3265-b147-78ba0868073f",
+          "image_addr": "0x100000000",
+          "image_size": 65536
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr":"0x100005f00",
+            "adjust_instruction_addr": false
+          }]
+        }]"#,
+    );
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let frames = &response.stacktraces[0].frames;
+    assert_eq!(frames.len(), 2);
+
+    assert_eq!(frames[0].raw.function.as_deref(), Some("__hidden#146_"));
+    assert!(frames[0].is_inline);
+
+    assert_eq!(frames[1].raw.function.as_deref(), Some("__hidden#160_"));
+    assert_eq!(frames[1].raw.lineno, Some(1533));
+    assert!(!frames[1].is_inline);
+
+    for frame in frames {
+        assert_eq!(frame.status, FrameStatus::Symbolicated);
+        assert_eq!(frame.original_index, Some(0));
+    }
+}
+
+/// Makes sure that the architecture aliases reported by clients are normalized, and that the
+/// original architecture is retained.
+#[tokio::test]
+async fn test_arch_aliases() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    for (alias, arch) in [
+        ("amd64", Arch::Amd64),
+        ("aarch64", Arch::Arm64),
+        ("arm64e", Arch::Arm64e),
+    ] {
+        let request = make_symbolication_request(
+            vec![],
+            &format!(
+                r#"[{{
+                  "type":"macho",
+                  "debug_id":"502fc0a5-1ec1-3e47-9998-684fa139dca7",
+                  "image_addr": "0x100000000",
+                  "image_size": 4096,
+                  "arch": "{alias}"
+                }}]"#
+            ),
+            r#"[{
+              "frames":[{
+                "instruction_addr":"0x100000fa0"
+              }]
+            }]"#,
+        );
+        let response = symbolication.symbolicate(request).await.unwrap();
+
+        let module = &response.modules[0];
+        assert_eq!(module.arch, arch);
+        assert_eq!(module.raw.arch.as_deref(), Some(alias));
+
+        let module = serde_json::to_value(module).unwrap();
+        assert_eq!(module["arch"], arch.name());
+        assert_eq!(module["original_arch"], alias);
+    }
+}
+
+/// Makes sure that modules without debug info fall back to the symbol table of the object,
+/// which yields function names without file and line information.
+#[tokio::test]
+async fn test_symtab_fallback() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    // A stripped ELF executable, which only has a symbol table.
+    let request = make_symbolication_request(
+        vec![local_source()],
+        r#"[{
+          "type":"elf",
+          "debug_id":"d6069e91-e306-43c4-d723-d295bb30b02d",
+          "code_id":"919e06d606e3c443d723d295bb30b02ddc3a02f8",
+          "image_addr": "0x400000",
+          "image_size": 16384
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr":"0x401107",
+            "adjust_instruction_addr": false
+          }, {
+            "instruction_addr":"0x401110",
+            "adjust_instruction_addr": false
+          }]
+        }]"#,
+    );
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let module = &response.modules[0];
+    assert_eq!(module.debug_status, ObjectFileStatus::Found);
+    assert!(module.features.has_symbols);
+    assert!(!module.features.has_debug_info);
+
+    let frames = &response.stacktraces[0].frames;
+    let functions: Vec<_> = frames
+        .iter()
+        .map(|frame| frame.raw.function.as_deref())
+        .collect();
+    assert_eq!(functions, [Some("bar"), Some("foo")]);
+
+    for frame in frames {
+        assert_eq!(frame.status, FrameStatus::Symbolicated);
+        assert_eq!(frame.symbolicated_with, Some(SymbolicatedWith::Symtab));
+        assert_eq!(frame.raw.abs_path, None);
+    }
+
+    let frame = serde_json::to_value(&frames[0]).unwrap();
+    assert_eq!(frame["symbolicated_with"], "symtab");
+}
+
+/// Makes sure that frames of WebAssembly modules, which are addressed relative to the code
+/// section of their module, resolve against the DWARF of the module.
+#[tokio::test]
+async fn test_wasm_frames() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    let request = make_symbolication_request(
+        vec![local_source()],
+        r#"[{
+          "type":"wasm",
+          "debug_id":"bda18fd8-5d4a-4eb8-9302-2d6bfad846b1",
+          "code_id":"bda18fd85d4a4eb893022d6bfad846b1"
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr":"0x8c",
+            "addr_mode":"rel:0"
+          }]
+        }]"#,
+    );
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let module = &response.modules[0];
+    assert_eq!(module.debug_status, ObjectFileStatus::Found);
+    assert_eq!(module.arch, Arch::Wasm32);
+
+    let frame = &response.stacktraces[0].frames[0];
+    assert_eq!(frame.status, FrameStatus::Symbolicated);
+    assert_eq!(frame.symbolicated_with, None);
+    assert_eq!(frame.raw.addr_mode, AddrMode::Rel(0));
+    assert_eq!(frame.raw.function.as_deref(), Some("internal_func"));
+    assert_eq!(frame.raw.filename.as_deref(), Some("lib.rs"));
+    assert_eq!(frame.raw.lineno, Some(19));
+}
+
+/// Makes sure that frames addressed relative to their module can be mixed with absolutely
+/// addressed frames in one request, and are reported with absolute addresses.
+#[tokio::test]
+async fn test_mixed_addr_modes() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    let request = make_symbolication_request(
+        vec![local_source()],
+        r#"[{
+          "type":"elf",
+          "debug_id":"d6069e91-e306-43c4-d723-d295bb30b02d",
+          "code_id":"919e06d606e3c443d723d295bb30b02ddc3a02f8",
+          "image_addr": "0x400000",
+          "image_size": 16384
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr":"0x401107",
+            "adjust_instruction_addr": false
+          }, {
+            "instruction_addr":"0x1110",
+            "addr_mode":"rel:0",
+            "adjust_instruction_addr": false
+          }, {
+            "instruction_addr":"0x1110",
+            "addr_mode":"rel:1",
+            "adjust_instruction_addr": false
+          }]
+        }]"#,
+    );
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    let frames = &response.stacktraces[0].frames;
+    assert_eq!(frames.len(), 3);
+
+    assert_eq!(frames[0].status, FrameStatus::Symbolicated);
+    assert_eq!(frames[0].raw.function.as_deref(), Some("bar"));
+    assert_eq!(frames[0].raw.addr_mode, AddrMode::Abs);
+    assert_eq!(frames[0].raw.instruction_addr.0, 0x401107);
+
+    assert_eq!(frames[1].status, FrameStatus::Symbolicated);
+    assert_eq!(frames[1].raw.function.as_deref(), Some("foo"));
+    assert_eq!(frames[1].raw.addr_mode, AddrMode::Abs);
+    assert_eq!(frames[1].raw.instruction_addr.0, 0x401110);
+
+    // There is no module with index 1
+    assert_eq!(frames[2].status, FrameStatus::UnknownImage);
+    assert_eq!(frames[2].raw.addr_mode, AddrMode::Rel(1));
+}
+
+/// Makes sure that the modules whose debug files were not found are reported once each.
+#[tokio::test]
+async fn test_missing_debug_files() {
+    let (symbolication, _cache_dir) = setup_service(|_| ());
+
+    // A module which is found, and the same unknown module loaded twice.
+    let request = make_symbolication_request(
+        vec![local_source()],
+        r#"[{
+          "type":"elf",
+          "debug_id":"d6069e91-e306-43c4-d723-d295bb30b02d",
+          "code_id":"919e06d606e3c443d723d295bb30b02ddc3a02f8",
+          "image_addr": "0x400000",
+          "image_size": 16384
+        }, {
+          "type":"elf",
+          "debug_id":"01234567-89ab-cdef-0123-456789abcdef",
+          "code_id":"67452301ab89efcd0123456789abcdef",
+          "image_addr": "0x10000000",
+          "image_size": 4096,
+          "arch": "x86_64"
+        }, {
+          "type":"elf",
+          "debug_id":"01234567-89ab-cdef-0123-456789abcdef",
+          "code_id":"67452301ab89efcd0123456789abcdef",
+          "image_addr": "0x20000000",
+          "image_size": 4096,
+          "arch": "x86_64"
+        }]"#,
+        r#"[{
+          "frames":[{
+            "instruction_addr":"0x401107"
+          }, {
+            "instruction_addr":"0x10000100"
+          }, {
+            "instruction_addr":"0x20000100"
+          }]
+        }]"#,
+    );
+    let response = symbolication.symbolicate(request).await.unwrap();
+
+    assert_eq!(response.modules[0].debug_status, ObjectFileStatus::Found);
+    assert_eq!(
+        response.missing_debug_files,
+        [MissingDebugFile {
+            debug_id: Some("01234567-89ab-cdef-0123-456789abcdef".into()),
+            code_id: Some("67452301ab89efcd0123456789abcdef".into()),
+            filetype: ObjectType::Elf,
+            arch: Arch::Amd64,
+        }]
+    );
+
+    let response = serde_json::to_value(&response).unwrap();
+    assert_eq!(
+        response["missing_debug_files"],
+        serde_json::json!([{
+            "debug_id": "01234567-89ab-cdef-0123-456789abcdef",
+            "code_id": "67452301ab89efcd0123456789abcdef",
+            "filetype": "elf",
+            "arch": "x86_64",
+        }])
+    );
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use symbolicator_service::caching::CacheMode;
 use symbolicator_service::config::Config;
 use symbolicator_service::services::create_service;
 use symbolicator_service::services::symbolication::{
@@ -9,7 +10,9 @@ use symbolicator_service::types::RawObjectInfo;
 use symbolicator_sources::SourceConfig;
 use symbolicator_test as test;
 
-pub use test::{assert_snapshot, fixture, read_fixture, source_config, symbol_server, Server};
+pub use test::{
+    assert_snapshot, fixture, local_source, read_fixture, source_config, symbol_server, Server,
+};
 
 /// Setup tests and create a test service.
 ///
@@ -58,6 +61,9 @@ pub fn make_symbolication_request(
         origin: StacktraceOrigin::Symbolicate,
         sources: Arc::from(sources),
         scope: Default::default(),
+        deadline: None,
+        apply_source_context: true,
+        cache_mode: CacheMode::Use,
     }
 }
 
@@ -7,12 +7,14 @@
 
 mod filetype;
 mod paths;
+mod redact;
 mod remotefile;
 mod sources;
 mod types;
 
 pub use filetype::*;
 pub use paths::*;
+pub use redact::*;
 pub use remotefile::*;
 pub use sources::*;
 pub use types::*;
@@ -707,6 +707,71 @@ mod tests {
         path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"_.debug/elf-buildid-sym-dfb85de42daffd09640c8fe377d572de3e168920/_.debug");
     }
 
+    #[test]
+    fn test_get_symstore_directory_paths() {
+        let identifier = ObjectId {
+            debug_id: Some("ff9f9f78-41db-88f0-cded-a9e1e9bff3b5-1".parse().unwrap()),
+            debug_file: Some("C:\\Windows\\System32\\wkernel32.pdb".into()),
+            object_type: ObjectType::Pe,
+            ..Default::default()
+        };
+        let layout = DirectoryLayout {
+            ty: DirectoryLayoutType::Symstore,
+            casing: FilenameCasing::Default,
+        };
+
+        // the cab-compressed file is tried first
+        assert_eq!(
+            get_directory_paths(layout, FileType::Pdb, &identifier),
+            [
+                "wkernel32.pdb/FF9F9F7841DB88F0CDEDA9E1E9BFF3B51/wkernel32.pd_",
+                "wkernel32.pdb/FF9F9F7841DB88F0CDEDA9E1E9BFF3B51/wkernel32.pdb",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pe_and_pdb_directory_paths() {
+        let layout = |ty| DirectoryLayout {
+            ty,
+            casing: FilenameCasing::Default,
+        };
+
+        // executables are looked up by their code id, debug files by their debug id
+        for (ty, prefix) in [
+            (DirectoryLayoutType::Native, ""),
+            (DirectoryLayoutType::Symstore, ""),
+            (DirectoryLayoutType::SymstoreIndex2, "cr/"),
+        ] {
+            assert_eq!(
+                get_directory_paths(layout(ty), FileType::Pe, &PE_OBJECT_ID),
+                [
+                    format!("{prefix}crash.exe/5AB380779000/crash.ex_"),
+                    format!("{prefix}crash.exe/5AB380779000/crash.exe"),
+                ]
+            );
+            assert_eq!(
+                get_directory_paths(layout(ty), FileType::Pdb, &PE_OBJECT_ID),
+                [
+                    format!("{prefix}crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pd_"),
+                    format!("{prefix}crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb"),
+                ]
+            );
+        }
+
+        // without a code id, only the debug file can be looked up
+        let identifier = ObjectId {
+            code_id: None,
+            ..PE_OBJECT_ID.clone()
+        };
+        let symstore = layout(DirectoryLayoutType::Symstore);
+        assert!(get_directory_paths(symstore, FileType::Pe, &identifier).is_empty());
+        assert_eq!(
+            get_directory_paths(symstore, FileType::Pdb, &identifier).len(),
+            2
+        );
+    }
+
     #[test]
     fn test_get_symstore_index2_path() {
         macro_rules! path_test {
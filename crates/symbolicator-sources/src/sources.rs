@@ -2,6 +2,7 @@
 
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
@@ -79,6 +80,32 @@ impl SourceConfig {
         }
     }
 
+    /// Whether this source should be used at all.
+    ///
+    /// Sentry sources are always enabled.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            Self::Filesystem(x) => x.files.enabled,
+            Self::Gcs(x) => x.files.enabled,
+            Self::Http(x) => x.files.enabled,
+            Self::S3(x) => x.files.enabled,
+            Self::Sentry(_) => true,
+        }
+    }
+
+    /// The priority of this source, sources with a lower priority are preferred.
+    ///
+    /// Sentry sources always have the default priority of `0`.
+    pub fn priority(&self) -> i32 {
+        match self {
+            Self::Filesystem(x) => x.files.priority,
+            Self::Gcs(x) => x.files.priority,
+            Self::Http(x) => x.files.priority,
+            Self::S3(x) => x.files.priority,
+            Self::Sentry(_) => 0,
+        }
+    }
+
     /// Name of this source.
     pub fn type_name(&self) -> &'static str {
         match self {
@@ -92,9 +119,17 @@ impl SourceConfig {
 }
 
 /// Common parameters for external filesystem-like buckets configured by users.
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct CommonSourceConfig {
+    /// Whether this source is used. Disabled sources are skipped entirely.
+    pub enabled: bool,
+
+    /// The order in which sources are preferred, lower values come first.
+    ///
+    /// Sources with the same priority are ordered by their id.
+    pub priority: i32,
+
     /// Influence whether this source will be selected
     pub filters: SourceFilters,
 
@@ -103,6 +138,46 @@ pub struct CommonSourceConfig {
 
     /// Whether debug files are shared across scopes.
     pub is_public: bool,
+
+    /// Whether the identifiers embedded in downloaded files are checked against the requested ones.
+    ///
+    /// Files with mismatching identifiers are rejected. Turn this off for sources which serve
+    /// stripped files without identifiers.
+    pub verify_ids: bool,
+
+    /// The timeout for establishing a connection to this source.
+    ///
+    /// Overrides the globally configured `connect_timeout` if set.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<Duration>,
+
+    /// The maximum time to wait for new data while downloading from this source.
+    ///
+    /// Overrides the globally configured `read_timeout` if set.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<Duration>,
+
+    /// The maximum size of files downloaded from this source, in bytes.
+    ///
+    /// Overrides the globally configured `max_download_size` if set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_download_size: Option<u64>,
+}
+
+impl Default for CommonSourceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            priority: 0,
+            filters: Default::default(),
+            layout: Default::default(),
+            is_public: false,
+            verify_ids: true,
+            connect_timeout: None,
+            read_timeout: None,
+            max_download_size: None,
+        }
+    }
 }
 
 impl CommonSourceConfig {
@@ -123,6 +198,8 @@ impl CommonSourceConfig {
 #[serde(default)]
 pub struct SourceFilters {
     /// File types that are supported by this server.
+    ///
+    /// Other file types are never looked up on this source. An empty list allows all file types.
     pub filetypes: Vec<FileType>,
 
     /// When nonempty, a list of glob patterns to fuzzy-match filepaths against. The source is then
@@ -189,7 +266,7 @@ pub enum DirectoryLayoutType {
     #[serde(rename = "debuginfod")]
     Debuginfod,
     /// Unified sentry proprietary bucket format.
-    #[serde(rename = "unified")]
+    #[serde(rename = "unified", alias = "sentry")]
     Unified,
 }
 
@@ -56,3 +56,69 @@ impl FilesystemRemoteFile {
         "local file".into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{DirectoryLayoutType, FileType, ObjectId, SourceConfig, SourceLocationIter};
+
+    fn layout_locations(layout: &str, filetype: FileType, object_id: &ObjectId) -> Vec<String> {
+        let yaml = format!(
+            r#"
+            type: filesystem
+            id: local
+            path: /symbols
+            layout:
+              type: {layout}
+            "#
+        );
+        let source = match serde_yaml::from_str(&yaml).unwrap() {
+            SourceConfig::Filesystem(source) => source,
+            source => panic!("expected a filesystem source, got: {source:?}"),
+        };
+        SourceLocationIter::new(&source.files, &[filetype], object_id)
+            .map(|location| location.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_filesystem_layouts() {
+        let elf = ObjectId {
+            code_id: Some("dfb85de42daffd09640c8fe377d572de3e168920".parse().unwrap()),
+            code_file: Some("/lib/x86_64-linux-gnu/libm-2.23.so".into()),
+            debug_id: Some("e45db8df-af2d-09fd-640c-8fe377d572de".parse().unwrap()),
+            debug_file: Some("/lib/x86_64-linux-gnu/libm-2.23.so".into()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            layout_locations("native", FileType::ElfDebug, &elf),
+            ["df/b85de42daffd09640c8fe377d572de3e168920.debug"]
+        );
+        assert_eq!(
+            layout_locations("symstore", FileType::ElfDebug, &elf),
+            ["_.debug/elf-buildid-sym-dfb85de42daffd09640c8fe377d572de3e168920/_.debug"]
+        );
+        assert_eq!(
+            layout_locations("debuginfod", FileType::ElfDebug, &elf),
+            ["dfb85de42daffd09640c8fe377d572de3e168920/debuginfo"]
+        );
+        // `sentry` is an alias of the `unified` layout
+        for layout in ["sentry", "unified"] {
+            assert_eq!(
+                layout_locations(layout, FileType::ElfDebug, &elf),
+                ["df/b85de42daffd09640c8fe377d572de3e168920/debuginfo"]
+            );
+        }
+
+        let source: SourceConfig = serde_yaml::from_str(
+            "{type: filesystem, id: local, path: /symbols, layout: {type: sentry}}",
+        )
+        .unwrap();
+        match source {
+            SourceConfig::Filesystem(source) => {
+                assert_eq!(source.files.layout.ty, DirectoryLayoutType::Unified)
+            }
+            source => panic!("expected a filesystem source, got: {source:?}"),
+        }
+    }
+}
@@ -72,3 +72,53 @@ pub struct GcsSourceKey {
     /// The client email.
     pub client_email: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gcs_file(prefix: &str, location: &str) -> GcsRemoteFile {
+        let source = Arc::new(GcsSourceConfig {
+            id: SourceId::new("gcs"),
+            bucket: "my-bucket".into(),
+            prefix: prefix.into(),
+            source_key: Arc::new(GcsSourceKey {
+                private_key: "the-private-key".into(),
+                client_email: "client@example.com".into(),
+            }),
+            files: Default::default(),
+        });
+        GcsRemoteFile::new(source, SourceLocation::new(location))
+    }
+
+    #[test]
+    fn test_gcs_remote_file_uri() {
+        let file = gcs_file("", "foo/bar.pdb");
+        assert_eq!(file.key(), "foo/bar.pdb");
+        assert_eq!(file.uri(), RemoteFileUri::new("gs://my-bucket/foo/bar.pdb"));
+
+        let file = gcs_file("", "foo bar/baz.pdb");
+        assert_eq!(
+            file.uri(),
+            RemoteFileUri::new("gs://my-bucket/foo%20bar/baz.pdb")
+        );
+    }
+
+    #[test]
+    fn test_gcs_remote_file_prefix() {
+        for prefix in ["symbols", "/symbols", "symbols/", "//symbols//"] {
+            let file = gcs_file(prefix, "foo/bar.pdb");
+            assert_eq!(file.key(), "symbols/foo/bar.pdb");
+            assert_eq!(
+                file.uri(),
+                RemoteFileUri::new("gs://my-bucket/symbols/foo/bar.pdb")
+            );
+        }
+
+        let file = gcs_file("/", "foo/bar.pdb");
+        assert_eq!(file.key(), "foo/bar.pdb");
+
+        let file = gcs_file("/nested/symbols/", "foo/bar.pdb");
+        assert_eq!(file.key(), "nested/symbols/foo/bar.pdb");
+    }
+}
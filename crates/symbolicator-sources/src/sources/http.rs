@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::fmt;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
@@ -19,11 +20,59 @@ pub struct HttpSourceConfig {
     #[serde(default)]
     pub headers: BTreeMap<String, String>,
 
+    /// Credentials to authenticate every request to the symbol server with.
+    ///
+    /// These are only sent with the requests, and are not part of the URI of the files on this
+    /// source, so changing them does not invalidate any caches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<HttpSourceAuth>,
+
+    /// Whether requests to the symbol server bypass the configured proxy.
+    ///
+    /// Just like the `auth`, this does not change the URI of the files on this source.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bypass_proxy: bool,
+
     /// Configuration common to all sources.
     #[serde(flatten)]
     pub files: CommonSourceConfig,
 }
 
+/// Credentials to authenticate requests to an HTTP symbol server.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HttpSourceAuth {
+    /// HTTP Basic authentication.
+    Basic {
+        /// The username.
+        username: String,
+        /// The optional password.
+        #[serde(default)]
+        password: Option<String>,
+    },
+    /// A bearer token, sent in the `Authorization` header.
+    Bearer {
+        /// The token.
+        token: String,
+    },
+}
+
+impl fmt::Debug for HttpSourceAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"[redacted]")
+                .finish(),
+            Self::Bearer { .. } => f
+                .debug_struct("Bearer")
+                .field("token", &"[redacted]")
+                .finish(),
+        }
+    }
+}
+
 /// The HTTP-specific [`RemoteFile`].
 #[derive(Debug, Clone)]
 pub struct HttpRemoteFile {
@@ -57,6 +106,8 @@ impl HttpRemoteFile {
             id: SourceId::new("web-scraping"),
             url,
             headers: Default::default(),
+            auth: None,
+            bypass_proxy: false,
             files: Default::default(),
         });
         let location = SourceLocation::new("");
@@ -80,3 +131,23 @@ impl HttpRemoteFile {
         self.source.url.host_str().unwrap_or_default().to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::SourceConfig;
+
+    #[test]
+    fn test_serialize_bypass_proxy() {
+        let source: SourceConfig =
+            serde_yaml::from_str("{type: http, id: web, url: 'https://example.com/'}").unwrap();
+        let serialized = serde_yaml::to_value(&source).unwrap();
+        assert!(serialized.get("bypass_proxy").is_none());
+
+        let source: SourceConfig = serde_yaml::from_str(
+            "{type: http, id: web, url: 'https://example.com/', bypass_proxy: true}",
+        )
+        .unwrap();
+        let serialized = serde_yaml::to_value(&source).unwrap();
+        assert_eq!(serialized["bypass_proxy"], serde_yaml::Value::Bool(true));
+    }
+}
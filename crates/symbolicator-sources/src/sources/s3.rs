@@ -209,6 +209,37 @@ mod tests {
 
     use crate::{SourceConfig, SourceId};
 
+    fn s3_source(prefix: &str) -> Arc<S3SourceConfig> {
+        Arc::new(S3SourceConfig {
+            id: SourceId::new("s3"),
+            bucket: "my-bucket".into(),
+            prefix: prefix.into(),
+            source_key: Arc::new(S3SourceKey {
+                region: S3Region::from("us-east-1"),
+                aws_credentials_provider: Default::default(),
+                access_key: "the-access-key".into(),
+                secret_key: "the-secret-key".into(),
+            }),
+            files: Default::default(),
+        })
+    }
+
+    #[test]
+    fn test_s3_remote_file_uri() {
+        let location = SourceLocation::new("foo/bar.pdb");
+
+        let file = S3RemoteFile::new(s3_source(""), location.clone());
+        assert_eq!(file.key(), "foo/bar.pdb");
+        assert_eq!(file.uri(), RemoteFileUri::new("s3://my-bucket/foo/bar.pdb"));
+
+        let file = S3RemoteFile::new(s3_source("/symbols/"), location);
+        assert_eq!(file.key(), "symbols/foo/bar.pdb");
+        assert_eq!(
+            file.uri(),
+            RemoteFileUri::new("s3://my-bucket/symbols/foo/bar.pdb")
+        );
+    }
+
     #[test]
     fn test_s3_config_builtin_region() {
         let text = r#"
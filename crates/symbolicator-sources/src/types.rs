@@ -39,7 +39,7 @@ impl Deref for Glob {
 }
 
 /// The type of an executable object file.
-#[derive(Serialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Serialize, Clone, Copy, Debug, Eq, PartialEq, Hash)]
 #[serde(rename_all = "snake_case")]
 #[derive(Default)]
 pub enum ObjectType {
@@ -9,6 +9,7 @@ use humantime::parse_duration;
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
+use symbolicator_service::caching::CacheMode;
 use symbolicator_service::config::Config as SymbolicatorConfig;
 use symbolicator_service::services::download::SourceConfig;
 use symbolicator_service::services::symbolication::{
@@ -127,6 +128,9 @@ async fn main() -> Result<()> {
 
                         stacktraces,
                         modules,
+                        deadline: None,
+                        apply_source_context: true,
+                        cache_mode: CacheMode::Use,
                     })
                 }
             };
@@ -121,6 +121,8 @@ pub fn microsoft_symsrv() -> SourceConfig {
             .parse()
             .unwrap(),
         headers: Default::default(),
+        auth: None,
+        bypass_proxy: false,
         files: CommonSourceConfig {
             filters: SourceFilters {
                 filetypes: vec![FileType::Pe, FileType::Pdb],
@@ -286,8 +288,7 @@ impl Server {
                 filetypes: vec![FileType::MachCode],
                 path_patterns: vec![],
             },
-            layout: Default::default(),
-            is_public: false,
+            ..Default::default()
         };
         self.source_with_config(id, path, files)
     }
@@ -304,6 +305,8 @@ impl Server {
             id: SourceId::new(id),
             url: self.url(path),
             headers: Default::default(),
+            auth: None,
+            bypass_proxy: false,
             files,
         }))
     }
@@ -339,6 +342,8 @@ pub fn symbol_server() -> (Server, SourceConfig) {
         id: SourceId::new("local"),
         url: server.url("symbols/"),
         headers: Default::default(),
+        auth: None,
+        bypass_proxy: false,
         files: Default::default(),
     }));
 
@@ -522,7 +527,9 @@ macro_rules! assert_snapshot {
             ),
             ".**.abs_path" => ::insta::dynamic_redaction(
                 $crate::redact_localhost_port
-            )
+            ),
+            // the cache key depends on the location, which includes random ports and paths
+            ".**.debug_cache_key" => "[cache_key]"
         });
     }
 }
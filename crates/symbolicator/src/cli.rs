@@ -1,6 +1,7 @@
 //! Exposes the command line application.
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use structopt::StructOpt;
@@ -10,7 +11,9 @@ use symbolicator_service::metrics;
 
 use crate::config::Config;
 use crate::logging;
+use crate::otel;
 use crate::server;
+use crate::symbolicate::{self, SymbolicateArgs};
 
 fn get_crate_version() -> &'static str {
     env!("CARGO_PKG_VERSION")
@@ -36,6 +39,101 @@ enum Command {
     /// Clean local caches.
     #[structopt(name = "cleanup")]
     Cleanup,
+
+    /// Symbolicate a minidump and print the result as JSON, without starting the web server.
+    #[structopt(name = "symbolicate")]
+    Symbolicate(SymbolicateArgs),
+
+    /// Inspect local caches.
+    #[structopt(name = "cache")]
+    Cache(CacheCommand),
+}
+
+/// Commands to inspect local caches.
+#[derive(StructOpt)]
+enum CacheCommand {
+    /// Print the metadata of a cache file, and validate its path and contents.
+    #[structopt(name = "inspect")]
+    Inspect {
+        /// Path to the cache file.
+        #[structopt(value_name = "PATH")]
+        path: PathBuf,
+    },
+
+    /// Remove cache files which were not used recently, or which exceed a total size.
+    #[structopt(name = "prune")]
+    Prune {
+        /// Remove files which were not used for longer than this, like `30d`.
+        #[structopt(
+            long = "max-age",
+            value_name = "DURATION",
+            parse(try_from_str = humantime::parse_duration)
+        )]
+        max_age: Option<Duration>,
+
+        /// Evict the least recently used files until all caches fit into this size, like `50G`.
+        #[structopt(long = "max-size", value_name = "SIZE", parse(try_from_str = parse_size))]
+        max_size: Option<u64>,
+
+        /// Only print the files which would be removed.
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+}
+
+/// Parses a number of bytes, with an optional binary `K`, `M`, `G` or `T` suffix.
+fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((index, _)) => value.split_at(index),
+        None => (value, ""),
+    };
+    let exponent = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => anyhow::bail!("invalid size unit `{unit}`"),
+    };
+    let number: u64 = number.parse().context("invalid size")?;
+    number
+        .checked_mul(1024u64.pow(exponent))
+        .context("size is too large")
+}
+
+impl CacheCommand {
+    fn execute(self, config: Config) -> Result<()> {
+        match self {
+            Self::Inspect { path } => {
+                let info = caching::inspect(&path)
+                    .with_context(|| format!("failed to read `{}`", path.display()))?;
+                println!("{info}");
+                anyhow::ensure!(info.is_valid(), "the cache file is invalid");
+            }
+            Self::Prune {
+                max_age,
+                max_size,
+                dry_run,
+            } => {
+                let options = caching::PruneOptions {
+                    max_age,
+                    max_size,
+                    dry_run,
+                };
+                let caches = caching::Caches::from_config(&config)?;
+                let pruned = caches.prune(options).context("failed to prune caches")?;
+
+                for file in &pruned {
+                    println!("{} ({} bytes)", file.path.display(), file.size);
+                }
+                let size: u64 = pruned.iter().map(|file| file.size).sum();
+                let verb = if dry_run { "Would remove" } else { "Removed" };
+                println!("{verb} {} files ({size} bytes)", pruned.len());
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Command line interface parser.
@@ -64,6 +162,10 @@ impl Cli {
 pub fn execute() -> Result<()> {
     let cli = Cli::from_args();
     let config = Config::get(cli.config()).context("failed loading config")?;
+    // The other commands only use parts of the config, which they check when using them.
+    if let Command::Run = cli.command {
+        config.validate()?;
+    }
 
     let release = Some(env!("SYMBOLICATOR_RELEASE").into());
 
@@ -107,7 +209,7 @@ pub fn execute() -> Result<()> {
     });
 
     logging::init_logging(&config);
-    if let Some(ref statsd) = config.metrics.statsd {
+    if config.metrics.statsd.is_some() || config.metrics.prometheus.enabled {
         let mut tags = config.metrics.custom_tags.clone();
 
         if let Some(hostname_tag) = config.metrics.hostname_tag.clone() {
@@ -138,13 +240,37 @@ pub fn execute() -> Result<()> {
             }
         };
 
-        metrics::configure_statsd(&config.metrics.prefix, statsd, tags);
+        if config.metrics.prometheus.enabled {
+            metrics::configure_prometheus(&config.metrics.prefix, tags.clone());
+        }
+        if let Some(ref statsd) = config.metrics.statsd {
+            metrics::configure_statsd(&config.metrics.prefix, statsd, tags);
+        }
     }
 
-    match cli.command {
-        Command::Run => server::run(config).context("failed to start the server")?,
-        Command::Cleanup => caching::cleanup(config).context("failed to clean up caches")?,
-    }
+    let result = match cli.command {
+        Command::Run => server::run(config).context("failed to start the server"),
+        Command::Cleanup => caching::cleanup(config).context("failed to clean up caches"),
+        Command::Symbolicate(args) => symbolicate::run(config, args),
+        Command::Cache(command) => command.execute(config),
+    };
+
+    otel::shutdown();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1234").unwrap(), 1234);
+        assert_eq!(parse_size("2K").unwrap(), 2048);
+        assert_eq!(parse_size("50G").unwrap(), 50 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("3 MB").unwrap(), 3 * 1024 * 1024);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
 }
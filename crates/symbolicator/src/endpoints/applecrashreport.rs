@@ -1,5 +1,4 @@
 use axum::extract;
-use axum::http::StatusCode;
 use axum::response::Json;
 use tokio::fs::File;
 
@@ -8,7 +7,7 @@ use crate::service::{RequestOptions, RequestService, SymbolicationResponse};
 use crate::utils::sentry::ConfigureScope;
 
 use super::multipart::{read_multipart_data, stream_multipart_file};
-use super::ResponseError;
+use super::{ErrorCode, ResponseError};
 
 pub async fn handle_apple_crash_report_request(
     extract::State(service): extract::State<RequestService>,
@@ -20,7 +19,7 @@ pub async fn handle_apple_crash_report_request(
     params.configure_scope();
 
     let mut report = None;
-    let mut sources = service.config().default_sources();
+    let mut sources = service.default_sources();
     let mut options = RequestOptions::default();
 
     while let Some(field) = multipart.next_field().await? {
@@ -42,7 +41,7 @@ pub async fn handle_apple_crash_report_request(
         }
     }
 
-    let report = report.ok_or((StatusCode::BAD_REQUEST, "missing apple crash report"))?;
+    let report = report.ok_or((ErrorCode::InvalidRequest, "missing apple crash report"))?;
 
     let request_id = service.process_apple_crash_report(params.scope, report, sources, options)?;
 
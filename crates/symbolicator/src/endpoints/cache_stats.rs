@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use axum::extract;
+use axum::response::Json;
+
+use crate::service::{CacheStatsMap, RequestService};
+
+use super::ResponseError;
+
+/// Reports the number of files, their total size and age for each of the caches on disk.
+pub async fn cache_stats(
+    extract::State(service): extract::State<RequestService>,
+) -> Result<Json<Arc<CacheStatsMap>>, ResponseError> {
+    let stats = service.cache_stats().await?;
+    Ok(Json(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use reqwest::{Client, StatusCode};
+
+    use crate::config::Config;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_cache_stats() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let server = test::server_with_config(Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            ..Config::default()
+        });
+
+        let objects = cache_dir.path().join("objects");
+        fs::create_dir_all(objects.join("global")).unwrap();
+        fs::create_dir_all(objects.join("myscope")).unwrap();
+        fs::write(objects.join("global/a"), [0; 10]).unwrap();
+        fs::write(objects.join("global/a.txt"), [0; 5]).unwrap();
+        fs::write(objects.join("myscope/b"), [0; 20]).unwrap();
+
+        let symcaches = cache_dir.path().join("symcaches");
+        fs::create_dir_all(symcaches.join("global")).unwrap();
+        fs::write(symcaches.join("global/c"), [0; 7]).unwrap();
+
+        let response = Client::new()
+            .get(server.url("/admin/cache/stats"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+
+        let objects = &body["objects"];
+        assert_eq!(objects["files"], 2);
+        assert_eq!(objects["bytes"], 35);
+        assert!(objects["oldest"].is_string());
+        assert!(objects["newest"].is_string());
+
+        let symcaches = &body["symcaches"];
+        assert_eq!(symcaches["files"], 1);
+        assert_eq!(symcaches["bytes"], 7);
+        assert_eq!(symcaches["oldest"], symcaches["newest"]);
+
+        let cficaches = &body["cficaches"];
+        assert_eq!(cficaches["files"], 0);
+        assert_eq!(cficaches["bytes"], 0);
+        assert!(cficaches["oldest"].is_null());
+    }
+}
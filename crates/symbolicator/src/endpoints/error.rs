@@ -1,22 +1,83 @@
+use axum::body::HttpBody;
 use axum::extract::multipart::MultipartError;
-use axum::http::{Error as HttpError, StatusCode};
+use axum::http::{header, Error as HttpError, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use sentry::integrations::anyhow::capture_anyhow;
 use serde::{Deserialize, Serialize};
 
+use symbolicator_service::caching::CacheError;
+
 use crate::service::MaxRequestsError;
 
+use super::request_id::current_request_id;
+
+/// The number of seconds after which clients should retry requests that were rejected with a
+/// `429 Too Many Requests` status code.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// A stable, machine-readable code identifying the kind of an error response.
+///
+/// The HTTP status of a response is derived from its code, see [`ErrorCode::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed, or is missing required parts.
+    InvalidRequest,
+    /// The request body, or one of its parts, exceeds the size limit.
+    PayloadTooLarge,
+    /// The requested route does not exist.
+    NotFound,
+    /// The requested debug file was not found on any of the sources.
+    ObjectNotFound,
+    /// The requested symbolication request is unknown, or has already expired.
+    RequestNotFound,
+    /// The maximum number of concurrent requests is reached.
+    TooManyRequests,
+    /// The scope of the request exceeded its rate limit.
+    RateLimited,
+    /// A source could not be reached, or failed to serve a file.
+    SourceUnavailable,
+    /// An unexpected error in symbolicator itself.
+    Internal,
+}
+
+impl ErrorCode {
+    /// Returns the HTTP status of responses with this code.
+    pub fn status(self) -> StatusCode {
+        match self {
+            Self::InvalidRequest => StatusCode::BAD_REQUEST,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::NotFound | Self::ObjectNotFound | Self::RequestNotFound => StatusCode::NOT_FOUND,
+            Self::TooManyRequests | Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::SourceUnavailable => StatusCode::BAD_GATEWAY,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Returns the code for an error response which only carries an HTTP `status`, such as the
+    /// rejections of extractors.
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge,
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::TOO_MANY_REQUESTS => Self::TooManyRequests,
+            status if status.is_client_error() => Self::InvalidRequest,
+            _ => Self::Internal,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ResponseError {
-    status: StatusCode,
+    code: ErrorCode,
     err: anyhow::Error,
 }
 
 impl From<MultipartError> for ResponseError {
     fn from(err: MultipartError) -> Self {
         Self {
-            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::InvalidRequest,
             err: err.into(),
         }
     }
@@ -25,17 +86,21 @@ impl From<MultipartError> for ResponseError {
 impl From<serde_json::Error> for ResponseError {
     fn from(err: serde_json::Error) -> Self {
         Self {
-            status: StatusCode::BAD_REQUEST,
+            code: ErrorCode::InvalidRequest,
             err: err.into(),
         }
     }
 }
 
 impl From<MaxRequestsError> for ResponseError {
-    fn from(_: MaxRequestsError) -> Self {
+    fn from(err: MaxRequestsError) -> Self {
+        let code = match err {
+            MaxRequestsError::Concurrent => ErrorCode::TooManyRequests,
+            MaxRequestsError::RateLimited { .. } => ErrorCode::RateLimited,
+        };
         Self {
-            status: StatusCode::SERVICE_UNAVAILABLE,
-            err: anyhow::anyhow!("maximum number of concurrent requests reached"),
+            code,
+            err: err.into(),
         }
     }
 }
@@ -43,31 +108,31 @@ impl From<MaxRequestsError> for ResponseError {
 impl From<&'static str> for ResponseError {
     fn from(msg: &'static str) -> Self {
         Self {
-            status: StatusCode::INTERNAL_SERVER_ERROR,
+            code: ErrorCode::Internal,
             err: anyhow::anyhow!(msg),
         }
     }
 }
 
-impl From<(StatusCode, &'static str)> for ResponseError {
-    fn from((code, msg): (StatusCode, &'static str)) -> Self {
+impl From<(ErrorCode, &'static str)> for ResponseError {
+    fn from((code, msg): (ErrorCode, &'static str)) -> Self {
         Self {
-            status: code,
+            code,
             err: anyhow::anyhow!(msg),
         }
     }
 }
 
-impl From<(StatusCode, anyhow::Error)> for ResponseError {
-    fn from((status, err): (StatusCode, anyhow::Error)) -> Self {
-        Self { status, err }
+impl From<(ErrorCode, anyhow::Error)> for ResponseError {
+    fn from((code, err): (ErrorCode, anyhow::Error)) -> Self {
+        Self { code, err }
     }
 }
 
 impl From<anyhow::Error> for ResponseError {
     fn from(err: anyhow::Error) -> Self {
         Self {
-            status: StatusCode::INTERNAL_SERVER_ERROR,
+            code: ErrorCode::Internal,
             err,
         }
     }
@@ -76,7 +141,7 @@ impl From<anyhow::Error> for ResponseError {
 impl From<std::io::Error> for ResponseError {
     fn from(err: std::io::Error) -> Self {
         Self {
-            status: StatusCode::INTERNAL_SERVER_ERROR,
+            code: ErrorCode::Internal,
             err: err.into(),
         }
     }
@@ -85,7 +150,23 @@ impl From<std::io::Error> for ResponseError {
 impl From<HttpError> for ResponseError {
     fn from(err: HttpError) -> Self {
         Self {
-            status: StatusCode::INTERNAL_SERVER_ERROR,
+            code: ErrorCode::Internal,
+            err: err.into(),
+        }
+    }
+}
+
+impl From<CacheError> for ResponseError {
+    fn from(err: CacheError) -> Self {
+        let code = match err {
+            CacheError::NotFound => ErrorCode::ObjectNotFound,
+            CacheError::PermissionDenied(_)
+            | CacheError::Timeout(_)
+            | CacheError::DownloadError(_) => ErrorCode::SourceUnavailable,
+            CacheError::Malformed(_) | CacheError::InternalError => ErrorCode::Internal,
+        };
+        Self {
+            code,
             err: err.into(),
         }
     }
@@ -93,34 +174,181 @@ impl From<HttpError> for ResponseError {
 
 impl IntoResponse for ResponseError {
     fn into_response(self) -> Response {
-        if self.status.is_server_error() {
+        let status = self.code.status();
+        if status.is_server_error() {
             capture_anyhow(&self.err);
         }
-        let mut response = Json(ApiErrorResponse::from(self.err)).into_response();
-        *response.status_mut() = self.status;
+        let mut response = Json(ApiErrorResponse::new(self.code, self.err)).into_response();
+        *response.status_mut() = status;
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = match self.err.downcast_ref() {
+                Some(MaxRequestsError::RateLimited { retry_after }) => {
+                    (retry_after.as_secs_f64().ceil() as u64).max(RETRY_AFTER_SECS)
+                }
+                _ => RETRY_AFTER_SECS,
+            };
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
         response
     }
 }
 
 /// An error response from an api.
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ApiErrorResponse {
-    detail: Option<String>,
+    /// The stable code of the error.
+    code: ErrorCode,
+    /// A human-readable description of the error, which may change at any time.
+    message: String,
+    /// The chain of underlying errors that caused this error, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<Vec<String>>,
+    /// The id of the request which failed, see [`propagate_request_id`].
+    ///
+    /// [`propagate_request_id`]: super::request_id::propagate_request_id
     #[serde(skip_serializing_if = "Option::is_none")]
-    causes: Option<Vec<String>>,
+    request_id: Option<String>,
 }
 
-impl From<anyhow::Error> for ApiErrorResponse {
-    fn from(err: anyhow::Error) -> Self {
+impl ApiErrorResponse {
+    fn new(code: ErrorCode, err: anyhow::Error) -> Self {
         let mut chain = err.chain().map(|err| err.to_string());
-        let detail = chain.next();
-        let causes: Vec<_> = chain.collect();
-        let causes = if causes.is_empty() {
+        let message = chain.next().unwrap_or_default();
+        let detail: Vec<_> = chain.collect();
+        let detail = if detail.is_empty() {
             None
         } else {
-            Some(causes)
+            Some(detail)
         };
 
-        ApiErrorResponse { detail, causes }
+        ApiErrorResponse {
+            code,
+            message,
+            detail,
+            request_id: current_request_id(),
+        }
+    }
+}
+
+/// Turns error responses which are not an [`ApiErrorResponse`] yet, such as the rejections of
+/// extractors, into one.
+///
+/// The plain text body of these responses is used as the message.
+pub async fn structure_error_response(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map_or(false, |content_type| content_type == "application/json");
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let mut body = response.into_body();
+    let mut message = Vec::new();
+    while let Some(Ok(chunk)) = body.data().await {
+        message.extend_from_slice(&chunk);
+    }
+    let message = match String::from_utf8_lossy(&message).trim() {
+        "" => status.canonical_reason().unwrap_or_default().to_owned(),
+        message => message.to_owned(),
+    };
+
+    let code = ErrorCode::from_status(status);
+    let mut response = Json(ApiErrorResponse {
+        code,
+        message,
+        detail: None,
+        request_id: current_request_id(),
+    })
+    .into_response();
+    *response.status_mut() = code.status();
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::test;
+
+    use super::*;
+
+    #[test]
+    fn test_cache_error_codes() {
+        let cases = [
+            (CacheError::NotFound, ErrorCode::ObjectNotFound),
+            (
+                CacheError::DownloadError("500 Internal Server Error".into()),
+                ErrorCode::SourceUnavailable,
+            ),
+            (
+                CacheError::Timeout(std::time::Duration::from_secs(1)),
+                ErrorCode::SourceUnavailable,
+            ),
+            (CacheError::Malformed("bad".into()), ErrorCode::Internal),
+        ];
+        for (err, code) in cases {
+            assert_eq!(ResponseError::from(err).code, code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_responses() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let client = Client::new();
+
+        // rejections of extractors
+        let response = client
+            .post(server.url("/symbolicate"))
+            .header("Content-Type", "application/json")
+            .body("{")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_request");
+        assert!(body["message"].is_string());
+
+        let response = client
+            .get(server.url("/does-not-exist"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "not_found");
+
+        // errors of the handlers
+        let response = client
+            .get(server.url("/requests/00000000-0000-0000-0000-000000000000"))
+            .header("X-Request-Id", "test")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "code": "request_not_found",
+                "message": "the request does not exist or has expired",
+                "request_id": "test",
+            })
+        );
+
+        let response = client
+            .get(server.url("/proxy/wkernel32.pdb/FF9F9F7841DB88F0CDEDA9E1E9BFF3B51/wkernel32.pdb"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "object_not_found");
     }
 }
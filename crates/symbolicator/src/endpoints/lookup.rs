@@ -0,0 +1,103 @@
+use axum::extract;
+use axum::response::Json;
+use serde::Deserialize;
+
+use symbolicator_service::caching::CacheMode;
+use symbolicator_service::services::object_id_from_object_info;
+use symbolicator_sources::{FileType, SourceConfig};
+
+use crate::service::{
+    FindObject, LookupCandidate, ObjectPurpose, RawObjectInfo, RequestService, Scope,
+};
+
+/// Query parameters of the lookup explain request.
+#[derive(Deserialize)]
+pub struct ExplainLookupQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// JSON body of the lookup explain request.
+#[derive(Deserialize)]
+pub struct ExplainLookupRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    pub module: RawObjectInfo,
+}
+
+/// Lists the files which would be looked up for the given module, in the order of preference.
+///
+/// This is a dry run, none of the files are downloaded.
+pub async fn explain_lookup(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<ExplainLookupQueryParams>,
+    extract::Json(body): extract::Json<ExplainLookupRequestBody>,
+) -> Json<Vec<LookupCandidate>> {
+    let sources = match body.sources {
+        Some(sources) => sources.into(),
+        None => service.default_sources(),
+    };
+
+    let candidates = service
+        .explain_lookup(FindObject {
+            filetypes: FileType::from_object_type(body.module.ty),
+            purpose: ObjectPurpose::Debug,
+            scope: params.scope,
+            identifier: object_id_from_object_info(&body.module),
+            sources,
+            cache_mode: CacheMode::Use,
+        })
+        .await;
+
+    Json(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_explain_lookup() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let payload = serde_json::json!({
+            "sources": [{
+                "id": "local",
+                "type": "filesystem",
+                "path": test::fixture("symbols"),
+                "filters": {"filetypes": ["pdb"]},
+            }],
+            "module": {
+                "type": "pe",
+                "debug_id": "ff9f9f78-41db-88f0-cded-a9e1e9bff3b5-1",
+                "debug_file": "wkernel32.pdb",
+                "image_addr": "0x0",
+            },
+        });
+
+        let response = Client::new()
+            .post(server.url("/lookup/explain?scope=myscope"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        let candidates = body.as_array().unwrap();
+        assert_eq!(candidates.len(), 1);
+
+        let candidate = &candidates[0];
+        assert_eq!(candidate["source"], "local");
+        assert_eq!(candidate["cached"], false);
+        let location = candidate["location"].as_str().unwrap();
+        assert!(location.starts_with("file://"));
+        assert!(
+            location.ends_with("/wkernel32.pdb/FF9F9F7841DB88F0CDEDA9E1E9BFF3B51/wkernel32.pdb")
+        );
+    }
+}
@@ -3,11 +3,27 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Instant;
 
-use axum::http::{Response, StatusCode};
+use axum::http::{header, Response, StatusCode};
+use axum::response::IntoResponse;
 use tower_layer::Layer;
 use tower_service::Service as TowerService;
 
 use crate::metric;
+use symbolicator_service::metrics;
+
+/// The content type of the Prometheus text exposition format.
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Exposes all the recorded metrics in the Prometheus text format.
+///
+/// Responds with a `404` if Prometheus metrics are not enabled.
+pub async fn prometheus_metrics() -> Result<impl IntoResponse, StatusCode> {
+    let registry = metrics::prometheus_registry().ok_or(StatusCode::NOT_FOUND)?;
+    Ok((
+        [(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+        registry.render(),
+    ))
+}
 
 #[derive(Clone)]
 pub struct MetricsLayer;
@@ -72,3 +88,58 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::{Client, StatusCode};
+
+    use symbolicator_service::metrics::{self, PrometheusRegistry};
+
+    use crate::test;
+
+    #[test]
+    fn test_prometheus_metrics() {
+        test::setup();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        // The registry is only used on this thread, which also runs the server.
+        let registry = Arc::new(PrometheusRegistry::new("symbolicator", Default::default()));
+        metrics::with_prometheus_registry(registry, || {
+            runtime.block_on(async {
+                let client = Client::new();
+                let server = test::server_with_default_service();
+
+                let response = client.get(server.url("/healthcheck")).send().await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+
+                // This goes through the `MetricsLayer`, recording request durations and status
+                // codes.
+                let response = client
+                    .get(server.url("/requests/00000000-0000-0000-0000-000000000000"))
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+                let response = client.get(server.url("/metrics")).send().await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                assert_eq!(
+                    response.headers()["content-type"],
+                    "text/plain; version=0.0.4"
+                );
+
+                let body = response.text().await.unwrap();
+                assert!(body.contains("# TYPE symbolicator_healthcheck counter\n"));
+                assert!(body.contains("# TYPE symbolicator_requests_duration summary\n"));
+                assert!(body.contains("symbolicator_requests_duration_count "));
+                assert!(body.contains("symbolicator_responses_status_code_404 "));
+            })
+        });
+    }
+}
@@ -1,7 +1,13 @@
+use std::fs;
+use std::io::{self, BufReader, Read, Seek};
+
 use axum::extract;
-use axum::http::StatusCode;
+use axum::http::header::CONTENT_ENCODING;
 use axum::response::Json;
+use flate2::read::MultiGzDecoder;
 use symbolic::common::ByteView;
+use symbolicator_service::services::check_minidump_limits;
+use tempfile::{NamedTempFile, TempPath};
 use tokio::fs::File;
 
 use crate::endpoints::symbolicate::SymbolicationRequestQueryParams;
@@ -10,7 +16,55 @@ use crate::service::{RequestOptions, RequestService, SymbolicationResponse};
 use crate::utils::sentry::ConfigureScope;
 
 use super::multipart::{read_multipart_data, stream_multipart_file};
-use super::ResponseError;
+use super::{ErrorCode, ResponseError, MAX_BODY_SIZE};
+
+/// The magic bytes of gzip compressed data.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Creates a temporary file for an uploaded minidump.
+fn create_minidump_file(service: &RequestService) -> io::Result<NamedTempFile> {
+    let mut minidump_file = tempfile::Builder::new();
+    minidump_file.prefix("minidump").suffix(".dmp");
+    if let Some(tmp_dir) = service.config().cache_dir("tmp") {
+        minidump_file.tempfile_in(tmp_dir)
+    } else {
+        minidump_file.tempfile()
+    }
+}
+
+/// Decompresses the uploaded minidump if it is gzip compressed.
+///
+/// The minidump is compressed if its part was uploaded with `Content-Encoding: gzip`, or if it
+/// starts with the gzip magic bytes. To guard against decompression bombs, the decompressed
+/// minidump may not be larger than `limit`, which is the size limit of request bodies.
+fn maybe_decompress_minidump(
+    service: &RequestService,
+    minidump_file: TempPath,
+    gzip_encoded: bool,
+    limit: u64,
+) -> Result<TempPath, ResponseError> {
+    let mut file = fs::File::open(&minidump_file)?;
+    let mut magic = [0; 2];
+    let has_gzip_magic = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    if !gzip_encoded && !has_gzip_magic {
+        return Ok(minidump_file);
+    }
+    file.rewind()?;
+
+    let mut decompressed = create_minidump_file(service)?;
+    let mut reader = MultiGzDecoder::new(BufReader::new(file)).take(limit + 1);
+    let size = io::copy(&mut reader, decompressed.as_file_mut()).map_err(|err| {
+        let err = anyhow::Error::new(err).context("invalid gzip compressed minidump");
+        (ErrorCode::InvalidRequest, err)
+    })?;
+    if size > limit {
+        let err = anyhow::anyhow!("decompressed minidump larger than {limit} bytes");
+        return Err((ErrorCode::InvalidRequest, err).into());
+    }
+
+    metric!(counter("symbolication.minidump.decompressed") += 1);
+    Ok(decompressed.into_temp_path())
+}
 
 pub async fn handle_minidump_request(
     extract::State(service): extract::State<RequestService>,
@@ -22,19 +76,21 @@ pub async fn handle_minidump_request(
     params.configure_scope();
 
     let mut minidump = None;
-    let mut sources = service.config().default_sources();
+    let mut gzip_encoded = false;
+    let mut sources = service.default_sources();
     let mut options = RequestOptions::default();
 
     while let Some(field) = multipart.next_field().await? {
         match field.name() {
             Some("upload_file_minidump") => {
-                let mut minidump_file = tempfile::Builder::new();
-                minidump_file.prefix("minidump").suffix(".dmp");
-                let minidump_file = if let Some(tmp_dir) = service.config().cache_dir("tmp") {
-                    minidump_file.tempfile_in(tmp_dir)
-                } else {
-                    minidump_file.tempfile()
-                }?;
+                gzip_encoded = field
+                    .headers()
+                    .get(CONTENT_ENCODING)
+                    .map_or(false, |encoding| {
+                        encoding.as_bytes().eq_ignore_ascii_case(b"gzip")
+                    });
+
+                let minidump_file = create_minidump_file(&service)?;
                 let (file, temp_path) = minidump_file.into_parts();
                 let mut file = File::from_std(file);
                 stream_multipart_file(field, &mut file).await?;
@@ -52,7 +108,16 @@ pub async fn handle_minidump_request(
         }
     }
 
-    let minidump_file = minidump.ok_or((StatusCode::BAD_REQUEST, "missing minidump"))?;
+    let minidump_file = minidump.ok_or((ErrorCode::InvalidRequest, "missing minidump"))?;
+    let minidump_file = {
+        let service = service.clone();
+        let limit = MAX_BODY_SIZE as u64;
+        let decompress =
+            move || maybe_decompress_minidump(&service, minidump_file, gzip_encoded, limit);
+        tokio::task::spawn_blocking(decompress)
+            .await
+            .map_err(anyhow::Error::from)??
+    };
 
     // check if the minidump starts with multipart form data and discard it if so
     let minidump_path = minidump_file.to_path_buf();
@@ -60,11 +125,15 @@ pub async fn handle_minidump_request(
     if minidump.starts_with(b"--") {
         metric!(counter("symbolication.minidump.multipart_form_data") += 1);
         return Err((
-            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
             "minidump contains multipart form data",
         )
             .into());
     }
+    if let Err(err) = check_minidump_limits(&minidump, &service.config().minidump_limits) {
+        metric!(counter("symbolication.minidump.limit_exceeded") += 1, "limit" => err.limit());
+        return Err((ErrorCode::InvalidRequest, anyhow::Error::new(err)).into());
+    }
     let request_id = service.process_minidump(params.scope, minidump_file, sources, options)?;
 
     match service.get_response(request_id, params.timeout).await {
@@ -75,11 +144,22 @@ pub async fn handle_minidump_request(
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+    use std::sync::Arc;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_ENCODING};
     use reqwest::{multipart, Client, StatusCode};
 
-    use crate::service::SymbolicationResponse;
+    use axum::response::IntoResponse;
+
+    use crate::config::{Config, MinidumpLimits};
+    use crate::service::{RequestService, SymbolicationResponse};
     use crate::test;
 
+    use super::maybe_decompress_minidump;
+
     #[tokio::test]
     async fn test_basic() {
         test::setup();
@@ -134,6 +214,77 @@ mod tests {
         test::assert_snapshot!(response);
     }
 
+    /// Asserts that the debug files of the crashing module are resolved from the `sources`.
+    fn assert_symbolicated(response: &serde_json::Value) {
+        assert_eq!(response["status"], "completed");
+
+        let crash_pdb = response["modules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|module| {
+                module["debug_file"]
+                    .as_str()
+                    .unwrap()
+                    .ends_with("crash.pdb")
+            })
+            .unwrap();
+        assert_eq!(crash_pdb["debug_status"], "found");
+
+        let crashed_thread = &response["stacktraces"][0];
+        assert_eq!(crashed_thread["is_requesting"], true);
+        assert_eq!(crashed_thread["frames"][0]["function"], "main");
+    }
+
+    #[tokio::test]
+    async fn test_local_source() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let sources = serde_json::to_string(&[test::local_source()]).unwrap();
+
+        let file_part =
+            multipart::Part::bytes(test::read_fixture("windows.dmp")).file_name("windows.dmp");
+        let form = multipart::Form::new()
+            .part("upload_file_minidump", file_part)
+            .text("sources", sources);
+
+        let response = Client::new()
+            .post(server.url("/minidump"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_symbolicated(&response.json().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_default_sources() {
+        test::setup();
+
+        let server = test::server_with_config(Config {
+            sources: Arc::new([test::local_source()]),
+            ..Config::default()
+        });
+
+        // without a `sources` field, the configured sources are used
+        let file_part =
+            multipart::Part::bytes(test::read_fixture("windows.dmp")).file_name("windows.dmp");
+        let form = multipart::Form::new().part("upload_file_minidump", file_part);
+
+        let response = Client::new()
+            .post(server.url("/minidump"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_symbolicated(&response.json().await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_unknown_field() {
         test::setup();
@@ -214,4 +365,121 @@ mod tests {
         // more work inside of `axum`, see <https://github.com/tokio-rs/axum/issues/1623>.
         assert!(response.is_err() || !response.unwrap().status().is_success());
     }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn upload_minidump(
+        server: &test::Server,
+        file_part: multipart::Part,
+    ) -> reqwest::Response {
+        let form = multipart::Form::new()
+            .part("upload_file_minidump", file_part)
+            .text("sources", "[]");
+
+        Client::new()
+            .post(server.url("/minidump"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gzip_compressed() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let file_contents = test::read_fixture("windows.dmp");
+        let compressed = gzip(&file_contents);
+
+        let file_part = multipart::Part::bytes(file_contents).file_name("windows.dmp");
+        let response = upload_minidump(&server, file_part).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let expected: serde_json::Value = response.json().await.unwrap();
+
+        // detected by its magic bytes
+        let file_part = multipart::Part::bytes(compressed.clone()).file_name("windows.dmp.gz");
+        let response = upload_minidump(&server, file_part).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let actual: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(actual, expected);
+
+        // declared by the `Content-Encoding` of the part
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        let file_part = multipart::Part::bytes(compressed)
+            .file_name("windows.dmp")
+            .headers(headers);
+        let response = upload_minidump(&server, file_part).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let actual: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[tokio::test]
+    async fn test_gzip_invalid() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        // a truncated stream
+        let compressed = gzip(&test::read_fixture("windows.dmp"));
+        let file_part = multipart::Part::bytes(compressed[..compressed.len() / 2].to_vec())
+            .file_name("windows.dmp.gz");
+        let response = upload_minidump(&server, file_part).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_request");
+    }
+
+    #[tokio::test]
+    async fn test_gzip_limit() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(Config::default(), handle.clone(), handle).unwrap();
+        let compressed_minidump = |len| {
+            let mut buf = vec![b'.'; len];
+            buf[0..4].copy_from_slice(b"MDMP");
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(&gzip(&buf)).unwrap();
+            file.into_temp_path()
+        };
+
+        // a minidump which is exactly as large as the limit once decompressed
+        let decompressed =
+            maybe_decompress_minidump(&service, compressed_minidump(1024), false, 1024).unwrap();
+        assert_eq!(std::fs::metadata(&decompressed).unwrap().len(), 1024);
+
+        // a decompression bomb, which is larger than the limit once decompressed
+        let err = maybe_decompress_minidump(&service, compressed_minidump(1025), false, 1024)
+            .unwrap_err();
+        assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_minidump_limits() {
+        test::setup();
+
+        let server = test::server_with_config(Config {
+            minidump_limits: MinidumpLimits {
+                max_modules: Some(1),
+                ..Default::default()
+            },
+            ..Config::default()
+        });
+
+        let file_part =
+            multipart::Part::bytes(test::read_fixture("windows.dmp")).file_name("windows.dmp");
+        let response = upload_minidump(&server, file_part).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_request");
+        assert_eq!(body["message"], "minidump has 17 modules, the maximum is 1");
+    }
 }
@@ -1,5 +1,6 @@
 use axum::extract::DefaultBodyLimit;
-use axum::routing::{get, post};
+use axum::middleware;
+use axum::routing::{delete, get, post};
 use axum::Router;
 use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
 use tower::ServiceBuilder;
@@ -7,24 +8,44 @@ use tower::ServiceBuilder;
 use crate::service::RequestService;
 
 mod applecrashreport;
+mod cache_stats;
 mod error;
+mod lookup;
 mod metrics;
 mod minidump;
 mod multipart;
 mod proxy;
+mod readiness;
+mod reload;
+mod request_id;
 mod requests;
+mod scope;
+mod sources;
 mod symbolicate;
 mod symbolicate_js;
+mod warm;
 
-pub use error::ResponseError;
-use metrics::MetricsLayer;
+use error::structure_error_response;
+pub use error::{ErrorCode, ResponseError};
+use metrics::{prometheus_metrics, MetricsLayer};
 
 use self::minidump::handle_minidump_request as minidump;
 use applecrashreport::handle_apple_crash_report_request as applecrashreport;
+use cache_stats::cache_stats;
+use lookup::explain_lookup;
 use proxy::proxy_symstore_request as proxy;
+use readiness::readiness;
+use reload::reload;
+use request_id::propagate_request_id;
 use requests::poll_request as requests;
-use symbolicate::symbolicate_frames as symbolicate;
+use scope::purge_scope;
+use sources::list_sources;
+use symbolicate::{symbolicate_batch, symbolicate_frames as symbolicate};
 use symbolicate_js::handle_symbolication_request as symbolicate_js;
+use warm::warm_caches;
+
+/// The maximum size of request bodies, and of minidumps after decompression.
+const MAX_BODY_SIZE: usize = 100 * 1024 * 1024;
 
 pub async fn healthcheck() -> &'static str {
     crate::metric!(counter("healthcheck") += 1);
@@ -36,10 +57,14 @@ pub fn create_app(service: RequestService) -> Router {
     let layer = ServiceBuilder::new()
         .layer(NewSentryLayer::new_from_top())
         .layer(SentryHttpLayer::with_transaction())
+        .layer(middleware::from_fn(propagate_request_id))
         .layer(MetricsLayer)
-        .layer(DefaultBodyLimit::max(100 * 1024 * 1024));
+        .layer(middleware::map_response(structure_error_response))
+        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE));
     // We have a global 100M body limit, but a 5M symbolicate body limit
     let symbolicate_route = post(symbolicate).layer(DefaultBodyLimit::max(5 * 1024 * 1024));
+    // The readiness check bypasses all the middlewares, just like the healthcheck
+    let readiness_route = get(readiness).with_state(service.clone());
     Router::new()
         .route("/proxy/*path", get(proxy).head(proxy))
         .route("/requests/:request_id", get(requests))
@@ -47,8 +72,17 @@ pub fn create_app(service: RequestService) -> Router {
         .route("/minidump", post(minidump))
         .route("/symbolicate-js", post(symbolicate_js))
         .route("/symbolicate", symbolicate_route)
+        .route("/symbolicate/batch", post(symbolicate_batch))
+        .route("/reload", post(reload))
+        .route("/scope/:scope", delete(purge_scope))
+        .route("/lookup/explain", post(explain_lookup))
+        .route("/admin/cache/stats", get(cache_stats))
+        .route("/admin/sources", get(list_sources))
+        .route("/admin/warm", post(warm_caches))
         .with_state(service)
         .layer(layer)
-        // the healthcheck is last, as it will bypass all the middlewares
+        // the healthcheck and metrics are last, as they will bypass all the middlewares
         .route("/healthcheck", get(healthcheck))
+        .route("/readiness", readiness_route)
+        .route("/metrics", get(prometheus_metrics))
 }
@@ -1,12 +1,11 @@
 //! Helper utilities to handle HTTP multipart bodies.
 
 use axum::extract::multipart::Field;
-use axum::http::StatusCode;
 use futures::prelude::*;
 use tokio::fs::File;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
-use super::ResponseError;
+use super::{ErrorCode, ResponseError};
 
 /// Stream a multipart body to a file.
 ///
@@ -42,7 +41,7 @@ pub async fn read_multipart_data(
                 field.name().unwrap_or_default(),
                 max_size
             );
-            return Err((StatusCode::PAYLOAD_TOO_LARGE, err).into());
+            return Err((ErrorCode::PayloadTooLarge, err).into());
         }
         data.extend_from_slice(&chunk);
     }
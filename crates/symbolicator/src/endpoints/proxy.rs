@@ -1,12 +1,11 @@
 use std::io::Cursor;
 use std::sync::Arc;
 
-use anyhow::Context;
 use axum::body::Body;
 use axum::extract;
-use axum::http::{Method, Request, Response, StatusCode};
+use axum::http::{Method, Request, Response};
 
-use symbolicator_service::caching::{CacheEntry, CacheError};
+use symbolicator_service::caching::{CacheEntry, CacheError, CacheMode};
 use symbolicator_sources::parse_symstore_path;
 
 use crate::service::{FindObject, ObjectHandle, ObjectPurpose, RequestService, Scope};
@@ -27,9 +26,10 @@ async fn load_object(service: RequestService, path: String) -> CacheEntry<Arc<Ob
         .find_object(FindObject {
             filetypes,
             identifier: object_id,
-            sources: config.default_sources(),
+            sources: service.default_sources(),
             scope: Scope::Global,
             purpose: ObjectPurpose::Debug,
+            cache_mode: CacheMode::Use,
         })
         .await;
 
@@ -49,19 +49,7 @@ pub async fn proxy_symstore_request(
         scope.set_transaction(Some("GET /proxy"));
     });
 
-    let object_handle = match load_object(service, path).await {
-        Ok(handle) => handle,
-        Err(CacheError::NotFound) => {
-            return Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::empty())?)
-        }
-        Err(e) => {
-            return Err(e)
-                .context("failed to download object")
-                .map_err(|e| e.into())
-        }
-    };
+    let object_handle = load_object(service, path).await?;
 
     let data = object_handle.data().clone();
     let response = Response::builder()
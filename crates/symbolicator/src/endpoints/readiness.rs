@@ -0,0 +1,222 @@
+use axum::extract;
+use axum::http::StatusCode;
+use axum::response::Json;
+use serde::Serialize;
+
+use crate::service::{RequestService, UnhealthyComponent};
+
+/// The response of the readiness check.
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    status: &'static str,
+    unhealthy: Vec<UnhealthyComponent>,
+    /// Components which still work, but with limited functionality.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    degraded: Vec<UnhealthyComponent>,
+}
+
+/// Checks whether the service is ready to handle requests.
+///
+/// As opposed to the `healthcheck`, which only signals that the process is alive, this responds
+/// with a `503` and lists all unhealthy components if some part of the service is unusable.
+/// Degraded components, such as read-only caches, are listed without failing the check.
+pub async fn readiness(
+    extract::State(service): extract::State<RequestService>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    crate::metric!(counter("readiness") += 1);
+
+    let unhealthy = service.check_readiness().await;
+    let degraded = service.check_degraded();
+
+    if unhealthy.is_empty() {
+        let status = if degraded.is_empty() {
+            "ok"
+        } else {
+            "degraded"
+        };
+        let response = ReadinessResponse {
+            status,
+            unhealthy,
+            degraded,
+        };
+        (StatusCode::OK, Json(response))
+    } else {
+        tracing::warn!(?unhealthy, "Readiness check failed");
+        let response = ReadinessResponse {
+            status: "unhealthy",
+            unhealthy,
+            degraded,
+        };
+        (StatusCode::SERVICE_UNAVAILABLE, Json(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::config::{Config, FreeSpace};
+    use crate::endpoints;
+    use crate::service::RequestService;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_readiness_healthy() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let response = Client::new()
+            .get(server.url("/readiness"))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"status": "ok", "unhealthy": []}));
+    }
+
+    #[tokio::test]
+    async fn test_readiness_degraded() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let handle = tokio::runtime::Handle::current();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Config::default()
+        };
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(endpoints::create_app(service));
+
+        // make the cache directory unusable
+        let tmp_dir = cache_dir.path().join("tmp");
+        std::fs::remove_dir_all(&tmp_dir).ok();
+        std::fs::write(&tmp_dir, b"not a directory").unwrap();
+
+        let response = Client::new()
+            .get(server.url("/readiness"))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["status"], "unhealthy");
+        assert_eq!(body["unhealthy"][0]["component"], "cache_dir");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_readiness_read_only_cache() {
+        use std::os::unix::fs::PermissionsExt;
+
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let objects_path = cache_dir.path().join("objects");
+        std::fs::create_dir(&objects_path).unwrap();
+        std::fs::set_permissions(&objects_path, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let handle = tokio::runtime::Handle::current();
+        let mut config = Config {
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Config::default()
+        };
+        config.caches.objects_dir = Some(objects_path.clone());
+        let service = RequestService::create(config, handle.clone(), handle);
+        std::fs::set_permissions(&objects_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let server = test::Server::with_router(endpoints::create_app(service.unwrap()));
+
+        let response = Client::new()
+            .get(server.url("/readiness"))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        // Permissions are not enforced for root, in which case the cache stays writable.
+        if body["status"] == "ok" {
+            return;
+        }
+        // the running cache stays read-only, even though its directory is writable again
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(
+            body["degraded"],
+            serde_json::json!([{
+                "component": "cache_dir",
+                "detail": "the objects cache is read-only",
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_readiness_cache_dir_override() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let objects_dir = test::tempdir();
+        let handle = tokio::runtime::Handle::current();
+        let mut config = Config {
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Config::default()
+        };
+        let objects_path = objects_dir.path().join("objects");
+        config.caches.objects_dir = Some(objects_path.clone());
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(endpoints::create_app(service));
+
+        let response = Client::new()
+            .get(server.url("/readiness"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // make the overridden objects directory unusable, the `cache_dir` is still fine
+        std::fs::remove_dir_all(&objects_path).unwrap();
+        std::fs::write(&objects_path, b"not a directory").unwrap();
+
+        let response = Client::new()
+            .get(server.url("/readiness"))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["unhealthy"][0]["component"], "cache_dir");
+        let detail = body["unhealthy"][0]["detail"].as_str().unwrap();
+        assert!(
+            detail.contains(&objects_path.display().to_string()),
+            "{detail}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_readiness_low_disk_space() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let mut config = Config {
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            ..Config::default()
+        };
+        // no volume has all of its space free
+        config.caches.min_free_space = Some(FreeSpace::Percent(100.0));
+        let server = test::server_with_config(config);
+
+        let response = Client::new()
+            .get(server.url("/readiness"))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["unhealthy"][0]["component"], "cache_disk");
+    }
+}
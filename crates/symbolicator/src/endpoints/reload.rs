@@ -0,0 +1,80 @@
+use axum::extract;
+use axum::response::Json;
+use serde::Serialize;
+
+use crate::service::RequestService;
+
+use super::{ErrorCode, ResponseError};
+
+/// The response of a config reload.
+#[derive(Debug, Serialize)]
+pub struct ReloadResponse {
+    status: &'static str,
+}
+
+/// Reloads the `sources` from the config file.
+///
+/// If the reloaded config cannot be parsed or is invalid, this responds with a `400` listing its
+/// problems, and the previous sources remain active. Failing to read the config file is a `500`.
+pub async fn reload(
+    extract::State(service): extract::State<RequestService>,
+) -> Result<Json<ReloadResponse>, ResponseError> {
+    if let Err(err) = service.reload() {
+        tracing::error!("Failed to reload config: {:#}", err);
+        let code = if err.chain().any(|err| err.is::<std::io::Error>()) {
+            ErrorCode::Internal
+        } else {
+            ErrorCode::InvalidRequest
+        };
+        return Err((code, err).into());
+    }
+
+    Ok(Json(ReloadResponse { status: "ok" }))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::config::Config;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_reload() {
+        test::setup();
+
+        let config_dir = test::tempdir();
+        let config_path = config_dir.path().join("config.yml");
+        std::fs::write(&config_path, "sources: []\n").unwrap();
+
+        let client = Client::new();
+        let server = test::server_with_config(Config {
+            connect_to_reserved_ips: true,
+            path: Some(config_path.clone()),
+            ..Config::default()
+        });
+
+        let response = client.post(server.url("/reload")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"status": "ok"}));
+
+        // an invalid config lists its problems
+        let source = "{id: duplicate, type: http, url: 'https://example.com/'}";
+        std::fs::write(&config_path, format!("sources: [{source}, {source}]\n")).unwrap();
+        let response = client.post(server.url("/reload")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_request");
+        let message = body["message"].as_str().unwrap();
+        assert!(message.starts_with("invalid configuration: "));
+        assert!(message.contains("duplicate"));
+
+        // as does a config which cannot be parsed
+        std::fs::write(&config_path, "sources: 1\n").unwrap();
+        let response = client.post(server.url("/reload")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_request");
+    }
+}
@@ -0,0 +1,90 @@
+use axum::http::{HeaderName, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::Instrument;
+
+/// The header carrying the id of an HTTP request.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The maximum length of a request id supplied by the client.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// The id of the HTTP request currently being handled.
+    static REQUEST_ID: String;
+}
+
+/// Returns the id of the HTTP request currently being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Assigns an id to every request, reusing the one given in the `X-Request-Id` header.
+///
+/// The id is attached to all log lines emitted while handling the request, including the ones
+/// of the symbolication it spawns, and to its Sentry events. It is echoed back in the
+/// `X-Request-Id` header of the response, as well as in error responses.
+pub async fn propagate_request_id<B>(request: Request<B>, next: Next<B>) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LEN)
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    sentry::configure_scope(|scope| scope.set_tag("request_id", &request_id));
+    let span = tracing::info_span!("request", request_id = %request_id);
+    crate::otel::set_parent(&span, request.headers());
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+
+    // the id is either a valid header already, or a UUID
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::test;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_id() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let client = Client::new();
+        let url = server.url("/requests/00000000-0000-0000-0000-000000000000");
+
+        // a supplied id is echoed, both in the header and the error body
+        let response = client
+            .get(&url)
+            .header(REQUEST_ID_HEADER, "my-request")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "my-request");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["request_id"], "my-request");
+
+        // otherwise, a new id is generated for every request
+        let response = client.get(&url).send().await.unwrap();
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(request_id).is_ok());
+        let request_id = request_id.to_owned();
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["request_id"], request_id);
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_ne!(response.headers()[REQUEST_ID_HEADER], request_id.as_str());
+    }
+}
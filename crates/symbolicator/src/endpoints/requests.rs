@@ -1,10 +1,11 @@
 use axum::extract;
-use axum::http::StatusCode;
 use axum::response::Json;
 use serde::Deserialize;
 
 use crate::service::{RequestId, RequestService, SymbolicationResponse};
 
+use super::{ErrorCode, ResponseError};
+
 /// Query parameters of the symbolication poll request.
 #[derive(Deserialize)]
 pub struct PollSymbolicationRequestQueryParams {
@@ -16,7 +17,7 @@ pub async fn poll_request(
     extract::State(service): extract::State<RequestService>,
     extract::Path(request_id): extract::Path<RequestId>,
     extract::Query(query): extract::Query<PollSymbolicationRequestQueryParams>,
-) -> Result<Json<SymbolicationResponse>, StatusCode> {
+) -> Result<Json<SymbolicationResponse>, ResponseError> {
     sentry::configure_scope(|scope| {
         scope.set_transaction(Some("GET /requests"));
     });
@@ -25,7 +26,11 @@ pub async fn poll_request(
 
     match response_opt {
         Some(response) => Ok(Json(response)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err((
+            ErrorCode::RequestNotFound,
+            "the request does not exist or has expired",
+        )
+            .into()),
     }
 }
 
@@ -0,0 +1,74 @@
+use axum::extract;
+use axum::response::Json;
+use serde::Serialize;
+
+use crate::service::{RequestService, Scope};
+
+use super::{ErrorCode, ResponseError};
+
+/// The response of a scope purge.
+#[derive(Debug, Serialize)]
+pub struct PurgeScopeResponse {
+    /// The number of removed cache entries.
+    removed: usize,
+}
+
+/// Removes all the cache entries belonging to the given scope.
+///
+/// Purging the global scope is refused with a `400`, as its entries are shared with all scopes.
+pub async fn purge_scope(
+    extract::State(service): extract::State<RequestService>,
+    extract::Path(scope): extract::Path<String>,
+) -> Result<Json<PurgeScopeResponse>, ResponseError> {
+    if scope.is_empty() || scope == "global" {
+        return Err((
+            ErrorCode::InvalidRequest,
+            "the global scope cannot be purged",
+        )
+            .into());
+    }
+
+    let removed = service.purge_scope(Scope::Scoped(scope)).await?;
+    Ok(Json(PurgeScopeResponse { removed }))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_purge_scope() {
+        test::setup();
+
+        let client = Client::new();
+        let server = test::server_with_default_service();
+
+        let response = client
+            .delete(server.url("/scope/myscope"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({"removed": 0}));
+
+        let response = client
+            .delete(server.url("/scope/global"))
+            .header("X-Request-Id", "test")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "code": "invalid_request",
+                "message": "the global scope cannot be purged",
+                "request_id": "test",
+            })
+        );
+    }
+}
@@ -1,16 +1,24 @@
+use std::sync::Arc;
+
+use axum::body::StreamBody;
 use axum::extract;
-use axum::response::Json;
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Json, Response};
+use futures::channel::mpsc;
+use futures::{future, stream, StreamExt};
 use serde::{Deserialize, Serialize};
 
+use symbolicator_service::caching::CacheMode;
+use symbolicator_service::types::CompleteStacktrace;
 use symbolicator_sources::SourceConfig;
 
 use crate::service::{
-    RawObjectInfo, RawStacktrace, RequestOptions, RequestService, Scope, Signal, StacktraceOrigin,
-    SymbolicateStacktraces, SymbolicationResponse,
+    RawObjectInfo, RawStacktrace, RequestId, RequestOptions, RequestService, Scope, Signal,
+    StacktraceOrigin, StreamedSymbolicationResponse, SymbolicateStacktraces, SymbolicationResponse,
 };
 use crate::utils::sentry::ConfigureScope;
 
-use super::ResponseError;
+use super::{ErrorCode, ResponseError};
 
 /// Query parameters of the symbolication request.
 #[derive(Deserialize)]
@@ -47,45 +55,179 @@ pub struct SymbolicationRequestBody {
     pub options: RequestOptions,
 }
 
+/// The media type of streamed symbolication responses, see [`StreamedSymbolicationResponse`].
+const NDJSON: &str = "application/x-ndjson";
+
+/// Whether the `Accept` header of a request asks for a streamed response.
+fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|media_type| media_type.split(';').next())
+        .any(|media_type| media_type.trim().eq_ignore_ascii_case(NDJSON))
+}
+
+/// Streams the stack traces of a request as they are symbolicated, one JSON object per line,
+/// followed by the rest of the response.
+///
+/// As the status code is sent before symbolication finishes, failures are only reported in the
+/// last line.
+fn stream_response(
+    service: RequestService,
+    request_id: RequestId,
+    stacktraces: mpsc::Receiver<CompleteStacktrace>,
+) -> Response {
+    let response = stream::once(async move {
+        match service.get_response(request_id, None).await {
+            Some(response) => response,
+            None => SymbolicationResponse::Failed {
+                message: "symbolication request did not start".into(),
+            },
+        }
+    });
+    let lines = stacktraces
+        .map(|stacktrace| StreamedSymbolicationResponse::Stacktrace { stacktrace })
+        .chain(response.map(StreamedSymbolicationResponse::Response))
+        .map(|line| {
+            let mut line = serde_json::to_vec(&line)?;
+            line.push(b'\n');
+            Ok::<_, serde_json::Error>(line)
+        });
+
+    ([(header::CONTENT_TYPE, NDJSON)], StreamBody::new(lines)).into_response()
+}
+
 pub async fn symbolicate_frames(
     extract::State(service): extract::State<RequestService>,
     extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    headers: HeaderMap,
     extract::Json(body): extract::Json<SymbolicationRequestBody>,
-) -> Result<Json<SymbolicationResponse>, ResponseError> {
+) -> Result<Response, ResponseError> {
     sentry::start_session();
 
     params.configure_scope();
 
     let sources = match body.sources {
         Some(sources) => sources.into(),
-        None => service.config().default_sources(),
+        None => service.default_sources(),
     };
 
-    let request_id = service.symbolicate_stacktraces(
-        SymbolicateStacktraces {
-            scope: params.scope,
-            signal: body.signal,
-            sources,
-            origin: StacktraceOrigin::Symbolicate,
-            stacktraces: body.stacktraces,
-            modules: body.modules.into_iter().map(From::from).collect(),
-        },
-        body.options,
-    )?;
+    let request = SymbolicateStacktraces {
+        scope: params.scope,
+        signal: body.signal,
+        sources,
+        origin: StacktraceOrigin::Symbolicate,
+        stacktraces: body.stacktraces,
+        modules: body.modules.into_iter().map(From::from).collect(),
+        deadline: None,
+        apply_source_context: true,
+        cache_mode: CacheMode::Use,
+    };
+
+    if accepts_ndjson(&headers) {
+        let (request_id, stacktraces) =
+            service.symbolicate_stacktraces_streaming(request, body.options)?;
+        return Ok(stream_response(service, request_id, stacktraces));
+    }
 
+    let request_id = service.symbolicate_stacktraces(request, body.options)?;
     match service.get_response(request_id, params.timeout).await {
-        Some(response) => Ok(Json(response)),
+        Some(response) => Ok(Json(response).into_response()),
         None => Err("symbolication request did not start".into()),
     }
 }
 
+/// A single request of the batch symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct BatchedSymbolicationRequest {
+    #[serde(default)]
+    pub signal: Option<Signal>,
+    #[serde(default)]
+    pub stacktraces: Vec<RawStacktrace>,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+}
+
+/// JSON body of the batch symbolication request.
+#[derive(Serialize, Deserialize)]
+pub struct BatchSymbolicationRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    #[serde(default)]
+    pub requests: Vec<BatchedSymbolicationRequest>,
+    #[serde(default)]
+    pub options: RequestOptions,
+}
+
+pub async fn symbolicate_batch(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<SymbolicationRequestQueryParams>,
+    extract::Json(body): extract::Json<BatchSymbolicationRequestBody>,
+) -> Result<Json<Vec<SymbolicationResponse>>, ResponseError> {
+    sentry::start_session();
+
+    params.configure_scope();
+
+    if let Some(max_batch_size) = service.config().max_batch_size {
+        if body.requests.len() > max_batch_size {
+            let err = anyhow::anyhow!("batch contains more than {max_batch_size} requests");
+            return Err((ErrorCode::InvalidRequest, err).into());
+        }
+    }
+
+    let sources: Arc<[SourceConfig]> = match body.sources {
+        Some(sources) => sources.into(),
+        None => service.default_sources(),
+    };
+
+    let requests = body
+        .requests
+        .into_iter()
+        .map(|request| SymbolicateStacktraces {
+            scope: params.scope.clone(),
+            signal: request.signal,
+            sources: sources.clone(),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: request.stacktraces,
+            modules: request.modules.into_iter().map(From::from).collect(),
+            deadline: None,
+            apply_source_context: true,
+            cache_mode: CacheMode::Use,
+        })
+        .collect();
+
+    // The batch is admitted as a whole, but every request carries its own status, so a failing
+    // request does not fail the whole batch.
+    let timeout = params.timeout;
+    let request_ids = service.symbolicate_batch(&params.scope, requests, body.options)?;
+    let responses = request_ids.into_iter().map(|request_id| {
+        let service = &service;
+        async move {
+            match service.get_response(request_id, timeout).await {
+                Some(response) => response,
+                None => SymbolicationResponse::Failed {
+                    message: "symbolication request did not start".into(),
+                },
+            }
+        }
+    });
+
+    Ok(Json(future::join_all(responses).await))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use reqwest::{Client, StatusCode};
-    use symbolicator_service::types::CompletedSymbolicationResponse;
+    use symbolicator_service::types::{
+        CompletedResponse, CompletedSymbolicationResponse, FrameStatus,
+    };
+    use symbolicator_sources::{DirectoryLayoutType, FileType};
 
+    use crate::config::{RateLimit, ScopeRateLimits};
     use crate::test;
 
     #[tokio::test]
@@ -107,7 +249,7 @@ mod tests {
             .unwrap();
 
         // the JSON does not fit our schema :-)
-        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
         let mut buf = vec![b'.'; 8 * 1024 * 1024];
         buf[0] = b'"';
@@ -122,6 +264,8 @@ mod tests {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "payload_too_large");
     }
 
     #[tokio::test]
@@ -234,4 +378,325 @@ mod tests {
         let response: CompletedSymbolicationResponse = response.json().await.unwrap();
         test::assert_snapshot!(response);
     }
+
+    /// Asserts that a streamed response contains the same stack traces and modules as a buffered
+    /// response.
+    #[tokio::test]
+    async fn test_streaming() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let payload = serde_json::json!({
+            "stacktraces": [
+                {"frames": [{"instruction_addr": "0x401107"}]},
+                {"frames": [{"instruction_addr": "0x401110"}, {"instruction_addr": "0x10000100"}]},
+            ],
+            "modules": [
+                {
+                    "type": "elf",
+                    "debug_id": "d6069e91-e306-43c4-d723-d295bb30b02d",
+                    "code_id": "919e06d606e3c443d723d295bb30b02ddc3a02f8",
+                    "image_addr": "0x400000",
+                    "image_size": 16384,
+                },
+                {
+                    "type": "elf",
+                    "debug_id": "01234567-89ab-cdef-0123-456789abcdef",
+                    "image_addr": "0x10000000",
+                    "image_size": 4096,
+                },
+            ],
+            "sources": [test::local_source()],
+        });
+
+        let response = Client::new()
+            .post(server.url("/symbolicate"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let buffered: serde_json::Value = response.json().await.unwrap();
+
+        let response = Client::new()
+            .post(server.url("/symbolicate"))
+            .header("Accept", NDJSON)
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Content-Type"], NDJSON);
+        let body = response.text().await.unwrap();
+
+        let mut lines: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let mut streamed = lines.pop().unwrap();
+        assert_eq!(streamed["status"], "completed");
+        let stacktraces: Vec<_> = lines
+            .iter_mut()
+            .map(|line| line["stacktrace"].take())
+            .collect();
+        assert_eq!(stacktraces.len(), 2);
+
+        streamed["stacktraces"] = stacktraces.into();
+        assert_eq!(streamed, buffered);
+    }
+
+    /// Asserts that a debug file shared by multiple requests of a batch is only downloaded once.
+    #[tokio::test]
+    async fn test_batch_deduplication() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let hitcounter = test::Server::new();
+
+        let request = r##"{
+            "stacktraces": [{
+              "registers": {"eip": "0x0000000001509530"},
+              "frames": [{"instruction_addr": "0x749e8630"}]
+            }],
+            "modules": [{
+              "type": "pe",
+              "debug_id": "ff9f9f78-41db-88f0-cded-a9e1e9bff3b5-1",
+              "code_file": "C:\\Windows\\System32\\kernel32.dll",
+              "debug_file": "C:\\Windows\\System32\\wkernel32.pdb",
+              "image_addr": "0x749d0000",
+              "image_size": 851968
+            }]
+        }"##;
+        // both requests share the same module, and thus the same debug file
+        let requests = vec![
+            serde_json::from_str(request).unwrap(),
+            serde_json::from_str(request).unwrap(),
+        ];
+
+        let config = test::source_config(DirectoryLayoutType::Symstore, vec![FileType::Pdb]);
+        let source = hitcounter.source_with_config("batch", "msdl/", config);
+        let payload = BatchSymbolicationRequestBody {
+            sources: Some(vec![source]),
+            requests,
+            options: Default::default(),
+        };
+
+        let response = Client::new()
+            .post(server.url("/symbolicate/batch"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let responses: Vec<SymbolicationResponse> = response.json().await.unwrap();
+        assert_eq!(responses.len(), 2);
+        for response in responses {
+            let response = match response {
+                SymbolicationResponse::Completed(response) => *response,
+                response => panic!("expected a completed response, got: {response:#?}"),
+            };
+            let response = match response {
+                CompletedResponse::NativeSymbolication(response) => response,
+                response => panic!("expected a native response, got: {response:#?}"),
+            };
+            assert_eq!(
+                response.stacktraces[0].frames[0].status,
+                FrameStatus::Symbolicated
+            );
+        }
+
+        assert_eq!(
+            hitcounter.all_hits(),
+            [
+                (
+                    "/msdl/wkernel32.pdb/FF9F9F7841DB88F0CDEDA9E1E9BFF3B51/wkernel32.pd_".into(),
+                    1
+                ),
+                (
+                    "/msdl/wkernel32.pdb/FF9F9F7841DB88F0CDEDA9E1E9BFF3B51/wkernel32.pdb".into(),
+                    1
+                )
+            ]
+        );
+    }
+
+    /// Asserts that a batch is admitted as a single request, even if it has more requests than
+    /// `max_concurrent_requests` and the rate limit burst, and that `max_batch_size` is enforced.
+    #[tokio::test]
+    async fn test_batch_admission() {
+        test::setup();
+
+        let server = test::server_with_config(crate::config::Config {
+            max_concurrent_requests: Some(2),
+            max_batch_size: Some(5),
+            rate_limits: ScopeRateLimits {
+                default: Some(RateLimit {
+                    requests_per_second: 0.01,
+                    burst: 2,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let request = serde_json::json!({
+            "stacktraces": [{"frames": [{"instruction_addr": "0x749e8630"}]}],
+            "modules": [],
+        });
+        let batch = |len| serde_json::json!({"sources": [], "requests": vec![&request; len]});
+        let client = Client::new();
+
+        let response = client
+            .post(server.url("/symbolicate/batch"))
+            .json(&batch(5))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let responses: Vec<serde_json::Value> = response.json().await.unwrap();
+        assert_eq!(responses.len(), 5);
+        for response in responses {
+            assert_eq!(response["status"], "completed");
+        }
+
+        let response = client
+            .post(server.url("/symbolicate/batch"))
+            .json(&batch(6))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_request");
+
+        // the first batch used up only one of the two tokens of the scope
+        let response = client
+            .post(server.url("/symbolicate/batch"))
+            .json(&batch(1))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = client
+            .post(server.url("/symbolicate/batch"))
+            .json(&batch(1))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    /// Asserts that requests exceeding `max_concurrent_requests` are rejected with a `429`.
+    #[tokio::test]
+    async fn test_max_requests() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let config = crate::config::Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            connect_to_reserved_ips: true,
+            max_concurrent_requests: Some(2),
+            ..Default::default()
+        };
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let server = test::Server::with_router(crate::endpoints::create_app(service));
+
+        let hitcounter = test::Server::new();
+        let source = hitcounter.source("pending", "/delay/1h/");
+
+        let payload = r##"{
+            "stacktraces": [{
+              "registers": {"eip": "0x0000000001509530"},
+              "frames": [{"instruction_addr": "0x749e8630"}]
+            }],
+            "modules": [{
+              "type": "pe",
+              "debug_id": "ff9f9f78-41db-88f0-cded-a9e1e9bff3b5-1",
+              "code_file": "C:\\Windows\\System32\\kernel32.dll",
+              "debug_file": "C:\\Windows\\System32\\wkernel32.pdb",
+              "image_addr": "0x749d0000",
+              "image_size": 851968
+            }]
+        }"##;
+        let mut payload: SymbolicationRequestBody = serde_json::from_str(payload).unwrap();
+        payload.sources = Some(vec![source]);
+
+        let client = Client::new();
+
+        // The first two requests never resolve, so they are still running when the third one
+        // comes in, which is rejected.
+        for _ in 0..2 {
+            let response = client
+                .post(server.url("/symbolicate?timeout=0"))
+                .json(&payload)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let response: SymbolicationResponse = response.json().await.unwrap();
+            assert!(matches!(response, SymbolicationResponse::Pending { .. }));
+        }
+
+        let response = client
+            .post(server.url("/symbolicate?timeout=0"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "1");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "too_many_requests");
+    }
+
+    /// Asserts that a scope exceeding its rate limit does not affect requests of other scopes.
+    #[tokio::test]
+    async fn test_scope_rate_limits() {
+        test::setup();
+
+        let server = test::server_with_config(crate::config::Config {
+            rate_limits: ScopeRateLimits {
+                default: Some(RateLimit {
+                    requests_per_second: 0.01,
+                    burst: 2,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let payload = serde_json::json!({
+            "stacktraces": [{"frames": [{"instruction_addr": "0x749e8630"}]}],
+            "modules": [],
+            "sources": [],
+        });
+        let client = Client::new();
+        let symbolicate = |scope: &str| {
+            client
+                .post(server.url(&format!("/symbolicate?scope={scope}")))
+                .json(&payload)
+                .send()
+        };
+
+        // the burst of the noisy scope is used up
+        for _ in 0..2 {
+            let response = symbolicate("noisy").await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = symbolicate("noisy").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "100");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "rate_limited");
+
+        // while other scopes are still admitted
+        for scope in ["quiet", "global"] {
+            let response = symbolicate(scope).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
 }
@@ -11,7 +11,7 @@ use crate::endpoints::symbolicate::SymbolicationRequestQueryParams;
 use crate::service::{JsStacktrace, RequestService, SymbolicationResponse};
 use crate::utils::sentry::ConfigureScope;
 
-use super::ResponseError;
+use super::{ErrorCode, ResponseError};
 
 fn default_allow_scraping() -> bool {
     true
@@ -51,9 +51,17 @@ pub async fn handle_symbolication_request(
         allow_scraping,
     } = body;
 
+    let Some(source) = source else {
+        return Err((
+            ErrorCode::InvalidRequest,
+            "JavaScript symbolication requires a `source`",
+        )
+            .into());
+    };
+
     let request_id = service.symbolicate_js_stacktraces(SymbolicateJsStacktraces {
         scope: params.scope,
-        source: Arc::new(source.unwrap()),
+        source: Arc::new(source),
         stacktraces,
         modules,
         release,
@@ -66,3 +74,118 @@ pub async fn handle_symbolication_request(
         None => Err("symbolication request did not start".into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+    use serde_json::json;
+
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_external_sourcemap() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let (_srv, source) = test::sourcemap_server("01_sourcemap_expansion", |url, _query| {
+            json!([{
+                "type": "file",
+                "id": "1",
+                "url": format!("{url}/test.min.js"),
+                "abs_path": "~/test.min.js",
+            }, {
+                "type": "file",
+                "id": "2",
+                "url": format!("{url}/test.min.js.map"),
+                "abs_path": "~/test.min.js.map",
+            }])
+        });
+
+        let payload = json!({
+            "source": source,
+            "stacktraces": [{
+                "frames": [{
+                    "abs_path": "http://example.com/test.min.js",
+                    "filename": "test.min.js",
+                    "lineno": 1,
+                    "colno": 64,
+                    "function": "e",
+                }],
+            }],
+        });
+        let response = Client::new()
+            .post(server.url("/symbolicate-js"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["status"], "completed");
+        let frame = &body["stacktraces"][0]["frames"][0];
+        assert_eq!(frame["function"], "onFailure");
+        assert_eq!(frame["abs_path"], "test.js");
+        assert_eq!(frame["lineno"], 5);
+        assert_eq!(frame["colno"], 11);
+    }
+
+    #[tokio::test]
+    async fn test_inline_sourcemap() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+        let (_srv, source) = test::sourcemap_server("05_inlined_sources", |url, _query| {
+            json!([{
+                "type": "file",
+                "id": "1",
+                "url": format!("{url}/test.min.js"),
+                "abs_path": "~/test.min.js",
+            }])
+        });
+
+        let payload = json!({
+            "source": source,
+            "stacktraces": [{
+                "frames": [{
+                    "abs_path": "http://example.com/test.min.js",
+                    "filename": "test.js",
+                    "lineno": 1,
+                    "colno": 1,
+                }],
+            }],
+        });
+        let response = Client::new()
+            .post(server.url("/symbolicate-js"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["status"], "completed");
+        let frame = &body["stacktraces"][0]["frames"][0];
+        assert_eq!(frame["abs_path"], "/test.js");
+        assert_eq!(frame["context_line"], "console.log('hello, World!')");
+    }
+
+    #[tokio::test]
+    async fn test_missing_source() {
+        test::setup();
+
+        let server = test::server_with_default_service();
+
+        let payload = json!({"stacktraces": []});
+        let response = Client::new()
+            .post(server.url("/symbolicate-js"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_request");
+    }
+}
@@ -0,0 +1,132 @@
+use axum::extract;
+use axum::response::Json;
+use serde::Deserialize;
+
+use symbolicator_sources::SourceConfig;
+
+use crate::service::{RawObjectInfo, RequestService, Scope, WarmedModule};
+
+/// Query parameters of the cache warming request.
+#[derive(Deserialize)]
+pub struct WarmCachesQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// JSON body of the cache warming request.
+#[derive(Deserialize)]
+pub struct WarmCachesRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    pub modules: Vec<RawObjectInfo>,
+}
+
+/// Downloads and converts the debug files of the given modules, so that they are cached for
+/// subsequent symbolication requests.
+pub async fn warm_caches(
+    extract::State(service): extract::State<RequestService>,
+    extract::Query(params): extract::Query<WarmCachesQueryParams>,
+    extract::Json(body): extract::Json<WarmCachesRequestBody>,
+) -> Json<Vec<WarmedModule>> {
+    let sources = match body.sources {
+        Some(sources) => sources.into(),
+        None => service.default_sources(),
+    };
+
+    let warmed = service
+        .warm_caches(params.scope, sources, body.modules)
+        .await;
+
+    Json(warmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, StatusCode};
+
+    use crate::config::Config;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_warm_caches() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let server = test::server_with_config(Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            connect_to_reserved_ips: true,
+            ..Config::default()
+        });
+        let (symsrv, source) = test::symbol_server();
+
+        let module = serde_json::json!({
+            "type": "pe",
+            "debug_id": "ff9f9f78-41db-88f0-cded-a9e1e9bff3b5-1",
+            "code_file": "C:\\Windows\\System32\\kernel32.dll",
+            "debug_file": "C:\\Windows\\System32\\wkernel32.pdb",
+            "image_addr": "0x749d0000",
+            "image_size": 851968,
+        });
+        let missing_module = serde_json::json!({
+            "type": "pe",
+            "debug_id": "00000000-0000-0000-0000-000000000000-1",
+            "debug_file": "missing.pdb",
+            "image_addr": "0x0",
+        });
+
+        let client = Client::new();
+        let payload = serde_json::json!({
+            "sources": [source],
+            "modules": [module, missing_module],
+        });
+        let response = client
+            .post(server.url("/admin/warm"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([
+                {
+                    "debug_id": "ff9f9f78-41db-88f0-cded-a9e1e9bff3b5-1",
+                    "code_id": null,
+                    "status": "found",
+                },
+                {
+                    "debug_id": "00000000-0000-0000-0000-000000000000-1",
+                    "code_id": null,
+                    "status": "missing",
+                    "error": "not found",
+                },
+            ])
+        );
+        assert!(symsrv.accesses() > 0);
+
+        // symbolicating a frame of the warmed module does not hit the symbol server again
+        let payload = serde_json::json!({
+            "stacktraces": [{
+                "frames": [{"instruction_addr": "0x749e8630"}],
+            }],
+            "modules": [module],
+            "sources": [source],
+            // looking up source bundles is not part of warming the caches
+            "options": {"include_source_context": false},
+        });
+        let response = client
+            .post(server.url("/symbolicate"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["status"], "completed");
+        assert_eq!(body["modules"][0]["debug_status"], "found");
+        assert_eq!(symsrv.accesses(), 0);
+    }
+}
@@ -1,12 +1,16 @@
 use std::env;
 
 use tracing::level_filters::LevelFilter;
+use tracing::Subscriber;
 use tracing_subscriber::fmt::fmt;
 use tracing_subscriber::fmt::time::UtcTime;
+use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
 
 use crate::config::{Config, LogFormat};
+use crate::otel;
 
 fn get_rust_log(level: LevelFilter) -> &'static str {
     match level {
@@ -47,34 +51,64 @@ pub fn init_logging(config: &Config) {
     let rust_log =
         env::var("RUST_LOG").unwrap_or_else(|_| get_rust_log(config.logging.level).to_string());
 
+    let (tracer, tracer_err) = match otel::init_tracer(&config.tracing) {
+        Ok(tracer) => (tracer, None),
+        Err(err) => (None, Some(err)),
+    };
+
     let subscriber = fmt()
         .with_timer(UtcTime::rfc_3339())
         .with_target(true)
-        .with_env_filter(rust_log);
+        .with_env_filter(&rust_log);
 
     match (config.logging.format, console::user_attended()) {
         (LogFormat::Auto, true) | (LogFormat::Pretty, _) => subscriber
             .pretty()
             .finish()
             .with(sentry::integrations::tracing::layer())
+            .with(otel::layer(tracer))
             .init(),
         (LogFormat::Auto, false) | (LogFormat::Simplified, _) => subscriber
             .compact()
             .with_ansi(false)
             .finish()
             .with(sentry::integrations::tracing::layer())
+            .with(otel::layer(tracer))
             .init(),
-        (LogFormat::Json, _) => subscriber
-            .json()
-            .flatten_event(true)
-            .with_current_span(true)
-            .with_span_list(true)
-            .with_file(true)
-            .with_line_number(true)
-            .finish()
+        (LogFormat::Json, _) => json_subscriber(&rust_log, std::io::stdout)
             .with(sentry::integrations::tracing::layer())
+            .with(otel::layer(tracer))
             .init(),
     }
+
+    if let Some(err) = tracer_err {
+        tracing::error!("Failed to set up exporting traces: {:#}", err);
+    }
+}
+
+/// Creates a subscriber writing one JSON object per log line to the given `writer`.
+///
+/// Besides the timestamp, level, target and message, each object contains all the structured
+/// fields of the event, as well as the current span.
+fn json_subscriber<W>(
+    rust_log: &str,
+    writer: W,
+) -> impl Subscriber + for<'a> LookupSpan<'a> + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    fmt()
+        .with_timer(UtcTime::rfc_3339())
+        .with_target(true)
+        .with_env_filter(rust_log)
+        .with_writer(writer)
+        .json()
+        .flatten_event(true)
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_file(true)
+        .with_line_number(true)
+        .finish()
 }
 
 /// Logs an error to the configured logger or `stderr` if not yet configured.
@@ -87,3 +121,51 @@ pub fn ensure_log_error(error: &anyhow::Error) {
         eprintln!("{error:?}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A writer capturing all the log output in memory.
+    #[derive(Clone, Default)]
+    struct CapturingWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_logging() {
+        let writer = CapturingWriter::default();
+        let subscriber = json_subscriber("INFO", {
+            let writer = writer.clone();
+            move || writer.clone()
+        });
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(num_frames = 42, "Symbolication finished");
+            tracing::debug!("Filtered out");
+        });
+
+        let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 1);
+
+        let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert!(line["timestamp"].is_string());
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "symbolicator::logging::tests");
+        assert_eq!(line["message"], "Symbolication finished");
+        assert_eq!(line["num_frames"], 42);
+    }
+}
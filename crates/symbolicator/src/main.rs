@@ -24,8 +24,10 @@ pub use symbolicator_service::{config, metric, utils};
 mod cli;
 mod endpoints;
 mod logging;
+mod otel;
 mod server;
 mod service;
+mod symbolicate;
 
 #[cfg(test)]
 mod test {
@@ -36,11 +38,15 @@ mod test {
     use crate::endpoints;
 
     pub fn server_with_default_service() -> Server {
-        let handle = tokio::runtime::Handle::current();
-        let config = Config {
+        server_with_config(Config {
             connect_to_reserved_ips: true,
             ..Config::default()
-        };
+        })
+    }
+
+    /// Creates a [`Server`] for a [`RequestService`] with the given `config`.
+    pub fn server_with_config(config: Config) -> Server {
+        let handle = tokio::runtime::Handle::current();
         let service = RequestService::create(config, handle.clone(), handle).unwrap();
 
         Server::with_router(endpoints::create_app(service))
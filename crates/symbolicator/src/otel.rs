@@ -0,0 +1,248 @@
+//! Exports traces of requests via OpenTelemetry.
+//!
+//! Spans are only exported if symbolicator is built with the `opentelemetry` feature. Without
+//! it, configuring an endpoint is an error, and all the other functions do nothing.
+
+use anyhow::Result;
+use axum::http::HeaderMap;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::config::Tracing;
+
+#[cfg(feature = "opentelemetry")]
+pub use opentelemetry::sdk::trace::Tracer;
+
+/// A tracer which can never be created, as traces are not exported without the `opentelemetry`
+/// feature.
+#[cfg(not(feature = "opentelemetry"))]
+#[derive(Clone, Debug)]
+pub enum Tracer {}
+
+/// Creates the tracer exporting spans to the configured OTLP endpoint, if there is one.
+///
+/// This also registers the tracer globally, see [`shutdown`].
+#[cfg(feature = "opentelemetry")]
+pub fn init_tracer(config: &Tracing) -> Result<Option<Tracer>> {
+    use anyhow::Context;
+    use opentelemetry::sdk::trace::{self, Sampler};
+    use opentelemetry::sdk::Resource;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    // The exporter sends batches of spans from a background task, which needs a runtime before
+    // any of the runtimes of the server exist. It lives for the rest of the process.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("sym-otel")
+        .worker_threads(1)
+        .enable_all()
+        .build()?;
+    let runtime = Box::leak(Box::new(runtime));
+    let _guard = runtime.enter();
+
+    let sampler = Sampler::TraceIdRatioBased(config.sample_rate);
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_sampler(Sampler::ParentBased(Box::new(sampler)))
+                .with_resource(Resource::new([KeyValue::new(
+                    "service.name",
+                    "symbolicator",
+                )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .context("failed to create the OpenTelemetry exporter")?;
+
+    Ok(Some(tracer))
+}
+
+/// Fails if an OTLP endpoint is configured, as traces can not be exported.
+#[cfg(not(feature = "opentelemetry"))]
+pub fn init_tracer(config: &Tracing) -> Result<Option<Tracer>> {
+    match &config.otlp_endpoint {
+        Some(_) => anyhow::bail!("exporting traces requires the `opentelemetry` feature"),
+        None => Ok(None),
+    }
+}
+
+/// Creates the layer turning spans into OpenTelemetry spans, if there is a `tracer`.
+#[cfg(feature = "opentelemetry")]
+pub fn layer<S>(tracer: Option<Tracer>) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Creates a layer which does nothing, as there is never a `tracer`.
+#[cfg(not(feature = "opentelemetry"))]
+pub fn layer<S>(_tracer: Option<Tracer>) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_subscriber::layer::Identity::new()
+}
+
+/// Continues the trace given in the `traceparent` header of a request, if any, in `span`.
+#[cfg(feature = "opentelemetry")]
+pub fn set_parent(span: &tracing::Span, headers: &HeaderMap) {
+    use opentelemetry::propagation::{Extractor, TextMapPropagator};
+    use opentelemetry::sdk::propagation::TraceContextPropagator;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|key| key.as_str()).collect()
+        }
+    }
+
+    let context = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+    span.set_parent(context);
+}
+
+/// Does nothing, as there are no traces to continue.
+#[cfg(not(feature = "opentelemetry"))]
+pub fn set_parent(_span: &tracing::Span, _headers: &HeaderMap) {}
+
+/// Exports all remaining spans, and stops the exporter.
+pub fn shutdown() {
+    #[cfg(feature = "opentelemetry")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(all(test, feature = "opentelemetry"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::future::BoxFuture;
+    use opentelemetry::sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+    use opentelemetry::sdk::trace::TracerProvider;
+    use opentelemetry::trace::{SpanId, TraceId, TracerProvider as _};
+    use opentelemetry::Key;
+    use reqwest::{multipart, Client, StatusCode};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::test;
+
+    /// Collects all exported spans.
+    #[derive(Clone, Debug, Default)]
+    struct TestExporter(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for TestExporter {
+        fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+            self.0.lock().unwrap().extend(batch);
+            Box::pin(futures::future::ready(Ok(())))
+        }
+    }
+
+    /// Returns the names of the ancestors of `span`, starting with its parent.
+    fn ancestors<'a>(spans: &'a [SpanData], span: &'a SpanData) -> Vec<&'a str> {
+        let mut names = vec![];
+        let mut parent_id = span.parent_span_id;
+        while let Some(parent) = spans
+            .iter()
+            .find(|span| span.span_context.span_id() == parent_id)
+        {
+            names.push(parent.name.as_ref());
+            parent_id = parent.parent_span_id;
+        }
+        names
+    }
+
+    fn attribute(span: &SpanData, key: &'static str) -> Option<String> {
+        let value = span.attributes.get(&Key::from_static_str(key))?;
+        Some(value.as_str().into_owned())
+    }
+
+    #[tokio::test]
+    async fn test_span_hierarchy() {
+        let exporter = TestExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber =
+            tracing_subscriber::registry().with(layer(Some(provider.tracer("symbolicator"))));
+        let guard = tracing::subscriber::set_default(subscriber);
+
+        let server = test::server_with_default_service();
+
+        let file_part =
+            multipart::Part::bytes(test::read_fixture("windows.dmp")).file_name("windows.dmp");
+        let sources = serde_json::to_string(&[test::local_source()]).unwrap();
+        let form = multipart::Form::new()
+            .part("upload_file_minidump", file_part)
+            .text("sources", sources);
+
+        let response = Client::new()
+            .post(server.url("/minidump"))
+            .header(
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // dropping the provider exports all the spans which have ended
+        drop(guard);
+        drop(provider);
+        let spans = exporter.0.lock().unwrap().clone();
+
+        let trace_id = TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap();
+        assert!(spans
+            .iter()
+            .all(|span| span.span_context.trace_id() == trace_id));
+
+        let find_span = |name: &str| {
+            spans
+                .iter()
+                .find(|span| span.name == name)
+                .unwrap_or_else(|| panic!("missing span `{name}`"))
+        };
+
+        let request = find_span("request");
+        assert_eq!(
+            request.parent_span_id,
+            SpanId::from_hex("b7ad6b7169203331").unwrap()
+        );
+
+        let symbolicate = find_span("symbolicate");
+        assert!(ancestors(&spans, symbolicate).contains(&"request"));
+
+        let fetch_symcache = find_span("fetch_symcache");
+        assert!(ancestors(&spans, fetch_symcache).contains(&"symbolicate"));
+
+        let download = find_span("download");
+        assert!(ancestors(&spans, download).contains(&"request"));
+        assert_eq!(attribute(download, "source").as_deref(), Some("local"));
+
+        let cache = spans
+            .iter()
+            .find(|span| {
+                span.name == "cache" && attribute(span, "cache").as_deref() == Some("objects")
+            })
+            .unwrap();
+        assert!(ancestors(&spans, cache).contains(&"request"));
+        assert!(attribute(cache, "status").is_some());
+    }
+}
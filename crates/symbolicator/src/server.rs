@@ -1,18 +1,29 @@
 use axum_server::Handle;
 #[cfg(feature = "https")]
 use std::fs::read;
-use std::net::SocketAddr;
-#[cfg(feature = "https")]
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(any(unix, feature = "https"))]
 use std::path::PathBuf;
+#[cfg(unix)]
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 
+#[cfg(unix)]
+use axum::routing::IntoMakeService;
+#[cfg(unix)]
+use axum::Router;
 #[cfg(feature = "https")]
 use axum_server::tls_rustls::RustlsConfig;
 use futures::future::try_join_all;
 use futures::future::BoxFuture;
+use futures::TryFutureExt;
+use tokio_util::sync::CancellationToken;
 
-use crate::config::Config;
+use crate::config::{BindAddress, Config};
 use crate::endpoints;
 use crate::metric;
 use crate::service::RequestService;
@@ -31,11 +42,13 @@ pub fn run(config: Config) -> Result<()> {
     let megs = 1024 * 1024;
     let io_pool = tokio::runtime::Builder::new_multi_thread()
         .thread_name("sym-io")
+        .worker_threads(config.num_io_threads())
         .enable_all()
         .thread_stack_size(8 * megs)
         .build()?;
     let cpu_pool = tokio::runtime::Builder::new_multi_thread()
         .thread_name("sym-cpu")
+        .worker_threads(config.num_cpu_threads())
         .enable_all()
         .thread_stack_size(8 * megs)
         .build()?;
@@ -54,56 +67,322 @@ pub fn run(config: Config) -> Result<()> {
     )
     .context("failed to create service state")?;
 
-    let svc = endpoints::create_app(service).into_make_service();
-
-    let handle_http = Handle::new();
-    let socket_http = config.bind.parse::<SocketAddr>()?;
-    #[allow(clippy::redundant_clone)] // we need `svc` for the https case below
-    let server_http = axum_server::bind(socket_http)
-        .handle(handle_http.clone())
-        .serve(svc.clone());
-    servers.push(Box::pin(server_http));
-
-    let listening_http = async move {
-        if let Some(local_addr) = handle_http.listening().await {
-            tracing::info!("Starting HTTP server on {}", local_addr);
-        } else {
-            panic!("Unable to listen on HTTP port");
+    let svc = endpoints::create_app(service.clone()).into_make_service();
+
+    let mut handles = vec![];
+    let shutdown_token = CancellationToken::new();
+
+    for bind in &config.bind {
+        let address = bind
+            .parse::<BindAddress>()
+            .with_context(|| format!("invalid bind address `{bind}`"))?;
+        match address {
+            BindAddress::Tcp(addr) => {
+                let listener = bind_tcp(addr)?;
+                tracing::info!("Starting HTTP server on {}", listener.local_addr()?);
+
+                let handle_http = Handle::new();
+                handles.push(handle_http.clone());
+                let server_http = axum_server::from_tcp(listener)
+                    .handle(handle_http)
+                    .serve(svc.clone())
+                    .err_into();
+                servers.push(Box::pin(server_http));
+            }
+            #[cfg(unix)]
+            BindAddress::Unix(path) => {
+                let listener = bind_unix(&path)?;
+                tracing::info!("Starting HTTP server on {}", bind);
+
+                let server_unix = serve_unix(
+                    listener,
+                    path,
+                    svc.clone(),
+                    shutdown_token.clone(),
+                    config.shutdown_timeout,
+                );
+                servers.push(Box::pin(server_unix));
+            }
+            #[cfg(not(unix))]
+            BindAddress::Unix(_) => {
+                anyhow::bail!("bind address `{bind}` is a unix socket, which is not supported")
+            }
         }
-        Ok(())
-    };
-    servers.push(Box::pin(listening_http));
+    }
 
     #[cfg(feature = "https")]
     if let Some(ref bind_str) = config.bind_https {
         let handle_https = Handle::new();
-        let https_conf = match config.server_config.https {
-            None => panic!("Need HTTPS config"),
-            Some(ref conf) => conf,
-        };
-        let socket_https = bind_str.parse::<SocketAddr>()?;
+        handles.push(handle_https.clone());
+        let https_conf = config
+            .server_config
+            .https
+            .as_ref()
+            .context("bind_https requires server_config.https")?;
+        let socket_https = bind_str
+            .parse::<SocketAddr>()
+            .with_context(|| format!("invalid bind_https address `{bind_str}`"))?;
         let certificate = read_pem_file(&https_conf.certificate_path)?;
         let key = read_pem_file(&https_conf.key_path)?;
         let tls_config =
             web_pool.block_on(async { RustlsConfig::from_pem(certificate, key).await })?;
-        let server_https = axum_server::bind_rustls(socket_https, tls_config)
-            .handle(handle_https.clone())
-            .serve(svc);
+        let listener = bind_tcp(socket_https)?;
+        tracing::info!("Starting HTTPS server on {}", listener.local_addr()?);
+        let server_https = axum_server::from_tcp_rustls(listener, tls_config)
+            .handle(handle_https)
+            .serve(svc)
+            .err_into();
         servers.push(Box::pin(server_https));
-
-        let listening_https = async move {
-            if let Some(local_addr) = handle_https.listening().await {
-                tracing::info!("Starting HTTPS server on {}", local_addr);
-            } else {
-                panic!("Unable to listen on HTTPS port");
-            }
-            Ok(())
-        };
-        servers.push(Box::pin(listening_https));
     }
 
+    #[cfg(unix)]
+    web_pool.spawn(reload_on_sighup(service.clone()));
+
+    let shutdown_timeout = config.shutdown_timeout;
+    let shutdown = async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down, waiting for in-flight requests to complete");
+        metric!(counter("server.stopping") += 1);
+
+        // Stop accepting new connections, while giving open ones the chance to complete.
+        for handle in handles {
+            handle.graceful_shutdown(Some(shutdown_timeout));
+        }
+        shutdown_token.cancel();
+
+        // Requests that are being polled are not tied to any open connection.
+        let remaining = service.drain(shutdown_timeout).await;
+        if remaining > 0 {
+            tracing::warn!(
+                "Shutdown timeout of {:?} elapsed with {} requests still running",
+                shutdown_timeout,
+                remaining
+            );
+        }
+        Ok(())
+    };
+    servers.push(Box::pin(shutdown));
+
     web_pool.block_on(try_join_all(servers))?;
     tracing::info!("System shutdown complete");
 
     Ok(())
 }
+
+/// An error returned when the webserver cannot listen on one of its addresses.
+#[derive(Debug, thiserror::Error)]
+#[error("failed to bind to address `{address}`")]
+pub struct BindError {
+    /// The address that could not be bound.
+    pub address: BindAddress,
+    /// The underlying error, such as the address being in use already.
+    #[source]
+    pub source: io::Error,
+}
+
+/// Binds a TCP listener to `addr`.
+///
+/// Binding happens upfront, so that failures are reported along with the offending address.
+fn bind_tcp(addr: SocketAddr) -> Result<TcpListener, BindError> {
+    let bind = || -> io::Result<_> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    };
+    bind().map_err(|source| BindError {
+        address: BindAddress::Tcp(addr),
+        source,
+    })
+}
+
+/// Binds a listener to the Unix domain socket at `path`.
+///
+/// A socket left behind by a previous run is removed first.
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> Result<std::os::unix::net::UnixListener, BindError> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let bind = || -> io::Result<_> {
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    };
+    bind().map_err(|source| BindError {
+        address: BindAddress::Unix(path.to_owned()),
+        source,
+    })
+}
+
+/// Serves `svc` on the Unix domain socket `listener` until `shutdown` is cancelled.
+///
+/// Open connections get `shutdown_timeout` to complete, after which the socket is removed.
+#[cfg(unix)]
+async fn serve_unix(
+    listener: std::os::unix::net::UnixListener,
+    path: PathBuf,
+    svc: IntoMakeService<Router>,
+    shutdown: CancellationToken,
+    shutdown_timeout: Duration,
+) -> Result<()> {
+    let listener = tokio::net::UnixListener::from_std(listener)?;
+    let incoming = futures::stream::poll_fn(move |cx| {
+        listener
+            .poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _)| stream)))
+    });
+
+    let server = axum::Server::builder(hyper::server::accept::from_stream(incoming))
+        .serve(svc)
+        .with_graceful_shutdown(shutdown.cancelled());
+    // Unlike `axum_server`, hyper waits for open connections indefinitely.
+    let timeout = async {
+        shutdown.cancelled().await;
+        tokio::time::sleep(shutdown_timeout).await;
+    };
+
+    let serve_err = || format!("failed to serve on address `unix:{}`", path.display());
+    let result = tokio::select! {
+        result = server => result.with_context(serve_err),
+        _ = timeout => Ok(()),
+    };
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// Reloads the `sources` of the config whenever the process receives a `SIGHUP`.
+#[cfg(unix)]
+async fn reload_on_sighup(service: RequestService) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(err) => {
+            tracing::error!(
+                error = &err as &dyn std::error::Error,
+                "Failed to listen for SIGHUP"
+            );
+            return;
+        }
+    };
+
+    while sighup.recv().await.is_some() {
+        tracing::info!("Received SIGHUP, reloading sources");
+        if let Err(err) = service.reload() {
+            tracing::error!("Failed to reload config: {:#}", err);
+        }
+    }
+}
+
+/// Resolves once the process is asked to shut down, either via `SIGTERM` or `Ctrl-C`.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!(
+                error = &err as &dyn std::error::Error,
+                "Failed to listen for Ctrl-C"
+            );
+            futures::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                tracing::error!(
+                    error = &err as &dyn std::error::Error,
+                    "Failed to listen for SIGTERM"
+                );
+                futures::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = futures::future::pending::<()>();
+
+    futures::future::select(Box::pin(ctrl_c), Box::pin(terminate)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test;
+
+    #[test]
+    fn test_bind_tcp() {
+        let listener = bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // binding an address in use is an error rather than a panic
+        let err = bind_tcp(addr).unwrap_err();
+        assert_eq!(err.address, BindAddress::Tcp(addr));
+        assert_eq!(err.source.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(
+            err.to_string(),
+            format!("failed to bind to address `{addr}`")
+        );
+
+        // the error is preserved when returned from `run`
+        let err = anyhow::Error::from(err);
+        assert!(err.downcast_ref::<BindError>().is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serve_unix() {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+
+        test::setup();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("symbolicator.sock");
+        // a socket left behind by a previous run is replaced
+        drop(bind_unix(&path).unwrap());
+        let listener = bind_unix(&path).unwrap();
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(Config::default(), handle.clone(), handle).unwrap();
+        let svc = endpoints::create_app(service).into_make_service();
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(serve_unix(
+            listener,
+            path.clone(),
+            svc,
+            shutdown.clone(),
+            Duration::from_secs(1),
+        ));
+
+        let request_path = path.clone();
+        let response = tokio::task::spawn_blocking(move || {
+            let mut stream = UnixStream::connect(request_path).unwrap();
+            stream
+                .write_all(
+                    b"GET /healthcheck HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+        .await
+        .unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("ok"), "{response}");
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -15,35 +15,42 @@ use std::fmt;
 use std::fs::File;
 use std::future::Future;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use futures::channel::{mpsc, oneshot};
 use futures::future;
-use futures::{channel::oneshot, FutureExt as _};
+use futures::{FutureExt as _, SinkExt as _};
 use sentry::protocol::SessionStatus;
 use sentry::SentryFutureExt;
 use serde::{Deserialize, Deserializer, Serialize};
 use tempfile::TempPath;
+use tracing::Instrument;
 use uuid::Uuid;
 
-use symbolicator_service::caching::CacheEntry;
-use symbolicator_service::config::Config;
+use symbolicator_service::caching::{CacheEntry, CacheKey, CacheMode, CacheStats, Caches};
+use symbolicator_service::config::{Config, ConfigError};
 use symbolicator_service::metric;
-use symbolicator_service::services::objects::ObjectsActor;
+use symbolicator_service::services::objects::{CircuitBreakerStatus, ObjectsActor};
 use symbolicator_service::services::symbolication::SymbolicationActor;
-use symbolicator_service::types::{CompletedResponse, CompletedSymbolicationResponse};
+use symbolicator_service::types::{
+    CompleteStacktrace, CompletedResponse, CompletedSymbolicationResponse, FrameStatus,
+};
 use symbolicator_service::utils::futures::CallOnDrop;
-use symbolicator_service::utils::futures::{m, measure};
+use symbolicator_service::utils::futures::{m, measure, with_deadline};
+use symbolicator_service::utils::rate_limit::ScopeRateLimiter;
 use symbolicator_sources::SourceConfig;
 
 pub use symbolicator_service::services::objects::{
     FindObject, FindResult, ObjectHandle, ObjectMetaHandle, ObjectPurpose,
 };
 pub use symbolicator_service::services::symbolication::{
-    StacktraceOrigin, SymbolicateJsStacktraces, SymbolicateStacktraces,
+    StacktraceOrigin, SymbolicateJsStacktraces, SymbolicateStacktraces, WarmedModule,
+};
+pub use symbolicator_service::types::{
+    JsStacktrace, LookupCandidate, RawObjectInfo, RawStacktrace, Scope, Signal,
 };
-pub use symbolicator_service::types::{JsStacktrace, RawObjectInfo, RawStacktrace, Scope, Signal};
 
 /// Symbolication task identifier.
 #[derive(Debug, Clone, Copy, Serialize, Ord, PartialOrd, Eq, PartialEq)]
@@ -100,11 +107,24 @@ pub enum SymbolicationResponse {
     InternalError,
 }
 
+/// A single line of a streamed symbolication response.
+///
+/// A streamed response consists of one line per symbolicated stack trace, in the order of the
+/// request, followed by the [`SymbolicationResponse`] with everything but the stack traces.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum StreamedSymbolicationResponse {
+    /// A symbolicated stack trace.
+    Stacktrace { stacktrace: CompleteStacktrace },
+    /// The rest of the response, once all the stack traces were streamed.
+    Response(SymbolicationResponse),
+}
+
 /// Common options for all symbolication API requests.
 ///
 /// These options control some features which control the symbolication and general request
 /// handling behaviour.
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RequestOptions {
     /// Whether to return detailed information on DIF object candidates.
     ///
@@ -116,6 +136,59 @@ pub struct RequestOptions {
     /// for which extra information is returned for DIF objects.
     #[serde(default)]
     pub dif_candidates: bool,
+
+    /// The maximum number of seconds to spend symbolicating the request.
+    ///
+    /// Once this time has passed, frames whose debug files have not been fetched yet are
+    /// reported as timed out. Defaults to the configured `symbolication_timeout`, and is capped
+    /// by the configured `max_symbolication_timeout`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbolication_timeout: Option<u64>,
+
+    /// Whether to omit the frames of inlined functions.
+    ///
+    /// By default, an instruction address is expanded into the whole chain of inlined functions.
+    /// Enabling this only returns the outermost function of the chain.
+    #[serde(default)]
+    pub skip_inline_frames: bool,
+
+    /// Whether to add the surrounding source lines to the frames.
+    ///
+    /// Source context is only added if the source files are available. Missing sources leave the
+    /// frames without context.
+    #[serde(default = "default_include_source_context")]
+    pub include_source_context: bool,
+
+    /// The maximum number of frames returned for each thread.
+    ///
+    /// Only the topmost frames of longer stack traces are returned, and the stack traces are
+    /// marked as `truncated`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_frames_per_thread: Option<usize>,
+
+    /// How the caches are used for the debug files of the request.
+    ///
+    /// `refresh` downloads and converts the debug files again, replacing the cached versions,
+    /// while `bypass` does so without reading from or writing to the caches at all.
+    #[serde(default)]
+    pub cache: CacheMode,
+}
+
+fn default_include_source_context() -> bool {
+    true
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        Self {
+            dif_candidates: false,
+            symbolication_timeout: None,
+            skip_inline_frames: false,
+            include_source_context: default_include_source_context(),
+            max_frames_per_thread: None,
+            cache: CacheMode::Use,
+        }
+    }
 }
 
 /// Clears out all the information about the DIF object candidates in the modules list.
@@ -127,6 +200,140 @@ fn clear_dif_candidates(response: &mut CompletedSymbolicationResponse) {
         module.candidates.clear()
     }
 }
+
+/// Removes the frames of inlined functions from all the stack traces.
+///
+/// This keeps only the outermost frame of each instruction address.
+fn remove_inline_frames(response: &mut CompletedSymbolicationResponse) {
+    for stacktrace in response.stacktraces.iter_mut() {
+        stacktrace.frames.retain(|frame| !frame.is_inline);
+    }
+}
+
+/// Truncates the stack traces to the given limits, keeping their topmost frames.
+///
+/// Each stack trace is limited to `max_frames_per_thread` frames first. Then, at most `max_frames`
+/// frames are kept in total. The budget goes to the requesting (usually crashing) stack traces
+/// first, and to the remaining ones in their order afterwards. Stack traces which lost frames are
+/// marked as `truncated`.
+fn truncate_stacktraces(
+    response: &mut CompletedSymbolicationResponse,
+    max_frames_per_thread: Option<usize>,
+    max_frames: Option<usize>,
+) {
+    let mut remaining_frames = max_frames.unwrap_or(usize::MAX);
+    let (requesting, others): (Vec<_>, Vec<_>) = response
+        .stacktraces
+        .iter_mut()
+        .partition(|stacktrace| stacktrace.is_requesting == Some(true));
+    for stacktrace in requesting.into_iter().chain(others) {
+        truncate_stacktrace(stacktrace, max_frames_per_thread, &mut remaining_frames);
+    }
+}
+
+/// Truncates a single stack trace to `max_frames_per_thread` and the `remaining_frames` of its
+/// response, see [`truncate_stacktraces`].
+fn truncate_stacktrace(
+    stacktrace: &mut CompleteStacktrace,
+    max_frames_per_thread: Option<usize>,
+    remaining_frames: &mut usize,
+) {
+    let limit = max_frames_per_thread
+        .unwrap_or(usize::MAX)
+        .min(*remaining_frames);
+    if stacktrace.frames.len() > limit {
+        stacktrace.frames.truncate(limit);
+        stacktrace.truncated = true;
+    }
+    *remaining_frames -= stacktrace.frames.len();
+}
+
+/// Computes the key of a `symbolicate` request in the response cache.
+///
+/// The key covers everything the response depends on: the stack traces, modules and sources of
+/// the request, as well as the options which change the response. The deadline and cache mode are
+/// not part of the key, as responses containing timed out frames are never cached.
+fn response_cache_key(
+    request: &SymbolicateStacktraces,
+    options: &RequestOptions,
+) -> Option<ResponseCacheKey> {
+    // JSON serialization escapes newlines, keeping each attribute on a single line.
+    let attributes = [
+        (
+            "signal",
+            format!("{:?}", request.signal.map(|signal| signal.0)),
+        ),
+        ("origin", format!("{:?}", request.origin)),
+        ("source_context", request.apply_source_context.to_string()),
+        ("dif_candidates", options.dif_candidates.to_string()),
+        ("skip_inline_frames", options.skip_inline_frames.to_string()),
+        (
+            "max_frames_per_thread",
+            format!("{:?}", options.max_frames_per_thread),
+        ),
+        ("sources", serde_json::to_string(&request.sources).ok()?),
+        ("modules", serde_json::to_string(&request.modules).ok()?),
+        (
+            "stacktraces",
+            serde_json::to_string(&request.stacktraces).ok()?,
+        ),
+    ];
+
+    let mut builder = CacheKey::scoped_builder(&request.scope);
+    for (key, value) in attributes {
+        builder.write_attribute(key, value).ok()?;
+    }
+    Some(ResponseCacheKey {
+        scope: request.scope.clone(),
+        digest: builder.build().digest(),
+    })
+}
+
+/// The key of a response in the [`ResponseCache`].
+///
+/// Only the hash of the [`CacheKey`] is kept, as its metadata contains the whole request,
+/// including the credentials of its sources. The scope is kept for purging it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ResponseCacheKey {
+    scope: Scope,
+    digest: [u8; 32],
+}
+
+/// Whether any frame of the response was not symbolicated because of the request deadline.
+fn has_timed_out_frames(response: &CompletedSymbolicationResponse) -> bool {
+    response
+        .stacktraces
+        .iter()
+        .flat_map(|stacktrace| &stacktrace.frames)
+        .any(|frame| frame.status == FrameStatus::Timeout)
+}
+
+/// The interval at which in-flight requests are checked while draining the [`RequestService`].
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The maximum time a runtime may take to respond during a readiness check.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The time for which gathered cache statistics are reused, as gathering them walks all caches.
+const CACHE_STATS_TTL: Duration = Duration::from_secs(5);
+
+/// The number of symbolicated stack traces buffered for a streamed response.
+///
+/// Symbolication waits for the client to receive them once the buffer is full.
+const STREAMED_STACKTRACES_BUFFER: usize = 16;
+
+/// The [`CacheStats`] of all caches, keyed by the cache name.
+pub type CacheStatsMap = BTreeMap<String, CacheStats>;
+
+/// A component of the [`RequestService`] that failed its readiness check, or is degraded.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnhealthyComponent {
+    /// The name of the component.
+    pub component: &'static str,
+    /// A description of why the component is unhealthy.
+    pub detail: String,
+}
+
 /// The underlying service for the HTTP request handlers.
 #[derive(Clone)]
 pub struct RequestService {
@@ -138,17 +345,30 @@ type ComputationChannel = future::Shared<oneshot::Receiver<(Instant, Symbolicati
 
 type ComputationMap = Arc<Mutex<BTreeMap<RequestId, ComputationChannel>>>;
 
+/// Recent responses to `symbolicate` requests, keyed by [`response_cache_key`].
+type ResponseCache = moka::sync::Cache<ResponseCacheKey, CompletedSymbolicationResponse>;
+
 struct RequestServiceInner {
     config: Config,
+    /// The default sources, which can be swapped out by reloading the config.
+    sources: RwLock<Arc<[SourceConfig]>>,
 
     symbolication: SymbolicationActor,
     objects: ObjectsActor,
+    /// The local caches used by the services, sharing their state.
+    caches: Caches,
 
+    io_pool: tokio::runtime::Handle,
     cpu_pool: tokio::runtime::Handle,
     requests: ComputationMap,
     max_concurrent_requests: Option<usize>,
     current_requests: Arc<AtomicUsize>,
+    rate_limiter: Arc<ScopeRateLimiter>,
     symbolication_taskmon: tokio_metrics::TaskMonitor,
+    /// The most recently gathered cache statistics, along with the time they were gathered.
+    cache_stats: tokio::sync::Mutex<Option<(Instant, Arc<CacheStatsMap>)>>,
+    /// The response cache, if enabled by a non-zero `caches.in_memory.response_ttl`.
+    response_cache: Option<ResponseCache>,
 }
 
 impl RequestService {
@@ -165,8 +385,12 @@ impl RequestService {
             config.caches.in_memory.sentry_index_ttl = Duration::ZERO;
         }
 
-        let (symbolication, objects) =
-            symbolicator_service::services::create_service(&config, io_pool.clone())?;
+        let caches = Caches::from_config(&config).context("failed to create local caches")?;
+        let (symbolication, objects) = symbolicator_service::services::create_service_with_caches(
+            &config,
+            caches.clone(),
+            io_pool.clone(),
+        )?;
 
         let symbolication_taskmon = tokio_metrics::TaskMonitor::new();
         {
@@ -181,17 +405,48 @@ impl RequestService {
 
         let max_concurrent_requests = config.max_concurrent_requests;
 
+        let rate_limiter = Arc::new(ScopeRateLimiter::new(config.rate_limits.clone()));
+        if rate_limiter.is_enabled() {
+            let rate_limiter = rate_limiter.clone();
+            io_pool.spawn(async move {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    rate_limiter.remove_idle();
+                }
+            });
+        }
+
+        let in_memory = &config.caches.in_memory;
+        let response_cache = (!in_memory.response_ttl.is_zero()).then(|| {
+            ResponseCache::builder()
+                .max_capacity(in_memory.response_capacity)
+                // responses are weighed by the size of their JSON
+                .weigher(|_key, response| {
+                    let size = serde_json::to_vec(response).map_or(usize::MAX, |json| json.len());
+                    size.try_into().unwrap_or(u32::MAX)
+                })
+                .time_to_live(in_memory.response_ttl)
+                .support_invalidation_closures()
+                .build()
+        });
+
         let inner = RequestServiceInner {
+            sources: RwLock::new(config.default_sources()),
             config,
 
             symbolication,
             objects,
+            caches,
 
+            io_pool,
             cpu_pool,
             requests: Arc::new(Mutex::new(BTreeMap::new())),
             max_concurrent_requests,
             current_requests: Arc::new(AtomicUsize::new(0)),
+            rate_limiter,
             symbolication_taskmon,
+            cache_stats: Default::default(),
+            response_cache,
         };
 
         Ok(Self {
@@ -200,15 +455,206 @@ impl RequestService {
     }
 
     /// Gives access to the [`Config`].
+    ///
+    /// The `sources` of this config are the ones the service was created with, use
+    /// [`default_sources`](Self::default_sources) for the currently active ones.
     pub fn config(&self) -> &Config {
         &self.inner.config
     }
 
+    /// Returns the currently active default sources.
+    pub fn default_sources(&self) -> Arc<[SourceConfig]> {
+        self.inner.sources.read().unwrap().clone()
+    }
+
+    /// Returns the state of the circuit breaker of the `source`.
+    ///
+    /// This is `None` if the circuit breaker is disabled.
+    pub fn circuit_breaker_status(&self, source: &SourceConfig) -> Option<CircuitBreakerStatus> {
+        self.inner.objects.circuit_breaker().status(source)
+    }
+
+    /// Reloads the config from the file it was originally loaded from, and swaps in its `sources`.
+    ///
+    /// See [`reload_sources`](Self::reload_sources).
+    pub fn reload(&self) -> Result<()> {
+        let config = Config::get(self.inner.config.path.as_deref())?;
+        self.reload_sources(&config)?;
+        Ok(())
+    }
+
+    /// Swaps the default sources for the `sources` of the given `config`.
+    ///
+    /// The `config` is validated first, and the currently active sources are kept if it is
+    /// invalid. Requests that are already running keep using the sources they were created with,
+    /// and all caches are retained. All other config values are ignored.
+    pub fn reload_sources(&self, config: &Config) -> Result<(), ConfigError> {
+        config.validate()?;
+
+        let sources = config.default_sources();
+        let num_sources = sources.len();
+        *self.inner.sources.write().unwrap() = sources;
+
+        metric!(counter("sources.reloaded") += 1);
+        tracing::info!("Reloaded config with {} sources", num_sources);
+        Ok(())
+    }
+
+    /// Removes all the cache entries of the given non-global `scope` from the filesystem caches.
+    ///
+    /// Items of this scope which are currently held in memory, including cached responses, are
+    /// dropped as well. Items which were uploaded to the shared cache are not affected. Returns
+    /// the number of removed cache entries.
+    pub async fn purge_scope(&self, scope: Scope) -> Result<usize> {
+        let caches = self.inner.caches.clone();
+        let purged_scope = scope.clone();
+        let purge = move || caches.purge_scope(&purged_scope);
+        let removed = self.inner.io_pool.spawn_blocking(purge).await??;
+
+        if let Some(response_cache) = &self.inner.response_cache {
+            // This only fails if the cache does not support invalidation closures.
+            response_cache
+                .invalidate_entries_if(move |key, _| key.scope == scope)
+                .ok();
+        }
+
+        metric!(counter("scope.purged") += 1);
+        tracing::info!("Purged {} cache entries", removed);
+        Ok(removed)
+    }
+
+    /// Gathers statistics about the files of all caches on disk.
+    ///
+    /// As this walks all the cache directories, the statistics are reused for a few seconds.
+    /// Concurrent callers wait for the same walk instead of starting their own.
+    pub async fn cache_stats(&self) -> Result<Arc<CacheStatsMap>> {
+        let mut cached = self.inner.cache_stats.lock().await;
+        if let Some((gathered_at, ref stats)) = *cached {
+            if gathered_at.elapsed() < CACHE_STATS_TTL {
+                return Ok(Arc::clone(stats));
+            }
+        }
+
+        let caches = self.inner.caches.clone();
+        let stats = self
+            .inner
+            .io_pool
+            .spawn_blocking(move || caches.stats())
+            .await??;
+        let stats = Arc::new(stats);
+
+        *cached = Some((Instant::now(), Arc::clone(&stats)));
+        Ok(stats)
+    }
+
+    /// Checks whether the service is ready to handle requests.
+    ///
+    /// This makes sure that both the `io_pool` and `cpu_pool` runtimes are responsive, that
+    /// the directories of the caches are writable, and that the volumes of the caches have at
+    /// least the configured `min_free_space` available. Read-only caches are reported by
+    /// [`check_degraded`](Self::check_degraded) instead. Returns the list of components that are
+    /// unhealthy.
+    pub async fn check_readiness(&self) -> Vec<UnhealthyComponent> {
+        let mut unhealthy = Vec::new();
+
+        let pools = [
+            ("io_pool", &self.inner.io_pool),
+            ("cpu_pool", &self.inner.cpu_pool),
+        ];
+        for (component, pool) in pools {
+            let ping = tokio::time::timeout(READINESS_TIMEOUT, pool.spawn(async {}));
+            let detail = match ping.await {
+                Ok(Ok(())) => continue,
+                Ok(Err(err)) => err.to_string(),
+                Err(_) => format!("did not respond within {READINESS_TIMEOUT:?}"),
+            };
+            unhealthy.push(UnhealthyComponent { component, detail });
+        }
+
+        for (dir, err) in self.inner.caches.check_writable() {
+            unhealthy.push(UnhealthyComponent {
+                component: "cache_dir",
+                detail: format!("`{}` is not writable: {err}", dir.display()),
+            });
+        }
+
+        if let Some(min_free_space) = self.inner.config.caches.min_free_space {
+            for low in self.inner.caches.check_free_space(min_free_space) {
+                unhealthy.push(UnhealthyComponent {
+                    component: "cache_disk",
+                    detail: low.to_string(),
+                });
+            }
+        }
+
+        unhealthy
+    }
+
+    /// Checks for components which still work, but with limited functionality.
+    ///
+    /// This currently reports the caches whose directory is read-only, which serve their existing
+    /// files but do not store new items.
+    pub fn check_degraded(&self) -> Vec<UnhealthyComponent> {
+        self.inner
+            .caches
+            .read_only()
+            .into_iter()
+            .map(|name| UnhealthyComponent {
+                component: "cache_dir",
+                detail: format!("the {name} cache is read-only"),
+            })
+            .collect()
+    }
+
+    /// Waits for all the in-flight requests to complete, for at most `timeout`.
+    ///
+    /// Returns the number of requests that are still running after the `timeout`.
+    pub async fn drain(&self, timeout: Duration) -> usize {
+        let current_requests = &self.inner.current_requests;
+        let drained = async {
+            while current_requests.load(Ordering::Relaxed) > 0 {
+                tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(timeout, drained).await.ok();
+
+        current_requests.load(Ordering::Relaxed)
+    }
+
     /// Looks up the object according to the [`FindObject`] request.
     pub async fn find_object(&self, request: FindObject) -> FindResult {
         self.inner.objects.find(request).await
     }
 
+    /// Lists the files which would be looked up for the [`FindObject`] request.
+    ///
+    /// This does not download any of the files.
+    pub async fn explain_lookup(&self, request: FindObject) -> Vec<LookupCandidate> {
+        self.inner.objects.explain(request).await
+    }
+
+    /// Downloads the debug files of the given `modules` and converts them ahead of time.
+    ///
+    /// Subsequent symbolication requests using the same `scope` and `sources` are then served
+    /// from the caches. Returns the outcome for each of the `modules`.
+    pub async fn warm_caches(
+        &self,
+        scope: Scope,
+        sources: Arc<[SourceConfig]>,
+        modules: Vec<RawObjectInfo>,
+    ) -> Vec<WarmedModule> {
+        let warmed = self
+            .inner
+            .symbolication
+            .warm_caches(scope, sources, modules)
+            .await;
+
+        for module in &warmed {
+            metric!(counter("caches.warmed") += 1, "status" => module.status.name());
+        }
+        warmed
+    }
+
     /// Fetches the object given by the [`ObjectMetaHandle`].
     pub async fn fetch_object(
         &self,
@@ -223,9 +669,30 @@ impl RequestService {
     /// maximum number of requests, as configured by the `max_concurrent_requests` option.
     pub fn symbolicate_stacktraces(
         &self,
-        request: SymbolicateStacktraces,
+        mut request: SymbolicateStacktraces,
         options: RequestOptions,
     ) -> Result<RequestId, MaxRequestsError> {
+        request.deadline = request.deadline.or(self.symbolication_deadline(&options));
+        request.apply_source_context &= options.include_source_context;
+        request.cache_mode = options.cache;
+
+        // `bypass` neither reads nor writes the response cache, `refresh` only writes it.
+        let response_cache = match options.cache {
+            CacheMode::Bypass => None,
+            _ => self.inner.response_cache.clone(),
+        }
+        .and_then(|cache| Some((cache, response_cache_key(&request, &options)?)));
+        let cached_response = match (&response_cache, options.cache) {
+            (Some((cache, key)), CacheMode::Use) => cache.get(key),
+            _ => None,
+        };
+        if response_cache.is_some() {
+            match &cached_response {
+                Some(_) => metric!(counter("symbolication.response_cache.hit") += 1),
+                None => metric!(counter("symbolication.response_cache.miss") += 1),
+            }
+        }
+
         let slf = self.inner.clone();
         let span = sentry::configure_scope(|scope| scope.get_span());
         let ctx = sentry::TransactionContext::continue_from_span(
@@ -233,15 +700,164 @@ impl RequestService {
             "symbolicate_stacktraces",
             span,
         );
-        self.create_symbolication_request("symbolicate", options, async move {
+        let scope = request.scope.clone();
+        self.create_symbolication_request("symbolicate", &scope, options, async move {
+            if let Some(response) = cached_response {
+                return Ok(response.into());
+            }
+
             let transaction = sentry::start_transaction(ctx);
             sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
             let res = slf.symbolication.symbolicate(request).await;
             transaction.finish();
+
+            if let (Some((cache, key)), Ok(response)) = (response_cache, &res) {
+                if !has_timed_out_frames(response) {
+                    cache.insert(key, response.clone());
+                }
+            }
             res.map(Into::into)
         })
     }
 
+    /// Creates a new request to symbolicate stacktraces, streaming the symbolicated stack traces.
+    ///
+    /// This works like [`symbolicate_stacktraces`](Self::symbolicate_stacktraces), except that
+    /// each stack trace is sent to the returned receiver as soon as it is symbolicated, instead of
+    /// being part of the response. The receiver ends once all the stack traces were sent, after
+    /// which the rest of the response can be polled with the [`RequestId`]. The response cache
+    /// is not used for streamed requests.
+    ///
+    /// Only a few stack traces are buffered, symbolication waits for the receiver to catch up.
+    /// As the stack traces are sent in order, `max_response_frames` is applied in that order as
+    /// well, without reserving frames for the requesting stack traces.
+    pub fn symbolicate_stacktraces_streaming(
+        &self,
+        mut request: SymbolicateStacktraces,
+        options: RequestOptions,
+    ) -> Result<(RequestId, mpsc::Receiver<CompleteStacktrace>), MaxRequestsError> {
+        request.deadline = request.deadline.or(self.symbolication_deadline(&options));
+        request.apply_source_context &= options.include_source_context;
+        request.cache_mode = options.cache;
+
+        // The stack traces are post-processed just like the ones of a buffered response.
+        let (sender, receiver) = mpsc::channel(STREAMED_STACKTRACES_BUFFER);
+        let skip_inline_frames = options.skip_inline_frames;
+        let max_frames_per_thread = options.max_frames_per_thread;
+        let mut remaining_frames = self.inner.config.max_response_frames.unwrap_or(usize::MAX);
+        // sending fails once the receiver is gone, if the client disconnected
+        let emit = sender.with(move |mut stacktrace: CompleteStacktrace| {
+            if skip_inline_frames {
+                stacktrace.frames.retain(|frame| !frame.is_inline);
+            }
+            truncate_stacktrace(
+                &mut stacktrace,
+                max_frames_per_thread,
+                &mut remaining_frames,
+            );
+            future::ready(Ok::<_, mpsc::SendError>(stacktrace))
+        });
+
+        let slf = self.inner.clone();
+        let span = sentry::configure_scope(|scope| scope.get_span());
+        let ctx = sentry::TransactionContext::continue_from_span(
+            "symbolicate_stacktraces",
+            "symbolicate_stacktraces",
+            span,
+        );
+        let scope = request.scope.clone();
+        let request_id =
+            self.create_symbolication_request("symbolicate", &scope, options, async move {
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = slf
+                    .symbolication
+                    .symbolicate_incremental(request, emit)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            })?;
+
+        Ok((request_id, receiver))
+    }
+
+    /// Returns the deadline for symbolicating a request with the given `options`.
+    ///
+    /// The timeout of the request defaults to the configured `symbolication_timeout`, and is
+    /// capped by the configured `max_symbolication_timeout`.
+    fn symbolication_deadline(&self, options: &RequestOptions) -> Option<Instant> {
+        let config = &self.inner.config;
+        let timeout = options
+            .symbolication_timeout
+            .map(Duration::from_secs)
+            .or(config.symbolication_timeout);
+        let timeout = match (timeout, config.max_symbolication_timeout) {
+            (Some(timeout), Some(max_timeout)) => Some(timeout.min(max_timeout)),
+            (timeout, max_timeout) => timeout.or(max_timeout),
+        };
+        timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Creates new requests to symbolicate a batch of stacktraces.
+    ///
+    /// The debug files referenced by any of the `requests` are resolved only once for the whole
+    /// batch, before each of the `requests` is symbolicated on its own.
+    ///
+    /// The whole batch is admitted as a single request, counting once towards the
+    /// `max_concurrent_requests` and the rate limit of the `scope`. Returns a [`RequestId`] for
+    /// each of the `requests`, or an `Err` if the batch was not admitted.
+    pub fn symbolicate_batch(
+        &self,
+        scope: &Scope,
+        mut requests: Vec<SymbolicateStacktraces>,
+        options: RequestOptions,
+    ) -> Result<Vec<RequestId>, MaxRequestsError> {
+        let in_flight = self.admit_request(scope)?;
+
+        let deadline = self.symbolication_deadline(&options);
+        for request in &mut requests {
+            request.cache_mode = options.cache;
+        }
+        let prefetch = self
+            .inner
+            .symbolication
+            .prefetch_caches(&requests)
+            .boxed()
+            .shared();
+
+        let request_ids = requests
+            .into_iter()
+            .map(|mut request| {
+                request.deadline = request.deadline.or(deadline);
+                request.apply_source_context &= options.include_source_context;
+                let slf = self.inner.clone();
+                let prefetch = prefetch.clone();
+                let span = sentry::configure_scope(|scope| scope.get_span());
+                let ctx = sentry::TransactionContext::continue_from_span(
+                    "symbolicate_batch",
+                    "symbolicate_batch",
+                    span,
+                );
+                self.spawn_symbolication_request(
+                    "symbolicate_batch",
+                    options.clone(),
+                    in_flight.clone(),
+                    async move {
+                        let transaction = sentry::start_transaction(ctx);
+                        sentry::configure_scope(|scope| {
+                            scope.set_span(Some(transaction.clone().into()))
+                        });
+                        with_deadline(request.deadline, prefetch).await;
+                        let res = slf.symbolication.symbolicate(request).await;
+                        transaction.finish();
+                        res.map(Into::into)
+                    },
+                )
+            })
+            .collect();
+        Ok(request_ids)
+    }
+
     pub fn symbolicate_js_stacktraces(
         &self,
         request: SymbolicateJsStacktraces,
@@ -253,13 +869,19 @@ impl RequestService {
             "symbolicate_js_stacktraces",
             span,
         );
-        self.create_symbolication_request("symbolicate_js", RequestOptions::default(), async move {
-            let transaction = sentry::start_transaction(ctx);
-            sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
-            let res = slf.symbolication.symbolicate_js(request).await;
-            transaction.finish();
-            res.map(Into::into)
-        })
+        let scope = request.scope.clone();
+        self.create_symbolication_request(
+            "symbolicate_js",
+            &scope,
+            RequestOptions::default(),
+            async move {
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = slf.symbolication.symbolicate_js(request).await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
     }
 
     /// Creates a new request to process a minidump.
@@ -280,16 +902,21 @@ impl RequestService {
             "process_minidump",
             span,
         );
-        self.create_symbolication_request("minidump_stackwalk", options, async move {
-            let transaction = sentry::start_transaction(ctx);
-            sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
-            let res = slf
-                .symbolication
-                .process_minidump(scope, minidump_file, sources)
-                .await;
-            transaction.finish();
-            res.map(Into::into)
-        })
+        self.create_symbolication_request(
+            "minidump_stackwalk",
+            &scope.clone(),
+            options,
+            async move {
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = slf
+                    .symbolication
+                    .process_minidump(scope, minidump_file, sources)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
     }
 
     /// Creates a new request to process an Apple crash report.
@@ -310,16 +937,21 @@ impl RequestService {
             "process_apple_crash_report",
             span,
         );
-        self.create_symbolication_request("parse_apple_crash_report", options, async move {
-            let transaction = sentry::start_transaction(ctx);
-            sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
-            let res = slf
-                .symbolication
-                .process_apple_crash_report(scope, apple_crash_report, sources)
-                .await;
-            transaction.finish();
-            res.map(Into::into)
-        })
+        self.create_symbolication_request(
+            "parse_apple_crash_report",
+            &scope.clone(),
+            options,
+            async move {
+                let transaction = sentry::start_transaction(ctx);
+                sentry::configure_scope(|scope| scope.set_span(Some(transaction.clone().into())));
+                let res = slf
+                    .symbolication
+                    .process_apple_crash_report(scope, apple_crash_report, sources)
+                    .await;
+                transaction.finish();
+                res.map(Into::into)
+            },
+        )
     }
 
     /// Polls the status for a started symbolication task.
@@ -352,23 +984,29 @@ impl RequestService {
 
     /// Creates a new request to compute the given future.
     ///
-    /// Returns `None` if the `SymbolicationActor` is already processing the
-    /// maximum number of requests, as given by `max_concurrent_requests`.
+    /// Returns an `Err` if the `SymbolicationActor` is already processing the
+    /// maximum number of requests, as given by `max_concurrent_requests`, or if the `scope` has
+    /// exceeded its rate limit.
     fn create_symbolication_request<F>(
         &self,
         task_name: &'static str,
+        scope: &Scope,
         options: RequestOptions,
         f: F,
     ) -> Result<RequestId, MaxRequestsError>
     where
         F: Future<Output = Result<CompletedResponse>> + Send + 'static,
     {
-        let (sender, receiver) = oneshot::channel();
-
-        let hub = Arc::new(sentry::Hub::new_from_top(sentry::Hub::current()));
+        let in_flight = self.admit_request(scope)?;
+        Ok(self.spawn_symbolication_request(task_name, options, in_flight, f))
+    }
 
-        // Assume that there are no UUID4 collisions in practice.
-        let requests = Arc::clone(&self.inner.requests);
+    /// Admits a new request of the given `scope`, counting it as in flight.
+    ///
+    /// Returns an `Err` if the maximum number of requests, as given by `max_concurrent_requests`,
+    /// is already being processed, or if the `scope` has exceeded its rate limit. Otherwise, the
+    /// request stays in flight until the returned guard is dropped.
+    fn admit_request(&self, scope: &Scope) -> Result<Arc<InFlightRequest>, MaxRequestsError> {
         let current_requests = Arc::clone(&self.inner.current_requests);
 
         let num_requests = current_requests.load(Ordering::Relaxed);
@@ -378,16 +1016,46 @@ impl RequestService {
         if let Some(max_concurrent_requests) = self.inner.max_concurrent_requests {
             if num_requests >= max_concurrent_requests {
                 metric!(counter("requests.rejected") += 1);
-                return Err(MaxRequestsError);
+                return Err(MaxRequestsError::Concurrent);
             }
         }
 
+        if let Err(retry_after) = self.inner.rate_limiter.try_acquire(scope) {
+            metric!(counter("requests.rate_limited") += 1);
+            return Err(MaxRequestsError::RateLimited { retry_after });
+        }
+
+        current_requests.fetch_add(1, Ordering::Relaxed);
+        Ok(Arc::new(InFlightRequest(current_requests)))
+    }
+
+    /// Spawns a request to compute the given future, which was admitted by
+    /// [`admit_request`](Self::admit_request).
+    ///
+    /// The request stops counting as in flight once the `in_flight` guard is dropped by all the
+    /// requests sharing it.
+    fn spawn_symbolication_request<F>(
+        &self,
+        task_name: &'static str,
+        options: RequestOptions,
+        in_flight: Arc<InFlightRequest>,
+        f: F,
+    ) -> RequestId
+    where
+        F: Future<Output = Result<CompletedResponse>> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+
+        let hub = Arc::new(sentry::Hub::new_from_top(sentry::Hub::current()));
+
+        // Assume that there are no UUID4 collisions in practice.
+        let requests = Arc::clone(&self.inner.requests);
+
         let request_id = RequestId::new(uuid::Uuid::new_v4());
         requests
             .lock()
             .unwrap()
             .insert(request_id, receiver.shared());
-        current_requests.fetch_add(1, Ordering::Relaxed);
         let drop_hub = hub.clone();
         let token = CallOnDrop::new(move || {
             requests.lock().unwrap().remove(&request_id);
@@ -396,7 +1064,10 @@ impl RequestService {
             drop_hub.end_session_with_status(SessionStatus::Crashed);
         });
 
+        let max_poll_delay = self.inner.config.max_poll_delay;
+        let max_response_frames = self.inner.config.max_response_frames;
         let spawn_time = Instant::now();
+        let span = tracing::Span::current();
         let request_future = async move {
             metric!(timer("symbolication.create_request.first_poll") = spawn_time.elapsed());
 
@@ -404,12 +1075,23 @@ impl RequestService {
             let f = tokio::time::timeout(timeout, f);
             let f = measure(task_name, m::timed_result, f);
 
-            let response = match f.await {
+            // The span of the HTTP request, if any, is only kept until the response is ready, so
+            // that it ends along with the symbolication, see `propagate_request_id`.
+            let result = f.instrument(span.clone()).await;
+            let response = span.in_scope(|| match result {
                 Ok(Ok(mut response)) => {
-                    if !options.dif_candidates {
-                        if let CompletedResponse::NativeSymbolication(ref mut res) = response {
+                    if let CompletedResponse::NativeSymbolication(ref mut res) = response {
+                        if !options.dif_candidates {
                             clear_dif_candidates(res)
                         }
+                        if options.skip_inline_frames {
+                            remove_inline_frames(res)
+                        }
+                        truncate_stacktraces(
+                            res,
+                            options.max_frames_per_thread,
+                            max_response_frames,
+                        );
                     }
                     sentry::end_session_with_status(SessionStatus::Exited);
                     SymbolicationResponse::Completed(Box::new(response))
@@ -434,17 +1116,18 @@ impl RequestService {
                     sentry::end_session_with_status(SessionStatus::Abnormal);
                     SymbolicationResponse::Timeout
                 }
-            };
+            });
+            drop(span);
 
             sender.send((Instant::now(), response)).ok();
 
             // We stop counting the request as an in-flight request at this point, even though
-            // it will stay in the `requests` map for another 90s.
-            current_requests.fetch_sub(1, Ordering::Relaxed);
+            // it will stay in the `requests` map for another `max_poll_delay`.
+            drop(in_flight);
 
             // Wait before removing the channel from the computation map to allow clients to
             // poll the status.
-            tokio::time::sleep(MAX_POLL_DELAY).await;
+            tokio::time::sleep(max_poll_delay).await;
 
             drop(token);
         }
@@ -454,18 +1137,33 @@ impl RequestService {
             .cpu_pool
             .spawn(self.inner.symbolication_taskmon.instrument(request_future));
 
-        Ok(request_id)
+        request_id
     }
 }
 
-/// The maximum delay we allow for polling a finished request before dropping it.
-const MAX_POLL_DELAY: Duration = Duration::from_secs(90);
+/// Counts an admitted request as in flight until it is dropped.
+struct InFlightRequest(Arc<AtomicUsize>);
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 /// An error returned when symbolicator receives a request while already processing
-/// the maximum number of requests.
+/// the maximum number of requests, or when the scope of the request exceeded its rate limit.
 #[derive(Debug, Clone, thiserror::Error)]
-#[error("maximum number of concurrent requests reached")]
-pub struct MaxRequestsError;
+pub enum MaxRequestsError {
+    /// The maximum number of concurrent requests is reached.
+    #[error("maximum number of concurrent requests reached")]
+    Concurrent,
+    /// The scope of the request exceeded its rate limit.
+    #[error("rate limit of the scope exceeded")]
+    RateLimited {
+        /// The time after which a request of the scope is admitted again.
+        retry_after: Duration,
+    },
+}
 
 async fn wrap_response_channel(
     request_id: RequestId,
@@ -528,7 +1226,10 @@ pub fn record_task_metrics(name: &str, metrics: &tokio_metrics::TaskMetrics) {
 
 #[cfg(test)]
 mod tests {
-    use symbolicator_service::types::{CompleteObjectInfo, RawFrame};
+    use moka::sync::ConcurrentCacheExt as _;
+    use symbolicator_service::types::{
+        CompleteObjectInfo, CompleteStacktrace, RawFrame, SymbolicatedFrame,
+    };
     use symbolicator_service::utils::hex::HexValue;
     use symbolicator_sources::ObjectType;
 
@@ -564,6 +1265,9 @@ mod tests {
             origin: StacktraceOrigin::Symbolicate,
             sources: Arc::new([]),
             scope: Default::default(),
+            deadline: None,
+            apply_source_context: true,
+            cache_mode: CacheMode::Use,
         };
 
         let request_id = service
@@ -602,7 +1306,11 @@ mod tests {
                 code_file: None,
                 debug_file: None,
                 debug_checksum: None,
+                arch: None,
             })],
+            deadline: None,
+            apply_source_context: true,
+            cache_mode: CacheMode::Use,
         }
     }
 
@@ -642,4 +1350,305 @@ mod tests {
             .symbolicate_stacktraces(request, RequestOptions::default())
             .is_err());
     }
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        test::setup();
+
+        // Without a cache directory and in-memory file caches, every symbolication downloads the
+        // debug files again unless its response is cached.
+        let mut config = Config {
+            cache_dir: None,
+            connect_to_reserved_ips: true,
+            ..Default::default()
+        };
+        config.caches.in_memory.file_caches = false;
+        config.caches.in_memory.response_ttl = Duration::from_secs(3600);
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let (hitcounter, source) = test::symbol_server();
+
+        let symbolicate = |options: RequestOptions| {
+            let service = service.clone();
+            let request = get_symbolication_request(vec![source.clone()]);
+            async move {
+                let request_id = service.symbolicate_stacktraces(request, options).unwrap();
+                let response = service.get_response(request_id, None).await.unwrap();
+                serde_json::to_value(response).unwrap()
+            }
+        };
+        let with_cache = |cache| RequestOptions {
+            cache,
+            ..Default::default()
+        };
+
+        let response = symbolicate(with_cache(CacheMode::Use)).await;
+        assert!(hitcounter.accesses() > 0);
+
+        // An identical request is served from the response cache.
+        assert_eq!(symbolicate(with_cache(CacheMode::Use)).await, response);
+        assert_eq!(hitcounter.accesses(), 0);
+
+        // `refresh` and `bypass` symbolicate the request again.
+        assert_eq!(symbolicate(with_cache(CacheMode::Refresh)).await, response);
+        assert!(hitcounter.accesses() > 0);
+        assert_eq!(symbolicate(with_cache(CacheMode::Bypass)).await, response);
+        assert!(hitcounter.accesses() > 0);
+
+        // A request with different options is not served from the cache.
+        let options = RequestOptions {
+            skip_inline_frames: true,
+            ..Default::default()
+        };
+        symbolicate(options).await;
+        assert!(hitcounter.accesses() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_capacity() {
+        test::setup();
+
+        let mut config = Config {
+            cache_dir: None,
+            connect_to_reserved_ips: true,
+            ..Default::default()
+        };
+        config.caches.in_memory.file_caches = false;
+        config.caches.in_memory.response_ttl = Duration::from_secs(3600);
+        // responses are weighed by their size, which exceeds this capacity
+        config.caches.in_memory.response_capacity = 16;
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+        let (hitcounter, source) = test::symbol_server();
+
+        for _ in 0..2 {
+            let request = get_symbolication_request(vec![source.clone()]);
+            let request_id = service
+                .symbolicate_stacktraces(request, RequestOptions::default())
+                .unwrap();
+            service.get_response(request_id, None).await.unwrap();
+            assert!(hitcounter.accesses() > 0);
+
+            // oversized responses are evicted by the pending maintenance tasks
+            service.inner.response_cache.as_ref().unwrap().sync();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_poll_delay() {
+        test::setup();
+
+        let config = Config {
+            max_poll_delay: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let request = get_symbolication_request(vec![]);
+        let request_id = service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+
+        // the completed result can be polled repeatedly within the `max_poll_delay`
+        for _ in 0..2 {
+            let response = service.get_response(request_id, None).await;
+            assert!(matches!(
+                response,
+                Some(SymbolicationResponse::Completed(_))
+            ));
+        }
+
+        // and is evicted afterwards
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(service.get_response(request_id, None).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reload_sources() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let hitcounter = test::Server::new();
+        let old_source = hitcounter.source("old", "/symbols/");
+
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            connect_to_reserved_ips: true,
+            sources: Arc::new([old_source.clone()]),
+            ..Default::default()
+        };
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config.clone(), handle.clone(), handle).unwrap();
+
+        let symbolicate = |sources: Arc<[SourceConfig]>| {
+            let service = service.clone();
+            async move {
+                let mut request = get_symbolication_request(vec![]);
+                request.sources = sources;
+                let request_id = service
+                    .symbolicate_stacktraces(request, RequestOptions::default())
+                    .unwrap();
+                service.get_response(request_id, None).await.unwrap()
+            }
+        };
+
+        symbolicate(service.default_sources()).await;
+        assert!(hitcounter
+            .all_hits()
+            .iter()
+            .all(|(path, _)| path.starts_with("/symbols/")));
+
+        // an invalid config is rejected, keeping the old sources
+        let invalid_config = Config {
+            sources: Arc::new([old_source.clone(), old_source.clone()]),
+            ..config.clone()
+        };
+        assert!(service.reload_sources(&invalid_config).is_err());
+        assert_eq!(service.default_sources()[0].id().as_str(), "old");
+
+        let new_source = hitcounter.source("new", "/respond_statuscode/404/");
+        let new_config = Config {
+            sources: Arc::new([new_source]),
+            ..config
+        };
+        service.reload_sources(&new_config).unwrap();
+        assert_eq!(service.default_sources()[0].id().as_str(), "new");
+
+        // new requests use the new sources
+        symbolicate(service.default_sources()).await;
+        let hits = hitcounter.all_hits();
+        assert!(!hits.is_empty());
+        assert!(hits
+            .iter()
+            .all(|(path, _)| path.starts_with("/respond_statuscode/404/")));
+
+        // while the caches of the old sources are retained
+        symbolicate(Arc::new([old_source])).await;
+        assert_eq!(hitcounter.accesses(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        test::setup();
+
+        let config = Config {
+            connect_to_reserved_ips: true,
+            ..Default::default()
+        };
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(config, handle.clone(), handle).unwrap();
+
+        let hitcounter = test::Server::new();
+        let source = hitcounter.source("slow", "/delay/500ms/");
+
+        let request = get_symbolication_request(vec![source]);
+        let request_id = service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+
+        // the slow request completes while draining
+        assert_eq!(service.drain(Duration::from_secs(10)).await, 0);
+        let response = service.get_response(request_id, Some(0)).await;
+        assert!(matches!(
+            response,
+            Some(SymbolicationResponse::Completed(_))
+        ));
+
+        let source = hitcounter.source("pending", "/delay/1h/");
+        let request = get_symbolication_request(vec![source]);
+        service
+            .symbolicate_stacktraces(request, RequestOptions::default())
+            .unwrap();
+
+        // requests still running after the timeout are reported
+        assert_eq!(service.drain(Duration::from_millis(100)).await, 1);
+    }
+
+    fn stacktrace_with_frames(frames: usize) -> CompleteStacktrace {
+        CompleteStacktrace {
+            frames: (0..frames)
+                .map(|index| SymbolicatedFrame {
+                    original_index: Some(index),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn original_indexes(stacktrace: &CompleteStacktrace) -> Vec<usize> {
+        stacktrace
+            .frames
+            .iter()
+            .filter_map(|frame| frame.original_index)
+            .collect()
+    }
+
+    #[test]
+    fn test_truncate_stacktraces() {
+        let mut response = CompletedSymbolicationResponse {
+            stacktraces: vec![stacktrace_with_frames(5), stacktrace_with_frames(2)],
+            ..Default::default()
+        };
+
+        truncate_stacktraces(&mut response, Some(3), None);
+
+        // the topmost frames of the thread over the limit are kept
+        assert_eq!(original_indexes(&response.stacktraces[0]), [0, 1, 2]);
+        assert!(response.stacktraces[0].truncated);
+        // the thread under the limit is untouched
+        assert_eq!(original_indexes(&response.stacktraces[1]), [0, 1]);
+        assert!(!response.stacktraces[1].truncated);
+    }
+
+    #[test]
+    fn test_truncate_stacktraces_total() {
+        let mut response = CompletedSymbolicationResponse {
+            stacktraces: vec![
+                stacktrace_with_frames(3),
+                stacktrace_with_frames(3),
+                stacktrace_with_frames(3),
+            ],
+            ..Default::default()
+        };
+
+        truncate_stacktraces(&mut response, None, Some(4));
+
+        assert_eq!(original_indexes(&response.stacktraces[0]), [0, 1, 2]);
+        assert!(!response.stacktraces[0].truncated);
+        assert_eq!(original_indexes(&response.stacktraces[1]), [0]);
+        assert!(response.stacktraces[1].truncated);
+        assert!(response.stacktraces[2].frames.is_empty());
+        assert!(response.stacktraces[2].truncated);
+    }
+
+    #[test]
+    fn test_truncate_stacktraces_requesting_last() {
+        let mut crashing = stacktrace_with_frames(3);
+        crashing.is_requesting = Some(true);
+        let mut response = CompletedSymbolicationResponse {
+            stacktraces: vec![
+                stacktrace_with_frames(3),
+                stacktrace_with_frames(3),
+                crashing,
+            ],
+            ..Default::default()
+        };
+
+        truncate_stacktraces(&mut response, None, Some(4));
+
+        // the crashing thread keeps all its frames, even though it comes last
+        assert_eq!(original_indexes(&response.stacktraces[2]), [0, 1, 2]);
+        assert!(!response.stacktraces[2].truncated);
+        assert_eq!(original_indexes(&response.stacktraces[0]), [0]);
+        assert!(response.stacktraces[0].truncated);
+        assert!(response.stacktraces[1].frames.is_empty());
+        assert!(response.stacktraces[1].truncated);
+    }
 }
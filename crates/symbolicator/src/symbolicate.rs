@@ -0,0 +1,196 @@
+//! Symbolicates a single minidump without starting the web server.
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use structopt::StructOpt;
+
+use symbolicator_service::types::Scope;
+use symbolicator_sources::{FilesystemSourceConfig, HttpSourceConfig, SourceConfig, SourceId};
+
+use crate::config::Config;
+use crate::service::{RequestOptions, RequestService, SymbolicationResponse};
+
+/// Arguments of the `symbolicate` command.
+#[derive(Debug, StructOpt)]
+pub struct SymbolicateArgs {
+    /// Path to the minidump to symbolicate.
+    #[structopt(value_name = "MINIDUMP")]
+    pub minidump: PathBuf,
+
+    /// A local directory or an HTTP(S) symbol server to load symbols from.
+    ///
+    /// Can be given multiple times. Defaults to the `sources` of the configuration file.
+    #[structopt(long = "source", value_name = "SOURCE")]
+    pub sources: Vec<String>,
+}
+
+/// Parses a source given on the command line, which is either a URL or a directory.
+fn parse_source(index: usize, source: &str) -> Result<SourceConfig> {
+    let id = SourceId::new(format!("cli-{index}"));
+
+    if source.starts_with("http://") || source.starts_with("https://") {
+        // Locations are joined onto the URL, which thus needs to end in a slash.
+        let url = if source.ends_with('/') {
+            source.to_owned()
+        } else {
+            format!("{source}/")
+        };
+        let url = url
+            .parse()
+            .with_context(|| format!("invalid source URL `{source}`"))?;
+        return Ok(SourceConfig::Http(Arc::new(HttpSourceConfig {
+            id,
+            url,
+            headers: Default::default(),
+            auth: None,
+            bypass_proxy: false,
+            files: Default::default(),
+        })));
+    }
+
+    let path = Path::new(source);
+    anyhow::ensure!(path.is_dir(), "source `{source}` is not a directory");
+    Ok(SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+        id,
+        path: path.to_owned(),
+        files: Default::default(),
+    })))
+}
+
+/// Symbolicates the minidump at `path` with the given `service`.
+///
+/// The minidump is processed exactly like the ones uploaded to the `/minidump` endpoint.
+async fn symbolicate_minidump(
+    service: &RequestService,
+    path: &Path,
+    sources: Arc<[SourceConfig]>,
+) -> Result<SymbolicationResponse> {
+    // The minidump is removed once it was processed, so process a copy of it.
+    let mut minidump_file = tempfile::Builder::new();
+    minidump_file.prefix("minidump").suffix(".dmp");
+    let minidump_file = match service.config().cache_dir("tmp") {
+        Some(tmp_dir) => minidump_file.tempfile_in(tmp_dir),
+        None => minidump_file.tempfile(),
+    }?;
+    tokio::fs::copy(path, minidump_file.path())
+        .await
+        .with_context(|| format!("failed to read minidump `{}`", path.display()))?;
+
+    let request_id = service.process_minidump(
+        Scope::Global,
+        minidump_file.into_temp_path(),
+        sources,
+        RequestOptions::default(),
+    )?;
+
+    service
+        .get_response(request_id, None)
+        .await
+        .context("symbolication request did not start")
+}
+
+/// Symbolicates a single minidump and prints the response to stdout as JSON.
+pub fn run(config: Config, args: SymbolicateArgs) -> Result<()> {
+    let megs = 1024 * 1024;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("sym-cli")
+        .enable_all()
+        .thread_stack_size(8 * megs)
+        .build()?;
+
+    let service = RequestService::create(
+        config,
+        runtime.handle().to_owned(),
+        runtime.handle().to_owned(),
+    )
+    .context("failed to create service state")?;
+
+    let sources = if args.sources.is_empty() {
+        service.default_sources()
+    } else {
+        args.sources
+            .iter()
+            .enumerate()
+            .map(|(index, source)| parse_source(index, source))
+            .collect::<Result<_>>()?
+    };
+
+    let response = runtime.block_on(symbolicate_minidump(&service, &args.minidump, sources))?;
+
+    let stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(stdout, &response).context("failed to write the response")?;
+    println!();
+
+    match response {
+        SymbolicationResponse::Completed(_) => Ok(()),
+        _ => anyhow::bail!("failed to symbolicate the minidump"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use symbolicator_service::types::{CompletedResponse, FrameStatus};
+
+    use super::*;
+    use crate::test;
+
+    #[test]
+    fn test_parse_source() {
+        let source = parse_source(0, "http://localhost:1234/symbols").unwrap();
+        match source {
+            SourceConfig::Http(source) => {
+                assert_eq!(source.id.as_str(), "cli-0");
+                assert_eq!(source.url.as_str(), "http://localhost:1234/symbols/");
+            }
+            _ => panic!("unexpected source"),
+        }
+
+        let fixtures = test::fixture("symbols");
+        let source = parse_source(1, fixtures.to_str().unwrap()).unwrap();
+        match source {
+            SourceConfig::Filesystem(source) => {
+                assert_eq!(source.id.as_str(), "cli-1");
+                assert_eq!(source.path, fixtures);
+            }
+            _ => panic!("unexpected source"),
+        }
+
+        assert!(parse_source(2, "/does/not/exist").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_symbolicate_minidump() {
+        test::setup();
+
+        let handle = tokio::runtime::Handle::current();
+        let service = RequestService::create(Config::default(), handle.clone(), handle).unwrap();
+        let minidump = test::fixture("windows.dmp");
+        let source = parse_source(0, test::fixture("symbols").to_str().unwrap()).unwrap();
+
+        let response = symbolicate_minidump(&service, &minidump, Arc::new([source]))
+            .await
+            .unwrap();
+
+        // the fixture itself is not consumed
+        assert!(minidump.is_file());
+
+        let response = match response {
+            SymbolicationResponse::Completed(response) => *response,
+            _ => panic!("symbolication failed"),
+        };
+        let response = match response {
+            CompletedResponse::NativeSymbolication(response) => response,
+            _ => panic!("unexpected response"),
+        };
+
+        let functions: Vec<_> = response
+            .stacktraces
+            .iter()
+            .flat_map(|stacktrace| &stacktrace.frames)
+            .filter(|frame| frame.status == FrameStatus::Symbolicated)
+            .filter_map(|frame| frame.raw.function.as_deref())
+            .collect();
+        assert!(functions.contains(&"main"), "{functions:?}");
+    }
+}
@@ -77,7 +77,13 @@ async fn main() -> Result<()> {
         }
         None => {
             tracing::info!("event not found in local file system");
-            let Mode::Online { base_url, org, project, auth_token } = mode else {
+            let Mode::Online {
+                base_url,
+                org,
+                project,
+                auth_token,
+            } = mode
+            else {
                 anyhow::bail!("Event not found in local file system and `symbolicli` is in offline mode. Stopping.");
             };
 
@@ -453,8 +459,9 @@ mod remote {
         let Some(minidump_id) = attachments
             .iter()
             .find(|attachment| attachment.r#type == "event.minidump")
-            .map(|attachment| &attachment.id) else {
-                return Ok(None);
+            .map(|attachment| &attachment.id)
+        else {
+            return Ok(None);
         };
 
         let mut download_url = attachments_url.join(&format!("{minidump_id}/")).unwrap();
@@ -538,6 +545,7 @@ mod event {
     use anyhow::bail;
     use serde::Deserialize;
     use symbolic::common::Language;
+    use symbolicator_service::caching::CacheMode;
     use symbolicator_service::services::symbolication::{StacktraceOrigin, SymbolicateStacktraces};
     use symbolicator_service::types::{
         CompleteObjectInfo, FrameTrust, RawFrame, RawObjectInfo, RawStacktrace, Scope, Signal,
@@ -597,6 +605,9 @@ mod event {
             origin: StacktraceOrigin::Symbolicate,
             stacktraces,
             modules,
+            deadline: None,
+            apply_source_context: true,
+            cache_mode: CacheMode::Use,
         })
     }
 